@@ -0,0 +1,367 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    path::PathBuf,
+    process::Command,
+    sync::Mutex,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::{Engine, engine::general_purpose::URL_SAFE_NO_PAD};
+use k8s_openapi::{ByteString, chrono};
+use reqwest::blocking::{Client, RequestBuilder};
+use serde::Deserialize;
+
+// passed to credential plugins, kube-fuse can't prompt the user
+const EXEC_INFO: &str = r#"{"apiVersion":"client.authentication.k8s.io/v1","kind":"ExecCredential","spec":{"interactive":false}}"#;
+
+// credentials from a plugin or an OIDC issuer are renewed this long before
+// they expire
+const EXPIRY_MARGIN: Duration = Duration::from_secs(30);
+
+/// One way of authenticating requests, e.g. `token: <token>`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum AuthConfig {
+    /// A static bearer token.
+    Token { token: String },
+    /// A bearer token read from a file, read again whenever the file changes
+    /// or the token is rejected, e.g. a rotated service account token.
+    TokenFile {
+        #[serde(rename = "tokenFile")]
+        token_file: PathBuf,
+    },
+    /// A credential plugin printing an ExecCredential, like in kubeconfig
+    /// files, e.g. `aws eks get-token`.
+    Exec { exec: ExecConfig },
+    /// An OpenID Connect ID token renewed with a refresh token before it
+    /// expires, like the `oidc` auth provider of kubeconfig files.
+    Oidc { oidc: OidcConfig },
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct ExecConfig {
+    pub command: String,
+    #[serde(default)]
+    pub args: Vec<String>,
+    #[serde(default)]
+    pub env: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct OidcConfig {
+    pub issuer_url: String,
+    pub client_id: String,
+    #[serde(default)]
+    pub client_secret: Option<String>,
+    pub refresh_token: String,
+    /// The current ID token, used until it expires.
+    #[serde(default)]
+    pub id_token: Option<String>,
+    /// Base64 PEM certificates of the CA of the issuer, trusted in addition
+    /// to the system ones.
+    #[serde(default)]
+    pub certificate_authority_data: Option<ByteString>,
+}
+
+/// Adds credentials to outgoing requests. Failures are logged and the
+/// request is sent without them, for the API server to reject.
+pub trait AuthLayer: Send + Sync + fmt::Debug {
+    fn apply(&self, request: RequestBuilder) -> RequestBuilder;
+
+    /// Called when the API server rejected the credentials, to have them
+    /// reloaded for the next request.
+    fn unauthorized(&self) {}
+}
+
+/// The authentication layers every request passes through, in order.
+#[derive(Debug, Default)]
+pub struct AuthChain {
+    layers: Vec<Box<dyn AuthLayer>>,
+}
+
+impl AuthChain {
+    pub fn new(configs: &[AuthConfig]) -> Self {
+        let mut chain = AuthChain::default();
+        for config in configs {
+            match config {
+                AuthConfig::Token { token } => chain.push(BearerToken(token.clone())),
+                AuthConfig::TokenFile { token_file } => chain.push(TokenFile {
+                    path: token_file.clone(),
+                    cached: Mutex::new(None),
+                }),
+                AuthConfig::Exec { exec } => chain.push(ExecPlugin {
+                    config: exec.clone(),
+                    cached: Mutex::new(None),
+                }),
+                AuthConfig::Oidc { oidc } => chain.push(OidcProvider {
+                    config: oidc.clone(),
+                    tokens: Mutex::new(OidcTokens {
+                        id_token: oidc.id_token.clone().map(|token| {
+                            let expires_at = jwt_expiry(&token);
+                            (token, expires_at)
+                        }),
+                        refresh_token: oidc.refresh_token.clone(),
+                    }),
+                }),
+            }
+        }
+        chain
+    }
+
+    pub fn push(&mut self, layer: impl AuthLayer + 'static) {
+        self.layers.push(Box::new(layer));
+    }
+
+    pub fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        self.layers
+            .iter()
+            .fold(request, |request, layer| layer.apply(request))
+    }
+
+    pub fn unauthorized(&self) {
+        for layer in &self.layers {
+            layer.unauthorized();
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct BearerToken(pub String);
+
+impl AuthLayer for BearerToken {
+    fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        request.bearer_auth(&self.0)
+    }
+}
+
+#[derive(Debug)]
+struct TokenFile {
+    path: PathBuf,
+    // token and the modification time of the file it was read from
+    cached: Mutex<Option<(String, Option<SystemTime>)>>,
+}
+
+impl AuthLayer for TokenFile {
+    fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        let Ok(mut cached) = self.cached.lock() else {
+            return request;
+        };
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|metadata| metadata.modified())
+            .ok();
+        let changed = match &*cached {
+            None => true,
+            Some((_, read_modified)) => modified.is_none() || *read_modified != modified,
+        };
+        if changed {
+            match std::fs::read_to_string(&self.path) {
+                Ok(token) => *cached = Some((token.trim().to_string(), modified)),
+                // keep using the previous token, e.g. while the file is replaced
+                Err(e) => log::error!("failed to read {}: {e}", self.path.display()),
+            }
+        }
+
+        match &*cached {
+            Some((token, _)) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    fn unauthorized(&self) {
+        if let Ok(mut cached) = self.cached.lock() {
+            *cached = None;
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ExecPlugin {
+    config: ExecConfig,
+    // token and when it expires, if ever
+    cached: Mutex<Option<(String, Option<SystemTime>)>>,
+}
+
+impl ExecPlugin {
+    fn run(&self) -> Result<(String, Option<SystemTime>), String> {
+        let ExecConfig { command, args, env } = &self.config;
+        let output = Command::new(command)
+            .args(args)
+            .envs(env)
+            .env("KUBERNETES_EXEC_INFO", EXEC_INFO)
+            .output()
+            .map_err(|e| format!("failed to run {command}: {e}"))?;
+        if !output.status.success() {
+            return Err(format!(
+                "{command} failed with {}: {}",
+                output.status,
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        let credential: serde_json::Value = serde_json::from_slice(&output.stdout)
+            .map_err(|e| format!("invalid ExecCredential from {command}: {e}"))?;
+        let status = &credential["status"];
+        let token = status["token"]
+            .as_str()
+            .ok_or_else(|| format!("no token in the ExecCredential from {command}"))?;
+        let expires_at = status["expirationTimestamp"]
+            .as_str()
+            .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+            .and_then(|t| t.timestamp().try_into().ok())
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs));
+        Ok((token.to_string(), expires_at))
+    }
+}
+
+impl AuthLayer for ExecPlugin {
+    fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        let Ok(mut cached) = self.cached.lock() else {
+            return request;
+        };
+        let expired = match &*cached {
+            None => true,
+            Some((_, None)) => false,
+            Some((_, Some(expires_at))) => SystemTime::now() + EXPIRY_MARGIN >= *expires_at,
+        };
+        if expired {
+            match self.run() {
+                Ok(credential) => *cached = Some(credential),
+                Err(e) => log::error!("failed to get credentials: {e}"),
+            }
+        }
+
+        match &*cached {
+            Some((token, _)) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    fn unauthorized(&self) {
+        if let Ok(mut cached) = self.cached.lock() {
+            *cached = None;
+        }
+    }
+}
+
+#[derive(Debug)]
+struct OidcProvider {
+    config: OidcConfig,
+    tokens: Mutex<OidcTokens>,
+}
+
+#[derive(Debug)]
+struct OidcTokens {
+    // ID token and when it expires, if it says
+    id_token: Option<(String, Option<SystemTime>)>,
+    // issuers may hand out a new one with every refresh
+    refresh_token: String,
+}
+
+impl OidcProvider {
+    // trades the refresh token for a new ID token at the token endpoint of
+    // the issuer
+    fn refresh(&self, tokens: &mut OidcTokens) -> Result<(), Box<dyn std::error::Error>> {
+        let mut client = Client::builder();
+        if let Some(ByteString(ca)) = &self.config.certificate_authority_data {
+            for certificate in reqwest::Certificate::from_pem_bundle(ca)? {
+                client = client.add_root_certificate(certificate);
+            }
+        }
+        let client = client.build()?;
+
+        let issuer = self.config.issuer_url.trim_end_matches('/');
+        let discovery: serde_json::Value = client
+            .get(format!("{issuer}/.well-known/openid-configuration"))
+            .send()?
+            .error_for_status()?
+            .json()?;
+        let token_endpoint = discovery["token_endpoint"]
+            .as_str()
+            .ok_or_else(|| format!("{issuer} has no token endpoint"))?;
+
+        let mut form = vec![
+            ("grant_type", "refresh_token"),
+            ("refresh_token", &tokens.refresh_token),
+            ("client_id", &self.config.client_id),
+        ];
+        if let Some(secret) = &self.config.client_secret {
+            form.push(("client_secret", secret));
+        }
+        let response: serde_json::Value = client
+            .post(token_endpoint)
+            .form(&form)
+            .send()?
+            .error_for_status()?
+            .json()?;
+        let id_token = response["id_token"]
+            .as_str()
+            .ok_or_else(|| format!("no id_token in the response from {token_endpoint}"))?;
+
+        tokens.id_token = Some((id_token.to_string(), jwt_expiry(id_token)));
+        if let Some(refresh_token) = response["refresh_token"].as_str() {
+            tokens.refresh_token = refresh_token.to_string();
+        }
+        Ok(())
+    }
+}
+
+impl AuthLayer for OidcProvider {
+    fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        let Ok(mut tokens) = self.tokens.lock() else {
+            return request;
+        };
+        let expired = match &tokens.id_token {
+            None => true,
+            Some((_, None)) => false,
+            Some((_, Some(expires_at))) => SystemTime::now() + EXPIRY_MARGIN >= *expires_at,
+        };
+        if expired && let Err(e) = self.refresh(&mut tokens) {
+            log::error!(
+                "failed to refresh the ID token from {}: {e}",
+                self.config.issuer_url
+            );
+        }
+
+        match &tokens.id_token {
+            Some((token, _)) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    fn unauthorized(&self) {
+        if let Ok(mut tokens) = self.tokens.lock() {
+            tokens.id_token = None;
+        }
+    }
+}
+
+// the `exp` claim of a JWT, which is not verified, only the API server has
+// to trust it
+fn jwt_expiry(token: &str) -> Option<SystemTime> {
+    let payload = token.split('.').nth(1)?;
+    let claims: serde_json::Value =
+        serde_json::from_slice(&URL_SAFE_NO_PAD.decode(payload).ok()?).ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(claims["exp"].as_u64()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layers_are_configured_in_order() {
+        let configs: Vec<AuthConfig> = serde_yaml::from_str(
+            "- token: abc\n- exec:\n    command: aws\n    args: [eks, get-token]\n- tokenFile: /var/run/token\n- oidc: {issuerUrl: https://dex, clientId: k8s, refreshToken: r}\n",
+        )
+        .unwrap();
+        assert!(matches!(&configs[0], AuthConfig::Token { token } if token == "abc"));
+        assert!(
+            matches!(&configs[1], AuthConfig::Exec { exec } if exec.command == "aws" && exec.args.len() == 2)
+        );
+        assert!(matches!(&configs[2], AuthConfig::TokenFile { .. }));
+        assert!(matches!(&configs[3], AuthConfig::Oidc { oidc } if oidc.client_id == "k8s"));
+        assert_eq!(AuthChain::new(&configs).layers.len(), 4);
+    }
+}
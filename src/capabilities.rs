@@ -0,0 +1,129 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::api::authorization::v1::{
+    ResourceAttributes, SelfSubjectAccessReview, SelfSubjectAccessReviewSpec,
+};
+
+use crate::client::KubeClient;
+
+/// Verbs checked for every resource type, in the order they are reported.
+const VERBS: [&str; 4] = ["list", "watch", "get", "update"];
+
+/// What the mount's credentials allow to do with each presented resource
+/// type, as reported by the API server when mounting.
+#[derive(Default)]
+pub struct Capabilities {
+    // resource -> verb -> allowed, unset if the API server couldn't tell
+    resources: BTreeMap<&'static str, BTreeMap<&'static str, Option<bool>>>,
+    // resource -> API group, to ask about namespaces later
+    groups: BTreeMap<&'static str, &'static str>,
+    // (namespace, resource, verb) -> allowed, asked once denied cluster-wide
+    namespaced: BTreeMap<(String, String, String), Option<bool>>,
+}
+
+impl Capabilities {
+    /// Asks the API server which of the verbs the credentials allow for all
+    /// objects of type `T` across the cluster.
+    pub fn probe<T: k8s_openapi::Resource>(&mut self, client: &KubeClient) {
+        let verbs = VERBS
+            .into_iter()
+            .map(|verb| {
+                (
+                    verb,
+                    review(client, T::GROUP, T::URL_PATH_SEGMENT, None, verb),
+                )
+            })
+            .collect();
+        self.resources.insert(T::URL_PATH_SEGMENT, verbs);
+        self.groups.insert(T::URL_PATH_SEGMENT, T::GROUP);
+    }
+
+    /// Whether the verb is allowed for the resource, assuming it is unless the
+    /// API server said otherwise.
+    pub fn allows(&self, resource: &str, verb: &str) -> bool {
+        self.resources
+            .get(resource)
+            .and_then(|verbs| verbs.get(verb).copied().flatten())
+            .unwrap_or(true)
+    }
+
+    /// Whether the verb is allowed for the resource within a namespace. Where
+    /// it's denied across the cluster, the API server is asked about the
+    /// namespace, once, as a RoleBinding may still allow it there.
+    pub fn allows_in(
+        &mut self,
+        client: &KubeClient,
+        namespace: Option<&str>,
+        resource: &str,
+        verb: &str,
+    ) -> bool {
+        if self.allows(resource, verb) {
+            return true;
+        }
+        let (Some(namespace), Some(group)) = (namespace, self.groups.get(resource).copied()) else {
+            return false;
+        };
+        let key = (
+            namespace.to_string(),
+            resource.to_string(),
+            verb.to_string(),
+        );
+        let allowed = match self.namespaced.get(&key) {
+            Some(allowed) => *allowed,
+            None => {
+                let allowed = review(client, group, resource, Some(namespace), verb);
+                self.namespaced.insert(key, allowed);
+                allowed
+            }
+        };
+        allowed.unwrap_or(true)
+    }
+
+    /// Renders the contents of the `.kubefuse/capabilities.yaml` file.
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        for (resource, verbs) in &self.resources {
+            out.push_str(&format!("{resource}:\n"));
+            for verb in VERBS {
+                let allowed = match verbs.get(verb).copied().flatten() {
+                    Some(allowed) => allowed.to_string(),
+                    None => "unknown".to_string(),
+                };
+                out.push_str(&format!("  {verb}: {allowed}\n"));
+            }
+        }
+        out
+    }
+}
+
+// asks the API server whether the verb is allowed for the resource, across
+// the cluster or within a namespace, `None` if it couldn't tell
+fn review(
+    client: &KubeClient,
+    group: &str,
+    resource: &str,
+    namespace: Option<&str>,
+    verb: &str,
+) -> Option<bool> {
+    let review = SelfSubjectAccessReview {
+        spec: SelfSubjectAccessReviewSpec {
+            resource_attributes: Some(ResourceAttributes {
+                group: Some(group.to_string()),
+                resource: Some(resource.to_string()),
+                namespace: namespace.map(str::to_string),
+                verb: Some(verb.to_string()),
+                ..Default::default()
+            }),
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    match client.create(None, &review) {
+        Ok(review) => review.status.map(|status| status.allowed),
+        Err(e) => {
+            log::warn!("failed to check whether {verb} {resource} is allowed: {e}");
+            None
+        }
+    }
+}
@@ -0,0 +1,445 @@
+use std::{collections::HashMap, path::Path, time::Duration};
+
+use serde::Deserialize;
+
+use crate::{
+    auth::AuthConfig,
+    client::{ApiResource, RetryPolicy},
+    hooks::Hook,
+    layout::Layout,
+    quota::MutationLimit,
+};
+
+/// Namespaces hidden by `hideSystemNamespaces`, a trailing `*` matches any
+/// suffix.
+const SYSTEM_NAMESPACES: [&str; 4] = [
+    "kube-system",
+    "kube-public",
+    "kube-node-lease",
+    "openshift-*",
+];
+
+/// Contents of the kube-fuse configuration file.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Config {
+    pub profiles: HashMap<String, Profile>,
+    /// Refresh intervals in seconds of individual resource types, e.g.
+    /// `events: 10`, overriding `--refresh-interval`.
+    pub refresh_intervals: HashMap<String, u64>,
+    /// Times of day during which resource types are refreshed less often,
+    /// e.g. outside business hours.
+    pub refresh_windows: Vec<RefreshWindow>,
+    /// Limits on how often objects can be mutated through the mount.
+    pub mutation_limits: Vec<MutationLimit>,
+    /// How requests failing for reasons likely to pass are retried, e.g.
+    /// `{attempts: 6, maxDelay: 60}`.
+    pub retries: RetryPolicy,
+    /// How much of the histories collected while the mount runs is kept, e.g.
+    /// `{maxAge: 3600, maxBytes: 262144}`.
+    pub history_retention: HistoryRetention,
+    /// Bytes of manifests to keep in memory at most, evicting those read least
+    /// recently; 0 keeps them all.
+    pub memory_budget: u64,
+    /// Namespaces to hide regardless of the profile, see
+    /// [`Profile::hidden_namespaces`].
+    pub hidden_namespaces: Vec<String>,
+    /// Resource types to present regardless of the profile, see
+    /// [`Profile::custom_resources`].
+    pub custom_resources: Vec<ApiResource>,
+    /// Commands to run regardless of the profile, see [`Profile::hooks`].
+    pub hooks: Vec<Hook>,
+    /// How to authenticate to the API server when `--token` isn't given,
+    /// e.g. `- exec: {command: aws, args: [eks, get-token, ...]}`.
+    pub auth: Vec<AuthConfig>,
+}
+
+/// A named bundle of settings describing how a cluster is presented in the
+/// mount, so that e.g. developers and auditors can share a single config file.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(default, rename_all = "camelCase")]
+pub struct Profile {
+    /// Resource directories to present in each namespace, e.g. `configmaps`.
+    /// All supported resources are presented when unset.
+    pub resources: Option<Vec<String>>,
+    /// Resource directories never to present, e.g. `secrets`, taking
+    /// precedence over `resources`.
+    pub excluded_resources: Vec<String>,
+    /// Replace the values of Secret `data` and `stringData` keys.
+    pub redact_secrets: bool,
+    /// Mount the filesystem read-only.
+    pub read_only: bool,
+    /// Strip server-populated fields (`uid`, `managedFields`, `status`, ...)
+    /// from the rendered manifests.
+    pub sanitize: bool,
+    /// Hide namespaces of the cluster's own components, e.g. `kube-system`.
+    pub hide_system_namespaces: bool,
+    /// Names of namespaces not to present, a trailing `*` matches any suffix,
+    /// e.g. `monitoring-*`.
+    pub hidden_namespaces: Vec<String>,
+    /// Names of the only namespaces to present, a trailing `*` matches any
+    /// suffix. All namespaces not hidden are presented when empty.
+    pub namespaces: Vec<String>,
+    /// Don't list the namespaces until the root of the mount is listed, and
+    /// resolve those accessed by name with a request of their own, e.g. where
+    /// listing namespaces isn't allowed.
+    pub lazy_namespaces: bool,
+    /// Resource types whose objects are listed by their metadata only, e.g.
+    /// `configmaps`, each manifest being fetched when its file is opened.
+    pub metadata_only: Vec<String>,
+    /// Present objects that can't be rendered, and resource types that can't
+    /// be listed, as `.error` files holding the error rather than as empty
+    /// manifests or directories, failing the readiness check meanwhile.
+    pub strict: bool,
+    /// Extension of the files holding rendered manifests.
+    pub manifest_extension: ManifestExtension,
+    /// Format of the rendered manifests.
+    pub manifest_format: ManifestFormat,
+    /// Namespaced resource types kube-fuse has no typed model for, e.g.
+    /// custom resources, presented as the API server returns them. Pods are
+    /// among them, `{apiVersion: v1, plural: pods}`, so presenting their
+    /// logs, containers and `security.txt` takes listing them here unless
+    /// `discoverResources` is set.
+    pub custom_resources: Vec<ApiResource>,
+    /// Present every namespaced resource type the API server serves, as
+    /// found by discovery when mounting, in addition to `customResources`.
+    pub discover_resources: bool,
+    /// Delete objects from the cluster when their manifests are removed.
+    pub enable_delete: bool,
+    /// Commands to run when objects enter a status, e.g. `unhealthy`.
+    pub hooks: Vec<Hook>,
+    /// Layouts to present the manifests in besides the namespace tree, each
+    /// in a `_<layout>/` directory in the root of the mount.
+    pub layouts: Vec<Layout>,
+    /// Resource types whose directories are split into subdirectories, by
+    /// resource type, e.g. `events: creation-date`, for those holding more
+    /// objects than tools can list.
+    pub shards: HashMap<String, Sharding>,
+    /// Only present objects matching this label selector, e.g.
+    /// `app.kubernetes.io/part-of=shop`. Namespaces are always presented.
+    pub label_selector: Option<String>,
+    /// Field selectors to list resource types with, by resource type, e.g.
+    /// `pods: status.phase=Running`. One for `*` applies to all of them
+    /// but namespaces and events.
+    pub field_selectors: HashMap<String, String>,
+}
+
+/// How the directory of a resource type is split into subdirectories.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Sharding {
+    /// By the first two characters of the object names, e.g. `_we/`.
+    NamePrefix,
+    /// By the day the objects were created, e.g. `_2024-05-02/`.
+    CreationDate,
+}
+
+/// Extension appended to object names to form the names of the files holding
+/// their manifests.
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ManifestExtension {
+    #[default]
+    Yaml,
+    Yml,
+    /// Files are named after the objects, e.g. `configmaps/app-config`.
+    None,
+}
+
+impl ManifestExtension {
+    pub fn file_name(self, object_name: &str) -> String {
+        match self {
+            ManifestExtension::Yaml => format!("{object_name}.yaml"),
+            ManifestExtension::Yml => format!("{object_name}.yml"),
+            ManifestExtension::None => object_name.to_string(),
+        }
+    }
+
+    /// The object name a manifest file name was formed from.
+    pub fn object_name(self, file_name: &str) -> Option<&str> {
+        match self {
+            ManifestExtension::Yaml => file_name.strip_suffix(".yaml"),
+            ManifestExtension::Yml => file_name.strip_suffix(".yml"),
+            ManifestExtension::None => Some(file_name),
+        }
+    }
+}
+
+/// Format of the rendered manifests.
+#[derive(Deserialize, clap::ValueEnum, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ManifestFormat {
+    #[default]
+    Yaml,
+    /// Manifests are named `<object>.json` unless `manifestExtension` is
+    /// `none`.
+    Json,
+    /// YAML manifests, each with a read-only JSON copy named `<object>.json`
+    /// next to it.
+    Both,
+}
+
+impl Config {
+    pub fn load(path: &Path) -> Result<Config, Box<dyn std::error::Error>> {
+        let content = std::fs::read(path)?;
+        let config: Config = serde_yaml::from_slice(&content)?;
+        for window in &config.refresh_windows {
+            if window.bounds().is_none() {
+                return Err(format!(
+                    "invalid refresh window {}-{}, expected HH:MM times",
+                    window.from, window.to
+                )
+                .into());
+            }
+        }
+        Ok(config)
+    }
+
+    /// Looks up a profile by name. Profiles from the config file take
+    /// precedence over the built-in `developer`, `operator` and `auditor` ones.
+    pub fn profile(&self, name: &str) -> Option<Profile> {
+        self.profiles
+            .get(name)
+            .cloned()
+            .or_else(|| Profile::builtin(name))
+    }
+
+    /// The profile to mount with, the named one or else the default one, with
+    /// the namespaces to hide, resource types and hooks configured for every
+    /// profile added to it.
+    pub fn mount_profile(&self, name: Option<&str>) -> Option<Profile> {
+        let mut profile = match name {
+            Some(name) => self.profile(name)?,
+            None => Profile::default(),
+        };
+        profile
+            .hidden_namespaces
+            .extend(self.hidden_namespaces.iter().cloned());
+        profile
+            .custom_resources
+            .extend(self.custom_resources.iter().cloned());
+        profile.hooks.extend(self.hooks.iter().cloned());
+        Some(profile)
+    }
+}
+
+/// A time of day during which resource types are refreshed at most every
+/// `interval` seconds, and watches give way to polling at that interval.
+#[derive(Deserialize, Debug, Clone)]
+pub struct RefreshWindow {
+    /// Start of the window in UTC, e.g. `19:00`.
+    pub from: String,
+    /// End of the window in UTC, e.g. `07:00`. Windows may span midnight.
+    pub to: String,
+    pub interval: u64,
+}
+
+impl RefreshWindow {
+    /// Start and end of the window in minutes since midnight, `None` if
+    /// either isn't a valid `HH:MM` time.
+    pub fn bounds(&self) -> Option<(u32, u32)> {
+        let minutes = |time: &str| {
+            let (hours, minutes) = time.split_once(':')?;
+            let (hours, minutes): (u32, u32) = (hours.parse().ok()?, minutes.parse().ok()?);
+            (hours < 24 && minutes < 60).then_some(hours * 60 + minutes)
+        };
+        Some((minutes(&self.from)?, minutes(&self.to)?))
+    }
+
+    /// Whether the window covers the given minute since midnight.
+    pub fn contains(&self, minute: u32) -> bool {
+        match self.bounds() {
+            Some((from, to)) if from <= to => (from..to).contains(&minute),
+            Some((from, to)) => minute >= from || minute < to,
+            None => false,
+        }
+    }
+}
+
+/// How often each resource type is re-listed from the cluster.
+#[derive(Debug, Clone, Default)]
+pub struct RefreshIntervals {
+    pub default: Duration,
+    pub resources: HashMap<String, Duration>,
+    pub windows: Vec<RefreshWindow>,
+    /// Start with refreshing paused, see `.kubefuse/paused`.
+    pub paused: bool,
+}
+
+impl RefreshIntervals {
+    pub fn new(default: Duration, config: &Config) -> Self {
+        RefreshIntervals {
+            default,
+            resources: config
+                .refresh_intervals
+                .iter()
+                .map(|(resource, secs)| (resource.clone(), Duration::from_secs(*secs)))
+                .collect(),
+            windows: config.refresh_windows.clone(),
+            paused: false,
+        }
+    }
+
+    /// Returns the refresh interval of a resource type, zero if it should not
+    /// be refreshed at all.
+    pub fn interval(&self, resource: &str) -> Duration {
+        self.resources
+            .get(resource)
+            .copied()
+            .unwrap_or(self.default)
+    }
+}
+
+/// How much of the histories collected while the mount runs is kept:
+/// `changes.log` and the logs received through `.follow` files. Lines older
+/// than `max_age` seconds or beyond `max_bytes` per file are dropped, the
+/// oldest first; 0 disables either limit.
+#[derive(Deserialize, Debug, Clone, Copy)]
+#[serde(default, rename_all = "camelCase")]
+pub struct HistoryRetention {
+    pub max_age: u64,
+    pub max_bytes: u64,
+}
+
+impl Default for HistoryRetention {
+    fn default() -> Self {
+        HistoryRetention {
+            max_age: 24 * 60 * 60,
+            max_bytes: 1024 * 1024,
+        }
+    }
+}
+
+impl Profile {
+    fn builtin(name: &str) -> Option<Profile> {
+        match name {
+            "developer" => Some(Profile {
+                resources: Some(
+                    [
+                        "configmaps",
+                        "services",
+                        "endpointslices",
+                        "deployments",
+                        "replicasets",
+                        "statefulsets",
+                        "daemonsets",
+                    ]
+                    .map(String::from)
+                    .to_vec(),
+                ),
+                redact_secrets: true,
+                sanitize: true,
+                hide_system_namespaces: true,
+                ..Profile::default()
+            }),
+            "operator" => Some(Profile::default()),
+            "auditor" => Some(Profile {
+                redact_secrets: true,
+                read_only: true,
+                ..Profile::default()
+            }),
+            _ => None,
+        }
+    }
+
+    /// Name of the file holding the manifest of an object.
+    pub fn manifest_file_name(&self, object_name: &str) -> String {
+        match (self.manifest_format, self.manifest_extension) {
+            (ManifestFormat::Json, ManifestExtension::Yaml | ManifestExtension::Yml) => {
+                format!("{object_name}.json")
+            }
+            (_, extension) => extension.file_name(object_name),
+        }
+    }
+
+    /// The object name a manifest file name was formed from.
+    pub fn manifest_object_name<'a>(&self, file_name: &'a str) -> Option<&'a str> {
+        match (self.manifest_format, self.manifest_extension) {
+            (ManifestFormat::Json, ManifestExtension::Yaml | ManifestExtension::Yml) => {
+                file_name.strip_suffix(".json")
+            }
+            (_, extension) => extension.object_name(file_name),
+        }
+    }
+
+    pub fn shows_resource(&self, resource: &str) -> bool {
+        !self.excluded_resources.iter().any(|r| r == resource)
+            && self
+                .resources
+                .as_ref()
+                .is_none_or(|resources| resources.iter().any(|r| r == resource))
+    }
+
+    /// Whether the objects of a resource type are listed by their metadata
+    /// only, see [`Profile::metadata_only`].
+    pub fn lists_metadata_only(&self, resource: &str) -> bool {
+        self.metadata_only.iter().any(|r| r == resource)
+    }
+
+    pub fn hides_namespace(&self, namespace: &str) -> bool {
+        let matches = |pattern: &str| match pattern.strip_suffix('*') {
+            Some(prefix) => namespace.starts_with(prefix),
+            None => namespace == pattern,
+        };
+        if !self.namespaces.is_empty() && !self.namespaces.iter().any(|p| matches(p)) {
+            return true;
+        }
+        let system = if self.hide_system_namespaces {
+            &SYSTEM_NAMESPACES[..]
+        } else {
+            &[]
+        };
+        system
+            .iter()
+            .copied()
+            .chain(self.hidden_namespaces.iter().map(String::as_str))
+            .any(matches)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "
+hiddenNamespaces: [monitoring-*]
+customResources:
+- {apiVersion: cert-manager.io/v1, plural: certificates}
+profiles:
+  developer:
+    resources: [configmaps]
+    hiddenNamespaces: [sandbox]
+  reviewer:
+    readOnly: true
+    redactSecrets: true
+";
+
+    #[test]
+    fn config_profiles_take_precedence_over_builtin_ones() {
+        let config: Config = serde_yaml::from_str(CONFIG).unwrap();
+        let developer = config.profile("developer").unwrap();
+        assert_eq!(developer.resources, Some(vec!["configmaps".to_string()]));
+        // fields the config leaves out keep their defaults, not the builtin's
+        assert!(!developer.redact_secrets);
+        assert!(!developer.hide_system_namespaces);
+
+        assert!(config.profile("auditor").unwrap().read_only);
+        assert!(config.profile("reviewer").unwrap().read_only);
+        assert!(config.profile("nobody").is_none());
+    }
+
+    #[test]
+    fn config_wide_settings_are_added_to_the_profile() {
+        let config: Config = serde_yaml::from_str(CONFIG).unwrap();
+        let developer = config.mount_profile(Some("developer")).unwrap();
+        assert_eq!(developer.hidden_namespaces, ["sandbox", "monitoring-*"]);
+        assert!(developer.hides_namespace("monitoring-eu"));
+        assert_eq!(developer.custom_resources.len(), 1);
+
+        let default = config.mount_profile(None).unwrap();
+        assert_eq!(default.hidden_namespaces, ["monitoring-*"]);
+        assert!(default.shows_resource("secrets"));
+        assert_eq!(default.custom_resources[0].plural, "certificates");
+        assert!(config.mount_profile(Some("nobody")).is_none());
+    }
+}
@@ -0,0 +1,629 @@
+use std::{
+    error::Error,
+    ffi::OsStr,
+    time::{Duration, SystemTime},
+};
+
+use fuser::{FileAttr, FileType, Filesystem, Request};
+
+use crate::kubefuse::{self, KubeFilesystem, ROOT_ATTR};
+
+const TTL: Duration = Duration::from_secs(1);
+
+// holds a directory per context
+pub const CLUSTERS_DIR_NAME: &str = "clusters";
+const CLUSTERS_INODE: u64 = 2;
+
+// marks the directory of a context whose cluster couldn't be reached, holds
+// the reason
+const UNREACHABLE_NAME: &str = ".unreachable";
+
+// each context gets its own range of inodes, the first being the root of its
+// tree followed by the marker
+const CONTEXT_INODE_SHIFT: u32 = 40;
+const CONTEXT_INODE_MASK: u64 = (1 << CONTEXT_INODE_SHIFT) - 1;
+
+/// Presents the tree of one kubeconfig context, given its name.
+pub type Connector = Box<dyn FnMut(&str) -> Result<KubeFilesystem, Box<dyn Error>>>;
+
+enum Cluster {
+    /// Not accessed yet.
+    Pending,
+    Mounted(Box<KubeFilesystem>),
+    /// Connecting failed for the reason given.
+    Unreachable(String),
+}
+
+/// Presents every context of a kubeconfig in one mount, under
+/// `clusters/<context>/`. Each cluster is only connected to once its
+/// directory is first entered, and is then served by a [`KubeFilesystem`] of
+/// its own. A cluster that can't be reached keeps an empty directory holding
+/// a `.unreachable` marker.
+pub struct ContextsFilesystem {
+    contexts: Vec<(String, Cluster)>,
+    connect: Connector,
+}
+
+impl ContextsFilesystem {
+    pub fn new(contexts: Vec<String>, connect: Connector) -> Self {
+        ContextsFilesystem {
+            contexts: contexts
+                .into_iter()
+                .map(|name| (name, Cluster::Pending))
+                .collect(),
+            connect,
+        }
+    }
+
+    // the index of the context an inode belongs to
+    fn context_index(&self, ino: u64) -> Option<usize> {
+        let index = (ino >> CONTEXT_INODE_SHIFT).checked_sub(1)? as usize;
+        (index < self.contexts.len()).then_some(index)
+    }
+
+    // the inode of the root of a context's tree
+    fn context_root(index: usize) -> u64 {
+        (index as u64 + 1) << CONTEXT_INODE_SHIFT
+    }
+
+    /// Connects to the cluster of a context on the first access to its
+    /// directory.
+    fn connect_once(&mut self, ino: u64) {
+        let Some(index) = self.context_index(ino) else {
+            return;
+        };
+        let (name, cluster) = &mut self.contexts[index];
+        if !matches!(cluster, Cluster::Pending) {
+            return;
+        }
+
+        let connected = (self.connect)(name).and_then(|mut fs| {
+            fs.allocate_inodes(Self::context_root(index) + 2..Self::context_root(index + 1));
+            fs.build_tree()
+                .map_err(|_| "failed to list the namespaces, see the log for details")?;
+            Ok(fs)
+        });
+        *cluster = match connected {
+            Ok(fs) => {
+                log::info!("mounted context {name}");
+                Cluster::Mounted(Box::new(fs))
+            }
+            Err(e) => {
+                log::error!("context {name} is unreachable: {e}");
+                Cluster::Unreachable(format!("{e}\n"))
+            }
+        };
+    }
+
+    /// The tree an inode belongs to along with its inode there. Nodes this
+    /// filesystem presents itself belong to none.
+    fn route(&mut self, ino: u64) -> Result<(&mut KubeFilesystem, u64), libc::c_int> {
+        let index = self.context_index(ino).ok_or(libc::EPERM)?;
+        let Cluster::Mounted(fs) = &mut self.contexts[index].1 else {
+            return Err(libc::EPERM);
+        };
+        let local = match ino & CONTEXT_INODE_MASK {
+            0 => ROOT_ATTR.ino,
+            _ => ino,
+        };
+        Ok((fs.as_mut(), local))
+    }
+
+    // the reason a context was found unreachable, if its marker is this inode
+    fn unreachable_marker(&self, ino: u64) -> Option<&str> {
+        let index = self.context_index(ino)?;
+        match &self.contexts[index].1 {
+            Cluster::Unreachable(reason) if ino == Self::context_root(index) + 1 => Some(reason),
+            _ => None,
+        }
+    }
+
+    /// Attributes of the nodes this filesystem presents itself, including
+    /// the root of each context's tree so that they have distinct inodes.
+    fn own_attr(&self, ino: u64) -> Option<FileAttr> {
+        if let Some(reason) = self.unreachable_marker(ino) {
+            return Some(FileAttr {
+                ino,
+                size: reason.len() as u64,
+                blocks: 1,
+                kind: FileType::RegularFile,
+                perm: 0o444,
+                nlink: 1,
+                ..ROOT_ATTR
+            });
+        }
+        let is_context_root = self
+            .context_index(ino)
+            .is_some_and(|index| ino == Self::context_root(index));
+        (ino == ROOT_ATTR.ino || ino == CLUSTERS_INODE || is_context_root)
+            .then_some(FileAttr { ino, ..ROOT_ATTR })
+    }
+
+    /// Entries of the directories this filesystem presents itself.
+    fn own_entries(&self, ino: u64) -> Option<Vec<(u64, FileType, String)>> {
+        let children = if ino == ROOT_ATTR.ino {
+            vec![(
+                CLUSTERS_INODE,
+                FileType::Directory,
+                CLUSTERS_DIR_NAME.to_string(),
+            )]
+        } else if ino == CLUSTERS_INODE {
+            self.contexts
+                .iter()
+                .enumerate()
+                .map(|(index, (name, _))| {
+                    (Self::context_root(index), FileType::Directory, name.clone())
+                })
+                .collect()
+        } else {
+            let index = self.context_index(ino)?;
+            let Cluster::Unreachable(_) = &self.contexts[index].1 else {
+                return None;
+            };
+            if ino != Self::context_root(index) {
+                return None;
+            }
+            vec![(ino + 1, FileType::RegularFile, UNREACHABLE_NAME.to_string())]
+        };
+
+        let mut entries = vec![
+            (ino, FileType::Directory, ".".to_string()),
+            (ino, FileType::Directory, "..".to_string()),
+        ];
+        entries.extend(children);
+        Some(entries)
+    }
+}
+
+impl Filesystem for ContextsFilesystem {
+    fn init(
+        &mut self,
+        _req: &Request<'_>,
+        config: &mut fuser::KernelConfig,
+    ) -> Result<(), libc::c_int> {
+        kubefuse::configure_kernel(config);
+        Ok(())
+    }
+
+    fn lookup(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: fuser::ReplyEntry) {
+        log::debug!("lookup parent={parent} name={name:?}\n");
+        self.connect_once(parent);
+        if let Some(entries) = self.own_entries(parent) {
+            let child = entries
+                .iter()
+                .skip(2)
+                .find(|(_, _, entry_name)| OsStr::new(entry_name) == name)
+                .and_then(|(ino, _, _)| self.own_attr(*ino));
+            match child {
+                Some(attr) => reply.entry(&TTL, &attr, 0),
+                None => reply.error(libc::ENOENT),
+            }
+            return;
+        }
+        match self.route(parent) {
+            Ok((fs, parent)) => fs.lookup(req, parent, name, reply),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn forget(&mut self, req: &Request<'_>, ino: u64, nlookup: u64) {
+        if self.own_attr(ino).is_some() {
+            return;
+        }
+        if let Ok((fs, ino)) = self.route(ino) {
+            fs.forget(req, ino, nlookup);
+        }
+    }
+
+    fn getattr(&mut self, req: &Request<'_>, ino: u64, fh: Option<u64>, reply: fuser::ReplyAttr) {
+        if let Some(attr) = self.own_attr(ino) {
+            reply.attr(&TTL, &attr);
+            return;
+        }
+        match self.route(ino) {
+            Ok((fs, ino)) => fs.getattr(req, ino, fh, reply),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn statfs(&mut self, req: &Request<'_>, ino: u64, reply: fuser::ReplyStatfs) {
+        match self.route(ino) {
+            Ok((fs, ino)) => fs.statfs(req, ino, reply),
+            Err(_) => reply.statfs(0, 0, 0, 0, 0, 512, 255, 0),
+        }
+    }
+
+    fn opendir(&mut self, req: &Request<'_>, ino: u64, flags: i32, reply: fuser::ReplyOpen) {
+        self.connect_once(ino);
+        if self.own_entries(ino).is_some() {
+            reply.opened(0, 0);
+            return;
+        }
+        match self.route(ino) {
+            Ok((fs, ino)) => fs.opendir(req, ino, flags, reply),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn readdir(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        mut reply: fuser::ReplyDirectory,
+    ) {
+        if let Some(entries) = self.own_entries(ino) {
+            for (i, (ino, kind, name)) in entries.iter().enumerate().skip(offset as usize) {
+                if reply.add(*ino, i as i64 + 1, *kind, name) {
+                    break;
+                }
+            }
+            reply.ok();
+            return;
+        }
+        match self.route(ino) {
+            Ok((fs, ino)) => fs.readdir(req, ino, fh, offset, reply),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn releasedir(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        flags: i32,
+        reply: fuser::ReplyEmpty,
+    ) {
+        match self.route(ino) {
+            Ok((fs, ino)) => fs.releasedir(req, ino, fh, flags, reply),
+            Err(_) => reply.ok(),
+        }
+    }
+
+    fn open(&mut self, req: &Request<'_>, ino: u64, flags: i32, reply: fuser::ReplyOpen) {
+        if self.unreachable_marker(ino).is_some() {
+            match flags & libc::O_ACCMODE {
+                libc::O_RDONLY => reply.opened(0, 0),
+                _ => reply.error(libc::EACCES),
+            }
+            return;
+        }
+        match self.route(ino) {
+            Ok((fs, ino)) => fs.open(req, ino, flags, reply),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn read(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        flags: i32,
+        lock_owner: Option<u64>,
+        reply: fuser::ReplyData,
+    ) {
+        if let Some(reason) = self.unreachable_marker(ino) {
+            let start = (offset as usize).min(reason.len());
+            let end = (start + size as usize).min(reason.len());
+            reply.data(&reason.as_bytes()[start..end]);
+            return;
+        }
+        match self.route(ino) {
+            Ok((fs, ino)) => fs.read(req, ino, fh, offset, size, flags, lock_owner, reply),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn release(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        flags: i32,
+        lock_owner: Option<u64>,
+        flush: bool,
+        reply: fuser::ReplyEmpty,
+    ) {
+        match self.route(ino) {
+            Ok((fs, ino)) => fs.release(req, ino, fh, flags, lock_owner, flush, reply),
+            Err(_) => reply.ok(),
+        }
+    }
+
+    fn flush(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        lock_owner: u64,
+        reply: fuser::ReplyEmpty,
+    ) {
+        match self.route(ino) {
+            Ok((fs, ino)) => fs.flush(req, ino, fh, lock_owner, reply),
+            Err(_) => reply.ok(),
+        }
+    }
+
+    fn getxattr(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        size: u32,
+        reply: fuser::ReplyXattr,
+    ) {
+        match self.route(ino) {
+            Ok((fs, ino)) => fs.getxattr(req, ino, name, size, reply),
+            Err(_) => reply.error(libc::ENODATA),
+        }
+    }
+
+    fn listxattr(&mut self, req: &Request<'_>, ino: u64, size: u32, reply: fuser::ReplyXattr) {
+        match self.route(ino) {
+            Ok((fs, ino)) => fs.listxattr(req, ino, size, reply),
+            Err(_) if size == 0 => reply.size(0),
+            Err(_) => reply.data(&[]),
+        }
+    }
+
+    fn setattr(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        mode: Option<u32>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        size: Option<u64>,
+        atime: Option<fuser::TimeOrNow>,
+        mtime: Option<fuser::TimeOrNow>,
+        ctime: Option<SystemTime>,
+        fh: Option<u64>,
+        crtime: Option<SystemTime>,
+        chgtime: Option<SystemTime>,
+        bkuptime: Option<SystemTime>,
+        flags: Option<u32>,
+        reply: fuser::ReplyAttr,
+    ) {
+        match self.route(ino) {
+            Ok((fs, ino)) => fs.setattr(
+                req, ino, mode, uid, gid, size, atime, mtime, ctime, fh, crtime, chgtime, bkuptime,
+                flags, reply,
+            ),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn write(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        data: &[u8],
+        write_flags: u32,
+        flags: i32,
+        lock_owner: Option<u64>,
+        reply: fuser::ReplyWrite,
+    ) {
+        match self.route(ino) {
+            Ok((fs, ino)) => fs.write(
+                req,
+                ino,
+                fh,
+                offset,
+                data,
+                write_flags,
+                flags,
+                lock_owner,
+                reply,
+            ),
+            Err(_) => reply.error(libc::EBADF),
+        }
+    }
+
+    fn create(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        flags: i32,
+        reply: fuser::ReplyCreate,
+    ) {
+        match self.route(parent) {
+            Ok((fs, parent)) => fs.create(req, parent, name, mode, umask, flags, reply),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn unlink(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: fuser::ReplyEmpty) {
+        match self.route(parent) {
+            Ok((fs, parent)) => fs.unlink(req, parent, name, reply),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn rename(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        flags: u32,
+        reply: fuser::ReplyEmpty,
+    ) {
+        if self.context_index(parent) != self.context_index(newparent) {
+            reply.error(libc::EXDEV);
+            return;
+        }
+        let Ok((_, newparent)) = self.route(newparent) else {
+            reply.error(libc::EPERM);
+            return;
+        };
+        match self.route(parent) {
+            Ok((fs, parent)) => fs.rename(req, parent, name, newparent, newname, flags, reply),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn mkdir(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        reply: fuser::ReplyEntry,
+    ) {
+        match self.route(parent) {
+            Ok((fs, parent)) => fs.mkdir(req, parent, name, mode, umask, reply),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn rmdir(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: fuser::ReplyEmpty) {
+        match self.route(parent) {
+            Ok((fs, parent)) => fs.rmdir(req, parent, name, reply),
+            Err(errno) => reply.error(errno),
+        }
+    }
+
+    fn getlk(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        lock_owner: u64,
+        start: u64,
+        end: u64,
+        typ: i32,
+        pid: u32,
+        reply: fuser::ReplyLock,
+    ) {
+        match self.route(ino) {
+            Ok((fs, ino)) => fs.getlk(req, ino, fh, lock_owner, start, end, typ, pid, reply),
+            Err(_) => reply.error(libc::ENOSYS),
+        }
+    }
+
+    fn setlk(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        lock_owner: u64,
+        start: u64,
+        end: u64,
+        typ: i32,
+        pid: u32,
+        sleep: bool,
+        reply: fuser::ReplyEmpty,
+    ) {
+        match self.route(ino) {
+            Ok((fs, ino)) => fs.setlk(req, ino, fh, lock_owner, start, end, typ, pid, sleep, reply),
+            Err(_) => reply.error(libc::ENOSYS),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc};
+
+    use crate::config::Profile;
+
+    use super::*;
+
+    // a mount of the contexts recording those connected to
+    fn contexts(names: &[&str]) -> (ContextsFilesystem, Rc<RefCell<Vec<String>>>) {
+        let connected = Rc::new(RefCell::new(Vec::new()));
+        let recorded = connected.clone();
+        let fs = ContextsFilesystem::new(
+            names.iter().map(|name| name.to_string()).collect(),
+            Box::new(move |name| {
+                recorded.borrow_mut().push(name.to_string());
+                Err(format!("context {name} not found").into())
+            }),
+        );
+        (fs, connected)
+    }
+
+    #[test]
+    fn lists_every_context_without_connecting() {
+        let (fs, connected) = contexts(&["dev", "prod"]);
+        let names: Vec<String> = fs
+            .own_entries(CLUSTERS_INODE)
+            .unwrap()
+            .into_iter()
+            .skip(2)
+            .map(|(ino, _, name)| format!("{name}@{}", ino >> CONTEXT_INODE_SHIFT))
+            .collect();
+        assert_eq!(names, ["dev@1", "prod@2"]);
+        assert!(connected.borrow().is_empty());
+        assert_eq!(
+            fs.context_index(ContextsFilesystem::context_root(1) + 7),
+            Some(1)
+        );
+        assert_eq!(fs.context_index(ContextsFilesystem::context_root(2)), None);
+        assert_eq!(fs.context_index(CLUSTERS_INODE), None);
+    }
+
+    #[test]
+    fn connects_to_each_context_once_on_first_access() {
+        let (mut fs, connected) = contexts(&["dev", "gone"]);
+        let dev = ContextsFilesystem::context_root(0);
+        let gone = ContextsFilesystem::context_root(1);
+        fs.connect_once(dev);
+        fs.connect_once(dev + 5);
+        fs.connect_once(gone);
+        fs.connect_once(gone);
+        assert_eq!(*connected.borrow(), ["dev", "gone"]);
+
+        // unreachable clusters keep a directory holding the reason
+        assert_eq!(
+            fs.unreachable_marker(gone + 1),
+            Some("context gone not found\n")
+        );
+        assert_eq!(
+            fs.unreachable_marker(dev + 1),
+            Some("context dev not found\n")
+        );
+        assert_eq!(fs.unreachable_marker(gone), None);
+        let entries = fs.own_entries(gone).unwrap();
+        assert_eq!(
+            entries[2],
+            (
+                gone + 1,
+                FileType::RegularFile,
+                UNREACHABLE_NAME.to_string()
+            )
+        );
+        assert_eq!(fs.own_attr(gone + 1).unwrap().size, 23);
+        assert_eq!(fs.route(gone).err(), Some(libc::EPERM));
+    }
+
+    #[test]
+    fn routes_inodes_to_the_tree_of_their_context() {
+        let (mut fs, _) = contexts(&["dev", "prod"]);
+        fs.contexts[1].1 =
+            Cluster::Mounted(Box::new(KubeFilesystem::for_tests(Profile::default())));
+        let prod = ContextsFilesystem::context_root(1);
+
+        // the root of the context's directory is the root of its tree
+        assert_eq!(fs.route(prod).map(|(_, ino)| ino), Ok(ROOT_ATTR.ino));
+        assert_eq!(fs.route(prod + 9).map(|(_, ino)| ino), Ok(prod + 9));
+        // contexts not connected to yet, and the mount's own nodes, have no tree
+        assert_eq!(
+            fs.route(ContextsFilesystem::context_root(0)).err(),
+            Some(libc::EPERM)
+        );
+        assert_eq!(fs.route(CLUSTERS_INODE).err(), Some(libc::EPERM));
+    }
+}
@@ -0,0 +1,64 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use similar::TextDiff;
+
+use crate::sanitize::sanitize_manifest;
+
+const DIFF_SEPARATOR: &str = "...";
+const DIFF_CONTEXT_LINES: usize = 3;
+
+/// Manifests of a single namespace keyed by their path relative to the
+/// namespace directory, e.g. `configmaps/foo.yaml`.
+pub type NamespaceManifests = BTreeMap<String, Vec<u8>>;
+
+/// Splits a `<nsA>...<nsB>` diff file name into the two namespace names.
+pub fn parse_diff_name(name: &str) -> Option<(&str, &str)> {
+    let (ns_a, ns_b) = name.split_once(DIFF_SEPARATOR)?;
+    if ns_a.is_empty() || ns_b.is_empty() {
+        return None;
+    }
+    Some((ns_a, ns_b))
+}
+
+/// Produces a unified diff of the sanitized manifests of two namespaces.
+///
+/// Objects are matched by their relative path, i.e. by kind and name. Objects
+/// present on one side only are diffed against `/dev/null`.
+pub fn namespace_diff(
+    ns_a: &str,
+    manifests_a: &NamespaceManifests,
+    ns_b: &str,
+    manifests_b: &NamespaceManifests,
+) -> String {
+    let paths: BTreeSet<&String> = manifests_a.keys().chain(manifests_b.keys()).collect();
+
+    let mut out = String::new();
+    for path in paths {
+        let old = manifests_a.get(path).map(|m| sanitize_manifest(m));
+        let new = manifests_b.get(path).map(|m| sanitize_manifest(m));
+        if old == new {
+            continue;
+        }
+
+        let old_header = match old {
+            Some(_) => format!("{ns_a}/{path}"),
+            None => "/dev/null".to_string(),
+        };
+        let new_header = match new {
+            Some(_) => format!("{ns_b}/{path}"),
+            None => "/dev/null".to_string(),
+        };
+
+        let old = old.unwrap_or_default();
+        let new = new.unwrap_or_default();
+        let diff = TextDiff::from_lines(&old, &new);
+        out.push_str(
+            &diff
+                .unified_diff()
+                .context_radius(DIFF_CONTEXT_LINES)
+                .header(&old_header, &new_header)
+                .to_string(),
+        );
+    }
+    out
+}
@@ -0,0 +1,54 @@
+use k8s_openapi::api::discovery::v1::EndpointSlice;
+
+// label linking an EndpointSlice to the Service it was created for
+const SERVICE_NAME_LABEL: &str = "kubernetes.io/service-name";
+
+/// Renders the addresses behind the Services of a namespace, one
+/// `<service> <address>:<port>/<protocol> <ready|not-ready>` line per pair,
+/// sorted by Service. Slices without ports list the bare addresses.
+pub fn render(slices: &[EndpointSlice]) -> String {
+    let mut lines = Vec::new();
+    for slice in slices {
+        let service = slice
+            .metadata
+            .labels
+            .as_ref()
+            .and_then(|labels| labels.get(SERVICE_NAME_LABEL))
+            .map_or("-", String::as_str);
+        let ports: Vec<String> = slice
+            .ports
+            .iter()
+            .flatten()
+            .filter_map(|port| {
+                let number = port.port?;
+                let protocol = port.protocol.as_deref().unwrap_or("TCP");
+                Some(format!(":{number}/{protocol}"))
+            })
+            .collect();
+
+        for endpoint in &slice.endpoints {
+            // unset means ready
+            let ready = match endpoint.conditions.as_ref().and_then(|c| c.ready) {
+                Some(false) => "not-ready",
+                _ => "ready",
+            };
+            for address in &endpoint.addresses {
+                // IPv6 addresses are bracketed so that the port stays unambiguous
+                let address = if address.contains(':') && !ports.is_empty() {
+                    format!("[{address}]")
+                } else {
+                    address.clone()
+                };
+                if ports.is_empty() {
+                    lines.push(format!("{service} {address} {ready}\n"));
+                }
+                for port in &ports {
+                    lines.push(format!("{service} {address}{port} {ready}\n"));
+                }
+            }
+        }
+    }
+
+    lines.sort();
+    lines.concat()
+}
@@ -0,0 +1,193 @@
+use std::collections::BTreeMap;
+
+use k8s_openapi::{
+    api::core::v1::Event,
+    chrono::{DateTime, SecondsFormat, Utc},
+};
+
+/// A group of events about the same object with the same reason and message,
+/// e.g. every `BackOff` event of a crash-looping pod.
+struct EventGroup<'e> {
+    event: &'e Event,
+    count: u64,
+    first_seen: Option<DateTime<Utc>>,
+    last_seen: Option<DateTime<Utc>>,
+}
+
+fn first_seen(event: &Event) -> Option<DateTime<Utc>> {
+    event
+        .first_timestamp
+        .as_ref()
+        .map(|t| t.0)
+        .or_else(|| event.event_time.as_ref().map(|t| t.0))
+        .or_else(|| event.metadata.creation_timestamp.as_ref().map(|t| t.0))
+}
+
+fn last_seen(event: &Event) -> Option<DateTime<Utc>> {
+    event
+        .series
+        .as_ref()
+        .and_then(|s| s.last_observed_time.as_ref().map(|t| t.0))
+        .or_else(|| event.last_timestamp.as_ref().map(|t| t.0))
+        .or_else(|| first_seen(event))
+}
+
+/// Number of occurrences an event stands for, taking both the legacy `count`
+/// field and EventSeries into account.
+fn occurrences(event: &Event) -> u64 {
+    event
+        .series
+        .as_ref()
+        .and_then(|s| s.count)
+        .or(event.count)
+        .and_then(|c| u64::try_from(c).ok())
+        .unwrap_or(1)
+        .max(1)
+}
+
+fn format_time(time: Option<DateTime<Utc>>) -> String {
+    time.map(|t| t.to_rfc3339_opts(SecondsFormat::Secs, true))
+        .unwrap_or_else(|| "-".to_string())
+}
+
+fn format_event(event: &Event) -> String {
+    let object = &event.involved_object;
+    format!(
+        "{} {} {}/{}: {}",
+        event.type_.as_deref().unwrap_or("-"),
+        event.reason.as_deref().unwrap_or("-"),
+        object.kind.as_deref().unwrap_or("-").to_lowercase(),
+        object.name.as_deref().unwrap_or("-"),
+        event.message.as_deref().unwrap_or_default().trim_end(),
+    )
+}
+
+/// Renders every event on its own line, sorted by the time it was last seen.
+pub fn render_raw(events: &[Event]) -> String {
+    let mut events: Vec<&Event> = events.iter().collect();
+    events.sort_by_key(|e| last_seen(e));
+
+    events
+        .into_iter()
+        .map(|e| {
+            format!(
+                "{} {} (x{})\n",
+                format_time(last_seen(e)),
+                format_event(e),
+                occurrences(e)
+            )
+        })
+        .collect()
+}
+
+/// Renders events merged by involved object, type, reason and message, with
+/// the total number of occurrences and when they were first and last seen.
+pub fn render_deduplicated(events: &[Event]) -> String {
+    let mut groups: BTreeMap<_, EventGroup> = BTreeMap::new();
+    for event in events {
+        let object = &event.involved_object;
+        let key = (
+            object.kind.as_deref(),
+            object.name.as_deref(),
+            event.type_.as_deref(),
+            event.reason.as_deref(),
+            event.message.as_deref(),
+        );
+
+        let group = groups.entry(key).or_insert_with(|| EventGroup {
+            event,
+            count: 0,
+            first_seen: None,
+            last_seen: None,
+        });
+        group.count += occurrences(event);
+        group.first_seen = match (group.first_seen, first_seen(event)) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        group.last_seen = group.last_seen.max(last_seen(event));
+    }
+
+    let mut groups: Vec<EventGroup> = groups.into_values().collect();
+    groups.sort_by_key(|g| g.last_seen);
+
+    groups
+        .into_iter()
+        .map(|g| {
+            format!(
+                "{} {} (x{}, first seen {})\n",
+                format_time(g.last_seen),
+                format_event(g.event),
+                g.count,
+                format_time(g.first_seen),
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::{
+        api::core::v1::{EventSeries, ObjectReference},
+        apimachinery::pkg::apis::meta::v1::{MicroTime, Time},
+    };
+
+    use super::*;
+
+    fn at(secs: i64) -> DateTime<Utc> {
+        DateTime::from_timestamp(1_714_644_000 + secs, 0).unwrap()
+    }
+
+    fn back_off(pod: &str, first: i64, last: i64, count: i32) -> Event {
+        Event {
+            involved_object: ObjectReference {
+                kind: Some("Pod".to_string()),
+                name: Some(pod.to_string()),
+                ..Default::default()
+            },
+            type_: Some("Warning".to_string()),
+            reason: Some("BackOff".to_string()),
+            message: Some("Back-off restarting failed container\n".to_string()),
+            first_timestamp: Some(Time(at(first))),
+            last_timestamp: Some(Time(at(last))),
+            count: Some(count),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn merges_events_about_the_same_object() {
+        let mut series = back_off("web", 60, 60, 1);
+        series.series = Some(EventSeries {
+            count: Some(5),
+            last_observed_time: Some(MicroTime(at(600))),
+        });
+        let events = [
+            back_off("web", 0, 120, 3),
+            back_off("api", 30, 90, 2),
+            series,
+        ];
+        assert_eq!(
+            render_deduplicated(&events),
+            "2024-05-02T10:01:30Z Warning BackOff pod/api: \
+             Back-off restarting failed container (x2, first seen 2024-05-02T10:00:30Z)\n\
+             2024-05-02T10:10:00Z Warning BackOff pod/web: \
+             Back-off restarting failed container (x8, first seen 2024-05-02T10:00:00Z)\n"
+        );
+        assert_eq!(render_raw(&events).lines().count(), 3);
+    }
+
+    #[test]
+    fn keeps_events_with_other_reasons_apart() {
+        let mut pulled = back_off("web", 0, 0, 0);
+        pulled.type_ = Some("Normal".to_string());
+        pulled.reason = Some("Pulled".to_string());
+        let events = [back_off("web", 0, 10, 1), pulled];
+        let rendered = render_deduplicated(&events);
+        assert_eq!(rendered.lines().count(), 2);
+        // a count of zero still stands for the event itself
+        assert!(
+            rendered.contains("Normal Pulled pod/web: Back-off restarting failed container (x1,")
+        );
+    }
+}
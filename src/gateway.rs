@@ -0,0 +1,278 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt::Write as _,
+};
+
+use serde_json::Value;
+
+/// Gateways by namespace and name, e.g. `("infra", "public")`.
+pub type Gateways = HashMap<(String, String), Value>;
+
+/// A Service an HTTPRoute forwards to, by namespace and name.
+pub type Backend = (String, String);
+
+const GATEWAY_GROUP: &str = "gateway.networking.k8s.io";
+
+/// Whether an object belongs to the Gateway API, rather than e.g. being an
+/// Istio Gateway.
+pub fn is_gateway_api(object: &Value) -> bool {
+    object["apiVersion"]
+        .as_str()
+        .and_then(|api_version| api_version.split_once('/'))
+        .is_some_and(|(group, _)| group == GATEWAY_GROUP)
+}
+
+// the hostname matched by both a route and a listener, if any; either may be
+// a wildcard such as `*.example.com`
+fn intersect(route: &str, listener: &str) -> Option<String> {
+    let matches = |wildcard: &str, hostname: &str| {
+        wildcard
+            .strip_prefix('*')
+            .is_some_and(|suffix| hostname.ends_with(suffix) && hostname.len() > suffix.len())
+    };
+    if route == listener || matches(listener, route) {
+        Some(route.to_string())
+    } else if matches(route, listener) {
+        Some(listener.to_string())
+    } else {
+        None
+    }
+}
+
+// the strings of an array field
+fn strings(value: &Value) -> Vec<&str> {
+    value
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect()
+}
+
+/// The hostnames of the listeners of the Gateways a route attaches to, `None`
+/// for a listener accepting any hostname, or nothing if none of the Gateways
+/// is known.
+fn listener_hostnames(route: &Value, namespace: &str, gateways: &Gateways) -> Vec<Option<String>> {
+    let mut hostnames = Vec::new();
+    for parent in route["spec"]["parentRefs"].as_array().into_iter().flatten() {
+        let group = parent["group"].as_str().unwrap_or(GATEWAY_GROUP);
+        let kind = parent["kind"].as_str().unwrap_or("Gateway");
+        if group != GATEWAY_GROUP || kind != "Gateway" {
+            continue;
+        }
+        let gateway_namespace = parent["namespace"].as_str().unwrap_or(namespace);
+        let Some(gateway) = parent["name"]
+            .as_str()
+            .and_then(|name| gateways.get(&(gateway_namespace.to_string(), name.to_string())))
+        else {
+            continue;
+        };
+        let section = parent["sectionName"].as_str();
+        for listener in gateway["spec"]["listeners"]
+            .as_array()
+            .into_iter()
+            .flatten()
+        {
+            if section.is_some() && listener["name"].as_str() != section {
+                continue;
+            }
+            hostnames.push(listener["hostname"].as_str().map(str::to_string));
+        }
+    }
+    hostnames
+}
+
+/// The hostnames an HTTPRoute is effectively served for: its own narrowed
+/// down to those the listeners of its Gateways accept, or those of the
+/// listeners if it has none. `*` stands for any hostname.
+pub fn effective_hostnames(route: &Value, namespace: &str, gateways: &Gateways) -> Vec<String> {
+    let own = strings(&route["spec"]["hostnames"]);
+    let listeners = listener_hostnames(route, namespace, gateways);
+    let mut effective = BTreeSet::new();
+    if listeners.is_empty() {
+        effective.extend(own.iter().map(|hostname| hostname.to_string()));
+    }
+    for listener in &listeners {
+        match (listener, own.is_empty()) {
+            (None, true) => {
+                effective.insert("*".to_string());
+            }
+            (None, false) => effective.extend(own.iter().map(|hostname| hostname.to_string())),
+            (Some(listener), true) => {
+                effective.insert(listener.clone());
+            }
+            (Some(listener), false) => {
+                effective.extend(own.iter().filter_map(|own| intersect(own, listener)));
+            }
+        }
+    }
+    if own.is_empty() && listeners.is_empty() {
+        effective.insert("*".to_string());
+    }
+    effective.into_iter().collect()
+}
+
+/// The Services the rules of an HTTPRoute forward to.
+pub fn backends(route: &Value, namespace: &str) -> BTreeSet<Backend> {
+    let mut backends = BTreeSet::new();
+    for rule in route["spec"]["rules"].as_array().into_iter().flatten() {
+        for backend in rule["backendRefs"].as_array().into_iter().flatten() {
+            if let Some(backend) = service_backend(backend, namespace) {
+                backends.insert(backend);
+            }
+        }
+    }
+    backends
+}
+
+// the Service of a backendRef, if that's what it refers to
+fn service_backend(backend: &Value, namespace: &str) -> Option<Backend> {
+    let group = backend["group"].as_str().unwrap_or_default();
+    let kind = backend["kind"].as_str().unwrap_or("Service");
+    if !group.is_empty() || kind != "Service" {
+        return None;
+    }
+    let name = backend["name"].as_str()?;
+    let namespace = backend["namespace"].as_str().unwrap_or(namespace);
+    Some((namespace.to_string(), name.to_string()))
+}
+
+// a match of a rule, e.g. `PathPrefix /api, GET, header x-env=canary`
+fn describe_match(matched: &Value) -> String {
+    let path = &matched["path"];
+    let mut parts = vec![format!(
+        "{} {}",
+        path["type"].as_str().unwrap_or("PathPrefix"),
+        path["value"].as_str().unwrap_or("/")
+    )];
+    if let Some(method) = matched["method"].as_str() {
+        parts.push(method.to_string());
+    }
+    for (field, label) in [("headers", "header"), ("queryParams", "query")] {
+        for param in matched[field].as_array().into_iter().flatten() {
+            let operator = match param["type"].as_str() {
+                Some("RegularExpression") => "~",
+                _ => "=",
+            };
+            parts.push(format!(
+                "{label} {}{operator}{}",
+                param["name"].as_str().unwrap_or_default(),
+                param["value"].as_str().unwrap_or_default()
+            ));
+        }
+    }
+    parts.join(", ")
+}
+
+/// Renders `resolved.txt` of an HTTPRoute: the hostnames it's effectively
+/// served for, the Gateways it attaches to, then the matches and backends of
+/// every rule.
+pub fn render_resolved(route: &Value, namespace: &str, gateways: &Gateways) -> String {
+    let mut out = String::new();
+    let hostnames = effective_hostnames(route, namespace, gateways);
+    let _ = match hostnames.is_empty() {
+        true => writeln!(out, "hostnames: none the listeners accept"),
+        false => writeln!(out, "hostnames: {}", hostnames.join(", ")),
+    };
+    for parent in route["spec"]["parentRefs"].as_array().into_iter().flatten() {
+        let _ = write!(
+            out,
+            "parent: {}/{}",
+            parent["namespace"].as_str().unwrap_or(namespace),
+            parent["name"].as_str().unwrap_or_default()
+        );
+        if let Some(section) = parent["sectionName"].as_str() {
+            let _ = write!(out, " ({section})");
+        }
+        out.push('\n');
+    }
+    for (i, rule) in route["spec"]["rules"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .enumerate()
+    {
+        let _ = writeln!(out, "rule {}:", i + 1);
+        let matches = rule["matches"].as_array().filter(|m| !m.is_empty());
+        match matches {
+            Some(matches) => {
+                for matched in matches {
+                    let _ = writeln!(out, "  match: {}", describe_match(matched));
+                }
+            }
+            None => {
+                let _ = writeln!(out, "  match: PathPrefix /");
+            }
+        }
+        for backend in rule["backendRefs"].as_array().into_iter().flatten() {
+            let name = match service_backend(backend, namespace) {
+                Some((backend_namespace, name)) if backend_namespace != namespace => {
+                    format!("{backend_namespace}/{name}")
+                }
+                Some((_, name)) => name,
+                None => format!(
+                    "{} {}",
+                    backend["kind"].as_str().unwrap_or_default(),
+                    backend["name"].as_str().unwrap_or_default()
+                ),
+            };
+            let _ = write!(out, "  backend: {name}");
+            if let Some(port) = backend["port"].as_u64() {
+                let _ = write!(out, ":{port}");
+            }
+            if let Some(weight) = backend["weight"].as_u64() {
+                let _ = write!(out, " weight {weight}");
+            }
+            out.push('\n');
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn resolves_a_route_against_its_gateway() {
+        let mut gateways = Gateways::new();
+        gateways.insert(
+            ("infra".to_string(), "public".to_string()),
+            json!({"spec": {"listeners": [
+                {"name": "https", "hostname": "*.example.com"},
+                {"name": "internal", "hostname": "internal.local"},
+            ]}}),
+        );
+        let route = json!({"spec": {
+            "parentRefs": [{"name": "public", "namespace": "infra", "sectionName": "https"}],
+            "hostnames": ["shop.example.com", "shop.example.org"],
+            "rules": [{
+                "matches": [{"path": {"type": "PathPrefix", "value": "/api"}, "method": "GET"}],
+                "backendRefs": [
+                    {"name": "api", "port": 8080, "weight": 90},
+                    {"name": "api-next", "namespace": "canary", "port": 8080, "weight": 10},
+                ],
+            }],
+        }});
+
+        assert_eq!(
+            render_resolved(&route, "shop", &gateways),
+            "hostnames: shop.example.com\n\
+             parent: infra/public (https)\n\
+             rule 1:\n  \
+             match: PathPrefix /api, GET\n  \
+             backend: api:8080 weight 90\n  \
+             backend: canary/api-next:8080 weight 10\n"
+        );
+        let backends: Vec<_> = backends(&route, "shop").into_iter().collect();
+        assert_eq!(
+            backends,
+            [
+                ("canary".to_string(), "api-next".to_string()),
+                ("shop".to_string(), "api".to_string()),
+            ]
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::hash_map::RandomState,
+    fmt,
+    hash::BuildHasher,
+    time::{Duration, Instant},
+};
+
+use crate::client::DynamicObject;
+
+/// How long a pending action waits for confirmation before it is dropped.
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// An operation with a blast radius large enough to require confirmation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DestructiveAction {
+    DeleteNamespace(String),
+    /// Deleting a Node, which takes the node out of the cluster along with
+    /// everything scheduled to it.
+    DeleteNode {
+        path: String,
+        name: String,
+    },
+    /// Applying a written Secret manifest, kept as written until confirmed.
+    ApplySecret {
+        path: String,
+        inode: u64,
+        // the draft the manifest is kept in
+        draft: u64,
+        object: DynamicObject,
+        manifest: Vec<u8>,
+    },
+}
+
+impl DestructiveAction {
+    /// Path of the affected object relative to the mount root.
+    pub fn path(&self) -> String {
+        match self {
+            DestructiveAction::DeleteNamespace(name) => format!("/{name}"),
+            DestructiveAction::DeleteNode { path, .. }
+            | DestructiveAction::ApplySecret { path, .. } => path.clone(),
+        }
+    }
+}
+
+impl fmt::Display for DestructiveAction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DestructiveAction::DeleteNamespace(name) => write!(f, "delete namespace {name}"),
+            DestructiveAction::DeleteNode { name, .. } => write!(f, "delete node {name}"),
+            DestructiveAction::ApplySecret { object, .. } => write!(
+                f,
+                "apply secret {}/{}",
+                object.namespace.as_deref().unwrap_or_default(),
+                object.name
+            ),
+        }
+    }
+}
+
+struct PendingAction {
+    token: String,
+    action: DestructiveAction,
+    expires_at: Instant,
+}
+
+/// Two-step confirmation of destructive actions: the first attempt records
+/// the action along with a token, which has to be written to
+/// `.kubefuse/pending` before the timeout for the action to be performed.
+#[derive(Default)]
+pub struct Guardrail {
+    pending: Vec<PendingAction>,
+}
+
+impl Guardrail {
+    /// Records a pending action and returns the token confirming it. Repeated
+    /// attempts of an action that is still pending return the same token.
+    pub fn request(&mut self, action: DestructiveAction) -> String {
+        self.expire();
+        if let Some(pending) = self.pending.iter().find(|p| p.action == action) {
+            return pending.token.clone();
+        }
+
+        let token = format!("{:016x}", RandomState::new().hash_one(action.to_string()));
+        self.pending.push(PendingAction {
+            token: token.clone(),
+            action,
+            expires_at: Instant::now() + CONFIRMATION_TIMEOUT,
+        });
+        token
+    }
+
+    /// Returns the action the token would confirm, if it hasn't expired yet.
+    pub fn pending_action(&mut self, token: &str) -> Option<&DestructiveAction> {
+        self.expire();
+        self.pending
+            .iter()
+            .find(|p| p.token == token)
+            .map(|p| &p.action)
+    }
+
+    /// Takes the action confirmed by the token, if it hasn't expired yet.
+    pub fn confirm(&mut self, token: &str) -> Option<DestructiveAction> {
+        self.expire();
+        let index = self.pending.iter().position(|p| p.token == token)?;
+        Some(self.pending.remove(index).action)
+    }
+
+    /// Renders the contents of the `.kubefuse/pending` file.
+    pub fn render(&mut self) -> String {
+        self.expire();
+        let now = Instant::now();
+        self.pending
+            .iter()
+            .map(|p| {
+                format!(
+                    "{} {} (expires in {}s)\n",
+                    p.token,
+                    p.action,
+                    p.expires_at.saturating_duration_since(now).as_secs()
+                )
+            })
+            .collect()
+    }
+
+    fn expire(&mut self) {
+        let now = Instant::now();
+        self.pending.retain(|p| p.expires_at > now);
+    }
+}
@@ -0,0 +1,253 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write as _,
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    os::{linux::net::SocketAddrExt, unix::net::UnixDatagram},
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc,
+    },
+    thread,
+    time::Duration,
+};
+
+// how long a client may take to send its request before it's dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+// how long the mount may take to answer a stat before it's deemed hung
+const MOUNT_PROBE_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// What the filesystem last found out about the API server and its
+/// refreshers, reported by the health endpoint.
+#[derive(Debug, Default)]
+pub struct Health {
+    /// Why the API server is unreachable, if it is.
+    pub unreachable: Option<String>,
+    /// Resource types whose last refresh failed, with the error.
+    pub failing: BTreeMap<&'static str, String>,
+    /// Whether the API server rejected the credentials on the last refresh.
+    pub unauthorized: bool,
+    pub paused: bool,
+    /// Paths of the `.error` files standing in for what couldn't be rendered
+    /// or listed in strict mode, with the error.
+    pub invalid: BTreeMap<String, String>,
+}
+
+pub type SharedHealth = Arc<Mutex<Health>>;
+
+/// Checks that the mount answers, by stating its root from another thread.
+/// A hung mount leaves that thread blocked, so no other probe is started
+/// until it returns.
+#[derive(Clone)]
+struct MountProbe {
+    mountpoint: PathBuf,
+    in_flight: Arc<AtomicBool>,
+}
+
+impl MountProbe {
+    fn alive(&self) -> bool {
+        if self.in_flight.swap(true, Ordering::AcqRel) {
+            return false; // the previous probe still hangs
+        }
+        let (tx, rx) = mpsc::channel();
+        let (mountpoint, in_flight) = (self.mountpoint.clone(), self.in_flight.clone());
+        thread::spawn(move || {
+            let _ = tx.send(std::fs::metadata(&mountpoint).is_ok());
+            in_flight.store(false, Ordering::Release);
+        });
+        rx.recv_timeout(MOUNT_PROBE_TIMEOUT).unwrap_or(false)
+    }
+}
+
+/// Serves `GET /healthz` and `GET /readyz` on `addr` for supervisors, e.g. a
+/// container liveness probe. `/healthz` fails while the mount doesn't answer
+/// or the credentials are rejected, i.e. when restarting kube-fuse may help;
+/// `/readyz` also fails while the API server is unreachable, refreshing is
+/// paused or the refresh of a resource type fails. Both describe each check
+/// in the body. When run by systemd with `WatchdogSec=`, the watchdog is
+/// also fed for as long as `/healthz` would succeed.
+pub fn spawn(addr: SocketAddr, health: SharedHealth, mountpoint: Option<PathBuf>) {
+    let probe = mountpoint.map(|mountpoint| MountProbe {
+        mountpoint,
+        in_flight: Arc::new(AtomicBool::new(false)),
+    });
+    let listener = match TcpListener::bind(addr) {
+        Ok(listener) => listener,
+        Err(e) => {
+            log::error!("failed to serve health checks on {addr}: {e}");
+            return;
+        }
+    };
+
+    let spawned = thread::Builder::new()
+        .name("healthz".to_string())
+        .spawn(move || {
+            log::info!("serving health checks on http://{addr}/healthz");
+            if let Some(watchdog) = Watchdog::from_env() {
+                let (health, probe) = (health.clone(), probe.clone());
+                thread::spawn(move || watchdog.feed(&health, probe.as_ref()));
+            }
+            for stream in listener.incoming() {
+                let result = stream.and_then(|stream| handle(&health, probe.as_ref(), stream));
+                if let Err(e) = result {
+                    log::debug!("failed to serve a health check: {e}");
+                }
+            }
+        });
+    if let Err(e) = spawned {
+        log::error!("failed to start the health checks: {e}");
+    }
+}
+
+/// The outcome of the checks: whether kube-fuse is alive, whether it's
+/// ready, and a line per check.
+fn check(health: &SharedHealth, probe: Option<&MountProbe>) -> (bool, bool, String) {
+    let mount = probe.map(MountProbe::alive);
+    let health = health.lock().unwrap_or_else(|e| e.into_inner());
+
+    let mut report = String::new();
+    let _ = writeln!(
+        report,
+        "mount: {}",
+        match mount {
+            Some(true) => "ok",
+            Some(false) => "unresponsive",
+            None => "not mounted",
+        }
+    );
+    let _ = writeln!(
+        report,
+        "auth: {}",
+        if health.unauthorized {
+            "rejected"
+        } else {
+            "ok"
+        }
+    );
+    let _ = match &health.unreachable {
+        Some(error) => writeln!(report, "apiServer: unreachable: {error}"),
+        None => writeln!(report, "apiServer: ok"),
+    };
+    let _ = writeln!(
+        report,
+        "refresh: {}",
+        if health.paused { "paused" } else { "ok" }
+    );
+    for (resource, error) in &health.failing {
+        let _ = writeln!(report, "refresh.{resource}: {error}");
+    }
+    for (path, error) in &health.invalid {
+        let _ = writeln!(report, "invalid {path}: {error}");
+    }
+
+    let alive = mount != Some(false) && !health.unauthorized;
+    let ready = alive
+        && health.unreachable.is_none()
+        && !health.paused
+        && health.failing.is_empty()
+        && health.invalid.is_empty();
+    (alive, ready, report)
+}
+
+fn handle(
+    health: &SharedHealth,
+    probe: Option<&MountProbe>,
+    mut stream: TcpStream,
+) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // headers are not needed, but have to be read before replying
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (Some("GET"), Some(target)) = (parts.next(), parts.next()) else {
+        return respond(&mut stream, "405 Method Not Allowed", "");
+    };
+    let path = target.split('?').next().unwrap_or_default();
+    if path != "/healthz" && path != "/readyz" {
+        return respond(&mut stream, "404 Not Found", "not found\n");
+    }
+
+    let (alive, ready, report) = check(health, probe);
+    let ok = if path == "/healthz" { alive } else { ready };
+    let status = if ok {
+        "200 OK"
+    } else {
+        "503 Service Unavailable"
+    };
+    respond(&mut stream, status, &report)
+}
+
+fn respond(stream: &mut TcpStream, status: &str, body: &str) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+    stream.flush()
+}
+
+/// The systemd service manager kube-fuse runs under, expecting to be
+/// notified at least every `interval`.
+struct Watchdog {
+    socket: UnixDatagram,
+    path: String,
+    interval: Duration,
+}
+
+impl Watchdog {
+    fn from_env() -> Option<Watchdog> {
+        let path = std::env::var("NOTIFY_SOCKET").ok()?;
+        let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+        Some(Watchdog {
+            socket: UnixDatagram::unbound().ok()?,
+            path,
+            interval: Duration::from_micros(usec),
+        })
+    }
+
+    /// Notifies systemd that kube-fuse is ready, then feeds the watchdog
+    /// twice per interval for as long as the checks of `/healthz` pass.
+    fn feed(&self, health: &SharedHealth, probe: Option<&MountProbe>) {
+        let mut ready = false;
+        loop {
+            let (alive, _, _) = check(health, probe);
+            if alive {
+                let message = if ready {
+                    "WATCHDOG=1"
+                } else {
+                    "READY=1\nWATCHDOG=1"
+                };
+                ready = true;
+                if let Err(e) = self.notify(message) {
+                    log::warn!("failed to notify systemd: {e}");
+                }
+            }
+            thread::sleep(self.interval / 2);
+        }
+    }
+
+    fn notify(&self, message: &str) -> io::Result<()> {
+        match self.path.strip_prefix('@') {
+            // an abstract socket
+            Some(name) => {
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                self.socket.send_to_addr(message.as_bytes(), &addr)?;
+            }
+            None => {
+                self.socket.send_to(message.as_bytes(), &self.path)?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,58 @@
+use std::{
+    path::Path,
+    process::{Command, Stdio},
+    thread,
+};
+
+use serde::Deserialize;
+
+use crate::quota::path_matches;
+
+/// A command run when an object presented in the mount enters a status, e.g.
+/// to be notified when a pod starts crash looping.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Hook {
+    /// Path pattern of the manifests to watch relative to the mount root,
+    /// where `*` matches a single path component and `**` any number of them,
+    /// e.g. `/default/pods/*`.
+    pub path: String,
+    /// Prefix of the `user.kube.status` summary the object has to enter, e.g.
+    /// `unhealthy` or `unhealthy: CrashLoopBackOff`.
+    pub status: String,
+    pub command: String,
+    /// Arguments passed before the path of the manifest in the mount.
+    #[serde(default)]
+    pub args: Vec<String>,
+}
+
+impl Hook {
+    pub fn matches(&self, path: &str, status: &str) -> bool {
+        status.starts_with(&self.status) && path_matches(&self.path, path)
+    }
+
+    /// Starts the command without waiting for it, with the status of the
+    /// object in `$KUBEFUSE_STATUS`.
+    pub fn run(&self, manifest: &Path, status: &str) {
+        let child = Command::new(&self.command)
+            .args(&self.args)
+            .arg(manifest)
+            .env("KUBEFUSE_STATUS", status)
+            .stdin(Stdio::null())
+            .spawn();
+        let mut child = match child {
+            Ok(child) => child,
+            Err(e) => {
+                log::warn!("failed to run hook {}: {e}", self.command);
+                return;
+            }
+        };
+        log::info!("running hook {} for {}", self.command, manifest.display());
+        let command = self.command.clone();
+        thread::spawn(move || match child.wait() {
+            Ok(exit) if !exit.success() => log::warn!("hook {command} exited with {exit}"),
+            Ok(_) => {}
+            Err(e) => log::warn!("failed to wait for hook {command}: {e}"),
+        });
+    }
+}
@@ -0,0 +1,109 @@
+use std::{
+    io::{self, BufRead, BufReader, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    time::Duration,
+};
+
+use crate::kubefuse::{Entry, KubeFilesystem};
+
+// how long a client may take to send its request before it's dropped
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Serves the tree read-only over HTTP, e.g. `GET /team-a/configmaps/app.yaml`
+/// returns the manifest and `GET /team-a/` lists the namespace directory, one
+/// name per line. Requests are served one at a time, like FUSE callbacks.
+pub fn serve(mut fs: KubeFilesystem, addr: SocketAddr) -> io::Result<()> {
+    if let Err(errno) = fs.build_tree() {
+        return Err(io::Error::from_raw_os_error(errno));
+    }
+
+    let listener = TcpListener::bind(addr)?;
+    log::info!("serving the tree on http://{addr}/");
+    for stream in listener.incoming() {
+        let result = stream.and_then(|stream| handle(&mut fs, stream));
+        if let Err(e) = result {
+            log::debug!("failed to serve an HTTP request: {e}");
+        }
+    }
+    Ok(())
+}
+
+fn handle(fs: &mut KubeFilesystem, mut stream: TcpStream) -> io::Result<()> {
+    stream.set_read_timeout(Some(REQUEST_TIMEOUT))?;
+    let mut reader = BufReader::new(&stream);
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+    // headers are not needed, but have to be read before replying
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let (Some(method), Some(target)) = (parts.next(), parts.next()) else {
+        return respond(&mut stream, "400 Bad Request", b"malformed request\n", true);
+    };
+    let head = method == "HEAD";
+    if method != "GET" && !head {
+        return respond(&mut stream, "405 Method Not Allowed", b"read-only\n", head);
+    }
+    let path = target.split(['?', '#']).next().unwrap_or_default();
+    let Some(path) = percent_decode(path) else {
+        return respond(&mut stream, "400 Bad Request", b"malformed path\n", head);
+    };
+
+    match fs.entry(&path) {
+        Some(Entry::File(content)) => respond(&mut stream, "200 OK", &content, head),
+        Some(Entry::Dir(names)) => {
+            let listing: String = names.iter().map(|name| format!("{name}\n")).collect();
+            respond(&mut stream, "200 OK", listing.as_bytes(), head)
+        }
+        None => respond(&mut stream, "404 Not Found", b"not found\n", head),
+    }
+}
+
+fn respond(stream: &mut TcpStream, status: &str, body: &[u8], head: bool) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        body.len()
+    )?;
+    if !head {
+        stream.write_all(body)?;
+    }
+    stream.flush()
+}
+
+/// Decodes `%XX` escapes, e.g. `%23` in the path of a field view.
+fn percent_decode(path: &str) -> Option<String> {
+    let mut bytes = Vec::with_capacity(path.len());
+    let mut rest = path.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte == b'%' {
+            let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+            bytes.push(u8::from_str_radix(hex, 16).ok()?);
+            rest = &tail[2..];
+        } else {
+            bytes.push(byte);
+            rest = tail;
+        }
+    }
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn paths_are_percent_decoded() {
+        assert_eq!(
+            percent_decode("/default/configmaps/app.yaml%23").as_deref(),
+            Some("/default/configmaps/app.yaml#")
+        );
+        assert_eq!(percent_decode("/a%2"), None);
+        assert_eq!(percent_decode("/a%zz"), None);
+    }
+}
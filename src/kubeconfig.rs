@@ -0,0 +1,407 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    path::{Path, PathBuf},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use k8s_openapi::ByteString;
+use serde::Deserialize;
+
+use crate::{
+    auth::{AuthConfig, ExecConfig, OidcConfig},
+    client::TlsConfig,
+};
+
+/// The parts of a kubeconfig file kube-fuse uses.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "kebab-case")]
+struct Kubeconfig {
+    current_context: Option<String>,
+    contexts: Vec<NamedContext>,
+    clusters: Vec<NamedCluster>,
+    users: Vec<NamedUser>,
+}
+
+#[derive(Deserialize, Debug)]
+struct NamedContext {
+    name: String,
+    context: Context,
+}
+
+#[derive(Deserialize, Debug)]
+struct Context {
+    cluster: String,
+    user: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct NamedCluster {
+    name: String,
+    cluster: Cluster,
+    // relative paths in the cluster are relative to the file's directory
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "kebab-case")]
+struct Cluster {
+    server: String,
+    certificate_authority: Option<PathBuf>,
+    certificate_authority_data: Option<ByteString>,
+    #[serde(default)]
+    insecure_skip_tls_verify: bool,
+    tls_server_name: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct NamedUser {
+    name: String,
+    user: User,
+    #[serde(skip)]
+    dir: PathBuf,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, rename_all = "camelCase")]
+struct User {
+    token: Option<String>,
+    token_file: Option<PathBuf>,
+    exec: Option<Exec>,
+    #[serde(rename = "auth-provider")]
+    auth_provider: Option<AuthProvider>,
+    #[serde(rename = "client-certificate")]
+    client_certificate: Option<PathBuf>,
+    #[serde(rename = "client-certificate-data")]
+    client_certificate_data: Option<ByteString>,
+    #[serde(rename = "client-key")]
+    client_key: Option<PathBuf>,
+    #[serde(rename = "client-key-data")]
+    client_key_data: Option<ByteString>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Exec {
+    command: String,
+    #[serde(default)]
+    args: Vec<String>,
+    #[serde(default)]
+    env: Vec<EnvVar>,
+}
+
+// only the `oidc` provider is supported, the cloud ones were replaced by
+// credential plugins
+#[derive(Deserialize, Debug)]
+struct AuthProvider {
+    name: String,
+    #[serde(default)]
+    config: HashMap<String, String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct EnvVar {
+    name: String,
+    value: String,
+}
+
+/// Names of the contexts in the kubeconfig files, in the order they are
+/// defined.
+pub fn context_names() -> Result<Vec<String>, Box<dyn Error>> {
+    let mut names: Vec<String> = Vec::new();
+    for context in read_merged()?.contexts {
+        if !names.contains(&context.name) {
+            names.push(context.name);
+        }
+    }
+    Ok(names)
+}
+
+/// Where and how to connect to a cluster, as described by a kubeconfig
+/// context.
+#[derive(Debug)]
+pub struct Connection {
+    pub server: String,
+    pub auth: Vec<AuthConfig>,
+    pub tls: TlsConfig,
+}
+
+/// Reads the kubeconfig files listed in `$KUBECONFIG`, or `~/.kube/config`,
+/// and resolves the context to connect with, the current one unless given.
+/// The cluster and user of the context can be overridden. Like with kubectl,
+/// the first file to define a name wins.
+pub fn load(
+    context: Option<&str>,
+    cluster: Option<&str>,
+    user: Option<&str>,
+) -> Result<Connection, Box<dyn Error>> {
+    resolve(&read_merged()?, context, cluster, user)
+}
+
+fn resolve(
+    merged: &Kubeconfig,
+    context: Option<&str>,
+    cluster: Option<&str>,
+    user: Option<&str>,
+) -> Result<Connection, Box<dyn Error>> {
+    let context_name = context
+        .map(str::to_string)
+        .or(merged.current_context.clone())
+        .ok_or("no context given and no current context set")?;
+    let context = merged
+        .contexts
+        .iter()
+        .find(|c| c.name == context_name)
+        .map(|c| &c.context);
+    let cluster_name = cluster
+        .or(context.map(|c| c.cluster.as_str()))
+        .ok_or_else(|| format!("context {context_name} not found"))?;
+    let user_name = user
+        .or(context.map(|c| c.user.as_str()))
+        .ok_or_else(|| format!("context {context_name} not found"))?;
+
+    let cluster = merged
+        .clusters
+        .iter()
+        .find(|c| c.name == cluster_name)
+        .ok_or_else(|| format!("cluster {cluster_name} not found"))?;
+    let mut tls = TlsConfig {
+        server_name: cluster.cluster.tls_server_name.clone(),
+        insecure: cluster.cluster.insecure_skip_tls_verify,
+        ..Default::default()
+    };
+    if let Some(ByteString(ca)) = &cluster.cluster.certificate_authority_data {
+        tls.ca_certificates.push(ca.clone());
+    } else if let Some(path) = &cluster.cluster.certificate_authority {
+        tls.ca_certificates
+            .push(std::fs::read(cluster.dir.join(path))?);
+    }
+
+    let mut auth = Vec::new();
+    if let Some(user) = merged.users.iter().find(|u| u.name == user_name) {
+        let read = |data: &Option<ByteString>, path: &Option<PathBuf>| match (data, path) {
+            (Some(ByteString(data)), _) => Ok(Some(data.clone())),
+            (None, Some(path)) => std::fs::read(user.dir.join(path)).map(Some),
+            (None, None) => Ok(None),
+        };
+        let certificate = read(
+            &user.user.client_certificate_data,
+            &user.user.client_certificate,
+        )?;
+        let key = read(&user.user.client_key_data, &user.user.client_key)?;
+        tls.client_identity = certificate.zip(key);
+
+        if let Some(token) = &user.user.token {
+            auth.push(AuthConfig::Token {
+                token: token.clone(),
+            });
+        } else if let Some(path) = &user.user.token_file {
+            auth.push(AuthConfig::TokenFile {
+                token_file: user.dir.join(path),
+            });
+        } else if let Some(exec) = &user.user.exec {
+            auth.push(AuthConfig::Exec {
+                exec: ExecConfig {
+                    command: exec.command.clone(),
+                    args: exec.args.clone(),
+                    env: exec
+                        .env
+                        .iter()
+                        .map(|var| (var.name.clone(), var.value.clone()))
+                        .collect::<HashMap<_, _>>(),
+                },
+            });
+        } else if let Some(provider) = &user.user.auth_provider {
+            auth.push(oidc_config(provider, &user.dir)?);
+        }
+    } else if !user_name.is_empty() {
+        // an empty name is how contexts connect without credentials
+        return Err(format!("user {user_name} not found").into());
+    }
+
+    Ok(Connection {
+        server: cluster.cluster.server.clone(),
+        auth,
+        tls,
+    })
+}
+
+fn oidc_config(provider: &AuthProvider, dir: &Path) -> Result<AuthConfig, Box<dyn Error>> {
+    if provider.name != "oidc" {
+        return Err(format!("unsupported auth provider {}", provider.name).into());
+    }
+    let config = &provider.config;
+    let get = |key: &str| {
+        config
+            .get(key)
+            .cloned()
+            .ok_or_else(|| format!("the oidc auth provider has no {key}"))
+    };
+    let certificate_authority_data = match (
+        config.get("idp-certificate-authority-data"),
+        config.get("idp-certificate-authority"),
+    ) {
+        (Some(data), _) => Some(ByteString(STANDARD.decode(data)?)),
+        (None, Some(path)) => Some(ByteString(std::fs::read(dir.join(path))?)),
+        (None, None) => None,
+    };
+    Ok(AuthConfig::Oidc {
+        oidc: OidcConfig {
+            issuer_url: get("idp-issuer-url")?,
+            client_id: get("client-id")?,
+            client_secret: config.get("client-secret").cloned(),
+            refresh_token: get("refresh-token")?,
+            id_token: config.get("id-token").cloned(),
+            certificate_authority_data,
+        },
+    })
+}
+
+// reads the kubeconfig files listed in `$KUBECONFIG`, or `~/.kube/config`,
+// into one
+fn read_merged() -> Result<Kubeconfig, Box<dyn Error>> {
+    let paths: Vec<PathBuf> = match std::env::var_os("KUBECONFIG") {
+        Some(paths) => std::env::split_paths(&paths).collect(),
+        None => {
+            let home = std::env::var_os("HOME").ok_or("neither KUBECONFIG nor HOME is set")?;
+            vec![Path::new(&home).join(".kube").join("config")]
+        }
+    };
+    merge_files(paths)
+}
+
+// reads the kubeconfig files into one, skipping those that don't exist
+fn merge_files(paths: Vec<PathBuf>) -> Result<Kubeconfig, Box<dyn Error>> {
+    let mut merged = Kubeconfig::default();
+    for path in paths {
+        if !path.exists() {
+            continue;
+        }
+        let content = std::fs::read(&path)?;
+        let mut kubeconfig: Kubeconfig = serde_yaml::from_slice(&content)
+            .map_err(|e| format!("invalid kubeconfig {}: {e}", path.display()))?;
+        let dir = path.parent().map(Path::to_path_buf).unwrap_or_default();
+        for cluster in &mut kubeconfig.clusters {
+            cluster.dir = dir.clone();
+        }
+        for user in &mut kubeconfig.users {
+            user.dir = dir.clone();
+        }
+
+        merged.current_context = merged.current_context.or(kubeconfig.current_context);
+        merged.contexts.extend(kubeconfig.contexts);
+        merged.clusters.extend(kubeconfig.clusters);
+        merged.users.extend(kubeconfig.users);
+    }
+    Ok(merged)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // writes the files into a directory of their own, returning their paths
+    fn write_files(test: &str, files: &[(&str, &str)]) -> Vec<PathBuf> {
+        let dir = std::env::temp_dir().join(format!("kube-fuse-{test}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        files
+            .iter()
+            .map(|(name, content)| {
+                let path = dir.join(name);
+                std::fs::write(&path, content).unwrap();
+                path
+            })
+            .collect()
+    }
+
+    const DEV: &str = "
+current-context: dev
+contexts:
+- name: dev
+  context: {cluster: dev, user: dev}
+clusters:
+- name: dev
+  cluster: {server: https://dev.example.com, certificate-authority: dev-ca.crt}
+users:
+- name: dev
+  user: {token: dev-token}
+";
+
+    const PROD: &str = "
+current-context: prod
+contexts:
+- name: prod
+  context: {cluster: prod, user: admin}
+- name: dev
+  context: {cluster: prod, user: admin}
+clusters:
+- name: prod
+  cluster: {server: https://prod.example.com}
+- name: dev
+  cluster: {server: https://shadowed.example.com}
+users:
+- name: admin
+  user: {client-certificate-data: Y2VydA==, client-key: keys/admin.key}
+- name: dev
+  user: {token: shadowed-token}
+";
+
+    #[test]
+    fn merges_files_with_the_first_definition_winning() {
+        let paths = write_files(
+            "merge",
+            &[("dev", DEV), ("prod", PROD), ("dev-ca.crt", "ca")],
+        );
+        let merged = merge_files(vec![
+            paths[0].clone(),
+            paths[0].with_file_name("missing"),
+            paths[1].clone(),
+        ])
+        .unwrap();
+
+        assert_eq!(merged.current_context.as_deref(), Some("dev"));
+        let dev = resolve(&merged, None, None, None).unwrap();
+        assert_eq!(dev.server, "https://dev.example.com");
+        assert!(matches!(&dev.auth[..], [AuthConfig::Token { token }] if token == "dev-token"));
+
+        // prod's `dev` user and cluster are shadowed, its own ones aren't
+        let prod = resolve(&merged, Some("prod"), None, Some("dev")).unwrap();
+        assert_eq!(prod.server, "https://prod.example.com");
+        assert!(matches!(&prod.auth[..], [AuthConfig::Token { token }] if token == "dev-token"));
+    }
+
+    #[test]
+    fn resolves_relative_paths_against_the_defining_file() {
+        let dev = write_files("relative-dev", &[("config", DEV), ("dev-ca.crt", "ca")]);
+        let prod = write_files("relative-prod", &[("config", PROD)]);
+        let keys = prod[0].with_file_name("keys");
+        std::fs::create_dir_all(&keys).unwrap();
+        std::fs::write(keys.join("admin.key"), "key").unwrap();
+        let merged = merge_files(vec![dev[0].clone(), prod[0].clone()]).unwrap();
+
+        let dev = resolve(&merged, Some("dev"), None, None).unwrap();
+        assert_eq!(dev.tls.ca_certificates, [b"ca".to_vec()]);
+        let prod = resolve(&merged, Some("prod"), None, None).unwrap();
+        assert_eq!(
+            prod.tls.client_identity,
+            Some((b"cert".to_vec(), b"key".to_vec()))
+        );
+    }
+
+    #[test]
+    fn users_no_file_defines_are_refused() {
+        let paths = write_files("users", &[("config", DEV), ("dev-ca.crt", "ca")]);
+        let merged = merge_files(vec![paths[0].clone()]).unwrap();
+        let err = resolve(&merged, None, None, Some("ghost")).unwrap_err();
+        assert_eq!(err.to_string(), "user ghost not found");
+
+        let config = DEV.replace("user: dev}", "user: ghost}");
+        let paths = write_files(
+            "context-users",
+            &[("config", &config), ("dev-ca.crt", "ca")],
+        );
+        let merged = merge_files(vec![paths[0].clone()]).unwrap();
+        let err = resolve(&merged, None, None, None).unwrap_err();
+        assert_eq!(err.to_string(), "user ghost not found");
+    }
+}
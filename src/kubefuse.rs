@@ -1,6 +1,47 @@
+mod changes;
+mod cluster;
+mod compaction;
+mod deletion;
+mod drafts;
+mod eviction;
+mod fields;
+mod footprint;
+mod http_routes;
+mod json_copies;
+mod keys;
+mod layouts;
+mod logs;
+mod mesh_hosts;
+mod metadata_only;
+mod names;
+mod namespaces;
+mod node_pressure;
+mod orphans;
+mod panics;
+mod pods;
+mod refresh;
+mod replicas;
+mod restart;
+mod scratch;
+mod secret_sources;
+mod shards;
+mod snapshot;
+mod spec_status;
+mod state;
+mod statfs;
+mod strict;
+mod tree_hash;
+mod unstructured;
+mod used_by;
+
 use std::{
-    collections::HashMap,
-    sync::atomic::AtomicU64,
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::Range,
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        mpsc::{self, Receiver, Sender},
+    },
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
@@ -9,13 +50,109 @@ use libc;
 
 use reqwest;
 
-use k8s_openapi::{api::core::v1::Namespace, serde};
+use k8s_openapi::{
+    api::{
+        apps::v1::{DaemonSet, Deployment, ReplicaSet, StatefulSet},
+        core::v1::{
+            ConfigMap, Event, Namespace, Node as KubeNode, PersistentVolume, Secret, Service,
+        },
+        discovery::v1::EndpointSlice,
+        rbac::v1::{ClusterRole, ClusterRoleBinding},
+        storage::v1::StorageClass,
+    },
+    apimachinery::pkg::apis::meta::v1::ObjectMeta,
+    chrono, serde,
+};
+
+use crate::{
+    capabilities::Capabilities,
+    client::{ApiError, ApiResource, ApplyParams, KubeClient, ListParams, Page},
+    config::{HistoryRetention, ManifestFormat, Profile, RefreshIntervals},
+    diff, endpoints, events,
+    guardrail::{DestructiveAction, Guardrail},
+    healthz::{Health, SharedHealth},
+    quota::MutationQuotas,
+    sanitize, status,
+};
 
-use client_rs::{corev1::CoreV1Client, rest};
+use changes::ChangeFeed;
+use cluster::CLUSTER_DIR_NAME;
+use compaction::CompactionStats;
+use drafts::{DRAFT_SUFFIX, Draft};
+use footprint::FOOTPRINT_FILE_NAME;
+use json_copies::JsonCopies;
+use logs::LogStream;
+use metadata_only::LazyManifest;
+use node_pressure::NodePressure;
+use orphans::ORPHANS_FILE_NAME;
+use panics::PanicStats;
+use refresh::{RefreshSchedule, RefreshStatus, RefreshStrategy, Update, WatchChange};
+use shards::SHARD_PREFIX;
+use state::{FileLock, FsState, Node, NodeChildren, NodeContent};
+use strict::ERROR_SUFFIX;
+use tree_hash::{ManifestHashes, TREE_HASH_NAME};
+use used_by::ManifestReferences;
 
 const BLOCK_SIZE: u32 = 512;
 
-const ROOT_ATTR: FileAttr = FileAttr {
+/// The directory in the root of the mount holding kube-fuse's own virtual files.
+pub const CONTROL_DIR_NAME: &str = ".kubefuse";
+
+// names in the root of the mount that can't be namespaces, which are DNS
+// labels, are left to kube-fuse: the control, cluster and layout directories
+fn is_namespace_name(name: &str) -> bool {
+    !name.starts_with(['.', '_'])
+}
+
+/// The error to report for a failed request: `EACCES` if the API server
+/// refused it, e.g. to an impersonated user, `EIO` otherwise.
+fn request_errno(status: Option<reqwest::StatusCode>) -> libc::c_int {
+    match status {
+        Some(reqwest::StatusCode::UNAUTHORIZED | reqwest::StatusCode::FORBIDDEN) => libc::EACCES,
+        _ => libc::EIO,
+    }
+}
+
+/// Renders a manifest in the given format, YAML unless it's JSON.
+fn serialize_manifest(value: &serde_yaml::Value, format: ManifestFormat) -> Result<String, String> {
+    match format {
+        ManifestFormat::Json => serde_json::to_string_pretty(value)
+            .map(|json| json + "\n")
+            .map_err(|e| e.to_string()),
+        ManifestFormat::Yaml | ManifestFormat::Both => {
+            serde_yaml::to_string(value).map_err(|e| e.to_string())
+        }
+    }
+}
+
+/// A node of the tree as served by front-ends other than FUSE.
+pub enum Entry {
+    /// Names of the children, those of directories with a trailing `/`.
+    Dir(Vec<String>),
+    File(Vec<u8>),
+}
+
+/// Resource types presented through a typed model, which take precedence over
+/// discovered ones of the same name.
+pub const TYPED_RESOURCES: [&str; 15] = [
+    "namespaces",
+    "configmaps",
+    "secrets",
+    "services",
+    "endpointslices",
+    "deployments",
+    "replicasets",
+    "statefulsets",
+    "daemonsets",
+    "events",
+    "nodes",
+    "persistentvolumes",
+    "clusterroles",
+    "clusterrolebindings",
+    "storageclasses",
+];
+
+pub const ROOT_ATTR: FileAttr = FileAttr {
     ino: 1,
     size: 0,
     blocks: 0,
@@ -35,39 +172,204 @@ const ROOT_ATTR: FileAttr = FileAttr {
 
 const TTL: Duration = Duration::from_secs(1);
 
-type InodeTable = HashMap<u64, Node>;
-struct Node {
-    name: String,
-    attrs: FileAttr,
-    content: NodeContent,
-}
+// holds the manifests of the Events of a namespace, next to the event logs
+const EVENTS_DIR_NAME: &str = "events";
 
-type NodeChildren = HashMap<String, u64>;
-enum NodeContent {
-    Bytes(Vec<u8>),
-    Children(NodeChildren),
-}
-pub struct KubeFilesystem<'c> {
+// summary of the addresses behind the Services of a namespace
+const ENDPOINTS_NAME: &str = "endpoints.txt";
+
+// set on files cut at --max-file-size, holds the size of the full rendering
+const TRUNCATED_XATTR: &str = "user.kubefuse.truncated";
+
+// set on history files once lines were dropped under the retention, holds
+// how many
+const DROPPED_XATTR: &str = "user.kubefuse.dropped";
+
+// present in the control directory and as an xattr of every node while the
+// API server is unreachable
+const DEGRADED_NAME: &str = "DEGRADED";
+const DEGRADED_XATTR: &str = "user.kubefuse.degraded";
+
+// when the content of a node was last confirmed against the API server, and
+// whether it may have changed since
+const FETCHED_AT_XATTR: &str = "user.kubefuse.fetched_at";
+const STALE_XATTR: &str = "user.kubefuse.stale";
+
+// normalized health of the object presented by a manifest, see status::summarize
+const STATUS_XATTR: &str = "user.kube.status";
+
+pub struct KubeFilesystem {
     // Add fields as necessary
-    core_client: CoreV1Client<'c>,
+    kube_client: Arc<KubeClient>,
+    profile: Profile,
+    // resourceVersion the whole mount is pinned to
+    resource_version: Option<String>,
+
+    refresh_intervals: RefreshIntervals,
+    refresh_schedule: Arc<RefreshSchedule>,
+    refresh_statuses: BTreeMap<&'static str, RefreshStatus>,
+    // mirrors the refresh statuses for the health checks, see `--healthz-listen`
+    health: SharedHealth,
+    updates_tx: Sender<Update>,
+    updates_rx: Receiver<Update>,
+    // namespace -> event name -> event, kept to re-render the event logs of a
+    // namespace whenever one of its events changes
+    events: HashMap<String, BTreeMap<String, Event>>,
+    // namespace -> slice name -> EndpointSlice, kept to re-render the
+    // endpoints.txt of a namespace whenever one of its slices changes
+    endpoint_slices: HashMap<String, BTreeMap<String, EndpointSlice>>,
+    // namespace -> lines of its changes.log
+    changes: HashMap<String, ChangeFeed>,
+    history_retention: HistoryRetention,
+    // why and since when the API server has been unreachable
+    degraded: Option<(SystemTime, String)>,
+    // when the tree was first listed
+    populated_at: Option<SystemTime>,
+
+    capabilities: Capabilities,
+    guardrail: Guardrail,
+    mutation_quotas: MutationQuotas,
+    // rendered files are truncated beyond this size, 0 means unlimited
+    max_file_size: u64,
+    // where the filesystem is mounted, never written to by kube-fuse itself
+    mountpoint: PathBuf,
+
+    state: FsState,
+    panics: PanicStats,
+    compaction: CompactionStats,
 
-    inodes: InodeTable,
-    inode_counter: AtomicU64,
+    diff_inode: Option<u64>,
+    pinned_inode: Option<u64>,
+    stats_inode: Option<u64>,
+    panics_inode: Option<u64>,
+    pending_inode: Option<u64>,
+    paused_inode: Option<u64>,
+    scratch_inode: Option<u64>,
+    snapshot_inode: Option<u64>,
+    tree_hash_inode: Option<u64>,
+    manifest_hashes: ManifestHashes,
+    node_pressure_inode: Option<u64>,
+    node_pressure: NodePressure,
+    // (parent, name) -> node created on lookup and not listed in its parent,
+    // e.g. `<manifest>#`
+    hidden_inodes: HashMap<(u64, String), u64>,
+    // manifest -> draft of the edit in progress
+    drafts: HashMap<u64, Draft>,
+    json_copies: JsonCopies,
+    manifest_references: ManifestReferences,
+    // open `.follow` files by handle
+    log_streams: HashMap<u64, LogStream>,
+    // manifests presented from the metadata of their objects, by inode
+    lazy_manifests: HashMap<u64, LazyManifest>,
+    // `.error` files of entries that couldn't be rendered or listed in strict
+    // mode, by inode, with the error
+    invalid: HashMap<u64, String>,
+    // resource directories split into shards, see `shards`
+    sharded_dirs: HashSet<u64>,
+    // objects that couldn't be rendered since the mount
+    render_failures: u64,
+    // bytes of file content to keep in memory at most, 0 for no limit, and
+    // when files were last opened by a clock ticking with every open
+    memory_budget: u64,
+    last_read: HashMap<u64, u64>,
+    read_clock: u64,
+    evictions: u64,
+    // whether the namespaces were listed yet, with `lazyNamespaces`
+    namespaces_listed: bool,
 }
 
-impl<'c> KubeFilesystem<'c> {
-    pub fn new(rest_client: &'c rest::RestClient) -> Self {
+impl KubeFilesystem {
+    pub fn new(
+        kube_client: KubeClient,
+        profile: Profile,
+        resource_version: Option<String>,
+        refresh_intervals: RefreshIntervals,
+        mutation_quotas: MutationQuotas,
+        max_file_size: u64,
+        mountpoint: PathBuf,
+    ) -> Self {
+        let (updates_tx, updates_rx) = mpsc::channel();
         KubeFilesystem {
-            core_client: CoreV1Client::new(rest_client),
+            kube_client: Arc::new(kube_client),
+            profile,
+            resource_version,
 
-            inodes: InodeTable::new(),
-            inode_counter: AtomicU64::new(2),
+            refresh_schedule: Arc::new(RefreshSchedule::new(
+                refresh_intervals.windows.clone(),
+                refresh_intervals.paused,
+            )),
+            health: Arc::new(Mutex::new(Health {
+                paused: refresh_intervals.paused,
+                ..Health::default()
+            })),
+            refresh_intervals,
+            refresh_statuses: BTreeMap::new(),
+            updates_tx,
+            updates_rx,
+            events: HashMap::new(),
+            endpoint_slices: HashMap::new(),
+            changes: HashMap::new(),
+            history_retention: HistoryRetention::default(),
+            degraded: None,
+            populated_at: None,
+
+            capabilities: Capabilities::default(),
+            guardrail: Guardrail::default(),
+            mutation_quotas,
+            max_file_size,
+            mountpoint,
+
+            state: FsState::new(),
+            panics: PanicStats::default(),
+            compaction: CompactionStats::default(),
+
+            diff_inode: None,
+            pinned_inode: None,
+            stats_inode: None,
+            panics_inode: None,
+            pending_inode: None,
+            paused_inode: None,
+            scratch_inode: None,
+            snapshot_inode: None,
+            tree_hash_inode: None,
+            manifest_hashes: ManifestHashes::new(),
+            node_pressure_inode: None,
+            node_pressure: NodePressure::new(),
+            hidden_inodes: HashMap::new(),
+            drafts: HashMap::new(),
+            json_copies: JsonCopies::new(),
+            manifest_references: ManifestReferences::new(),
+            log_streams: HashMap::new(),
+            lazy_manifests: HashMap::new(),
+            invalid: HashMap::new(),
+            sharded_dirs: HashSet::new(),
+            render_failures: 0,
+            memory_budget: 0,
+            last_read: HashMap::new(),
+            read_clock: 0,
+            evictions: 0,
+            namespaces_listed: false,
         }
     }
 
-    fn next_inode(&self) -> u64 {
-        self.inode_counter
-            .fetch_add(1, std::sync::atomic::Ordering::SeqCst)
+    /// Keeps as much of `changes.log` and followed logs as the given
+    /// retention allows rather than the default one.
+    pub fn with_history_retention(mut self, history_retention: HistoryRetention) -> Self {
+        self.history_retention = history_retention;
+        self
+    }
+
+    /// Keeps at most this many bytes of file content in memory, evicting the
+    /// manifests read least recently, see [`Self::enforce_memory_budget`].
+    pub fn with_memory_budget(mut self, memory_budget: u64) -> Self {
+        self.memory_budget = memory_budget;
+        self
+    }
+
+    /// What the health checks report, updated as the refreshes succeed or
+    /// fail.
+    pub fn health(&self) -> SharedHealth {
+        self.health.clone()
     }
 
     fn create_namespace_node(&mut self, parent_inode: u64, namespace: &Namespace) -> Option<u64> {
@@ -87,359 +389,2730 @@ impl<'c> KubeFilesystem<'c> {
 
         let ns_inode = self.create_dir_node(parent_inode, ns_name)?;
 
-        let ns_yaml = serde_yaml::to_string(namespace)
-            .unwrap_or_default()
-            .into_bytes();
+        let ns_yaml = self.render_manifest(namespace);
 
-        self.create_content_node(ns_inode, "manifest.yaml", ns_yaml, creation_time); // FIXME: should use the actual namespace creation time
+        let manifest_name = self.manifest_file_name("manifest");
+        self.create_content_node(ns_inode, &manifest_name, ns_yaml, creation_time); // FIXME: should use the actual namespace creation time
+        self.sync_readme(ns_inode, namespace);
+        self.sync_terminating_marker(ns_inode, namespace);
+        if parent_inode == ROOT_ATTR.ino {
+            self.render_changes(ns_name); // not for namespaces pinned to the past
+            // rendered when looked up
+            self.create_content_node(ns_inode, ORPHANS_FILE_NAME, Vec::new(), SystemTime::now());
+            self.create_content_node(ns_inode, FOOTPRINT_FILE_NAME, Vec::new(), SystemTime::now());
+        }
 
         return Some(ns_inode);
     }
 
     fn namespace_inode(&self, namespace: &str) -> Option<u64> {
-        self.inodes.get(&1).and_then(|root| match &root.content {
-            NodeContent::Children(children) => children.get(namespace).copied(),
-            NodeContent::Bytes(_) => {
-                log::error!("root directory must not be a file");
-                return None;
-            }
-        })
+        if !is_namespace_name(namespace) {
+            return None;
+        }
+
+        self.state
+            .inodes
+            .get(&1)
+            .and_then(|root| match &root.content {
+                NodeContent::Children(children) => children.get(namespace).copied(),
+                NodeContent::Bytes(_) => {
+                    log::error!("root directory must not be a file");
+                    return None;
+                }
+            })
     }
 
-    fn create_manifests_node<T: k8s_openapi::ListableResource>(
-        &mut self,
-        namespace: &str,
-        list_result: Result<k8s_openapi::List<T>, reqwest::Error>,
-    ) -> Option<u64>
-    where
-        T: k8s_openapi::Metadata<Ty = k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta>
-            + serde::Serialize,
-    {
-        let ns_inode = match self.namespace_inode(namespace) {
-            Some(ns_inode) => ns_inode,
-            None => {
-                log::error!("namespace {namespace} not found or does not contain children");
-                return None;
-            }
-        };
+    fn namespace_names(&self) -> Vec<String> {
+        match self
+            .state
+            .inodes
+            .get(&ROOT_ATTR.ino)
+            .map(|root| &root.content)
+        {
+            Some(NodeContent::Children(children)) => children
+                .keys()
+                .filter(|name| is_namespace_name(name))
+                .cloned()
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
 
-        let resource_kind = T::KIND.to_lowercase() + "s";
-        let manifests_inode = self
-            .create_dir_node(ns_inode, resource_kind.as_str())
-            .expect("failed to create manifests directory node");
+    fn create_control_dir(&mut self, root_inode: u64) -> Option<u64> {
+        let control_inode = self.create_dir_node(root_inode, CONTROL_DIR_NAME)?;
+        self.diff_inode = Some(self.create_dir_node(control_inode, "diff")?);
+        self.pinned_inode = Some(self.create_dir_node(control_inode, "at")?);
+        self.stats_inode = Some(self.create_content_node(
+            control_inode,
+            "stats",
+            Vec::new(),
+            SystemTime::now(),
+        )?);
 
-        let resource_list = match list_result {
-            Err(e) => {
-                log::error!("manifests fetch failed for namespace {namespace}: {e}");
-                return None;
-            }
-            Ok(list) => list,
-        };
+        // rendered on lookup and open
+        self.tree_hash_inode = Some(self.create_content_node(
+            control_inode,
+            TREE_HASH_NAME,
+            Vec::new(),
+            SystemTime::now(),
+        )?);
 
-        for item in resource_list.items.iter() {
-            let name = match item.metadata().name.as_deref() {
-                Some(n) => n,
-                None => continue, // TODO: Should be an error? Should we panic?
-            }
-            .to_owned()
-                + ".yaml";
+        self.panics_inode = Some(self.create_content_node(
+            control_inode,
+            "panics",
+            self.panics.render().into_bytes(),
+            SystemTime::now(),
+        )?);
 
-            let manifest_yaml = serde_yaml::to_string(item).unwrap_or_default().into_bytes();
+        let pending_inode =
+            self.create_content_node(control_inode, "pending", Vec::new(), SystemTime::now())?;
+        if let Some(pending_node) = self.state.inodes.get_mut(&pending_inode) {
+            pending_node.attrs.perm = 0o644; // confirmations are written here
+        }
+        self.pending_inode = Some(pending_inode);
 
-            let manifest_creation_time = item
-                .metadata()
-                .creation_timestamp
-                .as_ref()
-                .and_then(|t| t.0.timestamp().try_into().ok())
-                .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
-                .unwrap_or(UNIX_EPOCH);
+        let paused = format!("{}\n", self.refresh_schedule.is_paused());
+        let paused_inode = self.create_content_node(
+            control_inode,
+            "paused",
+            paused.into_bytes(),
+            SystemTime::now(),
+        )?;
+        if let Some(paused_node) = self.state.inodes.get_mut(&paused_inode) {
+            paused_node.attrs.perm = 0o644; // refreshing is paused by writing to it
+        }
+        self.paused_inode = Some(paused_inode);
 
-            self.create_content_node(
-                manifests_inode,
-                &name,
-                manifest_yaml,
-                manifest_creation_time,
-            )
-            .expect("failed to create manifest content node");
+        let scratch_inode = self.create_dir_node(control_inode, "scratch")?;
+        if let Some(scratch_node) = self.state.inodes.get_mut(&scratch_inode) {
+            scratch_node.attrs.perm = 0o777; // users create their drafts here
         }
-        return Some(manifests_inode);
-    }
+        self.scratch_inode = Some(scratch_inode);
 
-    fn create_dir_node(&mut self, parent_inode: u64, name: &str) -> Option<u64> {
-        let new_inode = self.next_inode();
+        let actions_inode = self.create_dir_node(control_inode, "actions")?;
+        let snapshot_inode =
+            self.create_content_node(actions_inode, "snapshot", Vec::new(), SystemTime::now())?;
+        if let Some(snapshot_node) = self.state.inodes.get_mut(&snapshot_inode) {
+            snapshot_node.attrs.perm = 0o222; // takes a target directory
+        }
+        self.snapshot_inode = Some(snapshot_inode);
 
-        let node_creation_time = SystemTime::now();
-        let new_node = Node {
-            name: name.to_string(),
-            attrs: FileAttr {
-                ino: new_inode,
-                size: 0,
-                blocks: 0,
-                atime: node_creation_time,
-                mtime: node_creation_time,
-                ctime: node_creation_time,
-                crtime: node_creation_time,
-                kind: fuser::FileType::Directory,
-                perm: 0o755,
-                nlink: 2, // FIXME: should be updated when we add children directories
-                uid: 1000,
-                gid: 1000,
-                rdev: 0,
-                flags: 0,
-                blksize: BLOCK_SIZE,
-            },
-            content: NodeContent::Children(NodeChildren::new()),
-        };
+        self.create_content_node(
+            control_inode,
+            "ls-colors",
+            status::ls_colors_script().into_bytes(),
+            SystemTime::now(),
+        )?;
 
-        let Some(parent_node) = self.inodes.get_mut(&parent_inode) else {
-            log::error!("failed to create dir '{name}': parent inode {parent_inode} not found");
-            return None;
-        };
+        Some(control_inode)
+    }
 
-        match &mut parent_node.content {
-            NodeContent::Children(children) => {
-                children.insert(name.to_string(), new_inode);
-                parent_node.attrs.nlink += 1; // each child directory increases the link count of the parent
-            }
-            NodeContent::Bytes(_) => {
-                log::error!("parent node must be a directory");
-                return None;
-            }
+    /// Checks what the credentials allow for each of the presented resource
+    /// types and reports it in `.kubefuse/capabilities.yaml`.
+    fn probe_capabilities(&mut self, control_inode: u64) {
+        let mut capabilities = Capabilities::default();
+        capabilities.probe::<Namespace>(&self.kube_client);
+        if self.profile.shows_resource("configmaps") {
+            capabilities.probe::<ConfigMap>(&self.kube_client);
+        }
+        if self.profile.shows_resource("secrets") {
+            capabilities.probe::<Secret>(&self.kube_client);
+        }
+        if self.profile.shows_resource("services") {
+            capabilities.probe::<Service>(&self.kube_client);
+        }
+        if self.profile.shows_resource("endpointslices") {
+            capabilities.probe::<EndpointSlice>(&self.kube_client);
+        }
+        if self.profile.shows_resource("deployments") {
+            capabilities.probe::<Deployment>(&self.kube_client);
+        }
+        if self.profile.shows_resource("replicasets") {
+            capabilities.probe::<ReplicaSet>(&self.kube_client);
+        }
+        if self.profile.shows_resource("statefulsets") {
+            capabilities.probe::<StatefulSet>(&self.kube_client);
+        }
+        if self.profile.shows_resource("daemonsets") {
+            capabilities.probe::<DaemonSet>(&self.kube_client);
+        }
+        if self.profile.shows_resource("events") {
+            capabilities.probe::<Event>(&self.kube_client);
+        }
+        if self.profile.shows_resource("nodes") {
+            capabilities.probe::<KubeNode>(&self.kube_client);
+        }
+        if self.profile.shows_resource("persistentvolumes") {
+            capabilities.probe::<PersistentVolume>(&self.kube_client);
+        }
+        if self.profile.shows_resource("clusterroles") {
+            capabilities.probe::<ClusterRole>(&self.kube_client);
+        }
+        if self.profile.shows_resource("clusterrolebindings") {
+            capabilities.probe::<ClusterRoleBinding>(&self.kube_client);
+        }
+        if self.profile.shows_resource("storageclasses") {
+            capabilities.probe::<StorageClass>(&self.kube_client);
         }
 
-        self.inodes.insert(new_inode, new_node);
-        return Some(new_inode);
+        self.create_content_node(
+            control_inode,
+            "capabilities.yaml",
+            capabilities.render().into_bytes(),
+            SystemTime::now(),
+        );
+        self.capabilities = capabilities;
     }
 
-    fn create_content_node(
-        &mut self,
-        parent_inode: u64,
-        name: &str,
-        content: Vec<u8>,
-        creation_time: SystemTime,
-    ) -> Option<u64> {
-        let new_inode = self.next_inode();
-        let content_size = content.len() as u64;
+    /// Numbers the nodes of the tree but the root within `inodes`, so that
+    /// several trees can be presented in one mount.
+    pub fn allocate_inodes(&mut self, inodes: Range<u64>) {
+        self.state = FsState::with_inodes(inodes);
+    }
 
-        let new_node = Node {
-            name: name.to_string(),
-            attrs: FileAttr {
-                ino: new_inode,
-                size: content_size,
-                blocks: content_size.div_ceil(u64::from(BLOCK_SIZE)),
-                atime: creation_time,
-                mtime: creation_time,
-                ctime: creation_time,
-                crtime: creation_time,
-                kind: fuser::FileType::RegularFile,
-                perm: 0o444,
-                nlink: 1,
-                uid: 1000,
-                gid: 1000,
-                rdev: 0,
-                flags: 0,
-                blksize: BLOCK_SIZE,
-            },
-            content: NodeContent::Bytes(content),
+    /// Lists the cluster and creates the tree served by the front-ends, then
+    /// starts keeping it up to date unless the mount is pinned.
+    pub fn build_tree(&mut self) -> Result<(), libc::c_int> {
+        panics::install_hook();
+
+        let root_inode = ROOT_ATTR.ino;
+        self.kube_client.set_operation("init", "/");
+
+        let Some(control_inode) = self.create_control_dir(root_inode) else {
+            log::error!("failed to create the {CONTROL_DIR_NAME} control directory");
+            return Err(libc::EIO);
         };
+        self.probe_capabilities(control_inode);
 
-        let Some(parent_node) = self.inodes.get_mut(&parent_inode) else {
-            log::error!("parent inode {parent_inode} not found");
-            return None;
+        let params = match &self.resource_version {
+            Some(resource_version) => ListParams::pinned_to(resource_version),
+            None => ListParams::default(),
+        };
+        // lazily presented namespaces are resolved on lookup and listed along
+        // with the root
+        let namespaces = match self.profile.lazy_namespaces {
+            true => Vec::new(),
+            false => match self.kube_client.list::<Namespace>(None, &params) {
+                Err(e) => {
+                    log::error!("namespaces fetch failed: {e}");
+                    return Err(request_errno(e.status()));
+                }
+                Ok(resp) => {
+                    // share this to let others browse the same state under .kubefuse/at/
+                    let snapshot_version = resp.metadata.resource_version.unwrap_or_default();
+                    self.create_content_node(
+                        control_inode,
+                        "resourceVersion",
+                        format!("{snapshot_version}\n").into_bytes(),
+                        SystemTime::now(),
+                    );
+                    resp.items
+                }
+            },
         };
 
-        match &mut parent_node.content {
-            NodeContent::Children(children) => {
-                children.insert(name.to_string(), new_inode);
-            }
-            NodeContent::Bytes(_) => {
-                log::error!("parent node must be a directory");
-                return None;
-            }
-        }
+        let populated_at = SystemTime::now();
+        self.populate_namespaces(root_inode, &namespaces, &params);
+        self.populate_cluster(root_inode, &params);
+        self.sync_layouts();
+        self.sync_json_copies();
+        self.sync_used_by();
+        self.sync_secret_sources();
+        self.sync_http_routes();
+        self.sync_mesh_hosts();
+        self.sync_invalid();
+        self.sync_shards();
+        self.enforce_memory_budget();
+        // hooks only run on changes seen from here on
+        self.populated_at = Some(populated_at);
+        compaction::spawn_compactor(self.updates_tx.clone());
 
-        self.inodes.insert(new_inode, new_node);
-        return Some(new_inode);
+        // a pinned mount shows a fixed point in time, it never refreshes
+        if self.resource_version.is_none() {
+            self.start_refreshers();
+        }
+        Ok(())
     }
-}
 
-impl<'c> fuser::Filesystem for KubeFilesystem<'c> {
-    fn init(
-        &mut self,
-        _req: &fuser::Request<'_>,
-        _config: &mut fuser::KernelConfig,
-    ) -> Result<(), libc::c_int> {
-        let root_node = Node {
-            name: "/".to_string(),
-            attrs: ROOT_ATTR,
-            content: NodeContent::Children(NodeChildren::new()),
-        };
+    /// Looks up a child of a directory, materializing on-demand nodes such as
+    /// field or pinned views first.
+    fn lookup_child(&mut self, parent: u64, name: &str) -> Option<u64> {
+        if self.diff_inode == Some(parent) {
+            self.refresh_diff_node(parent, name);
+        }
+        if self.pinned_inode == Some(parent) {
+            self.create_pinned_node(parent, name);
+        }
+        if self.tree_hash_inode.is_some()
+            && self.state.child_inode(parent, name) == self.tree_hash_inode
+        {
+            self.render_tree_hash();
+        }
+        if name == ORPHANS_FILE_NAME
+            && let Some(inode) = self.state.child_inode(parent, name)
+        {
+            self.render_orphans(inode);
+        }
+        if name == FOOTPRINT_FILE_NAME
+            && let Some(inode) = self.state.child_inode(parent, name)
+        {
+            self.render_footprint(inode);
+        }
+        if name.ends_with(fields::FIELDS_SUFFIX) {
+            self.create_fields_node(parent, name);
+        }
+        if self.state.child_inode(parent, name).is_none() {
+            self.create_version_node(parent, name);
+        }
+        if parent == ROOT_ATTR.ino && self.state.child_inode(parent, name).is_none() {
+            self.resolve_namespace(name);
+        }
 
-        let root_inode = root_node.attrs.ino;
-        self.inodes.insert(root_inode, root_node);
+        match &self.state.inodes.get(&parent)?.content {
+            NodeContent::Children(children) => children
+                .get(name)
+                .copied()
+                .or_else(|| self.hidden_inode(parent, name)),
+            NodeContent::Bytes(_) => None,
+        }
+    }
 
-        match self.core_client.namespaces().list() {
-            Err(e) => {
-                log::error!("namespaces fetch failed: {e}");
-                Err(libc::EIO)
-            }
-            Ok(resp) => {
-                for item in resp.items.iter() {
-                    let ns_name = match item.metadata.name.as_deref() {
-                        Some(n) => n,
-                        None => continue, // TODO: Should be an error? Should we panic?
-                    };
-                    self.create_namespace_node(root_inode, item);
+    /// Resolves a `/`-separated path relative to the root of the tree, for
+    /// front-ends other than FUSE.
+    pub fn entry(&mut self, path: &str) -> Option<Entry> {
+        let mut entry = None;
+        let outcome = panics::catch(|| {
+            self.apply_refresh_updates();
+            self.kube_client.set_operation("http", path);
 
-                    self.create_manifests_node(
-                        ns_name,
-                        self.core_client.configmaps(ns_name).list(),
-                    );
-                    self.create_manifests_node(ns_name, self.core_client.secrets(ns_name).list());
-                }
-                Ok(())
+            let mut inode = ROOT_ATTR.ino;
+            for name in path.split('/').filter(|name| !name.is_empty()) {
+                let Some(child) = self.lookup_child(inode, name) else {
+                    return;
+                };
+                inode = child;
+            }
+            if let Some(Err(_)) = self.refresh_log_file(inode) {
+                return;
+            }
+            // manifests listed by metadata only or evicted are fetched here,
+            // as they are when opened
+            if let Some(content) = self.file_content(inode) {
+                entry = Some(Entry::File(content.to_vec()));
+                return;
             }
-        }
-    }
 
-    fn lookup(
-        &mut self,
-        _req: &fuser::Request<'_>,
-        parent: u64,
-        name: &std::ffi::OsStr,
-        reply: fuser::ReplyEntry,
-    ) {
-        log::debug!("lookup parent={parent} name={name:?}\n");
-        let child_node = self.inodes.get(&parent).and_then(|p| match &p.content {
-            NodeContent::Children(children) => {
-                let child_name = name.to_str()?;
-                let child_inode = children.get(child_name).copied()?;
-                self.inodes.get(&child_inode)
+            if let Some(NodeContent::Children(children)) =
+                &self.state.inodes.get(&inode).map(|node| &node.content)
+            {
+                entry = Some(Entry::Dir(
+                    children
+                        .iter()
+                        .map(|(name, child)| match self.state.inodes.get(child) {
+                            Some(Node {
+                                content: NodeContent::Children(_),
+                                ..
+                            }) => format!("{name}/"),
+                            _ => name.clone(),
+                        })
+                        .collect(),
+                ));
             }
-            NodeContent::Bytes(_) => None,
         });
-
-        match child_node {
-            Some(n) => reply.entry(&TTL, &n.attrs, 0),
-            None => reply.error(libc::ENOENT),
-        };
+        self.record_panic("http", outcome);
+        entry
     }
 
-    fn getattr(
-        &mut self,
-        _req: &fuser::Request<'_>,
-        ino: u64,
-        fh: Option<u64>,
-        reply: fuser::ReplyAttr,
-    ) {
-        log::debug!("getattr ino={ino} fh={:?}\n", fh);
-        if let Some(node) = self.inodes.get(&ino) {
-            return reply.attr(&TTL, &node.attrs);
-        } else {
-            return reply.error(libc::ENOENT);
+    /// The inode of the directory at a path relative to the root of the
+    /// tree, e.g. `team-a/configmaps`.
+    pub fn dir_inode(&mut self, path: &str) -> Option<u64> {
+        let mut inode = ROOT_ATTR.ino;
+        for name in path.split('/').filter(|name| !name.is_empty()) {
+            inode = self.lookup_child(inode, name)?;
         }
+        self.state
+            .inodes
+            .get(&inode)
+            .is_some_and(|node| matches!(node.content, NodeContent::Children(_)))
+            .then_some(inode)
     }
 
-    fn readdir(
-        &mut self,
-        _req: &fuser::Request<'_>,
-        inode: u64,
-        _fh: u64,
-        offset: i64,
-        mut reply: fuser::ReplyDirectory,
-    ) {
-        log::debug!("readdir inode={inode} offset={offset}\n");
-        let Some(node) = self.inodes.get(&inode) else {
-            reply.error(libc::ENOENT);
+    /// Reports a panic caught in a FUSE callback in `.kubefuse/panics`.
+    fn record_panic(&mut self, operation: &str, outcome: Result<(), String>) {
+        let Err(report) = outcome else {
             return;
         };
+        log::error!("{operation} panicked, replied with EIO");
+        self.panics.count += 1;
+        self.panics.last = Some(format!("{operation}: {report}"));
+        if let Some(panics_inode) = self.panics_inode {
+            self.set_node_content(panics_inode, self.panics.render().into_bytes());
+        }
+    }
 
-        if node.attrs.kind != fuser::FileType::Directory {
-            reply.error(libc::ENOTDIR);
-            return;
+    fn render_pending(&mut self) {
+        if let Some(pending_inode) = self.pending_inode {
+            let pending = self.guardrail.render();
+            self.set_node_content(pending_inode, pending.into_bytes());
         }
+    }
 
-        let mut entries = vec![
-            (inode, fuser::FileType::Directory, "."),
-            (1, fuser::FileType::Directory, ".."), // FIXME: should be pointing to the parent inode
-        ];
+    /// Records a destructive action that has to be confirmed through
+    /// `.kubefuse/pending` before it is performed.
+    fn request_confirmation(&mut self, action: DestructiveAction) {
+        let token = self.guardrail.request(action.clone());
+        log::warn!(
+            "refusing to {action} without confirmation, write {token} to {CONTROL_DIR_NAME}/pending to confirm"
+        );
+        self.render_pending();
+    }
 
-        if let NodeContent::Children(children) = &node.content {
-            for (name, &inode) in children.iter() {
-                if let Some(child_node) = self.inodes.get(&inode) {
-                    entries.push((inode, child_node.attrs.kind, child_node.name.as_str()));
-                } else {
-                    log::warn!("child {name} with inode {inode} was not found in inodes table");
-                }
+    /// Performs the pending actions confirmed by the tokens, one per line.
+    fn confirm_pending(&mut self, tokens: &str) -> Result<(), libc::c_int> {
+        let mut result = Ok(());
+        for token in tokens.split_whitespace() {
+            let Some(action) = self.guardrail.pending_action(token) else {
+                log::error!("no pending action for token {token}, it may have expired");
+                result = Err(libc::EINVAL);
+                continue;
+            };
+
+            // the action stays pending so that it can be confirmed after the cooldown
+            if !self.mutation_quotas.allow(&action.path()) {
+                log::error!(
+                    "refusing to {action}: mutation limit of {} exceeded",
+                    action.path()
+                );
+                result = Err(libc::EAGAIN);
+                continue;
             }
-        } else {
-            // TODO: this should probably panic
-            reply.error(libc::ENOTDIR);
-            return;
-        }
 
-        for (i, entry) in entries.into_iter().skip(offset as usize).enumerate() {
-            if reply.add(entry.0, (offset + i as i64 + 1) as i64, entry.1, entry.2) {
-                break;
+            let Some(action) = self.guardrail.confirm(token) else {
+                continue;
+            };
+
+            log::info!("confirmed: {action}");
+            match self.perform(&action) {
+                Err(errno) => result = Err(errno),
+                // the edit made it, so the draft it was kept in is done with
+                Ok(()) => {
+                    if let DestructiveAction::ApplySecret { inode, draft, .. } = action {
+                        self.discard_draft(inode);
+                        self.remove_draft_node(draft);
+                    }
+                }
             }
         }
-        reply.ok();
-        return;
+        self.render_pending();
+        result
     }
 
-    fn read(
-        &mut self,
-        _req: &fuser::Request<'_>,
-        ino: u64,
-        fh: u64,
-        offset: i64,
-        size: u32,
-        flags: i32,
-        lock_owner: Option<u64>,
-        reply: fuser::ReplyData,
-    ) {
-        log::debug!(
-            "read ino={ino} fh={fh} offset={offset} size={size} flags={flags} lock_owner={:?}\n",
-            lock_owner
-        );
-        let Some(node) = self.inodes.get(&ino) else {
-            reply.error(libc::ENOENT);
-            return;
-        };
-
-        if node.attrs.kind != fuser::FileType::RegularFile {
-            reply.error(libc::EISDIR);
-            return;
+    /// Performs a destructive action, unless the mount is pinned to the past.
+    fn perform(&self, action: &DestructiveAction) -> Result<(), libc::c_int> {
+        if self.resource_version.is_some() {
+            log::error!("refusing to {action}: the mount is pinned to a past resourceVersion");
+            return Err(libc::EROFS);
         }
+        self.send_action(action).map_err(|e| {
+            log::error!("failed to {action}: {e}");
+            request_errno(e.status())
+        })
+    }
 
-        if let NodeContent::Bytes(data) = &node.content {
-            let start = offset as usize;
-            let end = std::cmp::min(start + size as usize, data.len());
-            if start >= data.len() {
-                reply.data(&[]);
-            } else {
-                reply.data(&data[start..end]);
+    fn send_action(&self, action: &DestructiveAction) -> Result<(), ApiError> {
+        match action {
+            DestructiveAction::DeleteNamespace(name) => {
+                Ok(self.kube_client.delete::<Namespace>(None, name)?)
+            }
+            DestructiveAction::DeleteNode { name, .. } => {
+                Ok(self.kube_client.delete::<KubeNode>(None, name)?)
+            }
+            DestructiveAction::ApplySecret {
+                object, manifest, ..
+            } => {
+                // like any edit of a whole manifest
+                let params = ApplyParams {
+                    force: true,
+                    ..Default::default()
+                };
+                self.kube_client.apply(object, manifest, &params)
             }
         }
     }
 
-    fn open(&mut self, _req: &fuser::Request<'_>, _ino: u64, _flags: i32, reply: fuser::ReplyOpen) {
-        // TODO: should at least increase open file handles
-        // TODO: only allow RDONLY
-        reply.opened(0, 0);
-    }
-
-    fn release(
-        &mut self,
-        _req: &fuser::Request<'_>,
-        _ino: u64,
-        _fh: u64,
-        _flags: i32,
+    /// Starts the background refresh of every presented resource type whose
+    /// refresh interval is not zero.
+    fn start_refreshers(&mut self) {
+        // lazily presented namespaces are refreshed once they are listed
+        if !self.profile.lazy_namespaces {
+            self.start_namespace_refresher();
+        }
+        if self.profile.shows_resource("configmaps")
+            && !self.lists_metadata_only("configmaps", &ListParams::default())
+        {
+            self.start_refresher::<ConfigMap>(
+                "configmaps",
+                KubeFilesystem::sync_manifests,
+                KubeFilesystem::watch_manifests,
+            );
+        }
+        if self.profile.shows_resource("secrets")
+            && !self.lists_metadata_only("secrets", &ListParams::default())
+        {
+            self.start_refresher::<Secret>(
+                "secrets",
+                KubeFilesystem::sync_manifests,
+                KubeFilesystem::watch_manifests,
+            );
+        }
+        if self.profile.shows_resource("services")
+            && !self.lists_metadata_only("services", &ListParams::default())
+        {
+            self.start_refresher::<Service>(
+                "services",
+                KubeFilesystem::sync_manifests,
+                KubeFilesystem::watch_manifests,
+            );
+        }
+        if self.profile.shows_resource("endpointslices") {
+            self.start_refresher::<EndpointSlice>(
+                "endpointslices",
+                KubeFilesystem::sync_endpoint_slices,
+                KubeFilesystem::watch_endpoint_slices,
+            );
+        }
+        if self.profile.shows_resource("deployments")
+            && !self.lists_metadata_only("deployments", &ListParams::default())
+        {
+            self.start_refresher::<Deployment>(
+                "deployments",
+                KubeFilesystem::sync_manifests,
+                KubeFilesystem::watch_manifests,
+            );
+        }
+        if self.profile.shows_resource("replicasets")
+            && !self.lists_metadata_only("replicasets", &ListParams::default())
+        {
+            self.start_refresher::<ReplicaSet>(
+                "replicasets",
+                KubeFilesystem::sync_manifests,
+                KubeFilesystem::watch_manifests,
+            );
+        }
+        if self.profile.shows_resource("statefulsets")
+            && !self.lists_metadata_only("statefulsets", &ListParams::default())
+        {
+            self.start_refresher::<StatefulSet>(
+                "statefulsets",
+                KubeFilesystem::sync_manifests,
+                KubeFilesystem::watch_manifests,
+            );
+        }
+        if self.profile.shows_resource("daemonsets")
+            && !self.lists_metadata_only("daemonsets", &ListParams::default())
+        {
+            self.start_refresher::<DaemonSet>(
+                "daemonsets",
+                KubeFilesystem::sync_manifests,
+                KubeFilesystem::watch_manifests,
+            );
+        }
+        for api_resource in self.profile.custom_resources.clone() {
+            if self.profile.shows_resource(&api_resource.plural)
+                && !self.lists_metadata_only(&api_resource.plural, &ListParams::default())
+            {
+                self.start_unstructured_refresher(api_resource);
+            }
+        }
+        for api_resource in self.metadata_only_resources() {
+            self.start_metadata_refresher(api_resource);
+        }
+        if self.profile.shows_resource("events") {
+            self.start_refresher::<Event>(
+                "events",
+                KubeFilesystem::sync_events,
+                KubeFilesystem::watch_events,
+            );
+        }
+        if self.profile.shows_resource("nodes") {
+            self.start_refresher::<KubeNode>(
+                "nodes",
+                KubeFilesystem::sync_cluster_manifests,
+                KubeFilesystem::watch_cluster_manifests,
+            );
+        }
+        if self.profile.shows_resource("persistentvolumes") {
+            self.start_refresher::<PersistentVolume>(
+                "persistentvolumes",
+                KubeFilesystem::sync_cluster_manifests,
+                KubeFilesystem::watch_cluster_manifests,
+            );
+        }
+        if self.profile.shows_resource("clusterroles") {
+            self.start_refresher::<ClusterRole>(
+                "clusterroles",
+                KubeFilesystem::sync_cluster_manifests,
+                KubeFilesystem::watch_cluster_manifests,
+            );
+        }
+        if self.profile.shows_resource("clusterrolebindings") {
+            self.start_refresher::<ClusterRoleBinding>(
+                "clusterrolebindings",
+                KubeFilesystem::sync_cluster_manifests,
+                KubeFilesystem::watch_cluster_manifests,
+            );
+        }
+        if self.profile.shows_resource("storageclasses") {
+            self.start_refresher::<StorageClass>(
+                "storageclasses",
+                KubeFilesystem::sync_cluster_manifests,
+                KubeFilesystem::watch_cluster_manifests,
+            );
+        }
+        self.render_stats();
+    }
+
+    fn start_namespace_refresher(&mut self) {
+        self.start_refresher::<Namespace>(
+            "namespaces",
+            KubeFilesystem::sync_namespaces,
+            KubeFilesystem::watch_namespaces,
+        );
+    }
+
+    /// Keeps a resource type up to date with a watch, or by polling it if the
+    /// credentials don't allow watching it.
+    fn start_refresher<T>(
+        &mut self,
+        resource: &'static str,
+        sync: fn(&mut KubeFilesystem, Vec<T>),
+        apply: fn(&mut KubeFilesystem, WatchChange<T>),
+    ) where
+        T: k8s_openapi::ListableResource
+            + k8s_openapi::Metadata<Ty = ObjectMeta>
+            + serde::de::DeserializeOwned
+            + Send
+            + 'static,
+    {
+        let interval = self.refresh_intervals.interval(resource);
+        if interval.is_zero() {
+            return;
+        }
+
+        let strategy = if self.capabilities.allows(resource, "watch") {
+            refresh::spawn_watcher(
+                resource,
+                self.kube_client.clone(),
+                self.refresh_schedule.clone(),
+                interval,
+                self.updates_tx.clone(),
+                sync,
+                apply,
+            );
+            RefreshStrategy::Watch
+        } else {
+            refresh::spawn_poller(
+                resource,
+                self.kube_client.clone(),
+                self.refresh_schedule.clone(),
+                interval,
+                self.updates_tx.clone(),
+                sync,
+            );
+            RefreshStrategy::Poll(interval)
+        };
+        self.refresh_statuses
+            .insert(resource, RefreshStatus::new(strategy));
+    }
+
+    /// Keeps a resource type without a typed model up to date by polling it.
+    fn start_unstructured_refresher(&mut self, api_resource: ApiResource) {
+        // refreshed for as long as the filesystem is mounted
+        let resource: &'static str = Box::leak(api_resource.plural.clone().into_boxed_str());
+        let interval = self.refresh_intervals.interval(resource);
+        if interval.is_zero() {
+            return;
+        }
+
+        refresh::spawn_unstructured_poller(
+            resource,
+            api_resource,
+            self.kube_client.clone(),
+            self.refresh_schedule.clone(),
+            interval,
+            self.updates_tx.clone(),
+        );
+        self.refresh_statuses.insert(
+            resource,
+            RefreshStatus::new(RefreshStrategy::Poll(interval)),
+        );
+    }
+
+    fn set_refresh_strategy(&mut self, resource: &'static str, strategy: RefreshStrategy) {
+        if let Some(status) = self.refresh_statuses.get_mut(resource) {
+            status.strategy = strategy;
+        }
+        self.render_stats();
+    }
+
+    /// Applies the changes prepared by the background refreshers since the
+    /// last FUSE operation, then the changes of the manifests to their other
+    /// presentations.
+    fn apply_refresh_updates(&mut self) {
+        let updates: Vec<Update> = self.updates_rx.try_iter().collect();
+        if updates.is_empty() {
+            return;
+        }
+        for update in updates {
+            update(self);
+        }
+        self.sync_layouts();
+        self.sync_json_copies();
+        self.sync_used_by();
+        self.sync_secret_sources();
+        self.sync_http_routes();
+        self.sync_mesh_hosts();
+        self.sync_invalid();
+        self.sync_shards();
+        self.enforce_memory_budget();
+    }
+
+    fn record_refresh(&mut self, resource: &'static str, error: Option<String>) {
+        if let Ok(mut health) = self.health.lock() {
+            match &error {
+                Some(error) => health.failing.insert(resource, error.clone()),
+                None => health.failing.remove(resource),
+            };
+            if error.is_none() {
+                health.unauthorized = false;
+            }
+        }
+        if let Some(status) = self.refresh_statuses.get_mut(resource) {
+            status.refreshes += 1;
+            match error {
+                Some(error) => status.last_error = Some(error),
+                None => {
+                    status.last_refresh = Some(SystemTime::now());
+                    status.last_error = None;
+                }
+            }
+        }
+        self.render_stats();
+    }
+
+    /// Records whether the API server could be reached by the last request
+    /// made in the background. While it can't, the cached tree is still served
+    /// and the condition is reported in `.kubefuse/DEGRADED`.
+    fn set_reachable(&mut self, error: Option<String>) {
+        if let Ok(mut health) = self.health.lock() {
+            health.unreachable = error.clone();
+        }
+        let Some(control_inode) = self.state.child_inode(ROOT_ATTR.ino, CONTROL_DIR_NAME) else {
+            return;
+        };
+
+        match (error, &self.degraded) {
+            (None, None) => {}
+            (None, Some(_)) => {
+                log::info!("API server is reachable again");
+                self.degraded = None;
+                if let Some(inode) = self.state.child_inode(control_inode, DEGRADED_NAME) {
+                    self.state.remove_node(inode);
+                }
+            }
+            (Some(error), degraded) => {
+                let since = degraded
+                    .as_ref()
+                    .map_or_else(SystemTime::now, |(since, _)| *since);
+                if degraded.is_none() {
+                    log::warn!("API server is unreachable, serving cached data: {error}");
+                }
+
+                let since_time = since
+                    .duration_since(UNIX_EPOCH)
+                    .ok()
+                    .and_then(|d| chrono::DateTime::from_timestamp(d.as_secs() as i64, 0))
+                    .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+                    .unwrap_or_default();
+                let content = format!(
+                    "the API server has been unreachable since {since_time}, serving cached data\nlast error: {error}\n"
+                );
+                self.degraded = Some((since, error));
+                match self.state.child_inode(control_inode, DEGRADED_NAME) {
+                    Some(inode) => self.set_node_content(inode, content.into_bytes()),
+                    None => {
+                        self.create_content_node(
+                            control_inode,
+                            DEGRADED_NAME,
+                            content.into_bytes(),
+                            since,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the extended attributes of a node, both its own and those
+    /// describing the state of the whole mount.
+    fn node_xattrs(&self, inode: u64) -> Option<BTreeMap<String, Vec<u8>>> {
+        let mut xattrs = self.state.inodes.get(&inode)?.xattrs.clone();
+        if let Some((_, error)) = &self.degraded {
+            xattrs.insert(DEGRADED_XATTR.to_string(), error.clone().into_bytes());
+        }
+        if let Some((fetched_at, stale)) = self.freshness(inode) {
+            if let Some(fetched_at) = refresh::format_time(fetched_at) {
+                xattrs.insert(FETCHED_AT_XATTR.to_string(), fetched_at.into_bytes());
+            }
+            xattrs.insert(STALE_XATTR.to_string(), stale.to_string().into_bytes());
+        }
+        if let Some(dropped) = self.dropped_log_lines(inode) {
+            xattrs.insert(DROPPED_XATTR.to_string(), dropped.to_string().into_bytes());
+        }
+        Some(xattrs)
+    }
+
+    fn render_stats(&mut self) {
+        if let Some(stats_inode) = self.stats_inode {
+            let mut stats = refresh::render_stats(&self.refresh_statuses);
+            stats.push_str(&self.compaction.render());
+            stats.push_str(&format!(
+                "rendering:\n  failures: {}\n",
+                self.render_failures
+            ));
+            if self.memory_budget > 0 {
+                stats.push_str(&format!(
+                    "memory:\n  budget: {}\n  evictions: {}\n",
+                    self.memory_budget, self.evictions
+                ));
+            }
+            self.set_node_content(stats_inode, stats.into_bytes());
+        }
+    }
+
+    /// Brings the namespace directories in line with the listed namespaces,
+    /// populating new ones and dropping those that were deleted.
+    fn sync_namespaces(&mut self, namespaces: Vec<Namespace>) {
+        let mut stale: HashSet<String> = self.namespace_names().into_iter().collect();
+
+        let mut new_namespaces = Vec::new();
+        for namespace in namespaces {
+            let Some(ns_name) = namespace.metadata.name.as_deref() else {
+                continue;
+            };
+
+            if !stale.remove(ns_name) {
+                new_namespaces.push(namespace);
+                continue;
+            }
+
+            let manifest = self.render_manifest(&namespace);
+            let manifest_name = self.manifest_file_name("manifest");
+            if let Some(ns_inode) = self.namespace_inode(ns_name) {
+                if let Some(manifest_inode) = self.state.child_inode(ns_inode, &manifest_name) {
+                    self.update_node_content(manifest_inode, manifest);
+                }
+                self.sync_readme(ns_inode, &namespace);
+                self.sync_terminating_marker(ns_inode, &namespace);
+            }
+        }
+
+        for ns_name in stale {
+            if let Some(ns_inode) = self.namespace_inode(&ns_name) {
+                self.state.remove_node(ns_inode);
+            }
+        }
+
+        self.populate_namespaces(ROOT_ATTR.ino, &new_namespaces, &ListParams::default());
+    }
+
+    fn watch_namespaces(&mut self, change: WatchChange<Namespace>) {
+        match change {
+            WatchChange::Applied(namespace) => {
+                let Some(ns_name) = namespace.metadata.name.as_deref() else {
+                    return;
+                };
+                let Some(ns_inode) = self.namespace_inode(ns_name) else {
+                    self.populate_namespaces(ROOT_ATTR.ino, &[namespace], &ListParams::default());
+                    return;
+                };
+
+                let manifest = self.render_manifest(&namespace);
+                let manifest_name = self.manifest_file_name("manifest");
+                if let Some(manifest_inode) = self.state.child_inode(ns_inode, &manifest_name) {
+                    self.update_node_content(manifest_inode, manifest);
+                }
+                self.sync_readme(ns_inode, &namespace);
+                self.sync_terminating_marker(ns_inode, &namespace);
+            }
+            WatchChange::Deleted(namespace) => {
+                if let Some(ns_inode) = namespace
+                    .metadata
+                    .name
+                    .as_deref()
+                    .and_then(|ns_name| self.namespace_inode(ns_name))
+                {
+                    self.state.remove_node(ns_inode);
+                }
+                if let Some(ns_name) = &namespace.metadata.name {
+                    self.events.remove(ns_name);
+                }
+            }
+        }
+    }
+
+    /// Replaces the manifests of every namespace with the listed objects.
+    fn sync_manifests<T>(&mut self, objects: Vec<T>)
+    where
+        T: k8s_openapi::Resource + k8s_openapi::Metadata<Ty = ObjectMeta> + serde::Serialize,
+    {
+        let mut by_namespace: HashMap<String, Vec<T>> = HashMap::new();
+        for object in objects {
+            let namespace = object.metadata().namespace.clone().unwrap_or_default();
+            by_namespace.entry(namespace).or_default().push(object);
+        }
+
+        let resource_kind = T::URL_PATH_SEGMENT;
+        for ns_name in self.namespace_names() {
+            let Some(ns_inode) = self.namespace_inode(&ns_name) else {
+                continue;
+            };
+            let Some(manifests_inode) = self
+                .state
+                .child_inode(ns_inode, resource_kind)
+                .or_else(|| self.create_dir_node(ns_inode, resource_kind))
+            else {
+                continue;
+            };
+
+            let objects = by_namespace.remove(&ns_name).unwrap_or_default();
+            self.sync_manifest_files(manifests_inode, &objects);
+        }
+    }
+
+    /// Applies a change of a single object to the manifests of its namespace.
+    fn watch_manifests<T>(&mut self, change: WatchChange<T>)
+    where
+        T: k8s_openapi::Resource + k8s_openapi::Metadata<Ty = ObjectMeta> + serde::Serialize,
+    {
+        let (WatchChange::Applied(object) | WatchChange::Deleted(object)) = &change;
+        let (Some(ns_name), Some(name)) = (&object.metadata().namespace, &object.metadata().name)
+        else {
+            return;
+        };
+        let Some(ns_inode) = self.namespace_inode(ns_name) else {
+            return; // hidden, or the namespace watch hasn't caught up yet
+        };
+
+        let resource_kind = T::URL_PATH_SEGMENT;
+        let Some(manifests_inode) = self
+            .state
+            .child_inode(ns_inode, resource_kind)
+            .or_else(|| self.create_dir_node(ns_inode, resource_kind))
+        else {
+            return;
+        };
+
+        let verb = match &change {
+            WatchChange::Deleted(_) => "DELETED",
+            WatchChange::Applied(_)
+                if self
+                    .state
+                    .child_inode(manifests_inode, &self.manifest_file_name(name))
+                    .is_some() =>
+            {
+                "MODIFIED"
+            }
+            WatchChange::Applied(_) => "ADDED",
+        };
+        self.record_change(
+            ns_name,
+            verb,
+            T::KIND,
+            name,
+            object.metadata().resource_version.as_deref(),
+        );
+
+        self.apply_manifest_change(manifests_inode, change);
+    }
+
+    /// Creates, updates or removes the manifest file of a changed object.
+    fn apply_manifest_change<T>(&mut self, manifests_inode: u64, change: WatchChange<T>)
+    where
+        T: k8s_openapi::Resource + k8s_openapi::Metadata<Ty = ObjectMeta> + serde::Serialize,
+    {
+        match change {
+            WatchChange::Applied(object) => self.sync_manifest_file(manifests_inode, &object),
+            WatchChange::Deleted(object) => {
+                let Some(name) = object.metadata().name.as_deref() else {
+                    return;
+                };
+                let file_name = self.manifest_file_name(name);
+                if let Some(inode) = self.state.child_inode(manifests_inode, &file_name) {
+                    self.state.remove_node(inode);
+                }
+                self.remove_key_dir(manifests_inode, name);
+            }
+        }
+    }
+
+    fn sync_events(&mut self, events: Vec<Event>) {
+        self.events.clear();
+        for event in events {
+            let (Some(namespace), Some(name)) = (
+                event.metadata.namespace.clone(),
+                event.metadata.name.clone(),
+            ) else {
+                continue;
+            };
+            self.events
+                .entry(namespace)
+                .or_default()
+                .insert(name, event);
+        }
+
+        for ns_name in self.namespace_names() {
+            self.render_namespace_events(&ns_name);
+        }
+    }
+
+    fn watch_events(&mut self, change: WatchChange<Event>) {
+        let (WatchChange::Applied(event) | WatchChange::Deleted(event)) = &change;
+        let (Some(ns_name), Some(name)) = (
+            event.metadata.namespace.clone(),
+            event.metadata.name.clone(),
+        ) else {
+            return;
+        };
+
+        let namespace_events = self.events.entry(ns_name.clone()).or_default();
+        match change {
+            WatchChange::Applied(event) => {
+                namespace_events.insert(name, event);
+            }
+            WatchChange::Deleted(_) => {
+                namespace_events.remove(&name);
+            }
+        }
+        self.render_namespace_events(&ns_name);
+    }
+
+    fn render_namespace_events(&mut self, ns_name: &str) {
+        let Some(ns_inode) = self.namespace_inode(ns_name) else {
+            return;
+        };
+        let events: Vec<Event> = self
+            .events
+            .get(ns_name)
+            .map(|events| events.values().cloned().collect())
+            .unwrap_or_default();
+        self.write_events_files(ns_inode, &events);
+    }
+
+    fn sync_endpoint_slices(&mut self, slices: Vec<EndpointSlice>) {
+        self.endpoint_slices.clear();
+        for slice in &slices {
+            let (Some(namespace), Some(name)) = (
+                slice.metadata.namespace.clone(),
+                slice.metadata.name.clone(),
+            ) else {
+                continue;
+            };
+            self.endpoint_slices
+                .entry(namespace)
+                .or_default()
+                .insert(name, slice.clone());
+        }
+        self.sync_manifests(slices);
+
+        for ns_name in self.namespace_names() {
+            self.render_namespace_endpoints(&ns_name);
+        }
+    }
+
+    fn watch_endpoint_slices(&mut self, change: WatchChange<EndpointSlice>) {
+        let (WatchChange::Applied(slice) | WatchChange::Deleted(slice)) = &change;
+        let (Some(ns_name), Some(name)) = (
+            slice.metadata.namespace.clone(),
+            slice.metadata.name.clone(),
+        ) else {
+            return;
+        };
+
+        let namespace_slices = self.endpoint_slices.entry(ns_name.clone()).or_default();
+        match &change {
+            WatchChange::Applied(slice) => {
+                namespace_slices.insert(name, slice.clone());
+            }
+            WatchChange::Deleted(_) => {
+                namespace_slices.remove(&name);
+            }
+        }
+        self.watch_manifests(change);
+        self.render_namespace_endpoints(&ns_name);
+    }
+
+    fn render_namespace_endpoints(&mut self, ns_name: &str) {
+        let Some(ns_inode) = self.namespace_inode(ns_name) else {
+            return;
+        };
+        let slices: Vec<EndpointSlice> = self
+            .endpoint_slices
+            .get(ns_name)
+            .map(|slices| slices.values().cloned().collect())
+            .unwrap_or_default();
+        self.write_endpoints_file(ns_inode, &slices);
+    }
+
+    /// Creates a directory for each of the namespaces along with the resources
+    /// they contain, all listed with the given parameters.
+    fn populate_namespaces(
+        &mut self,
+        parent_inode: u64,
+        namespaces: &[Namespace],
+        params: &ListParams,
+    ) {
+        for item in namespaces.iter() {
+            let ns_name = match item.metadata.name.as_deref() {
+                Some(n) => n,
+                None => continue, // TODO: Should be an error? Should we panic?
+            };
+            if self.profile.hides_namespace(ns_name) {
+                continue;
+            }
+            let Some(ns_inode) = self.create_namespace_node(parent_inode, item) else {
+                continue;
+            };
+
+            if self.profile.shows_resource("configmaps")
+                && !self.lists_metadata_only("configmaps", params)
+            {
+                self.create_manifests_node::<ConfigMap>(ns_inode, Some(ns_name), params);
+            }
+            if self.profile.shows_resource("secrets")
+                && !self.lists_metadata_only("secrets", params)
+            {
+                self.create_manifests_node::<Secret>(ns_inode, Some(ns_name), params);
+            }
+            if self.profile.shows_resource("services")
+                && !self.lists_metadata_only("services", params)
+            {
+                self.create_manifests_node::<Service>(ns_inode, Some(ns_name), params);
+            }
+            if self.profile.shows_resource("endpointslices") {
+                let mut slices = Vec::new();
+                let listed = self.create_manifests_node_with::<EndpointSlice>(
+                    ns_inode,
+                    Some(ns_name),
+                    params,
+                    |page| {
+                        if page.relisted {
+                            slices.clear();
+                        }
+                        slices.extend(page.list.items.iter().cloned());
+                    },
+                );
+                if listed.is_some() {
+                    self.write_endpoints_file(ns_inode, &slices);
+                }
+            }
+            if self.profile.shows_resource("deployments")
+                && !self.lists_metadata_only("deployments", params)
+            {
+                self.create_manifests_node::<Deployment>(ns_inode, Some(ns_name), params);
+            }
+            if self.profile.shows_resource("replicasets")
+                && !self.lists_metadata_only("replicasets", params)
+            {
+                self.create_manifests_node::<ReplicaSet>(ns_inode, Some(ns_name), params);
+            }
+            if self.profile.shows_resource("statefulsets")
+                && !self.lists_metadata_only("statefulsets", params)
+            {
+                self.create_manifests_node::<StatefulSet>(ns_inode, Some(ns_name), params);
+            }
+            if self.profile.shows_resource("daemonsets")
+                && !self.lists_metadata_only("daemonsets", params)
+            {
+                self.create_manifests_node::<DaemonSet>(ns_inode, Some(ns_name), params);
+            }
+            for api_resource in self.profile.custom_resources.clone() {
+                if self.profile.shows_resource(&api_resource.plural)
+                    && !self.lists_metadata_only(&api_resource.plural, params)
+                {
+                    self.create_unstructured_node(ns_inode, ns_name, &api_resource, params);
+                }
+            }
+            if params.resource_version.is_none() {
+                for api_resource in self.metadata_only_resources() {
+                    self.create_metadata_only_node(ns_inode, ns_name, &api_resource, params);
+                }
+            }
+            if self.profile.shows_resource("events") {
+                let list_result = self.kube_client.list::<Event>(Some(ns_name), params);
+                self.create_events_nodes(ns_inode, ns_name, list_result);
+            }
+        }
+    }
+
+    /// Materializes `.kubefuse/at/<resourceVersion>/`, a read-only copy of the
+    /// namespaces as they were at the given resourceVersion. This only works
+    /// while the API server still has that revision, i.e. until it is compacted.
+    fn create_pinned_node(&mut self, pinned_inode: u64, resource_version: &str) {
+        if self
+            .state
+            .child_inode(pinned_inode, resource_version)
+            .is_some()
+        {
+            return;
+        }
+
+        let params = ListParams::pinned_to(resource_version);
+        let namespaces = match self.kube_client.list::<Namespace>(None, &params) {
+            Err(e) => {
+                log::error!("namespaces fetch at resourceVersion {resource_version} failed: {e}");
+                return;
+            }
+            Ok(list) => list,
+        };
+
+        if let Some(snapshot_inode) = self.create_dir_node(pinned_inode, resource_version) {
+            self.populate_namespaces(snapshot_inode, &namespaces.items, &params);
+        }
+    }
+
+    /// Collects the manifests of all resources in a namespace, keyed by their
+    /// path relative to the namespace directory.
+    fn namespace_manifests(&mut self, namespace: &str) -> Option<diff::NamespaceManifests> {
+        let ns_inode = self.namespace_inode(namespace)?;
+        let mut files = Vec::new();
+        for (dir_name, dir_inode) in self.children(ns_inode) {
+            if !self.is_dir(dir_inode) {
+                continue; // the namespace's own manifest.yaml
+            }
+            for (file_name, file_inode) in self.children(dir_inode) {
+                if !file_name.ends_with(DRAFT_SUFFIX) {
+                    let file_name = names::encode_name(&file_name);
+                    files.push((format!("{dir_name}/{file_name}"), file_inode));
+                }
+            }
+        }
+
+        let mut manifests = diff::NamespaceManifests::new();
+        for (path, inode) in files {
+            if let Some(data) = self.file_content(inode) {
+                manifests.insert(path, data.to_vec());
+            }
+        }
+        Some(manifests)
+    }
+
+    /// Regenerates the `.kubefuse/diff/<nsA>...<nsB>` file for the given name
+    /// so that every lookup reflects the current state of both namespaces.
+    fn refresh_diff_node(&mut self, diff_inode: u64, name: &str) {
+        let Some((ns_a, ns_b)) = diff::parse_diff_name(name) else {
+            return;
+        };
+        let (Some(manifests_a), Some(manifests_b)) = (
+            self.namespace_manifests(ns_a),
+            self.namespace_manifests(ns_b),
+        ) else {
+            log::debug!("diff {name}: namespace not found");
+            return;
+        };
+
+        let content = diff::namespace_diff(ns_a, &manifests_a, ns_b, &manifests_b).into_bytes();
+        match self.state.child_inode(diff_inode, name) {
+            Some(inode) => self.set_node_content(inode, content),
+            None => {
+                self.create_content_node(diff_inode, name, content, SystemTime::now());
+            }
+        }
+    }
+
+    /// Makes a node created on lookup reachable by its name only, replacing
+    /// the one created by the previous lookup of the same name.
+    fn hide_node(&mut self, parent_inode: u64, name: &str, inode: u64) {
+        self.state.detach_node(inode);
+        if let Some(previous_inode) = self
+            .hidden_inodes
+            .insert((parent_inode, name.to_string()), inode)
+        {
+            self.state.remove_node(previous_inode);
+        }
+    }
+
+    /// Looks up a node created by [`Self::hide_node`].
+    fn hidden_inode(&self, parent_inode: u64, name: &str) -> Option<u64> {
+        self.hidden_inodes
+            .get(&(parent_inode, name.to_string()))
+            .copied()
+    }
+
+    /// Returns the name of the file holding the manifest of an object.
+    fn manifest_file_name(&self, object_name: &str) -> String {
+        self.profile.manifest_file_name(object_name)
+    }
+
+    /// Serializes an object to YAML according to the active profile.
+    fn render_manifest<T>(&mut self, object: &T) -> Vec<u8>
+    where
+        T: k8s_openapi::Resource + k8s_openapi::Metadata<Ty = ObjectMeta> + serde::Serialize,
+    {
+        self.try_render_manifest(object).unwrap_or_else(|e| {
+            let metadata = object.metadata();
+            self.render_failure(
+                T::KIND,
+                metadata.namespace.as_deref(),
+                metadata.name.as_deref().unwrap_or_default(),
+                &e,
+            )
+        })
+    }
+
+    fn try_render_manifest<T>(&self, object: &T) -> Result<Vec<u8>, String>
+    where
+        T: k8s_openapi::Resource + serde::Serialize,
+    {
+        let mut value = serde_yaml::to_value(object).map_err(|e| e.to_string())?;
+
+        if self.profile.redact_secrets && T::KIND == "Secret" {
+            sanitize::redact_secret_data(&mut value);
+        }
+        if self.profile.sanitize {
+            sanitize::sanitize_value(&mut value);
+        }
+
+        serialize_manifest(&value, self.profile.manifest_format).map(String::into_bytes)
+    }
+
+    /// Creates the directory of a resource type in a namespace, or in
+    /// `_cluster/` if none is given, filling it with the objects as each page
+    /// of them is listed.
+    fn create_manifests_node<T>(
+        &mut self,
+        ns_inode: u64,
+        namespace: Option<&str>,
+        params: &ListParams,
+    ) -> Option<u64>
+    where
+        T: k8s_openapi::ListableResource
+            + k8s_openapi::Metadata<Ty = ObjectMeta>
+            + serde::Serialize
+            + serde::de::DeserializeOwned,
+    {
+        self.create_manifests_node_with::<T>(ns_inode, namespace, params, |_| {})
+    }
+
+    /// Creates the directory of a resource type like
+    /// [`Self::create_manifests_node`], also handing each listed page to
+    /// `on_page`, e.g. to gather what's derived from all of the objects.
+    fn create_manifests_node_with<T>(
+        &mut self,
+        ns_inode: u64,
+        namespace: Option<&str>,
+        params: &ListParams,
+        mut on_page: impl FnMut(&Page<k8s_openapi::List<T>>),
+    ) -> Option<u64>
+    where
+        T: k8s_openapi::ListableResource
+            + k8s_openapi::Metadata<Ty = ObjectMeta>
+            + serde::Serialize
+            + serde::de::DeserializeOwned,
+    {
+        let resource_kind = T::URL_PATH_SEGMENT;
+        let manifests_inode = self
+            .create_dir_node(ns_inode, resource_kind)
+            .expect("failed to create manifests directory node");
+        if !self
+            .capabilities
+            .allows_in(&self.kube_client, namespace, resource_kind, "list")
+            && let Some(node) = self.state.inodes.get_mut(&manifests_inode)
+        {
+            node.attrs.perm = 0o000; // would be empty anyway
+        }
+
+        let client = self.kube_client.clone();
+        let mut keep = HashSet::new();
+        let list_result = client.list_each_page::<T>(namespace, params, |page| {
+            if page.relisted {
+                keep.clear();
+            }
+            self.sync_manifest_page(manifests_inode, &page.list.items, &mut keep);
+            on_page(&page);
+        });
+        if let Err(e) = list_result {
+            let namespace = namespace.unwrap_or(CLUSTER_DIR_NAME);
+            log::error!("manifests fetch failed for namespace {namespace}: {e}");
+            self.report_invalid(ns_inode, resource_kind, &e.to_string());
+            return None;
+        }
+
+        self.clear_list_error(manifests_inode);
+        self.remove_stale_manifests(manifests_inode, &keep);
+        Some(manifests_inode)
+    }
+
+    /// Makes the manifest files in a directory match the given objects,
+    /// updating changed manifests in place and removing the ones of objects
+    /// that no longer exist.
+    fn sync_manifest_files<T>(&mut self, manifests_inode: u64, objects: &[T])
+    where
+        T: k8s_openapi::Resource + k8s_openapi::Metadata<Ty = ObjectMeta> + serde::Serialize,
+    {
+        self.clear_list_error(manifests_inode);
+        let mut keep = HashSet::new();
+        self.sync_manifest_page(manifests_inode, objects, &mut keep);
+        self.remove_stale_manifests(manifests_inode, &keep);
+    }
+
+    // creates or updates the manifest files of some of the objects of a
+    // directory, adding the entries to keep for them
+    fn sync_manifest_page<T>(
+        &mut self,
+        manifests_inode: u64,
+        objects: &[T],
+        keep: &mut HashSet<String>,
+    ) where
+        T: k8s_openapi::Resource + k8s_openapi::Metadata<Ty = ObjectMeta> + serde::Serialize,
+    {
+        for item in objects.iter() {
+            match item.metadata().name.as_deref() {
+                Some(n) => {
+                    keep.insert(self.manifest_file_name(n));
+                    keep.insert(n.to_string()); // its key or spec and status directory
+                }
+                None => continue, // TODO: Should be an error? Should we panic?
+            };
+            self.sync_manifest_file(manifests_inode, item);
+        }
+    }
+
+    /// Removes the entries of a resource directory other than the given ones,
+    /// keeping the drafts of edits in progress.
+    fn remove_stale_manifests(&mut self, manifests_inode: u64, keep: &HashSet<String>) {
+        let stale: Vec<String> = match self.state.inodes.get(&manifests_inode).map(|n| &n.content) {
+            // manifests being edited or created stay until they're closed
+            Some(NodeContent::Children(children)) => children
+                .iter()
+                .filter(|(name, inode)| {
+                    !keep.contains(*name)
+                        && !name
+                            .strip_suffix(ERROR_SUFFIX)
+                            .is_some_and(|manifest| keep.contains(manifest))
+                        && !name.ends_with(DRAFT_SUFFIX)
+                        && !self.drafts.contains_key(inode)
+                        && !self.json_copies.contains_key(inode)
+                        && !self.is_shard(**inode)
+                        && Some(**inode) != self.node_pressure_inode
+                })
+                .map(|(name, _)| name.clone())
+                .collect(),
+            _ => Vec::new(),
+        };
+
+        for name in stale {
+            if let Some(inode) = self.state.child_inode(manifests_inode, &name) {
+                self.state.remove_node(inode);
+            }
+        }
+    }
+
+    /// Creates or updates the manifest file of a single object.
+    fn sync_manifest_file<T>(&mut self, manifests_inode: u64, item: &T)
+    where
+        T: k8s_openapi::Resource + k8s_openapi::Metadata<Ty = ObjectMeta> + serde::Serialize,
+    {
+        let Some(object_name) = item.metadata().name.as_deref() else {
+            return;
+        };
+        let name = self.manifest_file_name(object_name);
+        let manifest_yaml = match self.try_render_manifest(item) {
+            Ok(manifest) => {
+                self.clear_invalid(manifests_inode, &name);
+                manifest
+            }
+            Err(e) => {
+                let namespace = item.metadata().namespace.as_deref();
+                let payload = self.render_failure(T::KIND, namespace, object_name, &e);
+                if self.report_invalid(manifests_inode, &name, &e) {
+                    return;
+                }
+                payload
+            }
+        };
+        self.sync_key_dir(manifests_inode, object_name, item, manifest_yaml.clone());
+        match serde_json::to_value(item) {
+            Ok(object) => {
+                self.sync_spec_status_files(manifests_inode, object_name, &object);
+                self.sync_replicas_file(manifests_inode, T::URL_PATH_SEGMENT, object_name, &object);
+                self.sync_restart_file(manifests_inode, T::URL_PATH_SEGMENT, object_name);
+            }
+            Err(e) => log::error!("failed to serialize {} {object_name}: {e}", T::KIND),
+        }
+
+        if let Some(manifest_inode) = self.state.child_inode(manifests_inode, &name) {
+            if !self.update_evicted(manifest_inode, &manifest_yaml) {
+                self.update_node_content(manifest_inode, manifest_yaml);
+            }
+            self.set_status_xattr(manifest_inode, T::URL_PATH_SEGMENT, item);
+            return;
+        }
+
+        let manifest_creation_time = item
+            .metadata()
+            .creation_timestamp
+            .as_ref()
+            .and_then(|t| t.0.timestamp().try_into().ok())
+            .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+            .unwrap_or(UNIX_EPOCH);
+
+        let manifest_inode = self
+            .create_manifest_node(
+                manifests_inode,
+                &name,
+                manifest_yaml,
+                manifest_creation_time,
+                item.metadata().uid.as_deref(),
+            )
+            .expect("failed to create manifest content node");
+        let perm = self.manifest_perm(item.metadata().namespace.as_deref(), T::URL_PATH_SEGMENT);
+        if let Some(node) = self.state.inodes.get_mut(&manifest_inode) {
+            node.attrs.perm = perm;
+        }
+        self.set_status_xattr(manifest_inode, T::URL_PATH_SEGMENT, item);
+    }
+
+    /// The mode of the manifests of a resource type in a namespace: readable
+    /// if the objects can be fetched, and writable if they can be updated, as
+    /// far as the API server said.
+    fn manifest_perm(&mut self, namespace: Option<&str>, resource: &str) -> u16 {
+        let mut perm = 0o000;
+        if self
+            .capabilities
+            .allows_in(&self.kube_client, namespace, resource, "get")
+        {
+            perm |= 0o444;
+        }
+        if self
+            .capabilities
+            .allows_in(&self.kube_client, namespace, resource, "update")
+        {
+            perm |= 0o200;
+        }
+        perm
+    }
+
+    /// Records the health of the object presented by a manifest in the
+    /// `user.kube.status` attribute, running the matching hooks when it
+    /// changes.
+    fn set_status_xattr(&mut self, inode: u64, resource: &str, object: &impl serde::Serialize) {
+        let summary = serde_json::to_value(object)
+            .ok()
+            .and_then(|object| status::summarize(resource, &object));
+        let Some(node) = self.state.inodes.get_mut(&inode) else {
+            return;
+        };
+        let Some(summary) = summary else {
+            node.xattrs.remove(STATUS_XATTR);
+            return;
+        };
+        let previous = node
+            .xattrs
+            .insert(STATUS_XATTR.to_string(), summary.clone().into_bytes());
+        if previous.as_deref() != Some(summary.as_bytes()) && self.populated_at.is_some() {
+            self.run_hooks(inode, &summary);
+        }
+    }
+
+    // runs the hooks watching a manifest that just entered a status
+    fn run_hooks(&self, inode: u64, status: &str) {
+        let path = self.state.node_path(inode);
+        for hook in &self.profile.hooks {
+            if hook.matches(&path, status) {
+                hook.run(&self.mountpoint.join(path.trim_start_matches('/')), status);
+            }
+        }
+    }
+
+    /// Creates the `events.log` file with recurring events merged together and
+    /// the `events.raw.log` file listing every event in the namespace.
+    fn create_events_nodes(
+        &mut self,
+        ns_inode: u64,
+        namespace: &str,
+        list_result: Result<k8s_openapi::List<Event>, reqwest::Error>,
+    ) -> Option<()> {
+        let event_list = match list_result {
+            Err(e) => {
+                log::error!("events fetch failed for namespace {namespace}: {e}");
+                return None;
+            }
+            Ok(list) => list,
+        };
+
+        self.write_events_files(ns_inode, &event_list.items);
+        Some(())
+    }
+
+    /// Renders the event logs of a namespace and `events/`, which holds the
+    /// manifest of every Event.
+    fn write_events_files(&mut self, ns_inode: u64, events: &[Event]) {
+        if let Some(events_inode) = self
+            .state
+            .child_inode(ns_inode, EVENTS_DIR_NAME)
+            .or_else(|| self.create_dir_node(ns_inode, EVENTS_DIR_NAME))
+        {
+            self.sync_manifest_files(events_inode, events);
+        }
+
+        let files = [
+            ("events.log", events::render_deduplicated(events)),
+            ("events.raw.log", events::render_raw(events)),
+        ];
+        for (name, content) in files {
+            match self.state.child_inode(ns_inode, name) {
+                Some(inode) => self.update_node_content(inode, content.into_bytes()),
+                None => {
+                    self.create_content_node(
+                        ns_inode,
+                        name,
+                        content.into_bytes(),
+                        SystemTime::now(),
+                    );
+                }
+            }
+        }
+    }
+
+    fn write_endpoints_file(&mut self, ns_inode: u64, slices: &[EndpointSlice]) {
+        let content = endpoints::render(slices).into_bytes();
+        match self.state.child_inode(ns_inode, ENDPOINTS_NAME) {
+            Some(inode) => self.update_node_content(inode, content),
+            None => {
+                self.create_content_node(ns_inode, ENDPOINTS_NAME, content, SystemTime::now());
+            }
+        }
+    }
+
+    /// The inode of a new node, derived from its path and, for manifests, the
+    /// UID of their object, so that it's the same across refreshes and
+    /// mounts. What was kept for a previous node of that inode is dropped.
+    fn allocate_inode(&mut self, parent_inode: u64, name: &str, uid: Option<&str>) -> u64 {
+        let mut key = format!(
+            "{}/{}",
+            self.state.node_path(parent_inode),
+            names::encode_name(name)
+        );
+        if let Some(uid) = uid {
+            key = format!("{key}#{uid}");
+        }
+        let inode = self.state.allocate_inode(&key);
+        self.lazy_manifests.remove(&inode);
+        self.last_read.remove(&inode);
+        self.invalid.remove(&inode);
+        inode
+    }
+
+    fn create_dir_node(&mut self, parent_inode: u64, name: &str) -> Option<u64> {
+        let new_inode = self.allocate_inode(parent_inode, name, None);
+
+        let node_creation_time = SystemTime::now();
+        let new_node = Node {
+            name: name.to_string(),
+            parent: parent_inode,
+            attrs: FileAttr {
+                ino: new_inode,
+                size: 0,
+                blocks: 0,
+                atime: node_creation_time,
+                mtime: node_creation_time,
+                ctime: node_creation_time,
+                crtime: node_creation_time,
+                kind: fuser::FileType::Directory,
+                perm: 0o755,
+                nlink: 2, // FIXME: should be updated when we add children directories
+                uid: 1000,
+                gid: 1000,
+                rdev: 0,
+                flags: 0,
+                blksize: BLOCK_SIZE,
+            },
+            content: NodeContent::Children(NodeChildren::new()),
+            xattrs: BTreeMap::new(),
+        };
+
+        self.state.insert_node(new_node)
+    }
+
+    /// Cuts content rendered from the cluster at the maximum file size,
+    /// appending a trailer explaining why. Returns the original size of the
+    /// content if it was truncated.
+    fn fit_content(&self, mut content: Vec<u8>) -> (Vec<u8>, Option<usize>) {
+        let original_size = content.len();
+        if self.max_file_size == 0 || original_size as u64 <= self.max_file_size {
+            return (content, None);
+        }
+
+        content.truncate(self.max_file_size as usize);
+        content.extend_from_slice(
+            format!(
+                "\n# kube-fuse: truncated, the full rendering has {original_size} bytes which exceeds --max-file-size={}\n",
+                self.max_file_size
+            )
+            .as_bytes(),
+        );
+        (content, Some(original_size))
+    }
+
+    /// Whether the content of a file is rendered by kube-fuse rather than
+    /// written by the user, e.g. in the scratch directory. Manifests are
+    /// rendered even where their mode shows them as writable.
+    fn is_rendered(&self, inode: u64) -> bool {
+        self.state
+            .inodes
+            .get(&inode)
+            .is_some_and(|node| node.attrs.perm & 0o222 == 0)
+            || self.manifest_location(inode).is_some()
+    }
+
+    fn set_node_content(&mut self, inode: u64, content: Vec<u8>) {
+        let (content, truncated_from) = if self.is_rendered(inode) {
+            self.fit_content(content)
+        } else {
+            (content, None)
+        };
+        self.store_node_content(inode, content, truncated_from);
+    }
+
+    /// Like [`Self::set_node_content`] but leaves the modification time
+    /// untouched when the content did not change.
+    fn update_node_content(&mut self, inode: u64, content: Vec<u8>) {
+        let (content, truncated_from) = if self.is_rendered(inode) {
+            self.fit_content(content)
+        } else {
+            (content, None)
+        };
+        if let Some(Node {
+            content: NodeContent::Bytes(current),
+            ..
+        }) = self.state.inodes.get(&inode)
+            && **current == content
+        {
+            return;
+        }
+        self.store_node_content(inode, content, truncated_from);
+    }
+
+    fn store_node_content(&mut self, inode: u64, content: Vec<u8>, truncated_from: Option<usize>) {
+        let Some(node) = self.state.inodes.get_mut(&inode) else {
+            log::error!("failed to update content: inode {inode} not found");
+            return;
+        };
+
+        let content_size = content.len() as u64;
+        node.attrs.size = content_size;
+        node.attrs.blocks = content_size.div_ceil(u64::from(BLOCK_SIZE));
+        node.attrs.mtime = SystemTime::now();
+        node.content = NodeContent::Bytes(Arc::new(content));
+        match truncated_from {
+            Some(size) => {
+                node.xattrs
+                    .insert(TRUNCATED_XATTR.to_string(), size.to_string().into_bytes());
+            }
+            None => {
+                node.xattrs.remove(TRUNCATED_XATTR);
+            }
+        }
+    }
+
+    /// Writes data into a file at the given offset, extending it if needed.
+    fn write_node_content(&mut self, inode: u64, offset: usize, data: &[u8]) -> Option<()> {
+        let mut content = self.take_node_content(inode)?;
+        if content.len() < offset + data.len() {
+            content.resize(offset + data.len(), 0);
+        }
+        content[offset..offset + data.len()].copy_from_slice(data);
+        self.set_node_content(inode, content);
+        Some(())
+    }
+
+    fn truncate_node_content(&mut self, inode: u64, size: usize) -> Option<()> {
+        let mut content = self.take_node_content(inode)?;
+        content.resize(size, 0);
+        self.set_node_content(inode, content);
+        Some(())
+    }
+
+    /// Takes the content of a file out of its node to be modified and set
+    /// again. It's only copied while handles still hold snapshots of it.
+    fn take_node_content(&mut self, inode: u64) -> Option<Vec<u8>> {
+        let NodeContent::Bytes(content) = &mut self.state.inodes.get_mut(&inode)?.content else {
+            return None;
+        };
+        Some(Arc::unwrap_or_clone(std::mem::take(content)))
+    }
+
+    fn create_content_node(
+        &mut self,
+        parent_inode: u64,
+        name: &str,
+        content: Vec<u8>,
+        creation_time: SystemTime,
+    ) -> Option<u64> {
+        self.create_manifest_node(parent_inode, name, content, creation_time, None)
+    }
+
+    /// Creates the file of a manifest, whose inode follows the UID of its
+    /// object, see [`Self::allocate_inode`].
+    fn create_manifest_node(
+        &mut self,
+        parent_inode: u64,
+        name: &str,
+        content: Vec<u8>,
+        creation_time: SystemTime,
+        uid: Option<&str>,
+    ) -> Option<u64> {
+        let new_inode = self.allocate_inode(parent_inode, name, uid);
+        let (content, truncated_from) = self.fit_content(content);
+        let content_size = content.len() as u64;
+
+        let mut xattrs = BTreeMap::new();
+        if let Some(size) = truncated_from {
+            xattrs.insert(TRUNCATED_XATTR.to_string(), size.to_string().into_bytes());
+        }
+
+        let new_node = Node {
+            name: name.to_string(),
+            parent: parent_inode,
+            attrs: FileAttr {
+                ino: new_inode,
+                size: content_size,
+                blocks: content_size.div_ceil(u64::from(BLOCK_SIZE)),
+                atime: creation_time,
+                mtime: creation_time,
+                ctime: creation_time,
+                crtime: creation_time,
+                kind: fuser::FileType::RegularFile,
+                perm: 0o444,
+                nlink: 1,
+                uid: 1000,
+                gid: 1000,
+                rdev: 0,
+                flags: 0,
+                blksize: BLOCK_SIZE,
+            },
+            content: NodeContent::Bytes(Arc::new(content)),
+            xattrs,
+        };
+
+        self.state.insert_node(new_node)
+    }
+
+    /// Creates a symbolic link pointing to `target`, relative to its parent.
+    fn create_symlink_node(&mut self, parent_inode: u64, name: &str, target: &str) -> Option<u64> {
+        let inode = self.create_content_node(
+            parent_inode,
+            name,
+            target.as_bytes().to_vec(),
+            SystemTime::now(),
+        )?;
+        let node = self.state.inodes.get_mut(&inode)?;
+        node.attrs.kind = fuser::FileType::Symlink;
+        node.attrs.perm = 0o777;
+        Some(inode)
+    }
+}
+
+/// Has the kernel forward both fcntl and flock locks to getlk/setlk.
+pub fn configure_kernel(config: &mut fuser::KernelConfig) {
+    if let Err(unsupported) =
+        config.add_capabilities(fuser::consts::FUSE_POSIX_LOCKS | fuser::consts::FUSE_FLOCK_LOCKS)
+    {
+        log::warn!(
+            "the kernel does not support lock capabilities {unsupported:#x}, locks are local only"
+        );
+    }
+}
+
+impl fuser::Filesystem for KubeFilesystem {
+    fn init(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        config: &mut fuser::KernelConfig,
+    ) -> Result<(), libc::c_int> {
+        configure_kernel(config);
+        self.build_tree()
+    }
+
+    fn lookup(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        parent: u64,
+        name: &std::ffi::OsStr,
+        reply: fuser::ReplyEntry,
+    ) {
+        let outcome = panics::catch(|| {
+            log::debug!("lookup parent={parent} name={name:?}\n");
+            self.apply_refresh_updates();
+            self.kube_client.set_operation(
+                "lookup",
+                &format!(
+                    "{}/{}",
+                    self.state.node_path(parent),
+                    name.to_string_lossy()
+                ),
+            );
+            let child_node = name
+                .to_str()
+                .and_then(|name| self.state.entry_name(parent, name))
+                .and_then(|name| self.lookup_child(parent, &name))
+                .and_then(|inode| self.state.inodes.get(&inode));
+
+            match child_node {
+                Some(n) => {
+                    let inode = n.attrs.ino;
+                    reply.entry(&TTL, &n.attrs, 0);
+                    self.state.remember(inode);
+                }
+                None => reply.error(libc::ENOENT),
+            };
+        });
+        self.record_panic("lookup", outcome);
+    }
+
+    fn forget(&mut self, _req: &fuser::Request<'_>, ino: u64, nlookup: u64) {
+        let outcome = panics::catch(|| {
+            log::debug!("forget ino={ino} nlookup={nlookup}\n");
+            self.state.forget(ino, nlookup);
+        });
+        self.record_panic("forget", outcome);
+    }
+
+    fn batch_forget(&mut self, _req: &fuser::Request<'_>, nodes: &[fuser::fuse_forget_one]) {
+        let outcome = panics::catch(|| {
+            log::debug!("batch_forget count={}\n", nodes.len());
+            for node in nodes {
+                self.state.forget(node.nodeid, node.nlookup);
+            }
+        });
+        self.record_panic("batch_forget", outcome);
+    }
+
+    fn getattr(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        ino: u64,
+        fh: Option<u64>,
+        reply: fuser::ReplyAttr,
+    ) {
+        let outcome = panics::catch(|| {
+            log::debug!("getattr ino={ino} fh={:?}\n", fh);
+            self.apply_refresh_updates();
+            if let Some(node) = self.state.inodes.get(&ino) {
+                if let Some(stream) = fh.and_then(|fh| self.log_streams.get(&fh)) {
+                    let size = stream.len();
+                    let attrs = FileAttr {
+                        size,
+                        blocks: size.div_ceil(u64::from(BLOCK_SIZE)),
+                        ..node.attrs
+                    };
+                    return reply.attr(&TTL, &attrs);
+                }
+                return reply.attr(&TTL, &node.attrs);
+            } else {
+                return reply.error(libc::ENOENT);
+            }
+        });
+        self.record_panic("getattr", outcome);
+    }
+
+    fn readlink(&mut self, _req: &fuser::Request<'_>, ino: u64, reply: fuser::ReplyData) {
+        let outcome = panics::catch(|| {
+            log::debug!("readlink ino={ino}");
+            self.apply_refresh_updates();
+            match self.state.inodes.get(&ino) {
+                Some(Node {
+                    attrs:
+                        FileAttr {
+                            kind: fuser::FileType::Symlink,
+                            ..
+                        },
+                    content: NodeContent::Bytes(target),
+                    ..
+                }) => reply.data(names::encode_path(&String::from_utf8_lossy(target)).as_bytes()),
+                Some(_) => reply.error(libc::EINVAL),
+                None => reply.error(libc::ENOENT),
+            }
+        });
+        self.record_panic("readlink", outcome);
+    }
+
+    fn statfs(&mut self, _req: &fuser::Request<'_>, ino: u64, reply: fuser::ReplyStatfs) {
+        let outcome = panics::catch(|| {
+            log::debug!("statfs ino={ino}");
+            self.apply_refresh_updates();
+            // sizes only mean something within a namespace with quotas
+            let (blocks, bfree, bavail, files, ffree) = self
+                .capacity_of(ino)
+                .map(|capacity| capacity.statfs())
+                .unwrap_or_default();
+            reply.statfs(
+                blocks, bfree, bavail, files, ffree, BLOCK_SIZE, 255, BLOCK_SIZE,
+            );
+        });
+        self.record_panic("statfs", outcome);
+    }
+
+    fn opendir(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        ino: u64,
+        flags: i32,
+        reply: fuser::ReplyOpen,
+    ) {
+        let outcome = panics::catch(|| {
+            log::debug!("opendir ino={ino} flags={flags}\n");
+            self.apply_refresh_updates();
+            if ino == ROOT_ATTR.ino {
+                self.list_lazy_namespaces();
+            }
+            match self.state.inodes.get(&ino).map(|node| node.attrs.kind) {
+                None => reply.error(libc::ENOENT),
+                // shards list what's in a sharded directory instead
+                Some(fuser::FileType::Directory) if self.sharded_dirs.contains(&ino) => {
+                    match self
+                        .state
+                        .open_dir_filtered(ino, |name| name.starts_with(SHARD_PREFIX))
+                    {
+                        Some(fh) => reply.opened(fh, 0),
+                        None => reply.error(libc::ENOTDIR),
+                    }
+                }
+                Some(fuser::FileType::Directory) => match self.state.open_dir(ino) {
+                    Some(fh) => reply.opened(fh, 0),
+                    None => reply.error(libc::ENOTDIR),
+                },
+                Some(_) => reply.error(libc::ENOTDIR),
+            }
+        });
+        self.record_panic("opendir", outcome);
+    }
+
+    fn readdir(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        inode: u64,
+        fh: u64,
+        offset: i64,
+        mut reply: fuser::ReplyDirectory,
+    ) {
+        let outcome = panics::catch(|| {
+            log::debug!("readdir inode={inode} fh={fh} offset={offset}\n");
+            // entries are served from the snapshot taken by opendir so that
+            // updates applied in between two readdir calls don't shift offsets
+            let Some(entries) = self.state.dir_entries(inode, fh) else {
+                reply.error(libc::EBADF);
+                return;
+            };
+
+            for (i, entry) in entries.iter().skip(offset as usize).enumerate() {
+                if reply.add(
+                    entry.inode,
+                    (offset + i as i64 + 1) as i64,
+                    entry.kind,
+                    &entry.name,
+                ) {
+                    break;
+                }
+            }
+            reply.ok();
+            return;
+        });
+        self.record_panic("readdir", outcome);
+    }
+
+    fn releasedir(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        ino: u64,
+        fh: u64,
+        _flags: i32,
+        reply: fuser::ReplyEmpty,
+    ) {
+        let outcome = panics::catch(|| {
+            log::debug!("releasedir ino={ino} fh={fh}\n");
+            self.state.release(fh);
+            reply.ok();
+        });
+        self.record_panic("releasedir", outcome);
+    }
+
+    fn read(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        flags: i32,
+        lock_owner: Option<u64>,
+        reply: fuser::ReplyData,
+    ) {
+        let outcome = panics::catch(|| {
+            log::debug!(
+                "read ino={ino} fh={fh} offset={offset} size={size} flags={flags} lock_owner={:?}\n",
+                lock_owner
+            );
+            if let Some(stream) = self.log_streams.get(&fh) {
+                stream.read(offset as u64, size, reply);
+                return;
+            }
+            let Some(data) = self.state.file_content(ino, fh) else {
+                match self.state.inodes.get(&ino) {
+                    Some(_) => reply.error(libc::EISDIR),
+                    None => reply.error(libc::ENOENT),
+                }
+                return;
+            };
+
+            let start = offset as usize;
+            let end = std::cmp::min(start + size as usize, data.len());
+            if start >= data.len() {
+                reply.data(&[]);
+            } else {
+                reply.data(&data[start..end]);
+            }
+        });
+        self.record_panic("read", outcome);
+    }
+
+    fn getxattr(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        ino: u64,
+        name: &std::ffi::OsStr,
+        size: u32,
+        reply: fuser::ReplyXattr,
+    ) {
+        let outcome = panics::catch(|| {
+            log::debug!("getxattr ino={ino} name={name:?} size={size}\n");
+            let Some(xattrs) = self.node_xattrs(ino) else {
+                reply.error(libc::ENOENT);
+                return;
+            };
+            let Some(value) = name.to_str().and_then(|name| xattrs.get(name)) else {
+                reply.error(libc::ENODATA);
+                return;
+            };
+
+            if size == 0 {
+                reply.size(value.len() as u32);
+            } else if (size as usize) < value.len() {
+                reply.error(libc::ERANGE);
+            } else {
+                reply.data(value);
+            }
+        });
+        self.record_panic("getxattr", outcome);
+    }
+
+    fn listxattr(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        ino: u64,
+        size: u32,
+        reply: fuser::ReplyXattr,
+    ) {
+        let outcome = panics::catch(|| {
+            log::debug!("listxattr ino={ino} size={size}\n");
+            let Some(xattrs) = self.node_xattrs(ino) else {
+                reply.error(libc::ENOENT);
+                return;
+            };
+
+            // NUL-terminated names, one after another
+            let mut names = Vec::new();
+            for name in xattrs.keys() {
+                names.extend_from_slice(name.as_bytes());
+                names.push(0);
+            }
+
+            if size == 0 {
+                reply.size(names.len() as u32);
+            } else if (size as usize) < names.len() {
+                reply.error(libc::ERANGE);
+            } else {
+                reply.data(&names);
+            }
+        });
+        self.record_panic("listxattr", outcome);
+    }
+
+    fn setattr(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        ino: u64,
+        _mode: Option<u32>,
+        _uid: Option<u32>,
+        _gid: Option<u32>,
+        size: Option<u64>,
+        _atime: Option<fuser::TimeOrNow>,
+        _mtime: Option<fuser::TimeOrNow>,
+        _ctime: Option<SystemTime>,
+        fh: Option<u64>,
+        _crtime: Option<SystemTime>,
+        _chgtime: Option<SystemTime>,
+        _bkuptime: Option<SystemTime>,
+        _flags: Option<u32>,
+        reply: fuser::ReplyAttr,
+    ) {
+        let outcome = panics::catch(|| {
+            log::debug!("setattr ino={ino} size={size:?} fh={fh:?}\n");
+            if self.drafts.contains_key(&ino) {
+                // e.g. O_TRUNC of a manifest being edited, reported on the manifest
+                // so the kernel's view of the open file stays consistent
+                let draft_size = match size {
+                    Some(size) => self.dirty_draft(ino).and_then(|draft_inode| {
+                        self.truncate_node_content(draft_inode, size as usize)?;
+                        Some(size)
+                    }),
+                    None => None,
+                };
+                match self.state.inodes.get(&ino) {
+                    Some(node) => {
+                        let mut attrs = node.attrs;
+                        if let Some(size) = draft_size {
+                            attrs.size = size;
+                        }
+                        reply.attr(&TTL, &attrs);
+                    }
+                    None => reply.error(libc::ENOENT),
+                }
+                return;
+            }
+
+            if self.is_scratch_manifest(ino) {
+                if let Some(size) = size {
+                    self.truncate_node_content(ino, size as usize);
+                }
+            } else if Some(ino) != self.pending_inode
+                && Some(ino) != self.paused_inode
+                && Some(ino) != self.snapshot_inode
+                && self.replicas_location(ino).is_none()
+                && self.restart_location(ino).is_none()
+            {
+                // truncating the pending file is part of writing a confirmation to it
+                reply.error(libc::EPERM);
+                return;
+            }
+
+            match self.state.inodes.get(&ino) {
+                Some(node) => reply.attr(&TTL, &node.attrs),
+                None => reply.error(libc::ENOENT),
+            }
+        });
+        self.record_panic("setattr", outcome);
+    }
+
+    fn write(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        data: &[u8],
+        _write_flags: u32,
+        _flags: i32,
+        _lock_owner: Option<u64>,
+        reply: fuser::ReplyWrite,
+    ) {
+        let outcome = panics::catch(|| {
+            log::debug!(
+                "write ino={ino} fh={fh} offset={offset} size={}\n",
+                data.len()
+            );
+            if let Some(draft_inode) = self.dirty_draft(ino) {
+                match self.write_node_content(draft_inode, offset as usize, data) {
+                    Some(()) => reply.written(data.len() as u32),
+                    None => reply.error(libc::EIO),
+                }
+                return;
+            }
+
+            if self.is_scratch_manifest(ino) {
+                match self.write_node_content(ino, offset as usize, data) {
+                    Some(()) => reply.written(data.len() as u32),
+                    None => reply.error(libc::EIO),
+                }
+                return;
+            }
+
+            if Some(ino) == self.snapshot_inode {
+                match self.take_snapshot(String::from_utf8_lossy(data).trim()) {
+                    Ok(()) => reply.written(data.len() as u32),
+                    Err(errno) => reply.error(errno),
+                }
+                return;
+            }
+
+            if Some(ino) == self.paused_inode {
+                match self.write_paused(data) {
+                    Ok(()) => reply.written(data.len() as u32),
+                    Err(errno) => reply.error(errno),
+                }
+                return;
+            }
+
+            if self.restart_location(ino).is_some() {
+                match self.restart_workload(ino) {
+                    Ok(()) => reply.written(data.len() as u32),
+                    Err(errno) => reply.error(errno),
+                }
+                return;
+            }
+
+            if self.replicas_location(ino).is_some() {
+                match self.write_replicas(ino, data) {
+                    Ok(()) => reply.written(data.len() as u32),
+                    Err(errno) => reply.error(errno),
+                }
+                return;
+            }
+
+            if Some(ino) != self.pending_inode {
+                reply.error(libc::EPERM);
+                return;
+            }
+
+            match self.confirm_pending(&String::from_utf8_lossy(data)) {
+                Ok(()) => reply.written(data.len() as u32),
+                Err(errno) => reply.error(errno),
+            }
+        });
+        self.record_panic("write", outcome);
+    }
+
+    fn create(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        parent: u64,
+        name: &std::ffi::OsStr,
+        _mode: u32,
+        _umask: u32,
+        flags: i32,
+        reply: fuser::ReplyCreate,
+    ) {
+        let outcome = panics::catch(|| {
+            log::debug!("create parent={parent} name={name:?} flags={flags}\n");
+            let Some(name) = name
+                .to_str()
+                .and_then(|name| self.state.entry_name(parent, name))
+            else {
+                reply.error(libc::EINVAL);
+                return;
+            };
+
+            let created = if Some(parent) == self.scratch_inode {
+                self.create_scratch_file(&name).ok_or(libc::EEXIST)
+            } else {
+                self.create_manifest(parent, &name)
+            };
+            let inode = match created {
+                Ok(inode) => inode,
+                Err(errno) => {
+                    reply.error(errno);
+                    return;
+                }
+            };
+
+            let fh = self.state.open_file(inode, true).unwrap_or_default();
+            match self.state.inodes.get(&inode) {
+                Some(node) => {
+                    reply.created(&TTL, &node.attrs, 0, fh, 0);
+                    self.state.remember(inode);
+                }
+                None => reply.error(libc::EIO),
+            }
+        });
+        self.record_panic("create", outcome);
+    }
+
+    fn unlink(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        parent: u64,
+        name: &std::ffi::OsStr,
+        reply: fuser::ReplyEmpty,
+    ) {
+        let outcome = panics::catch(|| {
+            log::debug!("unlink parent={parent} name={name:?}\n");
+            let Some(inode) = name
+                .to_str()
+                .and_then(|name| self.state.entry_name(parent, name))
+                .and_then(|name| self.state.child_inode(parent, &name))
+            else {
+                reply.error(libc::ENOENT);
+                return;
+            };
+
+            if self.is_layout_link(parent, inode) && !self.is_shard(parent) {
+                reply.error(libc::EROFS); // objects are deleted from the namespace tree
+                return;
+            }
+            if self.is_scratch_manifest(inode) {
+                self.remove_scratch_file(inode);
+                reply.ok();
+                return;
+            }
+            match self.delete_manifest(inode) {
+                Ok(()) => reply.ok(),
+                Err(errno) => reply.error(errno),
+            }
+        });
+        self.record_panic("unlink", outcome);
+    }
+
+    fn rename(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        parent: u64,
+        name: &std::ffi::OsStr,
+        newparent: u64,
+        newname: &std::ffi::OsStr,
+        _flags: u32,
+        reply: fuser::ReplyEmpty,
+    ) {
+        let outcome = panics::catch(|| {
+            log::debug!(
+                "rename parent={parent} name={name:?} newparent={newparent} newname={newname:?}\n"
+            );
+            let (Some(inode), Some(newname)) = (
+                name.to_str()
+                    .and_then(|name| self.state.entry_name(parent, name))
+                    .and_then(|name| self.state.child_inode(parent, &name)),
+                newname
+                    .to_str()
+                    .and_then(|newname| self.state.entry_name(newparent, newname)),
+            ) else {
+                reply.error(libc::ENOENT);
+                return;
+            };
+
+            if !self.is_scratch_manifest(inode) {
+                reply.error(libc::EPERM);
+                return;
+            }
+
+            // editors save through temporary files renamed over the original
+            if Some(newparent) == self.scratch_inode {
+                self.state.move_node(inode, newparent, &newname);
+                self.validate_scratch_file(inode);
+                reply.ok();
+                return;
+            }
+
+            match self.promote_scratch_file(inode, newparent, &newname) {
+                Ok(()) => reply.ok(),
+                Err(errno) => reply.error(errno),
+            }
+        });
+        self.record_panic("rename", outcome);
+    }
+
+    fn mkdir(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        parent: u64,
+        name: &std::ffi::OsStr,
+        _mode: u32,
+        _umask: u32,
+        reply: fuser::ReplyEntry,
+    ) {
+        let outcome = panics::catch(|| {
+            log::debug!("mkdir parent={parent} name={name:?}\n");
+            self.apply_refresh_updates();
+            let Some(name) = name
+                .to_str()
+                .and_then(|name| self.state.entry_name(parent, name))
+            else {
+                reply.error(libc::EINVAL);
+                return;
+            };
+
+            if parent != ROOT_ATTR.ino {
+                reply.error(libc::EPERM);
+                return;
+            }
+
+            let attrs = self
+                .create_namespace(&name)
+                .and_then(|inode| self.state.inodes.get(&inode).ok_or(libc::EIO))
+                .map(|node| node.attrs);
+            match attrs {
+                Ok(attrs) => {
+                    reply.entry(&TTL, &attrs, 0);
+                    self.state.remember(attrs.ino);
+                }
+                Err(errno) => reply.error(errno),
+            }
+        });
+        self.record_panic("mkdir", outcome);
+    }
+
+    fn rmdir(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        parent: u64,
+        name: &std::ffi::OsStr,
+        reply: fuser::ReplyEmpty,
+    ) {
+        let outcome = panics::catch(|| {
+            log::debug!("rmdir parent={parent} name={name:?}\n");
+            let Some(name) = name
+                .to_str()
+                .and_then(|name| self.state.entry_name(parent, name))
+            else {
+                reply.error(libc::ENOENT);
+                return;
+            };
+
+            if parent != ROOT_ATTR.ino {
+                reply.error(libc::EPERM);
+                return;
+            }
+
+            match self.remove_namespace(&name) {
+                Ok(()) => reply.ok(),
+                Err(errno) => reply.error(errno),
+            }
+        });
+        self.record_panic("rmdir", outcome);
+    }
+
+    fn open(&mut self, _req: &fuser::Request<'_>, ino: u64, flags: i32, reply: fuser::ReplyOpen) {
+        let outcome = panics::catch(|| {
+            log::debug!("open ino={ino} flags={flags}\n");
+            self.apply_refresh_updates();
+            if Some(ino) == self.pending_inode {
+                self.render_pending(); // drop expired actions
+            }
+
+            // manifests listed by metadata only are fetched first, also to be
+            // edited
+            let lazy = match self.fetch_lazy_manifest(ino) {
+                Some(Err(errno)) => {
+                    reply.error(errno);
+                    return;
+                }
+                lazy => lazy.is_some(),
+            };
+
+            let writable = flags & libc::O_ACCMODE != libc::O_RDONLY;
+            if writable
+                && self.is_rendered(ino)
+                && Some(ino) != self.pending_inode
+                && let Err(errno) = self.open_draft(ino)
+            {
+                reply.error(errno);
+                return;
+            }
+            // logs are fetched anew on every open and followed logs grow, read
+            // them regardless of the size the kernel has cached, and so are the
+            // tree hash, orphan and footprint reports rendered anew and the
+            // manifests fetched on open
+            let tree_hash = Some(ino) == self.tree_hash_inode;
+            if tree_hash {
+                self.render_tree_hash();
+            }
+            let orphans = self.orphans_namespace(ino).is_some();
+            if orphans {
+                self.render_orphans(ino);
+            }
+            let footprint = self.footprint_namespace(ino).is_some();
+            if footprint {
+                self.render_footprint(ino);
+            }
+            let open_flags = match self.refresh_log_file(ino) {
+                None if tree_hash || orphans || footprint || lazy => fuser::consts::FOPEN_DIRECT_IO,
+                None => 0,
+                Some(Ok(())) => fuser::consts::FOPEN_DIRECT_IO,
+                Some(Err(errno)) => {
+                    reply.error(errno);
+                    return;
+                }
+            };
+
+            match self.state.inodes.get(&ino).map(|node| node.attrs.kind) {
+                None => reply.error(libc::ENOENT),
+                Some(fuser::FileType::RegularFile) => match self.state.open_file(ino, writable) {
+                    Some(fh) => {
+                        match self.follow_log(ino, fh) {
+                            true => reply.opened(fh, fuser::consts::FOPEN_DIRECT_IO),
+                            false => reply.opened(fh, open_flags),
+                        }
+                        // read-only handles keep what they opened
+                        self.record_read(ino);
+                        if lazy {
+                            self.enforce_memory_budget();
+                        }
+                    }
+                    None => reply.error(libc::EISDIR),
+                },
+                Some(_) => reply.error(libc::EISDIR),
+            }
+        });
+        self.record_panic("open", outcome);
+    }
+
+    fn getlk(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        ino: u64,
+        fh: u64,
+        lock_owner: u64,
+        start: u64,
+        end: u64,
+        typ: i32,
+        pid: u32,
+        reply: fuser::ReplyLock,
+    ) {
+        let outcome = panics::catch(|| {
+            log::debug!(
+                "getlk ino={ino} fh={fh} owner={lock_owner} start={start} end={end} typ={typ}\n"
+            );
+            let lock = FileLock {
+                owner: lock_owner,
+                start,
+                end,
+                typ,
+                pid,
+            };
+            match self.state.conflicting_lock(ino, &lock) {
+                Some(held) => reply.locked(held.start, held.end, held.typ, held.pid),
+                None => reply.locked(start, end, libc::F_UNLCK, 0),
+            }
+        });
+        self.record_panic("getlk", outcome);
+    }
+
+    fn setlk(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        ino: u64,
+        fh: u64,
+        lock_owner: u64,
+        start: u64,
+        end: u64,
+        typ: i32,
+        pid: u32,
+        sleep: bool,
+        reply: fuser::ReplyEmpty,
+    ) {
+        let outcome = panics::catch(|| {
+            log::debug!(
+                "setlk ino={ino} fh={fh} owner={lock_owner} start={start} end={end} typ={typ} sleep={sleep}\n"
+            );
+            let lock = FileLock {
+                owner: lock_owner,
+                start,
+                end,
+                typ,
+                pid,
+            };
+            // waiting for a lock would block every other operation, so blocking
+            // requests fail like non-blocking ones
+            match self.state.set_lock(ino, fh, lock) {
+                Ok(()) => reply.ok(),
+                Err(errno) => reply.error(errno),
+            }
+        });
+        self.record_panic("setlk", outcome);
+    }
+
+    fn flush(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        ino: u64,
+        fh: u64,
+        lock_owner: u64,
+        reply: fuser::ReplyEmpty,
+    ) {
+        let outcome = panics::catch(|| {
+            log::debug!("flush ino={ino} fh={fh} owner={lock_owner}\n");
+            // closing a file releases the POSIX locks the process held on it
+            self.state.release_locks(fh, lock_owner);
+            // applied here rather than on release so that close() reports failures
+            match self.apply_draft(ino) {
+                Ok(()) => reply.ok(),
+                Err(errno) => reply.error(errno),
+            }
+        });
+        self.record_panic("flush", outcome);
+    }
+
+    fn release(
+        &mut self,
+        _req: &fuser::Request<'_>,
+        ino: u64,
+        fh: u64,
+        flags: i32,
         _lock_owner: Option<u64>,
         _flush: bool,
         reply: fuser::ReplyEmpty,
     ) {
-        // should at least release file handles
-        reply.ok();
+        let outcome = panics::catch(|| {
+            self.state.release(fh);
+            if let Some(stream) = self.log_streams.remove(&fh) {
+                stream.release();
+            }
+            if flags & libc::O_ACCMODE != libc::O_RDONLY {
+                if self.is_scratch_manifest(ino) {
+                    self.validate_scratch_file(ino);
+                } else {
+                    self.close_draft(ino);
+                }
+            }
+
+            reply.ok();
+        });
+        self.record_panic("release", outcome);
+    }
+}
+
+#[cfg(test)]
+impl KubeFilesystem {
+    /// A filesystem of a cluster that can't be reached, for tests building
+    /// parts of the tree from objects of their own.
+    pub(crate) fn for_tests(profile: Profile) -> Self {
+        let client = KubeClient::new(
+            "http://127.0.0.1:9",
+            &[],
+            &crate::client::TlsConfig::default(),
+            false,
+            None,
+            HashMap::new(),
+            &crate::client::Impersonation::default(),
+        )
+        .expect("no TLS to set up")
+        .with_retries(crate::client::RetryPolicy {
+            attempts: 1,
+            max_delay: 0,
+        });
+        KubeFilesystem::new(
+            client,
+            profile,
+            None,
+            RefreshIntervals::default(),
+            MutationQuotas::new(Vec::new()),
+            1024 * 1024,
+            PathBuf::from("/kubefuse"),
+        )
+    }
+
+    /// Creates a directory, for tests outside of this module.
+    pub(crate) fn create_test_dir(&mut self, parent_inode: u64, name: &str) -> u64 {
+        self.create_dir_node(parent_inode, name)
+            .expect("the parent is a directory")
     }
 }
@@ -0,0 +1,165 @@
+use std::{
+    collections::VecDeque,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use k8s_openapi::chrono;
+
+use crate::config::HistoryRetention;
+
+use super::{DROPPED_XATTR, KubeFilesystem};
+
+pub const CHANGES_LOG_NAME: &str = "changes.log";
+
+// lines kept per namespace, older ones are dropped first
+const CHANGES_RETENTION: usize = 1000;
+
+/// The lines of the `changes.log` of a namespace, oldest first, along with
+/// when they were recorded.
+#[derive(Debug, Default)]
+pub struct ChangeFeed {
+    lines: VecDeque<(SystemTime, String)>,
+    bytes: usize,
+    // lines dropped under the retention so far
+    dropped: u64,
+}
+
+impl ChangeFeed {
+    fn push(&mut self, time: SystemTime, line: String) {
+        self.bytes += line.len();
+        self.lines.push_back((time, line));
+    }
+
+    /// Drops the oldest lines beyond 1000, beyond `maxBytes` or older than
+    /// `maxAge`, returning whether any were.
+    fn expire(&mut self, retention: &HistoryRetention, now: SystemTime) -> bool {
+        let max_age = Duration::from_secs(retention.max_age);
+        let max_bytes = retention.max_bytes as usize;
+        let before = self.lines.len();
+        while let Some((time, line)) = self.lines.front() {
+            let expired =
+                retention.max_age > 0 && now.duration_since(*time).is_ok_and(|age| age > max_age);
+            if !(expired
+                || self.lines.len() > CHANGES_RETENTION
+                || (max_bytes > 0 && self.bytes > max_bytes))
+            {
+                break;
+            }
+            self.bytes -= line.len();
+            self.lines.pop_front();
+        }
+        self.dropped += (before - self.lines.len()) as u64;
+        self.lines.len() != before
+    }
+}
+
+impl KubeFilesystem {
+    /// Appends a watch event to the `changes.log` feed of a namespace, e.g.
+    /// `2024-05-01T10:00:00Z MODIFIED ConfigMap app-config 4711`.
+    pub(super) fn record_change(
+        &mut self,
+        namespace: &str,
+        verb: &str,
+        kind: &str,
+        name: &str,
+        resource_version: Option<&str>,
+    ) {
+        let now = SystemTime::now();
+        let time = now
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .and_then(|d| chrono::DateTime::from_timestamp(d.as_secs() as i64, 0))
+            .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+            .unwrap_or_default();
+        let line = format!(
+            "{time} {verb} {kind} {name} {}\n",
+            resource_version.unwrap_or("-")
+        );
+
+        let changes = self.changes.entry(namespace.to_string()).or_default();
+        changes.push(now, line);
+        changes.expire(&self.history_retention, now);
+        self.render_changes(namespace);
+    }
+
+    /// Drops the lines of every `changes.log` that expired since, for the
+    /// namespaces that saw no change in a while.
+    pub(super) fn expire_changes(&mut self) {
+        let now = SystemTime::now();
+        let expired: Vec<String> = self
+            .changes
+            .iter_mut()
+            .filter_map(|(namespace, changes)| {
+                changes
+                    .expire(&self.history_retention, now)
+                    .then(|| namespace.clone())
+            })
+            .collect();
+        for namespace in expired {
+            self.render_changes(&namespace);
+        }
+    }
+
+    /// Creates or updates `<namespace>/changes.log` from the recorded changes.
+    /// It carries `user.kubefuse.dropped`, the number of lines dropped under
+    /// the retention, once there are any.
+    pub(super) fn render_changes(&mut self, namespace: &str) {
+        let Some(ns_inode) = self.namespace_inode(namespace) else {
+            return;
+        };
+        let (content, dropped): (Vec<u8>, u64) = self
+            .changes
+            .get(namespace)
+            .map(|changes| {
+                let lines = changes.lines.iter().flat_map(|(_, line)| line.bytes());
+                (lines.collect(), changes.dropped)
+            })
+            .unwrap_or_default();
+
+        let inode = match self.state.child_inode(ns_inode, CHANGES_LOG_NAME) {
+            Some(inode) => {
+                self.update_node_content(inode, content);
+                inode
+            }
+            None => {
+                let Some(inode) = self.create_content_node(
+                    ns_inode,
+                    CHANGES_LOG_NAME,
+                    content,
+                    SystemTime::now(),
+                ) else {
+                    return;
+                };
+                inode
+            }
+        };
+        if let Some(node) = self.state.inodes.get_mut(&inode).filter(|_| dropped > 0) {
+            node.xattrs
+                .insert(DROPPED_XATTR.to_string(), dropped.to_string().into_bytes());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn expires_lines_beyond_the_retention() {
+        let start = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let mut changes = ChangeFeed::default();
+        for i in 0..4 {
+            changes.push(start + Duration::from_secs(i * 60), format!("line {i}\n"));
+        }
+        let retention = HistoryRetention {
+            max_age: 150,
+            max_bytes: 14,
+        };
+        // the first line is too old, the second one too many bytes
+        assert!(changes.expire(&retention, start + Duration::from_secs(200)));
+        let lines: Vec<&str> = changes.lines.iter().map(|(_, l)| l.as_str()).collect();
+        assert_eq!(lines, ["line 2\n", "line 3\n"]);
+        assert_eq!(changes.dropped, 2);
+        assert!(!changes.expire(&retention, start + Duration::from_secs(200)));
+    }
+}
@@ -0,0 +1,101 @@
+use k8s_openapi::{
+    Resource,
+    api::{
+        core::v1::{Node, PersistentVolume},
+        rbac::v1::{ClusterRole, ClusterRoleBinding},
+        storage::v1::StorageClass,
+    },
+    apimachinery::pkg::apis::meta::v1::ObjectMeta,
+};
+
+use crate::client::ListParams;
+
+use super::{KubeFilesystem, refresh::WatchChange};
+
+/// Root directory holding the resources that don't belong to a namespace.
+pub const CLUSTER_DIR_NAME: &str = "_cluster";
+
+impl KubeFilesystem {
+    /// Creates `_cluster/` with a directory for each of the presented
+    /// cluster-scoped resource types, all listed with the given parameters.
+    pub(super) fn populate_cluster(&mut self, root_inode: u64, params: &ListParams) {
+        let Some(cluster_inode) = self.create_dir_node(root_inode, CLUSTER_DIR_NAME) else {
+            return;
+        };
+
+        if self.profile.shows_resource("nodes") {
+            let mut nodes = Vec::new();
+            let nodes_inode =
+                self.create_manifests_node_with::<Node>(cluster_inode, None, params, |page| {
+                    if page.relisted {
+                        nodes.clear();
+                    }
+                    nodes.extend(
+                        page.list
+                            .items
+                            .iter()
+                            .filter_map(|node| serde_json::to_value(node).ok()),
+                    );
+                });
+            if let Some(nodes_inode) = nodes_inode {
+                self.sync_node_pressure(&nodes);
+                self.create_node_pressure_node(nodes_inode);
+            }
+        }
+        if self.profile.shows_resource("persistentvolumes") {
+            self.create_manifests_node::<PersistentVolume>(cluster_inode, None, params);
+        }
+        if self.profile.shows_resource("clusterroles") {
+            self.create_manifests_node::<ClusterRole>(cluster_inode, None, params);
+        }
+        if self.profile.shows_resource("clusterrolebindings") {
+            self.create_manifests_node::<ClusterRoleBinding>(cluster_inode, None, params);
+        }
+        if self.profile.shows_resource("storageclasses") {
+            self.create_manifests_node::<StorageClass>(cluster_inode, None, params);
+        }
+    }
+
+    fn cluster_manifests_inode<T: k8s_openapi::Resource>(&mut self) -> Option<u64> {
+        let cluster_inode = self
+            .state
+            .child_inode(super::ROOT_ATTR.ino, CLUSTER_DIR_NAME)?;
+        self.state
+            .child_inode(cluster_inode, T::URL_PATH_SEGMENT)
+            .or_else(|| self.create_dir_node(cluster_inode, T::URL_PATH_SEGMENT))
+    }
+
+    /// Replaces the manifests of a cluster-scoped resource type with the
+    /// listed objects.
+    pub(super) fn sync_cluster_manifests<T>(&mut self, objects: Vec<T>)
+    where
+        T: k8s_openapi::Resource + k8s_openapi::Metadata<Ty = ObjectMeta> + serde::Serialize,
+    {
+        if T::KIND == Node::KIND {
+            let nodes: Vec<_> = objects
+                .iter()
+                .filter_map(|node| serde_json::to_value(node).ok())
+                .collect();
+            self.sync_node_pressure(&nodes);
+        }
+        if let Some(manifests_inode) = self.cluster_manifests_inode::<T>() {
+            self.sync_manifest_files(manifests_inode, &objects);
+        }
+    }
+
+    /// Applies a change of a single cluster-scoped object to its manifest.
+    pub(super) fn watch_cluster_manifests<T>(&mut self, change: WatchChange<T>)
+    where
+        T: k8s_openapi::Resource + k8s_openapi::Metadata<Ty = ObjectMeta> + serde::Serialize,
+    {
+        if T::KIND == Node::KIND {
+            let (WatchChange::Applied(node) | WatchChange::Deleted(node)) = &change;
+            if let Ok(node) = serde_json::to_value(node) {
+                self.watch_node_pressure(&node, matches!(change, WatchChange::Deleted(_)));
+            }
+        }
+        if let Some(manifests_inode) = self.cluster_manifests_inode::<T>() {
+            self.apply_manifest_change(manifests_inode, change);
+        }
+    }
+}
@@ -0,0 +1,112 @@
+use std::{
+    collections::HashSet,
+    sync::mpsc::Sender,
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use super::{KubeFilesystem, refresh, refresh::Update};
+
+// how often the tables are compacted
+const COMPACTION_INTERVAL: Duration = Duration::from_secs(10 * 60);
+
+/// Compactions run so far, reported in `.kubefuse/stats`.
+#[derive(Debug, Default)]
+pub struct CompactionStats {
+    pub runs: u64,
+    pub last_run: Option<SystemTime>,
+    pub reclaimed_nodes: u64,
+    pub reclaimed_bytes: u64,
+}
+
+impl CompactionStats {
+    pub fn render(&self) -> String {
+        let last_run = self
+            .last_run
+            .and_then(refresh::format_time)
+            .unwrap_or_else(|| "never".to_string());
+        let mut out = String::from("compaction:\n");
+        out.push_str(&format!("  runs: {}\n", self.runs));
+        out.push_str(&format!("  lastRun: {last_run}\n"));
+        out.push_str(&format!("  reclaimedNodes: {}\n", self.reclaimed_nodes));
+        out.push_str(&format!("  reclaimedBytes: {}\n", self.reclaimed_bytes));
+        out
+    }
+}
+
+/// Starts a thread asking for a compaction every [`COMPACTION_INTERVAL`]. The
+/// thread exits once the filesystem stops receiving updates, i.e. after
+/// unmount.
+pub fn spawn_compactor(updates: Sender<Update>) {
+    let spawned = thread::Builder::new()
+        .name("compaction".to_string())
+        .spawn(move || {
+            loop {
+                thread::sleep(COMPACTION_INTERVAL);
+                if updates.send(Box::new(KubeFilesystem::compact)).is_err() {
+                    return;
+                }
+            }
+        });
+
+    if let Err(e) = spawned {
+        log::error!("failed to start the compaction: {e}");
+    }
+}
+
+impl KubeFilesystem {
+    /// Drops what a long-running mount accumulates without anything pointing
+    /// to it anymore: nodes created on lookup below removed nodes, and state
+    /// kept for manifests or namespaces that are gone, and changes past their
+    /// retention. Then compacts the inode table, see
+    /// [`super::state::FsState::compact`].
+    pub(super) fn compact(&mut self) {
+        // nodes created on lookup aren't listed, so they aren't removed with
+        // their parent
+        let state = &mut self.state;
+        self.hidden_inodes.retain(|(parent, _), inode| {
+            let keep = state.inodes.contains_key(parent);
+            if !keep {
+                state.remove_node(*inode);
+            }
+            keep
+        });
+        let reclaimed = self.state.compact(self.hidden_inodes.values().copied());
+
+        let inodes = &self.state.inodes;
+        self.drafts.retain(|manifest, draft| {
+            inodes.contains_key(manifest) || inodes.contains_key(&draft.inode)
+        });
+        self.lazy_manifests
+            .retain(|inode, _| inodes.contains_key(inode));
+        self.last_read.retain(|inode, _| inodes.contains_key(inode));
+        let namespaces: HashSet<String> = self.namespace_names().into_iter().collect();
+        self.events
+            .retain(|namespace, _| namespaces.contains(namespace));
+        self.endpoint_slices
+            .retain(|namespace, _| namespaces.contains(namespace));
+        self.changes
+            .retain(|namespace, _| namespaces.contains(namespace));
+        // quiet namespaces don't expire their changes on their own
+        self.expire_changes();
+
+        self.hidden_inodes.shrink_to_fit();
+        self.drafts.shrink_to_fit();
+        self.lazy_manifests.shrink_to_fit();
+        self.last_read.shrink_to_fit();
+        self.events.shrink_to_fit();
+        self.endpoint_slices.shrink_to_fit();
+        self.changes.shrink_to_fit();
+
+        log::debug!(
+            "compaction reclaimed {} nodes, {} bytes",
+            reclaimed.nodes,
+            reclaimed.bytes
+        );
+        self.compaction.runs += 1;
+        self.compaction.last_run = Some(SystemTime::now());
+        self.compaction.reclaimed_nodes += reclaimed.nodes;
+        self.compaction.reclaimed_bytes += reclaimed.bytes;
+        self.render_stats();
+    }
+}
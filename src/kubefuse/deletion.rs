@@ -0,0 +1,56 @@
+use crate::guardrail::DestructiveAction;
+
+use super::{KubeFilesystem, NodeContent, request_errno};
+
+impl KubeFilesystem {
+    /// Deletes the object presented by a manifest from the cluster and drops
+    /// the manifest along with its data keys. Namespaces are only deleted
+    /// through `rmdir`, which asks for confirmation, as does deleting a Node.
+    pub(super) fn delete_manifest(&mut self, inode: u64) -> Result<(), libc::c_int> {
+        let location = self.manifest_location(inode).ok_or(libc::EPERM)?;
+        if self.resource_version.is_some() || self.is_terminating(&location) {
+            return Err(libc::EROFS); // pinned to the past or being deleted
+        }
+        if !self.profile.enable_delete || location.resource == "namespaces" {
+            return Err(libc::EPERM);
+        }
+        if location.resource == "nodes" && location.namespace.is_none() {
+            self.request_confirmation(DestructiveAction::DeleteNode {
+                path: self.state.node_path(inode),
+                name: location.name,
+            });
+            return Err(libc::EPERM);
+        }
+        let node = self.state.inodes.get(&inode).ok_or(libc::ENOENT)?;
+        if !matches!(node.content, NodeContent::Bytes(_)) {
+            return Err(libc::EISDIR);
+        }
+        // from the directory rather than the manifest, which may be presented
+        // from metadata only, evicted or truncated
+        let Some(api_resource) = self.api_resource_of(&location.resource) else {
+            log::error!("failed to find the resource type of {}", node.name);
+            return Err(libc::EIO);
+        };
+        let parent = node.parent;
+
+        let path = self.state.node_path(inode);
+        if !self.mutation_quotas.allow(&path) {
+            log::error!("refusing to delete {path}: mutation limit exceeded");
+            return Err(libc::EAGAIN);
+        }
+        if let Err(e) = self.kube_client.delete_unstructured(
+            &api_resource,
+            location.namespace.as_deref(),
+            &location.name,
+        ) {
+            log::error!("failed to delete {path}: {e}");
+            return Err(request_errno(e.status()));
+        }
+
+        log::info!("deleted {path}");
+        self.discard_draft(inode);
+        self.state.remove_node(inode);
+        self.remove_key_dir(parent, &location.name);
+        Ok(())
+    }
+}
@@ -0,0 +1,308 @@
+use std::time::SystemTime;
+
+use serde_yaml::Value;
+
+use crate::{client::ApplyParams, guardrail::DestructiveAction};
+
+use super::{
+    CLUSTER_DIR_NAME, CONTROL_DIR_NAME, KubeFilesystem, NodeContent, ROOT_ATTR, TRUNCATED_XATTR,
+    request_errno, scratch::manifest_object,
+};
+
+// appended to a manifest name for the sibling holding the edited buffer
+pub const DRAFT_SUFFIX: &str = ".draft";
+
+// set on a draft whose last apply was rejected, holds the reason
+const APPLY_ERROR_XATTR: &str = "user.kubefuse.apply-error";
+
+/// Identifies the object a manifest file presents.
+pub struct ManifestLocation {
+    pub namespace: Option<String>,
+    /// Resource directory, e.g. `configmaps`.
+    pub resource: String,
+    pub name: String,
+}
+
+/// A manifest opened for writing. Writes go to the draft until the edit is
+/// applied to the cluster.
+pub struct Draft {
+    pub inode: u64,
+    // written to since the last apply
+    dirty: bool,
+}
+
+/// Drops fields the API server refuses in apply requests, which are part of
+/// the rendered manifest a draft starts from.
+fn prepare_for_apply(manifest: &[u8]) -> Result<Vec<u8>, String> {
+    let mut value: Value =
+        serde_yaml::from_slice(manifest).map_err(|e| format!("invalid manifest: {e}"))?;
+    if let Some(metadata) = value.get_mut("metadata").and_then(Value::as_mapping_mut) {
+        metadata.remove("managedFields");
+    }
+    serde_yaml::to_string(&value)
+        .map(String::into_bytes)
+        .map_err(|e| format!("invalid manifest: {e}"))
+}
+
+impl KubeFilesystem {
+    /// Where a manifest presented from the cluster is, e.g.
+    /// `<namespace>/configmaps/foo.yaml`, the `<namespace>/manifest.yaml` of a
+    /// namespace itself or `_cluster/nodes/foo.yaml`.
+    pub(super) fn manifest_location(&self, inode: u64) -> Option<ManifestLocation> {
+        let node = self.state.inodes.get(&inode)?;
+        if node.name.ends_with(DRAFT_SUFFIX)
+            || self.json_copies.contains_key(&inode)
+            || Some(inode) == self.node_pressure_inode
+        {
+            return None;
+        }
+        let dir_node = self.state.inodes.get(&node.parent)?;
+        if self.namespace_inode(&dir_node.name) == Some(node.parent) {
+            return (node.name == self.manifest_file_name("manifest")).then(|| ManifestLocation {
+                namespace: None,
+                resource: "namespaces".to_string(),
+                name: dir_node.name.clone(),
+            });
+        }
+
+        let name = self.profile.manifest_object_name(&node.name)?.to_string();
+        let resource = dir_node.name.clone();
+        if self.state.child_inode(ROOT_ATTR.ino, CLUSTER_DIR_NAME) == Some(dir_node.parent) {
+            return Some(ManifestLocation {
+                namespace: None,
+                resource,
+                name,
+            });
+        }
+        let ns_node = self.state.inodes.get(&dir_node.parent)?;
+        if self.namespace_inode(&ns_node.name) != Some(dir_node.parent) {
+            return None;
+        }
+        Some(ManifestLocation {
+            namespace: Some(ns_node.name.clone()),
+            resource,
+            name,
+        })
+    }
+
+    /// Starts editing a manifest: materializes `<manifest>.draft` next to it
+    /// with the current content. The live file is only replaced once the
+    /// API server accepts the edit.
+    pub(super) fn open_draft(&mut self, inode: u64) -> Result<(), libc::c_int> {
+        let location = self.manifest_location(inode).ok_or(libc::EPERM)?;
+        if self.resource_version.is_some() || self.is_terminating(&location) {
+            return Err(libc::EROFS); // pinned to the past or being deleted
+        }
+        if location.resource == "secrets" && self.profile.redact_secrets {
+            return Err(libc::EACCES); // the values would be applied redacted
+        }
+        let node = self.state.inodes.get(&inode).ok_or(libc::ENOENT)?;
+        if node.xattrs.contains_key(TRUNCATED_XATTR) {
+            return Err(libc::EFBIG);
+        }
+        let NodeContent::Bytes(content) = &node.content else {
+            return Err(libc::EISDIR);
+        };
+        if let Some(draft) = self.drafts.get(&inode)
+            && self.state.inodes.contains_key(&draft.inode)
+        {
+            return Ok(()); // already being edited through another handle
+        }
+
+        // a draft left behind by a failed apply is restarted from the live
+        // content, like the file the editor opened
+        let (parent, content) = (node.parent, content.to_vec());
+        let draft_name = format!("{}{DRAFT_SUFFIX}", node.name);
+        let draft_inode = match self.state.child_inode(parent, &draft_name) {
+            Some(draft_inode) => {
+                self.set_node_content(draft_inode, content);
+                draft_inode
+            }
+            None => self
+                .create_content_node(parent, &draft_name, content, SystemTime::now())
+                .ok_or(libc::EIO)?,
+        };
+        if let Some(draft_node) = self.state.inodes.get_mut(&draft_inode) {
+            draft_node.attrs.perm = 0o644;
+            draft_node.xattrs.remove(APPLY_ERROR_XATTR);
+        }
+
+        self.drafts.insert(
+            inode,
+            Draft {
+                inode: draft_inode,
+                dirty: false,
+            },
+        );
+        Ok(())
+    }
+
+    /// Creates an empty manifest in a resource directory, e.g. for
+    /// `cp app-config.yaml <namespace>/configmaps/`. What is written to it is
+    /// applied when it's closed, like an edit, which creates the object.
+    pub(super) fn create_manifest(&mut self, parent: u64, name: &str) -> Result<u64, libc::c_int> {
+        if self.state.child_inode(parent, name).is_some() {
+            return Err(libc::EEXIST);
+        }
+        if name.ends_with(DRAFT_SUFFIX) {
+            return Err(libc::EPERM);
+        }
+
+        let inode = self
+            .create_content_node(parent, name, Vec::new(), SystemTime::now())
+            .ok_or(libc::EIO)?;
+        if let Err(errno) = self.open_draft(inode) {
+            self.state.remove_node(inode);
+            return Err(errno);
+        }
+        // applied even if nothing gets written, to report the empty manifest
+        self.dirty_draft(inode);
+        Ok(inode)
+    }
+
+    /// The draft writes to a manifest are redirected to, marking it as
+    /// needing an apply.
+    pub(super) fn dirty_draft(&mut self, inode: u64) -> Option<u64> {
+        let draft = self.drafts.get_mut(&inode)?;
+        draft.dirty = true;
+        Some(draft.inode)
+    }
+
+    /// Applies the draft of a manifest if it changed. On success the draft is
+    /// removed and its content replaces the live file, otherwise the draft is
+    /// kept with the reason in its `user.kubefuse.apply-error` xattr.
+    pub(super) fn apply_draft(&mut self, inode: u64) -> Result<(), libc::c_int> {
+        let Some(draft) = self.drafts.get_mut(&inode).filter(|draft| draft.dirty) else {
+            return Ok(());
+        };
+        draft.dirty = false;
+        let draft_inode = draft.inode;
+
+        let (Some(location), Some(draft_node)) = (
+            self.manifest_location(inode),
+            self.state.inodes.get(&draft_inode),
+        ) else {
+            return Err(libc::ENOENT);
+        };
+        let NodeContent::Bytes(manifest) = &draft_node.content else {
+            return Err(libc::EISDIR);
+        };
+        let manifest = manifest.to_vec();
+        let path = self.state.node_path(inode);
+
+        match self.apply_manifest(inode, draft_inode, &path, &location, &manifest) {
+            Ok(()) => {
+                self.drafts.remove(&inode);
+                self.state.remove_node(draft_inode);
+                // replaced by the server's rendering of the object on the next refresh
+                self.set_node_content(inode, manifest);
+                Ok(())
+            }
+            Err((errno, reason)) => {
+                log::error!("failed to apply {path}: {reason}, the edit is kept in its draft");
+                if let Some(draft_node) = self.state.inodes.get_mut(&draft_inode) {
+                    draft_node
+                        .xattrs
+                        .insert(APPLY_ERROR_XATTR.to_string(), reason.into_bytes());
+                }
+                // a manifest created for a new object only exists once it's applied
+                if let Some(NodeContent::Bytes(live)) =
+                    self.state.inodes.get(&inode).map(|node| &node.content)
+                    && live.is_empty()
+                {
+                    self.state.remove_node(inode);
+                }
+                Err(errno)
+            }
+        }
+    }
+
+    fn apply_manifest(
+        &mut self,
+        inode: u64,
+        draft_inode: u64,
+        path: &str,
+        location: &ManifestLocation,
+        manifest: &[u8],
+    ) -> Result<(), (libc::c_int, String)> {
+        let object = manifest_object(manifest, location.namespace.as_deref())
+            .map_err(|e| (libc::EINVAL, e))?;
+        if object.plural != location.resource || object.name != location.name {
+            return Err((
+                libc::EINVAL,
+                format!("the manifest describes {}/{}", object.plural, object.name),
+            ));
+        }
+        let manifest = prepare_for_apply(manifest).map_err(|e| (libc::EINVAL, e))?;
+
+        if object.plural == "secrets" && location.namespace.is_some() {
+            self.request_confirmation(DestructiveAction::ApplySecret {
+                path: path.to_string(),
+                inode,
+                draft: draft_inode,
+                object,
+                manifest,
+            });
+            return Err((
+                libc::EPERM,
+                format!("confirm the edit through {CONTROL_DIR_NAME}/pending"),
+            ));
+        }
+        if !self.mutation_quotas.allow(path) {
+            return Err((libc::EAGAIN, "mutation limit exceeded".to_string()));
+        }
+        // the whole object was edited, so the edit wins over other managers
+        let params = ApplyParams {
+            force: true,
+            ..Default::default()
+        };
+        self.kube_client
+            .apply(&object, &manifest, &params)
+            .map_err(|e| (request_errno(e.status()), e.to_string()))
+    }
+
+    /// Drops the draft of a manifest, including one kept after failing to
+    /// apply.
+    pub(super) fn discard_draft(&mut self, inode: u64) {
+        self.drafts.remove(&inode);
+        let Some(node) = self.state.inodes.get(&inode) else {
+            return;
+        };
+        let draft_name = format!("{}{DRAFT_SUFFIX}", node.name);
+        if let Some(draft_inode) = self.state.child_inode(node.parent, &draft_name) {
+            self.state.remove_node(draft_inode);
+        }
+    }
+
+    /// Drops a draft by its own inode, e.g. that of a new object, whose
+    /// manifest is gone until the object is applied and listed.
+    pub(super) fn remove_draft_node(&mut self, draft_inode: u64) {
+        if self
+            .state
+            .inodes
+            .get(&draft_inode)
+            .is_some_and(|node| node.name.ends_with(DRAFT_SUFFIX))
+        {
+            self.state.remove_node(draft_inode);
+        }
+    }
+
+    /// Stops editing a manifest once its last writable handle is closed,
+    /// dropping the draft unless it holds an edit that failed to apply.
+    pub(super) fn close_draft(&mut self, inode: u64) {
+        if self.state.has_writers(inode) {
+            return;
+        }
+        let Some(draft) = self.drafts.remove(&inode) else {
+            return;
+        };
+        let failed = self
+            .state
+            .inodes
+            .get(&draft.inode)
+            .is_some_and(|node| node.xattrs.contains_key(APPLY_ERROR_XATTR));
+        if !failed {
+            self.state.remove_node(draft.inode);
+        }
+    }
+}
@@ -0,0 +1,163 @@
+use std::sync::Arc;
+
+use super::{BLOCK_SIZE, KubeFilesystem, NodeContent, metadata_only::LazyManifest};
+
+impl KubeFilesystem {
+    /// Records that a file was opened, for the eviction of the least recently
+    /// read ones.
+    pub(super) fn record_read(&mut self, inode: u64) {
+        if self.memory_budget == 0 {
+            return;
+        }
+        self.read_clock += 1;
+        self.last_read.insert(inode, self.read_clock);
+    }
+
+    /// Keeps the size of an evicted manifest up to date with what a refresh
+    /// got, without keeping the content in memory. Returns whether it was
+    /// evicted.
+    pub(super) fn update_evicted(&mut self, inode: u64, manifest: &[u8]) -> bool {
+        if !self
+            .lazy_manifests
+            .get(&inode)
+            .is_some_and(LazyManifest::is_evicted)
+        {
+            return false;
+        }
+        if let Some(node) = self.state.inodes.get_mut(&inode) {
+            node.attrs.size = manifest.len() as u64;
+            node.attrs.blocks = node.attrs.size.div_ceil(u64::from(BLOCK_SIZE));
+        }
+        true
+    }
+
+    /// Evicts the content of the least recently read manifests from memory
+    /// for as long as the files hold more than `--memory-budget` bytes. Their
+    /// attributes stay, and their content is fetched again once opened.
+    /// Manifests being edited are never evicted.
+    pub(super) fn enforce_memory_budget(&mut self) {
+        if self.memory_budget == 0 {
+            return;
+        }
+        let mut used: u64 = self
+            .state
+            .inodes
+            .values()
+            .map(|node| match &node.content {
+                NodeContent::Bytes(content) => content.len() as u64,
+                NodeContent::Children(_) => 0,
+            })
+            .sum();
+        if used <= self.memory_budget {
+            return;
+        }
+
+        let mut candidates: Vec<_> = self
+            .presented_manifests()
+            .into_iter()
+            .filter(|(_, inode)| !self.drafts.contains_key(inode))
+            .map(|(location, inode)| {
+                let last_read = self.last_read.get(&inode).copied().unwrap_or_default();
+                (last_read, inode, location)
+            })
+            .collect();
+        candidates.sort_by_key(|(last_read, inode, _)| (*last_read, *inode));
+
+        let mut evicted = 0;
+        for (_, inode, location) in candidates {
+            if used <= self.memory_budget {
+                break;
+            }
+            let Some(api_resource) = self.api_resource_of(&location.resource) else {
+                continue; // can't be fetched again
+            };
+            let Some(NodeContent::Bytes(content)) = self
+                .state
+                .inodes
+                .get_mut(&inode)
+                .map(|node| &mut node.content)
+            else {
+                continue;
+            };
+            if content.is_empty() {
+                continue;
+            }
+            used -= content.len() as u64;
+            // the size stays as it was
+            *content = Arc::new(Vec::new());
+            evicted += 1;
+            match self.lazy_manifests.get_mut(&inode) {
+                Some(lazy) => lazy.forget_content(),
+                None => {
+                    let lazy =
+                        LazyManifest::evicted(api_resource, location.namespace, location.name);
+                    self.lazy_manifests.insert(inode, lazy);
+                }
+            }
+        }
+        log::debug!("evicted {evicted} manifests, {used} bytes left");
+        self.evictions += evicted;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use crate::{
+        config::{ManifestFormat, Profile},
+        kubefuse::{ROOT_ATTR, tree_hash::TREE_HASH_NAME},
+    };
+
+    use super::*;
+
+    fn content(fs: &KubeFilesystem, inode: u64) -> Vec<u8> {
+        match &fs.state.inodes[&inode].content {
+            NodeContent::Bytes(content) => content.to_vec(),
+            NodeContent::Children(_) => panic!("not a file"),
+        }
+    }
+
+    #[test]
+    fn evicted_manifests_keep_their_hash_and_json_copy() {
+        let mut fs = KubeFilesystem::for_tests(Profile {
+            manifest_format: ManifestFormat::Both,
+            ..Profile::default()
+        });
+        let control_inode = fs.create_control_dir(ROOT_ATTR.ino).unwrap();
+        let ns_inode = fs.create_dir_node(ROOT_ATTR.ino, "team-a").unwrap();
+        let dir_inode = fs.create_dir_node(ns_inode, "configmaps").unwrap();
+        let manifest =
+            "apiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: app\ndata:\n  port: '80'\n";
+        let manifest_inode = fs
+            .create_content_node(
+                dir_inode,
+                "app.yaml",
+                manifest.as_bytes().to_vec(),
+                SystemTime::now(),
+            )
+            .unwrap();
+
+        fs.sync_json_copies();
+        fs.render_tree_hash();
+        let tree_hash_inode = fs.state.child_inode(control_inode, TREE_HASH_NAME).unwrap();
+        let copy_inode = fs.state.child_inode(dir_inode, "app.json").unwrap();
+        let (tree_hash, copy) = (content(&fs, tree_hash_inode), content(&fs, copy_inode));
+        assert!(String::from_utf8_lossy(&copy).contains("\"port\": \"80\""));
+
+        fs.memory_budget = 1;
+        fs.enforce_memory_budget();
+        assert!(content(&fs, manifest_inode).is_empty());
+        assert_eq!(fs.evictions, 1);
+
+        // the API server can't be reached to fetch it again
+        fs.sync_json_copies();
+        fs.render_tree_hash();
+        assert_eq!(content(&fs, tree_hash_inode), tree_hash);
+        assert_eq!(
+            fs.state.child_inode(dir_inode, "app.json"),
+            Some(copy_inode)
+        );
+        assert_eq!(content(&fs, copy_inode), copy);
+    }
+}
@@ -0,0 +1,271 @@
+use std::time::SystemTime;
+
+use k8s_openapi::ByteString;
+use serde_yaml::{Mapping, Value};
+
+use super::{KubeFilesystem, NodeContent};
+
+// appended to a manifest name to browse the fields of the object it describes
+pub const FIELDS_SUFFIX: char = '#';
+
+/// Escapes a mapping key into a path component the way JSON pointers do, so
+/// that e.g. annotation keys containing `/` remain reachable.
+fn escape_key(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// Renders a scalar field: strings are served verbatim so that embedded files
+/// like `data/config.ini` can be consumed directly, anything else as YAML.
+fn render_field(value: &Value) -> Vec<u8> {
+    match value {
+        Value::String(s) => s.clone().into_bytes(),
+        Value::Null => Vec::new(),
+        other => serde_yaml::to_string(other)
+            .unwrap_or_default()
+            .into_bytes(),
+    }
+}
+
+impl KubeFilesystem {
+    /// Materializes `<manifest>#`, a hidden directory mirroring the fields of
+    /// the object in `<manifest>` so that e.g. `foo.yaml#/data/config.ini`
+    /// reads a single field. Mappings and sequences become directories,
+    /// scalars become files. The tree is rebuilt on every lookup.
+    pub(super) fn create_fields_node(&mut self, parent_inode: u64, name: &str) {
+        let Some(manifest_name) = name.strip_suffix(FIELDS_SUFFIX) else {
+            return;
+        };
+        let Some(manifest_inode) = self.state.child_inode(parent_inode, manifest_name) else {
+            return;
+        };
+        // manifests listed by metadata only are empty until fetched
+        if let Some(Err(errno)) = self.fetch_lazy_manifest(manifest_inode) {
+            log::error!("failed to fetch {manifest_name} for field lookup: errno {errno}");
+            return;
+        }
+        let Some(NodeContent::Bytes(manifest)) = self
+            .state
+            .inodes
+            .get(&manifest_inode)
+            .map(|node| &node.content)
+        else {
+            return;
+        };
+
+        let mut value: Value = match serde_yaml::from_slice(manifest) {
+            Ok(value) => value,
+            Err(e) => {
+                log::error!("failed to parse {manifest_name} for field lookup: {e}");
+                return;
+            }
+        };
+
+        // base64 in the manifest, but the keys hold arbitrary bytes
+        let is_config_map = value["kind"].as_str() == Some("ConfigMap");
+        let binary_data = match value.as_mapping_mut() {
+            Some(object) if is_config_map => object.remove("binaryData"),
+            _ => None,
+        };
+
+        let Some(fields_inode) = self.create_field_node(parent_inode, name, &value) else {
+            return;
+        };
+        if let Some(Value::Mapping(binary_data)) = binary_data {
+            self.create_binary_data_node(fields_inode, &binary_data);
+        }
+        // not listed in the parent directory, only reachable by its name
+        self.hide_node(parent_inode, name, fields_inode);
+    }
+
+    /// Creates `binaryData/` with a file per key holding the decoded bytes.
+    fn create_binary_data_node(&mut self, fields_inode: u64, binary_data: &Mapping) {
+        let Some(dir_inode) = self.create_dir_node(fields_inode, "binaryData") else {
+            return;
+        };
+        for (key, encoded) in binary_data {
+            let Some(key) = key.as_str() else {
+                continue;
+            };
+            let content = match serde_yaml::from_value::<ByteString>(encoded.clone()) {
+                Ok(ByteString(bytes)) => bytes,
+                Err(e) => {
+                    log::warn!("binaryData key {key} is not valid base64: {e}");
+                    render_field(encoded)
+                }
+            };
+            self.create_content_node(dir_inode, &escape_key(key), content, SystemTime::now());
+        }
+    }
+
+    fn create_field_node(&mut self, parent_inode: u64, name: &str, value: &Value) -> Option<u64> {
+        match value {
+            Value::Mapping(mapping) => {
+                let inode = self.create_dir_node(parent_inode, name)?;
+                for (key, child) in mapping {
+                    let key = match key {
+                        Value::String(s) => escape_key(s),
+                        other => {
+                            escape_key(serde_yaml::to_string(other).unwrap_or_default().trim_end())
+                        }
+                    };
+                    self.create_field_node(inode, &key, child);
+                }
+                Some(inode)
+            }
+            Value::Sequence(sequence) => {
+                let inode = self.create_dir_node(parent_inode, name)?;
+                for (i, child) in sequence.iter().enumerate() {
+                    self.create_field_node(inode, &i.to_string(), child);
+                }
+                Some(inode)
+            }
+            Value::Tagged(tagged) => self.create_field_node(parent_inode, name, &tagged.value),
+            scalar => self.create_content_node(
+                parent_inode,
+                name,
+                render_field(scalar),
+                SystemTime::now(),
+            ),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        client::ApiResource,
+        config::Profile,
+        kubefuse::{Entry, ROOT_ATTR, metadata_only::LazyManifest},
+    };
+
+    use super::*;
+
+    const MANIFEST: &str = "\
+apiVersion: v1
+kind: ConfigMap
+metadata:
+  name: app
+  annotations:
+    example.com/owner~team: shop
+data:
+  config.ini: |
+    [server]
+    port = 80
+  ports:
+  - 80
+  - 443
+  replicas: !Scaled 3
+binaryData:
+  logo.png: iVBORw==
+";
+
+    // a filesystem with `configs/app.yaml` holding the manifest
+    fn filesystem(manifest: &str) -> (KubeFilesystem, u64) {
+        let mut fs = KubeFilesystem::for_tests(Profile::default());
+        let dir_inode = fs.create_dir_node(ROOT_ATTR.ino, "configs").unwrap();
+        let manifest_inode = fs
+            .create_content_node(
+                dir_inode,
+                "app.yaml",
+                manifest.as_bytes().to_vec(),
+                SystemTime::now(),
+            )
+            .unwrap();
+        (fs, manifest_inode)
+    }
+
+    fn read(fs: &mut KubeFilesystem, path: &str) -> Option<String> {
+        match fs.entry(path)? {
+            Entry::File(content) => Some(String::from_utf8_lossy(&content).into_owned()),
+            Entry::Dir(mut names) => {
+                names.sort();
+                Some(names.join(" "))
+            }
+        }
+    }
+
+    #[test]
+    fn escapes_keys_like_json_pointers() {
+        assert_eq!(
+            escape_key("example.com/owner~team"),
+            "example.com~1owner~0team"
+        );
+        assert_eq!(escape_key("~1"), "~01");
+
+        let (mut fs, _) = filesystem(MANIFEST);
+        assert_eq!(
+            read(
+                &mut fs,
+                "configs/app.yaml#/metadata/annotations/example.com~1owner~0team"
+            )
+            .as_deref(),
+            Some("shop")
+        );
+    }
+
+    #[test]
+    fn browses_sequences_tagged_values_and_binary_data() {
+        let (mut fs, _) = filesystem(MANIFEST);
+        assert_eq!(
+            read(&mut fs, "configs/app.yaml#/data/config.ini").as_deref(),
+            Some("[server]\nport = 80\n")
+        );
+        assert_eq!(
+            read(&mut fs, "configs/app.yaml#/data/ports").as_deref(),
+            Some("0 1")
+        );
+        assert_eq!(
+            read(&mut fs, "configs/app.yaml#/data/ports/1").as_deref(),
+            Some("443\n")
+        );
+        // the tag is dropped, the value presented as is
+        assert_eq!(
+            read(&mut fs, "configs/app.yaml#/data/replicas").as_deref(),
+            Some("3\n")
+        );
+        assert_eq!(
+            fs.entry("configs/app.yaml#/binaryData/logo.png")
+                .map(|entry| matches!(entry, Entry::File(content) if content == b"\x89PNG")),
+            Some(true)
+        );
+        // hidden from the listing of the manifest's directory
+        assert_eq!(read(&mut fs, "configs").as_deref(), Some("app.yaml"));
+    }
+
+    #[test]
+    fn rebuilds_the_fields_on_every_lookup() {
+        let (mut fs, manifest_inode) = filesystem(MANIFEST);
+        assert_eq!(
+            read(&mut fs, "configs/app.yaml#/metadata/name").as_deref(),
+            Some("app")
+        );
+        let first_inode = fs.dir_inode("configs/app.yaml#");
+
+        fs.set_node_content(
+            manifest_inode,
+            MANIFEST.replace("name: app", "name: web").into_bytes(),
+        );
+        assert_eq!(
+            read(&mut fs, "configs/app.yaml#/metadata/name").as_deref(),
+            Some("web")
+        );
+        // the tree of the first lookup is gone
+        let first_inode = first_inode.unwrap();
+        assert!(!fs.state.inodes.contains_key(&first_inode));
+    }
+
+    #[test]
+    fn refuses_lookups_of_manifests_it_cannot_fetch() {
+        let (mut fs, manifest_inode) = filesystem("");
+        let api_resource = ApiResource {
+            api_version: "v1".to_string(),
+            plural: "configmaps".to_string(),
+        };
+        fs.lazy_manifests.insert(
+            manifest_inode,
+            LazyManifest::evicted(api_resource, Some("default".to_string()), "app".to_string()),
+        );
+        assert!(read(&mut fs, "configs/app.yaml#/metadata/name").is_none());
+        assert!(read(&mut fs, "configs/app.yaml#").is_none());
+    }
+}
@@ -0,0 +1,262 @@
+use std::{collections::BTreeMap, fmt::Write as _};
+
+use serde_json::Value;
+
+use super::{KubeFilesystem, ROOT_ATTR};
+
+pub const FOOTPRINT_FILE_NAME: &str = "footprint.txt";
+
+// resources whose pod templates stand in for the pods when those aren't
+// presented, along with where their pod spec is
+const WORKLOAD_TEMPLATES: [(&str, &[&str]); 3] = [
+    ("deployments", &["spec", "template", "spec"]),
+    ("statefulsets", &["spec", "template", "spec"]),
+    ("daemonsets", &["spec", "template", "spec"]),
+];
+
+/// Parses a resource quantity, e.g. `250m`, `1.5` or `512Mi`, into base
+/// units: cores or bytes.
+pub(super) fn parse_quantity(quantity: &str) -> Option<f64> {
+    let quantity = quantity.trim();
+    let split = quantity
+        .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == '+' || c == '-'))
+        .unwrap_or(quantity.len());
+    let (number, suffix) = quantity.split_at(split);
+    let number: f64 = number.parse().ok()?;
+    let multiplier = match suffix {
+        "" => 1.0,
+        "m" => 1e-3,
+        "k" => 1e3,
+        "M" => 1e6,
+        "G" => 1e9,
+        "T" => 1e12,
+        "P" => 1e15,
+        "E" => 1e18,
+        "Ki" => 1024.0,
+        "Mi" => 1024f64.powi(2),
+        "Gi" => 1024f64.powi(3),
+        "Ti" => 1024f64.powi(4),
+        "Pi" => 1024f64.powi(5),
+        "Ei" => 1024f64.powi(6),
+        exponent => 10f64.powf(exponent.strip_prefix(['e', 'E'])?.parse().ok()?),
+    };
+    Some(number * multiplier)
+}
+
+// cores, e.g. `1.5` or `250m`
+fn format_cpu(cores: f64) -> String {
+    match cores.fract() == 0.0 || cores >= 10.0 {
+        true => format!("{cores:.0}"),
+        false if cores < 1.0 => format!("{:.0}m", cores * 1000.0),
+        false => format!("{cores:.2}")
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string(),
+    }
+}
+
+// bytes, in the largest binary unit that keeps them at 1 or more
+fn format_bytes(bytes: f64) -> String {
+    let units = ["", "Ki", "Mi", "Gi", "Ti", "Pi"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < units.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    let value = format!("{value:.1}");
+    format!("{}{}", value.trim_end_matches(".0"), units[unit])
+}
+
+/// The CPU and memory requests and limits of a pod, in cores and bytes: the
+/// sum over its containers, or more if an init container asks for more,
+/// plus its overhead.
+#[derive(Debug, Default, PartialEq)]
+struct PodResources {
+    requests: [f64; 2],
+    limits: [f64; 2],
+}
+
+fn pod_resources(spec: &Value) -> PodResources {
+    let amounts = |container: &Value, field: &str| {
+        ["cpu", "memory"].map(|resource| {
+            container["resources"][field][resource]
+                .as_str()
+                .and_then(parse_quantity)
+                .unwrap_or_default()
+        })
+    };
+    let mut resources = PodResources::default();
+    for (field, total) in [
+        ("requests", &mut resources.requests),
+        ("limits", &mut resources.limits),
+    ] {
+        for container in spec["containers"].as_array().into_iter().flatten() {
+            for (total, amount) in total.iter_mut().zip(amounts(container, field)) {
+                *total += amount;
+            }
+        }
+        for container in spec["initContainers"].as_array().into_iter().flatten() {
+            for (total, amount) in total.iter_mut().zip(amounts(container, field)) {
+                *total = total.max(amount);
+            }
+        }
+        let overhead = ["cpu", "memory"].map(|resource| {
+            spec["overhead"][resource]
+                .as_str()
+                .and_then(parse_quantity)
+                .unwrap_or_default()
+        });
+        for (total, amount) in total.iter_mut().zip(overhead) {
+            *total += amount;
+        }
+    }
+    resources
+}
+
+impl KubeFilesystem {
+    /// The namespace an inode is the `footprint.txt` of, if it is one.
+    pub(super) fn footprint_namespace(&self, inode: u64) -> Option<String> {
+        let node = self.state.inodes.get(&inode)?;
+        let ns_node = self.state.inodes.get(&node.parent)?;
+        (node.name == FOOTPRINT_FILE_NAME
+            && ns_node.parent == ROOT_ATTR.ino
+            && self.namespace_inode(&ns_node.name) == Some(node.parent))
+        .then(|| ns_node.name.clone())
+    }
+
+    /// Renders the `footprint.txt` of a namespace from the objects presented
+    /// in it: how many there are of each resource, the CPU and memory its
+    /// pods request and are limited to, and the storage its
+    /// PersistentVolumeClaims request. When pods aren't presented, the pod
+    /// templates of its workloads stand in for them, times their replicas.
+    pub(super) fn render_footprint(&mut self, inode: u64) {
+        let Some(namespace) = self.footprint_namespace(inode) else {
+            return;
+        };
+        let pods_presented = self
+            .namespace_inode(&namespace)
+            .and_then(|ns_inode| self.state.child_inode(ns_inode, "pods"))
+            .is_some();
+
+        let mut counts: BTreeMap<String, usize> = BTreeMap::new();
+        let (mut pods, mut resources) = (0, PodResources::default());
+        let (mut claims, mut storage) = (0, 0.0);
+        for (location, manifest) in self.presented_manifests() {
+            if location.namespace.as_ref() != Some(&namespace) {
+                continue;
+            }
+            *counts.entry(location.resource.clone()).or_default() += 1;
+
+            let resource = location.resource.as_str();
+            let template = WORKLOAD_TEMPLATES
+                .iter()
+                .find(|(name, _)| *name == resource && !pods_presented);
+            if resource != "pods" && resource != "persistentvolumeclaims" && template.is_none() {
+                continue;
+            }
+            let Some(content) = self.file_content(manifest) else {
+                continue;
+            };
+            let Ok(object) = serde_yaml::from_slice::<Value>(&content) else {
+                continue; // e.g. truncated
+            };
+
+            let (spec, replicas) = match (resource, template) {
+                ("persistentvolumeclaims", _) => {
+                    claims += 1;
+                    storage += object["spec"]["resources"]["requests"]["storage"]
+                        .as_str()
+                        .and_then(parse_quantity)
+                        .unwrap_or_default();
+                    continue;
+                }
+                // those done don't hold on to their resources
+                ("pods", _)
+                    if matches!(
+                        object["status"]["phase"].as_str(),
+                        Some("Succeeded" | "Failed")
+                    ) =>
+                {
+                    continue;
+                }
+                ("pods", _) => (&object["spec"], 1),
+                ("daemonsets", Some((_, path))) => (
+                    path.iter().fold(&object, |value, field| &value[field]),
+                    object["status"]["desiredNumberScheduled"]
+                        .as_u64()
+                        .unwrap_or_default(),
+                ),
+                (_, Some((_, path))) => (
+                    path.iter().fold(&object, |value, field| &value[field]),
+                    object["spec"]["replicas"].as_u64().unwrap_or(1),
+                ),
+                _ => continue,
+            };
+            let pod = pod_resources(spec);
+            pods += replicas;
+            for (total, amount) in resources.requests.iter_mut().zip(pod.requests) {
+                *total += amount * replicas as f64;
+            }
+            for (total, amount) in resources.limits.iter_mut().zip(pod.limits) {
+                *total += amount * replicas as f64;
+            }
+        }
+
+        let mut out = String::from("objects:\n");
+        for (resource, count) in &counts {
+            let _ = writeln!(out, "  {resource}: {count}");
+        }
+        let _ = match pods_presented {
+            true => writeln!(out, "pods: {pods}"),
+            false => writeln!(out, "pods: {pods}, as the workloads ask for"),
+        };
+        for (label, [cpu, memory]) in [
+            ("requests", resources.requests),
+            ("limits", resources.limits),
+        ] {
+            let _ = writeln!(
+                out,
+                "{label}: cpu {}, memory {}",
+                format_cpu(cpu),
+                format_bytes(memory)
+            );
+        }
+        let _ = writeln!(
+            out,
+            "storage: {} in {claims} persistentvolumeclaims",
+            format_bytes(storage)
+        );
+        self.set_node_content(inode, out.into_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn sums_the_resources_of_a_pod() {
+        let spec = json!({
+            "initContainers": [
+                {"resources": {"requests": {"cpu": "1", "memory": "64Mi"}}},
+            ],
+            "containers": [
+                {"resources": {"requests": {"cpu": "250m", "memory": "128Mi"}}},
+                {"resources": {
+                    "requests": {"cpu": "250m", "memory": "0.5Gi"},
+                    "limits": {"memory": "1Gi"},
+                }},
+            ],
+        });
+        let resources = pod_resources(&spec);
+        assert_eq!(resources.requests, [1.0, 640.0 * 1024.0 * 1024.0]);
+        assert_eq!(resources.limits, [0.0, 1024.0 * 1024.0 * 1024.0]);
+        assert_eq!(format_cpu(resources.requests[0]), "1");
+        assert_eq!(format_cpu(0.25), "250m");
+        assert_eq!(format_bytes(resources.requests[1]), "640Mi");
+        assert_eq!(parse_quantity("1e3"), Some(1000.0));
+    }
+}
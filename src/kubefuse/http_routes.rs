@@ -0,0 +1,117 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    time::SystemTime,
+};
+
+use serde_json::Value;
+
+use crate::gateway::{self, Gateways};
+
+use super::{KubeFilesystem, drafts::ManifestLocation};
+
+const RESOLVED_FILE_NAME: &str = "resolved.txt";
+const BACKENDS_DIR_NAME: &str = "backends";
+
+impl KubeFilesystem {
+    /// Resolves the HTTPRoutes presented, e.g. as custom resources of the
+    /// Gateway API, against the Gateways they attach to. The directory of
+    /// every route holds `resolved.txt`, its effective hostnames, matches and
+    /// backends, and `backends/`, a link per Service it forwards to, e.g.
+    /// `api -> ../../../services/api.yaml`.
+    pub(super) fn sync_http_routes(&mut self) {
+        let mut gateways = Gateways::new();
+        let mut routes = Vec::new();
+        for (location, inode) in self.presented_manifests() {
+            if !matches!(location.resource.as_str(), "gateways" | "httproutes") {
+                continue;
+            }
+            let Some(namespace) = location.namespace.clone() else {
+                continue;
+            };
+            let Some(manifest) = self.file_content(inode) else {
+                continue;
+            };
+            // truncated manifests don't parse
+            let Ok(object) = serde_yaml::from_slice::<Value>(&manifest) else {
+                continue;
+            };
+            match location.resource.as_str() {
+                // Istio has Gateways of its own
+                "gateways" if !gateway::is_gateway_api(&object) => {}
+                "gateways" => {
+                    gateways.insert((namespace, location.name), object);
+                }
+                _ => routes.push((location, inode, object)),
+            }
+        }
+
+        for (location, inode, route) in routes {
+            self.sync_route_dir(&location, inode, &route, &gateways);
+        }
+    }
+
+    fn sync_route_dir(
+        &mut self,
+        location: &ManifestLocation,
+        manifest_inode: u64,
+        route: &Value,
+        gateways: &Gateways,
+    ) {
+        let (Some(namespace), Some(parent)) = (
+            location.namespace.as_deref(),
+            self.state
+                .inodes
+                .get(&manifest_inode)
+                .map(|node| node.parent),
+        ) else {
+            return;
+        };
+        let Some(dir_inode) = self
+            .state
+            .child_inode(parent, &location.name)
+            .filter(|inode| self.is_dir(*inode))
+        else {
+            return; // the manifest itself, without an extension
+        };
+
+        let resolved = gateway::render_resolved(route, namespace, gateways).into_bytes();
+        match self.state.child_inode(dir_inode, RESOLVED_FILE_NAME) {
+            Some(inode) => self.update_node_content(inode, resolved),
+            None => {
+                self.create_content_node(
+                    dir_inode,
+                    RESOLVED_FILE_NAME,
+                    resolved,
+                    SystemTime::now(),
+                );
+            }
+        }
+
+        let backends_inode = match self.state.child_inode(dir_inode, BACKENDS_DIR_NAME) {
+            Some(inode) if self.is_dir(inode) => inode,
+            Some(_) => return,
+            None => match self.create_dir_node(dir_inode, BACKENDS_DIR_NAME) {
+                Some(inode) => inode,
+                None => return,
+            },
+        };
+        let links: BTreeMap<String, String> = gateway::backends(route, namespace)
+            .into_iter()
+            .map(|(backend_namespace, name)| {
+                let manifest = self.manifest_file_name(&name);
+                match backend_namespace == namespace {
+                    true => (name, format!("../../../services/{manifest}")),
+                    false => (
+                        format!("{backend_namespace}_{name}"),
+                        format!("../../../../{backend_namespace}/services/{manifest}"),
+                    ),
+                }
+            })
+            .collect();
+        let keep: HashSet<String> = links.keys().cloned().collect();
+        self.remove_stale_entries(backends_inode, &keep);
+        for (link, target) in links {
+            self.sync_link(backends_inode, &link, Some(&target));
+        }
+    }
+}
@@ -0,0 +1,89 @@
+use std::{
+    collections::HashMap,
+    hash::{DefaultHasher, Hash, Hasher},
+    time::SystemTime,
+};
+
+use crate::config::ManifestFormat;
+
+use super::KubeFilesystem;
+
+/// The `<object>.json` copies of manifests by inode, along with a hash of the
+/// manifest they were converted from.
+pub type JsonCopies = HashMap<u64, u64>;
+
+impl KubeFilesystem {
+    /// With `manifestFormat: both`, brings the `<object>.json` copies next to
+    /// the manifests in line with them. Only manifests that changed since the
+    /// last time are converted again.
+    pub(super) fn sync_json_copies(&mut self) {
+        if self.profile.manifest_format != ManifestFormat::Both {
+            return;
+        }
+
+        let mut copies = JsonCopies::new();
+        for (_, inode) in self.presented_manifests() {
+            let Some(node) = self.state.inodes.get(&inode) else {
+                continue;
+            };
+            let Some(object_name) = self.profile.manifest_object_name(&node.name) else {
+                continue;
+            };
+            let (parent, copy_name) = (node.parent, format!("{object_name}.json"));
+            let existing = self.state.child_inode(parent, &copy_name);
+            let Some(manifest) = self.file_content(inode) else {
+                // e.g. evicted while the API server is unreachable
+                if let Some(copy) = existing
+                    && let Some(copied) = self.json_copies.get(&copy)
+                {
+                    copies.insert(copy, *copied);
+                }
+                continue;
+            };
+            let mut hasher = DefaultHasher::new();
+            manifest.hash(&mut hasher);
+            let hash = hasher.finish();
+
+            if let Some(copy) = existing {
+                match self.json_copies.get(&copy) {
+                    Some(copied) if *copied == hash => {
+                        copies.insert(copy, hash);
+                        continue;
+                    }
+                    Some(_) => {}
+                    // a file of its own, e.g. the manifest of an object named so
+                    None => continue,
+                }
+            }
+            let json = serde_yaml::from_slice::<serde_yaml::Value>(&manifest)
+                .map_err(|e| e.to_string())
+                .and_then(|value| serde_json::to_string_pretty(&value).map_err(|e| e.to_string()));
+            let json = match json {
+                Ok(json) => (json + "\n").into_bytes(),
+                Err(e) => {
+                    // e.g. truncated manifests
+                    log::debug!("no JSON copy of {copy_name}: {e}");
+                    continue;
+                }
+            };
+            let copy = match existing {
+                Some(copy) => {
+                    self.update_node_content(copy, json);
+                    Some(copy)
+                }
+                None => self.create_content_node(parent, &copy_name, json, SystemTime::now()),
+            };
+            if let Some(copy) = copy {
+                copies.insert(copy, hash);
+            }
+        }
+
+        // copies of manifests that are gone
+        for copy in self.json_copies.keys() {
+            if !copies.contains_key(copy) {
+                self.state.remove_node(*copy);
+            }
+        }
+        self.json_copies = copies;
+    }
+}
@@ -0,0 +1,225 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    time::SystemTime,
+};
+
+use k8s_openapi::ByteString;
+use serde_yaml::Value;
+
+use super::{
+    KubeFilesystem, NodeContent, secret_sources::SOURCE_LINK_NAME, used_by::USED_BY_DIR_NAME,
+};
+
+/// Decodes the base64 values of a mapping, e.g. a Secret's `data`.
+fn decode_keys(keys: &mut BTreeMap<String, Vec<u8>>, encoded_keys: &Value) {
+    for (key, encoded) in encoded_keys.as_mapping().into_iter().flatten() {
+        let Some(key) = key.as_str() else {
+            continue;
+        };
+        match serde_yaml::from_value::<ByteString>(encoded.clone()) {
+            Ok(ByteString(bytes)) => {
+                keys.insert(key.to_string(), bytes);
+            }
+            Err(e) => log::warn!("key {key} is not valid base64: {e}"),
+        }
+    }
+}
+
+/// The files of the key directory of an object, keyed by name, along with
+/// their permissions. `None` for kinds that are only presented as manifests.
+fn object_keys(
+    kind: &str,
+    object: &Value,
+    redacted: bool,
+) -> Option<(BTreeMap<String, Vec<u8>>, u16)> {
+    let mut keys = BTreeMap::new();
+    match kind {
+        // withheld rather than decoded from the redacted placeholders
+        "Secret" if redacted => Some((keys, 0o400)),
+        "Secret" => {
+            decode_keys(&mut keys, &object["data"]);
+            Some((keys, 0o400))
+        }
+        "ConfigMap" => {
+            for (key, value) in object["data"].as_mapping().into_iter().flatten() {
+                if let (Some(key), Some(value)) = (key.as_str(), value.as_str()) {
+                    keys.insert(key.to_string(), value.as_bytes().to_vec());
+                }
+            }
+            decode_keys(&mut keys, &object["binaryData"]);
+            Some((keys, 0o444))
+        }
+        _ => None,
+    }
+}
+
+impl KubeFilesystem {
+    /// Creates or updates `<name>/` next to the manifest of an object whose
+    /// data is worth consuming directly, e.g. certificates in a Secret or
+    /// config files in a ConfigMap. It holds a file per data key with the
+    /// raw or decoded value, plus the manifest.
+    pub(super) fn sync_key_dir<T>(
+        &mut self,
+        manifests_inode: u64,
+        object_name: &str,
+        item: &T,
+        manifest: Vec<u8>,
+    ) where
+        T: k8s_openapi::Resource + serde::Serialize,
+    {
+        let object = match serde_yaml::to_value(item) {
+            Ok(object) => object,
+            Err(e) => {
+                log::error!("failed to serialize {} {object_name}: {e}", T::KIND);
+                return;
+            }
+        };
+        let Some((mut files, perm)) = object_keys(T::KIND, &object, self.profile.redact_secrets)
+        else {
+            return;
+        };
+        // a key of the same name takes precedence
+        files
+            .entry(self.manifest_file_name(object_name))
+            .or_insert(manifest);
+
+        let Some(dir_inode) = self
+            .state
+            .child_inode(manifests_inode, object_name)
+            .or_else(|| self.create_dir_node(manifests_inode, object_name))
+        else {
+            return;
+        };
+
+        let mut stale: HashSet<String> = match self.state.inodes.get(&dir_inode).map(|n| &n.content)
+        {
+            Some(NodeContent::Children(children)) => children.keys().cloned().collect(),
+            _ => HashSet::new(),
+        };
+        // a key of the same name takes precedence over `used-by/` too
+        match self.state.child_inode(dir_inode, USED_BY_DIR_NAME) {
+            Some(inode) if files.contains_key(USED_BY_DIR_NAME) && self.is_dir(inode) => {
+                self.state.remove_node(inode);
+            }
+            _ => {
+                stale.remove(USED_BY_DIR_NAME);
+            }
+        }
+        if !files.contains_key(SOURCE_LINK_NAME) {
+            stale.remove(SOURCE_LINK_NAME);
+        }
+        for (name, content) in files {
+            stale.remove(&name);
+            if let Some(inode) = self.state.child_inode(dir_inode, &name) {
+                self.update_node_content(inode, content);
+                continue;
+            }
+            let Some(inode) =
+                self.create_content_node(dir_inode, &name, content, SystemTime::now())
+            else {
+                continue;
+            };
+            if let Some(node) = self.state.inodes.get_mut(&inode) {
+                node.attrs.perm = perm;
+            }
+        }
+        for name in stale {
+            if let Some(inode) = self.state.child_inode(dir_inode, &name) {
+                self.state.remove_node(inode);
+            }
+        }
+    }
+
+    /// Removes the directory next to the manifest of a deleted object, e.g.
+    /// its key directory.
+    pub(super) fn remove_key_dir(&mut self, manifests_inode: u64, object_name: &str) {
+        if let Some(inode) = self.state.child_inode(manifests_inode, object_name) {
+            self.state.remove_node(inode);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k8s_openapi::api::core::v1::ConfigMap;
+
+    use crate::{
+        config::Profile,
+        kubefuse::{Entry, ROOT_ATTR},
+    };
+
+    use super::*;
+
+    #[test]
+    fn decodes_binary_data_byte_for_byte() {
+        let bytes: Vec<u8> = (0..=255).collect();
+        let config_map = ConfigMap {
+            data: Some(BTreeMap::from([(
+                "app.ini".to_string(),
+                "a=1\n".to_string(),
+            )])),
+            binary_data: Some(BTreeMap::from([(
+                "blob".to_string(),
+                ByteString(bytes.clone()),
+            )])),
+            ..Default::default()
+        };
+        let object = serde_yaml::to_value(&config_map).unwrap();
+        let (keys, perm) = object_keys("ConfigMap", &object, false).unwrap();
+        assert_eq!(keys["blob"], bytes);
+        assert_eq!(keys["app.ini"], b"a=1\n");
+        assert_eq!(perm, 0o444);
+
+        let object: Value = serde_yaml::from_str("binaryData: {blob: not base64!}").unwrap();
+        let (keys, _) = object_keys("ConfigMap", &object, false).unwrap();
+        assert!(keys.is_empty());
+    }
+
+    #[test]
+    fn withholds_the_keys_of_redacted_secrets() {
+        let object: Value = serde_yaml::from_str("data: {password: aHVudGVyMg==}").unwrap();
+        let (keys, perm) = object_keys("Secret", &object, false).unwrap();
+        assert_eq!(keys["password"], b"hunter2");
+        assert_eq!(perm, 0o400);
+
+        let (keys, perm) = object_keys("Secret", &object, true).unwrap();
+        assert!(keys.is_empty());
+        assert_eq!(perm, 0o400);
+        assert!(object_keys("Service", &object, false).is_none());
+    }
+
+    #[test]
+    fn keys_shadow_the_manifest_of_the_same_name() {
+        let mut fs = KubeFilesystem::for_tests(Profile::default());
+        let manifests_inode = fs.create_dir_node(ROOT_ATTR.ino, "configmaps").unwrap();
+        let config_map = ConfigMap {
+            data: Some(BTreeMap::from([(
+                "app.yaml".to_string(),
+                "from the key".to_string(),
+            )])),
+            ..Default::default()
+        };
+        fs.sync_key_dir(
+            manifests_inode,
+            "app",
+            &config_map,
+            b"the manifest".to_vec(),
+        );
+        assert!(matches!(
+            fs.entry("configmaps/app/app.yaml"),
+            Some(Entry::File(content)) if content == b"from the key"
+        ));
+
+        // without such a key, the manifest is there as usual
+        fs.sync_key_dir(
+            manifests_inode,
+            "web",
+            &config_map,
+            b"the manifest".to_vec(),
+        );
+        assert!(matches!(
+            fs.entry("configmaps/web/web.yaml"),
+            Some(Entry::File(content)) if content == b"the manifest"
+        ));
+    }
+}
@@ -0,0 +1,164 @@
+use std::collections::BTreeMap;
+
+use crate::layout::{LayoutStrategy, ManifestPath};
+
+use super::{CLUSTER_DIR_NAME, KubeFilesystem, NodeContent, ROOT_ATTR, drafts::ManifestLocation};
+
+// the tree of a layout: directories of its own and links to manifests
+enum LayoutEntry {
+    Dir(BTreeMap<String, LayoutEntry>),
+    Manifest(u64),
+}
+
+impl LayoutEntry {
+    fn insert(&mut self, path: &[String], inode: u64) {
+        let (LayoutEntry::Dir(entries), Some((name, rest))) = (self, path.split_first()) else {
+            return;
+        };
+        if rest.is_empty() {
+            entries.insert(name.clone(), LayoutEntry::Manifest(inode));
+            return;
+        }
+        entries
+            .entry(name.clone())
+            .or_insert_with(|| LayoutEntry::Dir(BTreeMap::new()))
+            .insert(rest, inode);
+    }
+}
+
+impl KubeFilesystem {
+    /// Brings the `_<layout>/` directories of the configured layouts in line
+    /// with the namespace tree. Their files are links to the manifests in the
+    /// namespace tree, so they share content, attributes and edits.
+    pub(super) fn sync_layouts(&mut self) {
+        if self.profile.layouts.is_empty() {
+            return;
+        }
+        let manifests = self.presented_manifests();
+
+        for layout in self.profile.layouts.clone() {
+            let mut tree = LayoutEntry::Dir(BTreeMap::new());
+            for (location, inode) in &manifests {
+                let file_name = self.manifest_file_name(&location.name);
+                let path = layout.path(&ManifestPath {
+                    namespace: location.namespace.as_deref(),
+                    resource: &location.resource,
+                    file_name: &file_name,
+                });
+                tree.insert(&path, *inode);
+            }
+
+            let dir_name = format!("_{}", layout.name());
+            let Some(layout_inode) = self
+                .state
+                .child_inode(ROOT_ATTR.ino, &dir_name)
+                .or_else(|| self.create_dir_node(ROOT_ATTR.ino, &dir_name))
+            else {
+                continue;
+            };
+            if let LayoutEntry::Dir(entries) = tree {
+                self.sync_layout_dir(layout_inode, entries);
+            }
+        }
+    }
+
+    /// The manifests of the namespace tree, two levels deep in the namespace
+    /// and cluster directories.
+    pub(super) fn presented_manifests(&self) -> Vec<(ManifestLocation, u64)> {
+        let mut dirs: Vec<u64> = self
+            .namespace_names()
+            .iter()
+            .filter_map(|namespace| self.namespace_inode(namespace))
+            .collect();
+        dirs.extend(self.state.child_inode(ROOT_ATTR.ino, CLUSTER_DIR_NAME));
+
+        let mut manifests = Vec::new();
+        for dir in dirs {
+            for (_, child) in self.children(dir) {
+                // `<namespace>/manifest.yaml` or a resource directory
+                let candidates = self.children(child).into_iter().map(|(_, inode)| inode);
+                for inode in std::iter::once(child).chain(candidates) {
+                    let is_file = self
+                        .state
+                        .inodes
+                        .get(&inode)
+                        .is_some_and(|node| node.attrs.kind == fuser::FileType::RegularFile);
+                    if let Some(location) = self.manifest_location(inode).filter(|_| is_file) {
+                        manifests.push((location, inode));
+                    }
+                }
+            }
+        }
+        manifests
+    }
+
+    pub(super) fn children(&self, inode: u64) -> Vec<(String, u64)> {
+        match self.state.inodes.get(&inode).map(|node| &node.content) {
+            Some(NodeContent::Children(children)) => children
+                .iter()
+                .map(|(name, inode)| (name.clone(), *inode))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    fn sync_layout_dir(&mut self, dir_inode: u64, entries: BTreeMap<String, LayoutEntry>) {
+        for (name, inode) in self.children(dir_inode) {
+            let owned = self.owns(dir_inode, inode);
+            let wanted = match entries.get(&name) {
+                Some(LayoutEntry::Manifest(target)) => !owned && *target == inode,
+                Some(LayoutEntry::Dir(_)) => owned,
+                None => false,
+            };
+            if !wanted {
+                self.remove_layout_entry(dir_inode, &name, inode);
+            }
+        }
+
+        for (name, entry) in entries {
+            match entry {
+                LayoutEntry::Manifest(inode) => self.state.link(dir_inode, &name, inode),
+                LayoutEntry::Dir(entries) => {
+                    let Some(inode) = self
+                        .state
+                        .child_inode(dir_inode, &name)
+                        .or_else(|| self.create_dir_node(dir_inode, &name))
+                    else {
+                        continue;
+                    };
+                    self.sync_layout_dir(inode, entries);
+                }
+            }
+        }
+    }
+
+    // whether a directory entry is a node of its own rather than a link
+    fn owns(&self, dir_inode: u64, inode: u64) -> bool {
+        self.state
+            .inodes
+            .get(&inode)
+            .is_some_and(|node| node.parent == dir_inode)
+    }
+
+    // unlinks a manifest, or removes a directory of the layout after
+    // unlinking what it holds, leaving the manifests to the namespace tree
+    fn remove_layout_entry(&mut self, dir_inode: u64, name: &str, inode: u64) {
+        if !self.owns(dir_inode, inode) {
+            self.state.unlink(dir_inode, name);
+            return;
+        }
+        for (child_name, child) in self.children(inode) {
+            self.remove_layout_entry(inode, &child_name, child);
+        }
+        self.state.remove_node(inode);
+    }
+
+    /// Whether a directory entry links to a manifest in the namespace tree
+    /// from a layout directory.
+    pub(super) fn is_layout_link(&self, parent: u64, inode: u64) -> bool {
+        self.state
+            .inodes
+            .get(&inode)
+            .is_some_and(|node| node.parent != parent)
+    }
+}
@@ -0,0 +1,379 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    io::{self, BufRead, BufReader},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use k8s_openapi::chrono::{DateTime, FixedOffset};
+
+use crate::client::KubeClient;
+
+use super::{KubeFilesystem, NodeContent, pods, request_errno};
+
+const LOGS_DIR_NAME: &str = "logs";
+const LOG_SUFFIX: &str = ".log";
+const PREVIOUS_LOG_SUFFIX: &str = ".previous.log";
+const FOLLOW_SUFFIX: &str = ".follow";
+
+// how long a log is followed before the request is made again, which is also
+// how long a quiet log is still followed after its file was closed
+const FOLLOW_TIMEOUT: Duration = Duration::from_secs(60);
+
+impl KubeFilesystem {
+    /// Creates `<pod>/logs/<container>.log` for each container of a pod, init
+    /// containers included, and
+    /// `<container>.previous.log` for those that were restarted, dropping the
+    /// files of containers that are gone. The logs are fetched when the files
+    /// are opened.
+    pub(super) fn sync_log_files(&mut self, pod_inode: u64, pod: &serde_json::Value) {
+        let Some(logs_inode) = self
+            .state
+            .child_inode(pod_inode, LOGS_DIR_NAME)
+            .or_else(|| self.create_dir_node(pod_inode, LOGS_DIR_NAME))
+        else {
+            return;
+        };
+
+        let files: HashSet<String> = pods::container_names(pod)
+            .flat_map(|container| {
+                [LOG_SUFFIX, FOLLOW_SUFFIX].map(|suffix| format!("{container}{suffix}"))
+            })
+            .chain(
+                ["initContainerStatuses", "containerStatuses"]
+                    .iter()
+                    .filter_map(|field| pod["status"][field].as_array())
+                    .flatten()
+                    .filter(|status| status["lastState"]["terminated"].is_object())
+                    .filter_map(|status| status["name"].as_str())
+                    .map(|container| format!("{container}{PREVIOUS_LOG_SUFFIX}")),
+            )
+            .collect();
+        let stale: Vec<u64> = match self.state.inodes.get(&logs_inode).map(|n| &n.content) {
+            Some(NodeContent::Children(children)) => children
+                .iter()
+                .filter(|(name, _)| !files.contains(*name))
+                .map(|(_, inode)| *inode)
+                .collect(),
+            _ => Vec::new(),
+        };
+        for inode in stale {
+            self.state.remove_node(inode);
+        }
+        for file_name in files {
+            if self.state.child_inode(logs_inode, &file_name).is_none() {
+                self.create_content_node(logs_inode, &file_name, Vec::new(), SystemTime::now());
+            }
+        }
+    }
+
+    /// Fetches the log behind a pod log file, returning `None` for any other
+    /// file. Of logs larger than `--max-file-size`, only the end is kept.
+    /// Fails with ENOENT when the container has no previous instance (anymore)
+    /// for a `.previous.log` file.
+    pub(super) fn refresh_log_file(&mut self, inode: u64) -> Option<Result<(), libc::c_int>> {
+        // container names can't contain dots, the suffixes don't clash
+        let (location, previous) = match self.log_location(inode, PREVIOUS_LOG_SUFFIX) {
+            Some(location) => (location, true),
+            None => (self.log_location(inode, LOG_SUFFIX)?, false),
+        };
+        let (namespace, pod, container) = location;
+        let log = self
+            .kube_client
+            .pod_log(&namespace, &pod, &container, previous);
+        let mut log = match log {
+            Ok(log) => log,
+            // the API server answers 400 when there is no previous instance
+            Err(e)
+                if previous
+                    && e.status().is_some_and(|status| {
+                        status == reqwest::StatusCode::BAD_REQUEST
+                            || status == reqwest::StatusCode::NOT_FOUND
+                    }) =>
+            {
+                log::debug!("no previous log of {namespace}/{pod}/{container}: {e}");
+                return Some(Err(libc::ENOENT));
+            }
+            Err(e) => {
+                log::error!("failed to get the log of {namespace}/{pod}/{container}: {e}");
+                return Some(Err(request_errno(e.status())));
+            }
+        };
+        let max_size = self.max_file_size as usize;
+        if max_size > 0 && log.len() > max_size {
+            log.drain(..log.len() - max_size);
+        }
+        self.set_node_content(inode, log);
+        Some(Ok(()))
+    }
+
+    /// Starts following the log behind a `.follow` file for a handle opened
+    /// on it, returning false for any other file. Of the log received, the
+    /// lines past the history retention or `--max-file-size` are dropped.
+    pub(super) fn follow_log(&mut self, inode: u64, fh: u64) -> bool {
+        let Some((namespace, pod, container)) = self.log_location(inode, FOLLOW_SUFFIX) else {
+            return false;
+        };
+        let max_size = [self.max_file_size, self.history_retention.max_bytes]
+            .into_iter()
+            .filter(|max| *max > 0)
+            .min()
+            .unwrap_or_default();
+        let stream = LogStream {
+            inode,
+            buffer: Arc::new(Mutex::new(StreamBuffer {
+                max_size: max_size as usize,
+                max_age: Duration::from_secs(self.history_retention.max_age),
+                ..Default::default()
+            })),
+        };
+        let buffer = stream.buffer.clone();
+        let client = self.kube_client.clone();
+        let spawned = thread::Builder::new()
+            .name(format!("follow-{pod}/{container}"))
+            .spawn(move || follow(&client, &namespace, &pod, &container, &buffer));
+        if let Err(e) = spawned {
+            log::error!("failed to start following a log: {e}");
+            if let Ok(mut buffer) = stream.buffer.lock() {
+                buffer.end();
+            }
+        }
+        self.log_streams.insert(fh, stream);
+        true
+    }
+
+    /// How many lines of a `.follow` file were dropped under the retention,
+    /// the most of any handle open on it, if any were.
+    pub(super) fn dropped_log_lines(&self, inode: u64) -> Option<u64> {
+        self.log_streams
+            .values()
+            .filter(|stream| stream.inode == inode)
+            .map(LogStream::dropped)
+            .max()
+            .filter(|dropped| *dropped > 0)
+    }
+
+    // the namespace, pod and container of `<namespace>/pods/<pod>/logs/<container><suffix>`
+    fn log_location(&self, inode: u64, suffix: &str) -> Option<(String, String, String)> {
+        let node = self.state.inodes.get(&inode)?;
+        let container = node.name.strip_suffix(suffix)?;
+        let logs_dir = self.state.inodes.get(&node.parent)?;
+        let pod_dir = self.state.inodes.get(&logs_dir.parent)?;
+        let pods_dir = self.state.inodes.get(&pod_dir.parent)?;
+        let ns_dir = self.state.inodes.get(&pods_dir.parent)?;
+        if logs_dir.name != LOGS_DIR_NAME
+            || pods_dir.name != "pods"
+            || self.namespace_inode(&ns_dir.name) != Some(pods_dir.parent)
+        {
+            return None;
+        }
+        Some((
+            ns_dir.name.clone(),
+            pod_dir.name.clone(),
+            container.to_string(),
+        ))
+    }
+}
+
+/// The log behind a `.follow` file opened by one handle, filled by a thread
+/// following it. Reads past what was received so far wait for more.
+pub(super) struct LogStream {
+    inode: u64,
+    buffer: Arc<Mutex<StreamBuffer>>,
+}
+
+impl LogStream {
+    /// How much of the log was received, the size of the file for the handle.
+    pub(super) fn len(&self) -> u64 {
+        self.buffer
+            .lock()
+            .map(|buffer| buffer.len())
+            .unwrap_or_default()
+    }
+
+    fn dropped(&self) -> u64 {
+        self.buffer
+            .lock()
+            .map(|mut buffer| {
+                buffer.expire();
+                buffer.dropped
+            })
+            .unwrap_or_default()
+    }
+
+    pub(super) fn read(&self, offset: u64, size: u32, reply: fuser::ReplyData) {
+        let Ok(mut buffer) = self.buffer.lock() else {
+            reply.error(libc::EIO);
+            return;
+        };
+        buffer.expire();
+        if let Some(reply) = buffer.answer(offset, size, reply) {
+            buffer.waiting.push((offset, size, reply));
+        }
+    }
+
+    /// Stops following the log once the handle is released.
+    pub(super) fn release(self) {
+        if let Ok(mut buffer) = self.buffer.lock() {
+            buffer.released = true;
+            buffer.end();
+        }
+    }
+}
+
+#[derive(Default)]
+struct StreamBuffer {
+    // the log from offset `start` on, the first lines are dropped beyond
+    // max_size or once older than max_age
+    data: Vec<u8>,
+    start: u64,
+    max_size: usize,
+    max_age: Duration,
+    // the length of every line in data and when it was received
+    lines: VecDeque<(usize, Instant)>,
+    dropped: u64,
+    // no more of the log is coming, reads past the end see EOF
+    ended: bool,
+    released: bool,
+    // reads past the end, answered when more of the log arrives
+    waiting: Vec<(u64, u32, fuser::ReplyData)>,
+}
+
+impl StreamBuffer {
+    fn len(&self) -> u64 {
+        self.start + self.data.len() as u64
+    }
+
+    // answers a read unless it has to wait for more of the log, giving the
+    // reply back then
+    fn answer(&self, offset: u64, size: u32, reply: fuser::ReplyData) -> Option<fuser::ReplyData> {
+        if offset >= self.len() && !self.ended {
+            return Some(reply);
+        }
+        let from = (offset.saturating_sub(self.start) as usize).min(self.data.len());
+        let to = (from + size as usize).min(self.data.len());
+        reply.data(&self.data[from..to]);
+        None
+    }
+
+    // appends a line
+    fn append(&mut self, bytes: &[u8]) {
+        self.data.extend_from_slice(bytes);
+        self.lines.push_back((bytes.len(), Instant::now()));
+        self.expire();
+        self.answer_waiting();
+    }
+
+    // drops the first lines beyond the retention
+    fn expire(&mut self) {
+        let (mut lines, mut bytes) = (0, 0);
+        for (len, received) in &self.lines {
+            let too_old = !self.max_age.is_zero() && received.elapsed() > self.max_age;
+            let too_large = self.max_size > 0 && self.data.len() - bytes > self.max_size;
+            if !(too_old || too_large) {
+                break;
+            }
+            lines += 1;
+            bytes += len;
+        }
+        self.lines.drain(..lines);
+        self.data.drain(..bytes);
+        self.start += bytes as u64;
+        self.dropped += lines as u64;
+    }
+
+    fn end(&mut self) {
+        self.ended = true;
+        self.answer_waiting();
+    }
+
+    fn answer_waiting(&mut self) {
+        for (offset, size, reply) in std::mem::take(&mut self.waiting) {
+            if let Some(reply) = self.answer(offset, size, reply) {
+                self.waiting.push((offset, size, reply));
+            }
+        }
+    }
+}
+
+// follows a log into the buffer until the container exits or the handle is
+// released, requesting it again from the last line received whenever the
+// request times out
+fn follow(
+    client: &KubeClient,
+    namespace: &str,
+    pod: &str,
+    container: &str,
+    buffer: &Mutex<StreamBuffer>,
+) {
+    let mut last: Option<(DateTime<FixedOffset>, String)> = None;
+    loop {
+        if buffer.lock().map_or(true, |buffer| buffer.released) {
+            return;
+        }
+        let since_time = last.as_ref().map(|(_, timestamp)| timestamp.as_str());
+        let response =
+            match client.follow_pod_log(namespace, pod, container, since_time, FOLLOW_TIMEOUT) {
+                Ok(response) => response,
+                Err(e) => {
+                    log::error!("failed to follow the log of {namespace}/{pod}/{container}: {e}");
+                    break;
+                }
+            };
+
+        let mut reader = BufReader::new(response);
+        let mut line = Vec::new();
+        let timed_out = loop {
+            line.clear();
+            match reader.read_until(b'\n', &mut line) {
+                Ok(0) => break false, // the container exited
+                Ok(_) => {}
+                // a line cut short is received again with the next request
+                Err(e) if is_timeout(&e) => break true,
+                Err(e) => {
+                    log::error!("failed to follow the log of {namespace}/{pod}/{container}: {e}");
+                    break false;
+                }
+            }
+            let Some((time, timestamp, text)) = split_timestamp(&line) else {
+                continue;
+            };
+            // lines since the second of the last one are sent again
+            if last
+                .as_ref()
+                .is_some_and(|(last_time, _)| time <= *last_time)
+            {
+                continue;
+            }
+            let Ok(mut buffer) = buffer.lock() else {
+                return;
+            };
+            if buffer.released {
+                return;
+            }
+            buffer.append(text);
+            last = Some((time, timestamp.to_string()));
+        };
+        if !timed_out {
+            break;
+        }
+    }
+    if let Ok(mut buffer) = buffer.lock() {
+        buffer.end();
+    }
+}
+
+fn is_timeout(e: &io::Error) -> bool {
+    e.get_ref()
+        .and_then(|e| e.downcast_ref::<reqwest::Error>())
+        .is_some_and(reqwest::Error::is_timeout)
+}
+
+// splits `<RFC 3339 timestamp> <text>` as sent with `timestamps=true`
+fn split_timestamp(line: &[u8]) -> Option<(DateTime<FixedOffset>, &str, &[u8])> {
+    let space = line.iter().position(|b| *b == b' ')?;
+    let timestamp = std::str::from_utf8(&line[..space]).ok()?;
+    let time = DateTime::parse_from_rfc3339(timestamp).ok()?;
+    Some((time, timestamp, &line[space + 1..]))
+}
@@ -0,0 +1,197 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    time::SystemTime,
+};
+
+use serde_json::Value;
+
+use crate::mesh;
+
+use super::KubeFilesystem;
+
+/// Directory of a namespace grouping its Istio rules by the host they apply
+/// to.
+const MESH_HOSTS_DIR_NAME: &str = "mesh-hosts";
+const ROUTING_FILE_NAME: &str = "routing.txt";
+
+// the rules of a namespace applying to a host, by name
+#[derive(Default)]
+struct HostRules {
+    virtual_services: BTreeMap<String, Value>,
+    destination_rules: BTreeMap<String, Value>,
+}
+
+// the link name and target of a manifest from `mesh-hosts/<host>/`
+fn manifest_link(
+    namespace: &str,
+    resource: &str,
+    name: &str,
+    file_name: &str,
+    other_namespace: Option<&str>,
+) -> (String, String) {
+    match other_namespace.filter(|other| *other != namespace) {
+        Some(other) => (
+            format!("{resource}_{other}_{name}"),
+            format!("../../../{other}/{resource}/{file_name}"),
+        ),
+        None => (
+            format!("{resource}_{name}"),
+            format!("../../{resource}/{file_name}"),
+        ),
+    }
+}
+
+fn is_istio(object: &Value) -> bool {
+    object["apiVersion"]
+        .as_str()
+        .and_then(|api_version| api_version.split_once('/'))
+        .is_some_and(|(group, _)| group == mesh::ISTIO_GROUP)
+}
+
+impl KubeFilesystem {
+    /// Groups the Istio VirtualServices and DestinationRules presented, e.g.
+    /// as custom resources, by the host they apply to. Each namespace having
+    /// any gets `mesh-hosts/<host>/`, holding `routing.txt`, a summary of the
+    /// traffic rules in effect for the host, and a link to the manifest of
+    /// every rule and Gateway involved.
+    pub(super) fn sync_mesh_hosts(&mut self) {
+        // namespace -> host -> rules
+        let mut hosts: BTreeMap<String, BTreeMap<String, HostRules>> = BTreeMap::new();
+        for (location, inode) in self.presented_manifests() {
+            if !matches!(
+                location.resource.as_str(),
+                "virtualservices" | "destinationrules"
+            ) {
+                continue;
+            }
+            let Some(namespace) = location.namespace else {
+                continue;
+            };
+            let Some(manifest) = self.file_content(inode) else {
+                continue;
+            };
+            // truncated manifests don't parse
+            let Some(object) = serde_yaml::from_slice::<Value>(&manifest)
+                .ok()
+                .filter(is_istio)
+            else {
+                continue;
+            };
+            let namespace_hosts = hosts.entry(namespace.clone()).or_default();
+            match location.resource.as_str() {
+                "virtualservices" => {
+                    for host in mesh::virtual_service_hosts(&object, &namespace) {
+                        namespace_hosts
+                            .entry(host)
+                            .or_default()
+                            .virtual_services
+                            .insert(location.name.clone(), object.clone());
+                    }
+                }
+                _ => {
+                    if let Some(host) = mesh::destination_rule_host(&object, &namespace) {
+                        namespace_hosts
+                            .entry(host)
+                            .or_default()
+                            .destination_rules
+                            .insert(location.name.clone(), object);
+                    }
+                }
+            }
+        }
+
+        for namespace in self.namespace_names() {
+            let Some(ns_inode) = self.namespace_inode(&namespace) else {
+                continue;
+            };
+            let existing = self.state.child_inode(ns_inode, MESH_HOSTS_DIR_NAME);
+            let Some(namespace_hosts) = hosts.remove(&namespace) else {
+                if let Some(inode) = existing.filter(|inode| self.is_dir(*inode)) {
+                    self.state.remove_node(inode);
+                }
+                continue;
+            };
+            let dir_inode = match existing {
+                Some(inode) if self.is_dir(inode) => inode,
+                Some(_) => continue,
+                None => match self.create_dir_node(ns_inode, MESH_HOSTS_DIR_NAME) {
+                    Some(inode) => inode,
+                    None => continue,
+                },
+            };
+            let keep: HashSet<String> = namespace_hosts.keys().cloned().collect();
+            self.remove_stale_entries(dir_inode, &keep);
+            for (host, rules) in namespace_hosts {
+                self.sync_mesh_host_dir(dir_inode, &namespace, &host, &rules);
+            }
+        }
+    }
+
+    fn sync_mesh_host_dir(
+        &mut self,
+        dir_inode: u64,
+        namespace: &str,
+        host: &str,
+        rules: &HostRules,
+    ) {
+        let Some(host_inode) = self
+            .state
+            .child_inode(dir_inode, host)
+            .or_else(|| self.create_dir_node(dir_inode, host))
+        else {
+            return;
+        };
+
+        let virtual_services: Vec<(&str, &Value)> = rules
+            .virtual_services
+            .iter()
+            .map(|(name, object)| (name.as_str(), object))
+            .collect();
+        let destination_rules: Vec<(&str, &Value)> = rules
+            .destination_rules
+            .iter()
+            .map(|(name, object)| (name.as_str(), object))
+            .collect();
+        let routing = mesh::render_routing(host, namespace, &virtual_services, &destination_rules)
+            .into_bytes();
+
+        let mut links = BTreeMap::new();
+        for (resource, objects) in [
+            ("virtualservices", &rules.virtual_services),
+            ("destinationrules", &rules.destination_rules),
+        ] {
+            for name in objects.keys() {
+                let file_name = self.manifest_file_name(name);
+                let (link, target) = manifest_link(namespace, resource, name, &file_name, None);
+                links.insert(link, target);
+            }
+        }
+        for object in rules.virtual_services.values() {
+            let gateways = object["spec"]["gateways"].as_array().into_iter().flatten();
+            for gateway in gateways.filter_map(Value::as_str) {
+                let (gateway_namespace, name) = match gateway.split_once('/') {
+                    Some((gateway_namespace, name)) => (Some(gateway_namespace), name),
+                    None if gateway == "mesh" => continue, // the sidecars
+                    None => (None, gateway),
+                };
+                let file_name = self.manifest_file_name(name);
+                let (link, target) =
+                    manifest_link(namespace, "gateways", name, &file_name, gateway_namespace);
+                links.insert(link, target);
+            }
+        }
+
+        let mut keep: HashSet<String> = links.keys().cloned().collect();
+        keep.insert(ROUTING_FILE_NAME.to_string());
+        self.remove_stale_entries(host_inode, &keep);
+        match self.state.child_inode(host_inode, ROUTING_FILE_NAME) {
+            Some(inode) => self.update_node_content(inode, routing),
+            None => {
+                self.create_content_node(host_inode, ROUTING_FILE_NAME, routing, SystemTime::now());
+            }
+        }
+        for (link, target) in links {
+            self.sync_link(host_inode, &link, Some(&target));
+        }
+    }
+}
@@ -0,0 +1,374 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use k8s_openapi::apimachinery::pkg::apis::meta::v1::ObjectMeta;
+
+use crate::client::{ApiResource, ListParams};
+
+use super::{
+    KubeFilesystem, NodeContent,
+    refresh::{self, RefreshStatus, RefreshStrategy},
+    request_errno,
+};
+
+// the apiVersion of every resource type with a typed model, those that can
+// be listed by metadata only first
+const TYPED_RESOURCES: [(&str, &str); 15] = [
+    ("configmaps", "v1"),
+    ("secrets", "v1"),
+    ("services", "v1"),
+    ("deployments", "apps/v1"),
+    ("replicasets", "apps/v1"),
+    ("statefulsets", "apps/v1"),
+    ("daemonsets", "apps/v1"),
+    // these feed reports reading them whole, or are cluster-scoped
+    ("endpointslices", "discovery.k8s.io/v1"),
+    ("events", "v1"),
+    ("namespaces", "v1"),
+    ("nodes", "v1"),
+    ("persistentvolumes", "v1"),
+    ("clusterroles", "rbac.authorization.k8s.io/v1"),
+    ("clusterrolebindings", "rbac.authorization.k8s.io/v1"),
+    ("storageclasses", "storage.k8s.io/v1"),
+];
+const METADATA_ONLY_RESOURCES: usize = 7;
+
+/// A manifest presented from the metadata of its object, fetched when its
+/// file is opened.
+#[derive(Debug)]
+pub struct LazyManifest {
+    api_resource: ApiResource,
+    namespace: Option<String>,
+    name: String,
+    // the resourceVersion listed last, and that of the content fetched
+    resource_version: Option<String>,
+    fetched: Option<String>,
+    // evicted from memory rather than listed by metadata, presented as usual
+    // again once fetched
+    evicted: bool,
+}
+
+impl LazyManifest {
+    /// The manifest of an object whose content was evicted from memory.
+    pub fn evicted(api_resource: ApiResource, namespace: Option<String>, name: String) -> Self {
+        LazyManifest {
+            api_resource,
+            namespace,
+            name,
+            resource_version: None,
+            fetched: None,
+            evicted: true,
+        }
+    }
+
+    /// Whether the content was evicted from memory.
+    pub fn is_evicted(&self) -> bool {
+        self.evicted
+    }
+
+    /// Drops what's known of the content, e.g. after evicting it, so it's
+    /// fetched again when opened.
+    pub fn forget_content(&mut self) {
+        self.fetched = None;
+    }
+}
+
+impl KubeFilesystem {
+    /// The resource types presented from the metadata of their objects, see
+    /// `metadataOnly`.
+    pub(super) fn metadata_only_resources(&self) -> Vec<ApiResource> {
+        let typed =
+            TYPED_RESOURCES[..METADATA_ONLY_RESOURCES]
+                .iter()
+                .map(|(plural, api_version)| ApiResource {
+                    api_version: api_version.to_string(),
+                    plural: plural.to_string(),
+                });
+        typed
+            .chain(self.profile.custom_resources.iter().cloned())
+            .filter(|resource| {
+                self.profile.shows_resource(&resource.plural)
+                    && self.profile.lists_metadata_only(&resource.plural)
+            })
+            .collect()
+    }
+
+    /// The resource type presented in a resource directory, to fetch objects
+    /// of it with.
+    pub(super) fn api_resource_of(&self, plural: &str) -> Option<ApiResource> {
+        let typed = TYPED_RESOURCES
+            .iter()
+            .find(|(typed, _)| *typed == plural)
+            .map(|(plural, api_version)| ApiResource {
+                api_version: api_version.to_string(),
+                plural: plural.to_string(),
+            });
+        typed.or_else(|| {
+            self.profile
+                .custom_resources
+                .iter()
+                .find(|resource| resource.plural == plural)
+                .cloned()
+        })
+    }
+
+    /// Whether a resource type is listed by metadata only with the given
+    /// parameters. Lists pinned to a resourceVersion are always complete, as
+    /// the objects couldn't be fetched as of then later.
+    pub(super) fn lists_metadata_only(&self, resource: &str, params: &ListParams) -> bool {
+        params.resource_version.is_none()
+            && self
+                .metadata_only_resources()
+                .iter()
+                .any(|api_resource| api_resource.plural == resource)
+    }
+
+    /// Keeps a resource type listed by metadata only up to date by polling
+    /// it.
+    pub(super) fn start_metadata_refresher(&mut self, api_resource: ApiResource) {
+        // refreshed for as long as the filesystem is mounted
+        let resource: &'static str = Box::leak(api_resource.plural.clone().into_boxed_str());
+        let interval = self.refresh_intervals.interval(resource);
+        if interval.is_zero() {
+            return;
+        }
+
+        refresh::spawn_metadata_poller(
+            resource,
+            api_resource,
+            self.kube_client.clone(),
+            self.refresh_schedule.clone(),
+            interval,
+            self.updates_tx.clone(),
+        );
+        self.refresh_statuses.insert(
+            resource,
+            RefreshStatus::new(RefreshStrategy::Poll(interval)),
+        );
+    }
+
+    /// Creates the directory of a resource type listed by metadata only in a
+    /// namespace, e.g. `<namespace>/configmaps/`, with an empty manifest file
+    /// for every object.
+    pub(super) fn create_metadata_only_node(
+        &mut self,
+        ns_inode: u64,
+        namespace: &str,
+        api_resource: &ApiResource,
+        params: &ListParams,
+    ) -> Option<u64> {
+        let dir_inode = self.create_dir_node(ns_inode, &api_resource.plural)?;
+        let client = self.kube_client.clone();
+        let mut keep = HashSet::new();
+        let list_result =
+            client.list_metadata_pages(api_resource, Some(namespace), params, |page| {
+                if page.relisted {
+                    keep.clear();
+                }
+                let objects: Vec<ObjectMeta> = page
+                    .list
+                    .items
+                    .into_iter()
+                    .map(|item| item.metadata)
+                    .collect();
+                self.sync_metadata_only_page(
+                    dir_inode,
+                    namespace,
+                    api_resource,
+                    &objects,
+                    &mut keep,
+                );
+            });
+        if let Err(e) = list_result {
+            log::error!(
+                "{} fetch failed for namespace {namespace}: {e}",
+                api_resource.plural
+            );
+            self.report_invalid(ns_inode, &api_resource.plural, &e.to_string());
+            return None;
+        }
+
+        self.clear_list_error(dir_inode);
+        self.remove_stale_manifests(dir_inode, &keep);
+        Some(dir_inode)
+    }
+
+    /// Replaces the manifests of every namespace with the listed objects, see
+    /// [`Self::sync_manifests`].
+    pub(super) fn sync_metadata_only(
+        &mut self,
+        api_resource: &ApiResource,
+        objects: Vec<ObjectMeta>,
+    ) {
+        let mut by_namespace: HashMap<String, Vec<ObjectMeta>> = HashMap::new();
+        for object in objects {
+            let namespace = object.namespace.clone().unwrap_or_default();
+            by_namespace.entry(namespace).or_default().push(object);
+        }
+
+        for ns_name in self.namespace_names() {
+            let Some(ns_inode) = self.namespace_inode(&ns_name) else {
+                continue;
+            };
+            let Some(dir_inode) = self
+                .state
+                .child_inode(ns_inode, &api_resource.plural)
+                .or_else(|| self.create_dir_node(ns_inode, &api_resource.plural))
+            else {
+                continue;
+            };
+
+            let objects = by_namespace.remove(&ns_name).unwrap_or_default();
+            self.sync_metadata_only_files(dir_inode, &ns_name, api_resource, &objects);
+        }
+    }
+
+    // creates the manifest files of new objects, and drops the content
+    // fetched for those that changed since
+    fn sync_metadata_only_files(
+        &mut self,
+        dir_inode: u64,
+        namespace: &str,
+        api_resource: &ApiResource,
+        objects: &[ObjectMeta],
+    ) {
+        self.clear_list_error(dir_inode);
+        let mut keep = HashSet::new();
+        self.sync_metadata_only_page(dir_inode, namespace, api_resource, objects, &mut keep);
+        self.remove_stale_manifests(dir_inode, &keep);
+    }
+
+    // creates the manifest files of some of the objects of a directory,
+    // adding the entries to keep for them
+    fn sync_metadata_only_page(
+        &mut self,
+        dir_inode: u64,
+        namespace: &str,
+        api_resource: &ApiResource,
+        objects: &[ObjectMeta],
+        keep: &mut HashSet<String>,
+    ) {
+        for object in objects {
+            let Some(name) = object.name.as_deref() else {
+                continue;
+            };
+            let file_name = self.manifest_file_name(name);
+            keep.insert(file_name.clone());
+
+            let inode = match self.state.child_inode(dir_inode, &file_name) {
+                Some(inode) => inode,
+                None => {
+                    let creation_time = object
+                        .creation_timestamp
+                        .as_ref()
+                        .and_then(|t| t.0.timestamp().try_into().ok())
+                        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+                        .unwrap_or(UNIX_EPOCH);
+                    let Some(inode) = self.create_manifest_node(
+                        dir_inode,
+                        &file_name,
+                        Vec::new(),
+                        creation_time,
+                        object.uid.as_deref(),
+                    ) else {
+                        continue;
+                    };
+                    inode
+                }
+            };
+            let lazy = self.lazy_manifests.entry(inode).or_insert(LazyManifest {
+                api_resource: api_resource.clone(),
+                namespace: Some(namespace.to_string()),
+                name: name.to_string(),
+                resource_version: None,
+                fetched: None,
+                evicted: false,
+            });
+            lazy.resource_version = object.resource_version.clone();
+            if lazy.fetched.is_some() && lazy.fetched != lazy.resource_version {
+                lazy.fetched = None;
+                self.set_node_content(inode, Vec::new());
+            }
+        }
+    }
+
+    /// Fetches the manifest behind a file presented from the metadata of its
+    /// object, or evicted from memory, unless it's current, returning `None`
+    /// for any other file.
+    pub(super) fn fetch_lazy_manifest(&mut self, inode: u64) -> Option<Result<(), libc::c_int>> {
+        let lazy = self.lazy_manifests.get(&inode)?;
+        if lazy.fetched.is_some() && lazy.fetched == lazy.resource_version {
+            return Some(Ok(()));
+        }
+        let (api_resource, namespace, name) = (
+            lazy.api_resource.clone(),
+            lazy.namespace.clone(),
+            lazy.name.clone(),
+        );
+        let path = match &namespace {
+            Some(namespace) => format!("{namespace}/{name}"),
+            None => name.clone(),
+        };
+
+        let object =
+            match self
+                .kube_client
+                .get_unstructured(&api_resource, namespace.as_deref(), &name)
+            {
+                Ok(object) => object,
+                Err(e) => {
+                    log::error!("failed to get {} {path}: {e}", api_resource.plural);
+                    return Some(Err(match e.status() {
+                        Some(reqwest::StatusCode::NOT_FOUND) => libc::ENOENT,
+                        status => request_errno(status),
+                    }));
+                }
+            };
+        let manifest = match self.try_render_unstructured(&object) {
+            Ok(manifest) => manifest,
+            Err(e) => {
+                let payload =
+                    self.render_failure(&api_resource.plural, namespace.as_deref(), &name, &e);
+                let file_name = self.manifest_file_name(&name);
+                let dir_inode = self.state.inodes.get(&inode).map(|node| node.parent)?;
+                if self.report_invalid(dir_inode, &file_name, &e) {
+                    self.lazy_manifests.remove(&inode);
+                    return Some(Err(libc::EIO));
+                }
+                payload
+            }
+        };
+        self.set_node_content(inode, manifest);
+        self.set_status_xattr(inode, &api_resource.plural, &object);
+        match self.lazy_manifests.get_mut(&inode) {
+            Some(lazy) if lazy.evicted => {
+                self.lazy_manifests.remove(&inode);
+            }
+            Some(lazy) => {
+                // possibly newer than listed
+                lazy.fetched = object["metadata"]["resourceVersion"]
+                    .as_str()
+                    .map(str::to_string);
+                lazy.resource_version = lazy.fetched.clone();
+            }
+            None => {}
+        }
+        Some(Ok(()))
+    }
+
+    /// The content of a file, fetched first if it's a manifest presented
+    /// from the metadata of its object or evicted from memory. `None` for
+    /// directories and for manifests that can't be fetched.
+    pub(super) fn file_content(&mut self, inode: u64) -> Option<Arc<Vec<u8>>> {
+        if let Some(Err(_)) = self.fetch_lazy_manifest(inode) {
+            return None;
+        }
+        match &self.state.inodes.get(&inode)?.content {
+            NodeContent::Bytes(content) => Some(Arc::clone(content)),
+            NodeContent::Children(_) => None,
+        }
+    }
+}
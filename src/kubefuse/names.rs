@@ -0,0 +1,138 @@
+//! Names of nodes as presented to the kernel. Objects, and custom resources in
+//! particular, may be named in ways that make for unusable file names, e.g.
+//! holding control characters or longer than NAME_MAX, which a single one of
+//! would be enough to make listing its directory fail. The tree keeps the
+//! names as they are; only what crosses to the kernel is encoded.
+//!
+//! Names that are fine are left alone. Others are percent-encoded behind a
+//! leading `%`, which is why names starting with `%` are encoded as well, so
+//! that every encoded name decodes back to the name it's from. Names still
+//! longer than NAME_MAX once encoded are cut short and end with `%~` and a
+//! hash of the whole name instead; those only decode by looking them up in
+//! their directory.
+
+use std::borrow::Cow;
+
+use sha2::{Digest, Sha256};
+
+/// The longest a file name can be, in bytes.
+pub const NAME_MAX: usize = 255;
+
+// marks a name cut short, followed by the start of the hash of the name
+const SHORTENED_MARKER: &str = "%~";
+const SHORTENED_HASH_LEN: usize = 16;
+
+fn needs_escaping(c: char) -> bool {
+    c == '%' || c == '/' || c.is_control()
+}
+
+/// The name a node goes by towards the kernel.
+pub fn encode_name(name: &str) -> Cow<'_, str> {
+    let fine = !name.is_empty()
+        && name != "."
+        && name != ".."
+        && !name.starts_with('%')
+        && !name.contains(|c: char| c == '/' || c.is_control())
+        && name.len() <= NAME_MAX;
+    if fine {
+        return Cow::Borrowed(name);
+    }
+
+    let mut encoded = String::from("%");
+    let mut bytes = [0; 4];
+    for c in name.chars() {
+        match needs_escaping(c) || (c == '.' && (name == "." || name == "..")) {
+            true => {
+                for byte in c.encode_utf8(&mut bytes).bytes() {
+                    encoded.push_str(&format!("%{byte:02X}"));
+                }
+            }
+            false => encoded.push(c),
+        }
+    }
+    if encoded.len() > NAME_MAX {
+        let hash = format!("{:x}", Sha256::digest(name.as_bytes()));
+        let mut cut = NAME_MAX - SHORTENED_MARKER.len() - SHORTENED_HASH_LEN;
+        while !encoded.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        encoded.truncate(cut);
+        encoded.push_str(SHORTENED_MARKER);
+        encoded.push_str(&hash[..SHORTENED_HASH_LEN]);
+    }
+    Cow::Owned(encoded)
+}
+
+/// The name an encoded one is from, or `None` for names cut short, and for
+/// names no node could go by.
+pub fn decode_name(encoded: &str) -> Option<Cow<'_, str>> {
+    let Some(escaped) = encoded.strip_prefix('%') else {
+        return Some(Cow::Borrowed(encoded));
+    };
+    let mut bytes = Vec::with_capacity(escaped.len());
+    let mut rest = escaped.as_bytes();
+    while let Some((&byte, tail)) = rest.split_first() {
+        if byte != b'%' {
+            bytes.push(byte);
+            rest = tail;
+            continue;
+        }
+        let hex = std::str::from_utf8(tail.get(..2)?).ok()?;
+        bytes.push(u8::from_str_radix(hex, 16).ok()?);
+        rest = &tail[2..];
+    }
+    let name = String::from_utf8(bytes).ok()?;
+    // `%6Fk` isn't how `ok` is encoded
+    (encode_name(&name) == encoded).then_some(Cow::Owned(name))
+}
+
+/// Encodes the names along a path, e.g. the target of a link, leaving its
+/// separators and `.` and `..` components alone.
+pub fn encode_path(path: &str) -> Cow<'_, str> {
+    let components: Vec<&str> = path.split('/').collect();
+    let fine = components
+        .iter()
+        .all(|component| is_path_syntax(component) || encode_name(component) == *component);
+    if fine {
+        return Cow::Borrowed(path);
+    }
+    let encoded: Vec<Cow<str>> = components
+        .into_iter()
+        .map(|component| match is_path_syntax(component) {
+            true => Cow::Borrowed(component),
+            false => encode_name(component),
+        })
+        .collect();
+    Cow::Owned(encoded.join("/"))
+}
+
+fn is_path_syntax(component: &str) -> bool {
+    matches!(component, "" | "." | "..")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encodes_unusable_names_reversibly() {
+        assert_eq!(encode_name("web-7d4b9c.yaml"), "web-7d4b9c.yaml");
+        for name in ["bell\u{7}.yaml", "%41", "..", "a/b", ""] {
+            let encoded = encode_name(name);
+            assert!(!encoded.contains(|c: char| c == '/' || c.is_control()));
+            assert_eq!(decode_name(&encoded).as_deref(), Some(name));
+        }
+        assert_eq!(encode_name("bell\u{7}.yaml"), "%bell%07.yaml");
+        assert_eq!(decode_name("%6Fk"), None);
+
+        let long = "x".repeat(300);
+        let encoded = encode_name(&long);
+        assert!(encoded.len() <= NAME_MAX);
+        assert!(encoded.contains(SHORTENED_MARKER));
+        assert_eq!(decode_name(&encoded), None);
+        assert_eq!(
+            encode_path("../../widgets/bell\u{7}.yaml"),
+            "../../widgets/%bell%07.yaml"
+        );
+    }
+}
@@ -0,0 +1,295 @@
+use std::{collections::BTreeMap, time::SystemTime};
+
+use k8s_openapi::{
+    api::core::v1::Namespace, apimachinery::pkg::apis::meta::v1::ObjectMeta, chrono,
+};
+use reqwest::StatusCode;
+use serde::Serialize;
+
+use crate::{client::ListParams, guardrail::DestructiveAction};
+
+use super::{
+    EVENTS_DIR_NAME, KubeFilesystem, NodeContent, ROOT_ATTR,
+    drafts::{DRAFT_SUFFIX, ManifestLocation},
+};
+
+/// Annotation of namespaces presented as the `README` file in their
+/// directory, e.g. operational notes of the team owning the namespace.
+const README_ANNOTATION: &str = "kubefuse.io/readme";
+const README_FILE_NAME: &str = "README";
+
+/// Marks the directory of a namespace being deleted, which is read-only.
+const TERMINATING_FILE_NAME: &str = ".terminating";
+
+// what `.terminating` holds
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct Termination {
+    since: Option<String>,
+    finalizers: Vec<String>,
+    // condition type -> message of the conditions holding the deletion up,
+    // e.g. resources or finalizers remaining in the namespace
+    blocked_by: BTreeMap<String, String>,
+}
+
+// objects the cluster creates in every namespace, they don't make it non-empty
+pub(super) const IMPLICIT_OBJECTS: [(&str, &str); 2] = [
+    ("configmaps", "kube-root-ca.crt"),
+    ("serviceaccounts", "default"),
+];
+
+impl KubeFilesystem {
+    /// Creates a namespace for `mkdir` in the root of the mount, then
+    /// populates its directory like that of any other namespace.
+    pub(super) fn create_namespace(&mut self, name: &str) -> Result<u64, libc::c_int> {
+        if self.resource_version.is_some() {
+            return Err(libc::EROFS); // pinned to the past
+        }
+        if self.state.child_inode(ROOT_ATTR.ino, name).is_some() {
+            return Err(libc::EEXIST);
+        }
+        if self.profile.hides_namespace(name) {
+            return Err(libc::EPERM); // it wouldn't be presented
+        }
+        let path = format!("/{name}");
+        if !self.mutation_quotas.allow(&path) {
+            log::error!("refusing to create namespace {name}: mutation limit exceeded");
+            return Err(libc::EAGAIN);
+        }
+
+        let namespace = Namespace {
+            metadata: ObjectMeta {
+                name: Some(name.to_string()),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        let namespace = self.kube_client.create(None, &namespace).map_err(|e| {
+            log::error!("failed to create namespace {name}: {e}");
+            match e.status() {
+                Some(StatusCode::CONFLICT) => libc::EEXIST,
+                Some(StatusCode::BAD_REQUEST | StatusCode::UNPROCESSABLE_ENTITY) => libc::EINVAL,
+                Some(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN) => libc::EACCES,
+                _ => libc::EIO,
+            }
+        })?;
+
+        log::info!("created namespace {name}");
+        self.populate_namespaces(ROOT_ATTR.ino, &[namespace], &ListParams::default());
+        self.namespace_inode(name).ok_or(libc::EIO)
+    }
+
+    /// Presents a namespace accessed by name before the namespaces were
+    /// listed, with `lazyNamespaces`. It's requested on its own, so it can be
+    /// presented even where listing namespaces isn't allowed.
+    pub(super) fn resolve_namespace(&mut self, name: &str) {
+        if !self.profile.lazy_namespaces
+            || self.namespaces_listed
+            || !super::is_namespace_name(name)
+            || self.profile.hides_namespace(name)
+        {
+            return;
+        }
+        match self.kube_client.get::<Namespace>(None, name) {
+            Ok(namespace) => {
+                log::debug!("resolved namespace {name}");
+                self.populate_namespaces(ROOT_ATTR.ino, &[namespace], &ListParams::default());
+            }
+            Err(e) if e.status() == Some(StatusCode::NOT_FOUND) => {}
+            Err(e) => log::warn!("failed to resolve namespace {name}: {e}"),
+        }
+    }
+
+    /// Lists the namespaces once the root of the mount is listed, with
+    /// `lazyNamespaces`, and keeps them up to date from then on. Until that
+    /// succeeds, e.g. while listing namespaces isn't allowed, only those
+    /// accessed by name are presented.
+    pub(super) fn list_lazy_namespaces(&mut self) {
+        if !self.profile.lazy_namespaces || self.namespaces_listed {
+            return;
+        }
+        match self
+            .kube_client
+            .list::<Namespace>(None, &ListParams::default())
+        {
+            Ok(resp) => {
+                let unresolved: Vec<Namespace> = resp
+                    .items
+                    .into_iter()
+                    .filter(|namespace| {
+                        let name = namespace.metadata.name.as_deref().unwrap_or_default();
+                        self.namespace_inode(name).is_none()
+                    })
+                    .collect();
+                self.populate_namespaces(ROOT_ATTR.ino, &unresolved, &ListParams::default());
+                self.namespaces_listed = true;
+                self.start_namespace_refresher();
+            }
+            Err(e) => log::warn!("namespaces fetch failed, presenting those accessed by name: {e}"),
+        }
+    }
+
+    /// Presents the readme annotation of a namespace as the `README` file in
+    /// its directory, removing the file once the annotation is gone.
+    pub(super) fn sync_readme(&mut self, ns_inode: u64, namespace: &Namespace) {
+        let readme = namespace
+            .metadata
+            .annotations
+            .as_ref()
+            .and_then(|annotations| annotations.get(README_ANNOTATION));
+        let readme_inode = self.state.child_inode(ns_inode, README_FILE_NAME);
+        let Some(readme) = readme else {
+            if let Some(readme_inode) = readme_inode {
+                self.state.remove_node(readme_inode);
+            }
+            return;
+        };
+
+        let mut content = readme.clone().into_bytes();
+        if !content.ends_with(b"\n") {
+            content.push(b'\n');
+        }
+        match readme_inode {
+            Some(readme_inode) => self.update_node_content(readme_inode, content),
+            None => {
+                self.create_content_node(ns_inode, README_FILE_NAME, content, SystemTime::now());
+            }
+        }
+    }
+
+    /// Presents the deletion of a namespace in progress as the `.terminating`
+    /// file in its directory: since when, the finalizers remaining and what
+    /// holds the deletion up according to the conditions of the namespace.
+    /// The file goes away with the namespace, or if the deletion is undone.
+    pub(super) fn sync_terminating_marker(&mut self, ns_inode: u64, namespace: &Namespace) {
+        let marker_inode = self.state.child_inode(ns_inode, TERMINATING_FILE_NAME);
+        let terminating = namespace.metadata.deletion_timestamp.is_some()
+            || namespace
+                .status
+                .as_ref()
+                .and_then(|status| status.phase.as_deref())
+                == Some("Terminating");
+        if !terminating {
+            if let Some(marker_inode) = marker_inode {
+                self.state.remove_node(marker_inode);
+            }
+            return;
+        }
+
+        let mut finalizers = namespace
+            .spec
+            .as_ref()
+            .and_then(|spec| spec.finalizers.clone())
+            .unwrap_or_default();
+        finalizers.extend(namespace.metadata.finalizers.iter().flatten().cloned());
+        let blocked_by = namespace
+            .status
+            .as_ref()
+            .and_then(|status| status.conditions.as_ref())
+            .into_iter()
+            .flatten()
+            .filter(|condition| condition.status == "True")
+            .map(|condition| {
+                let message = condition.message.clone().unwrap_or_default();
+                (condition.type_.clone(), message)
+            })
+            .collect();
+        let termination = Termination {
+            since: namespace
+                .metadata
+                .deletion_timestamp
+                .as_ref()
+                .map(|time| time.0.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+            finalizers,
+            blocked_by,
+        };
+        let content = serde_yaml::to_string(&termination)
+            .unwrap_or_default()
+            .into_bytes();
+        match marker_inode {
+            Some(marker_inode) => self.update_node_content(marker_inode, content),
+            None => {
+                self.create_content_node(
+                    ns_inode,
+                    TERMINATING_FILE_NAME,
+                    content,
+                    SystemTime::now(),
+                );
+            }
+        }
+    }
+
+    /// Whether a manifest belongs to a namespace being deleted, or is the
+    /// manifest of one. They can't be changed anymore.
+    pub(super) fn is_terminating(&self, location: &ManifestLocation) -> bool {
+        let namespace = match location.resource.as_str() {
+            "namespaces" if location.namespace.is_none() => &location.name,
+            _ => match &location.namespace {
+                Some(namespace) => namespace,
+                None => return false,
+            },
+        };
+        self.namespace_inode(namespace)
+            .and_then(|ns_inode| self.state.child_inode(ns_inode, TERMINATING_FILE_NAME))
+            .is_some()
+    }
+
+    /// Deletes a namespace for `rmdir`. Unless deletes are enabled and the
+    /// namespace holds no objects, the deletion has to be confirmed first.
+    /// The directory stays until the API server finishes the deletion.
+    pub(super) fn remove_namespace(&mut self, name: &str) -> Result<(), libc::c_int> {
+        let ns_inode = self.namespace_inode(name).ok_or(libc::EPERM)?;
+        if self.resource_version.is_some() {
+            return Err(libc::EROFS); // pinned to the past
+        }
+        let action = DestructiveAction::DeleteNamespace(name.to_string());
+        if !self.profile.enable_delete || !self.namespace_is_empty(ns_inode) {
+            self.request_confirmation(action);
+            return Err(libc::EPERM);
+        }
+        if !self.mutation_quotas.allow(&action.path()) {
+            log::error!("refusing to {action}: mutation limit exceeded");
+            return Err(libc::EAGAIN);
+        }
+
+        self.perform(&action)?;
+        log::info!("deleted namespace {name}");
+        Ok(())
+    }
+
+    /// Whether the presented resource directories of a namespace hold no
+    /// objects but those the cluster creates in every namespace. Events don't
+    /// count, they expire on their own.
+    fn namespace_is_empty(&self, ns_inode: u64) -> bool {
+        let Some(NodeContent::Children(resources)) =
+            self.state.inodes.get(&ns_inode).map(|node| &node.content)
+        else {
+            return false;
+        };
+
+        resources
+            .iter()
+            .filter(|(resource, _)| *resource != EVENTS_DIR_NAME)
+            .filter_map(
+                |(resource, inode)| match &self.state.inodes.get(inode)?.content {
+                    NodeContent::Children(files) => Some((resource, files)),
+                    NodeContent::Bytes(_) => None,
+                },
+            )
+            .all(|(resource, files)| {
+                files.iter().all(|(file_name, inode)| {
+                    let is_file = self
+                        .state
+                        .inodes
+                        .get(inode)
+                        .is_some_and(|node| matches!(node.content, NodeContent::Bytes(_)));
+                    !is_file
+                        || file_name.ends_with(DRAFT_SUFFIX)
+                        || self
+                            .profile
+                            .manifest_object_name(file_name)
+                            .is_some_and(|name| IMPLICIT_OBJECTS.contains(&(resource, name)))
+                })
+            })
+    }
+}
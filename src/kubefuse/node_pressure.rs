@@ -0,0 +1,135 @@
+use std::{collections::BTreeMap, time::SystemTime};
+
+use serde_json::Value;
+
+use super::KubeFilesystem;
+
+const NODE_PRESSURE_NAME: &str = "pressure.txt";
+
+const HEADER: [&str; 7] = [
+    "NODE",
+    "READY",
+    "SCHEDULABLE",
+    "MEMORY",
+    "DISK",
+    "PID",
+    "TAINTS",
+];
+const PRESSURE_CONDITIONS: [&str; 3] = ["MemoryPressure", "DiskPressure", "PIDPressure"];
+
+/// The row of every node in `_cluster/nodes/pressure.txt`, by node name.
+pub type NodePressure = BTreeMap<String, Vec<String>>;
+
+// the columns of a node after its name
+fn node_row(node: &Value) -> Vec<String> {
+    let condition = |type_: &str| {
+        node["status"]["conditions"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .find(|condition| condition["type"].as_str() == Some(type_))
+            .and_then(|condition| condition["status"].as_str())
+            .unwrap_or("-")
+            .to_string()
+    };
+    let schedulable = match node["spec"]["unschedulable"].as_bool() {
+        Some(true) => "no (cordoned)",
+        _ => "yes",
+    };
+    let taints: Vec<String> = node["spec"]["taints"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|taint| {
+            let key = taint["key"].as_str().unwrap_or_default();
+            let effect = taint["effect"].as_str().unwrap_or_default();
+            match taint["value"].as_str().filter(|value| !value.is_empty()) {
+                Some(value) => format!("{key}={value}:{effect}"),
+                None => format!("{key}:{effect}"),
+            }
+        })
+        .collect();
+
+    let mut row = vec![condition("Ready"), schedulable.to_string()];
+    row.extend(PRESSURE_CONDITIONS.map(condition));
+    row.push(match taints.is_empty() {
+        true => "<none>".to_string(),
+        false => taints.join(","),
+    });
+    row
+}
+
+impl KubeFilesystem {
+    /// Creates `pressure.txt` in the directory of the node manifests.
+    pub(super) fn create_node_pressure_node(&mut self, nodes_inode: u64) {
+        self.node_pressure_inode = self.create_content_node(
+            nodes_inode,
+            NODE_PRESSURE_NAME,
+            Vec::new(),
+            SystemTime::now(),
+        );
+        self.render_node_pressure();
+    }
+
+    /// Replaces the rows of `pressure.txt` with those of the given nodes.
+    pub(super) fn sync_node_pressure<'a>(&mut self, nodes: impl IntoIterator<Item = &'a Value>) {
+        self.node_pressure = nodes
+            .into_iter()
+            .filter_map(|node| {
+                Some((
+                    node["metadata"]["name"].as_str()?.to_string(),
+                    node_row(node),
+                ))
+            })
+            .collect();
+        self.render_node_pressure();
+    }
+
+    /// Updates the row of a node in `pressure.txt`, removing it once the node
+    /// is gone.
+    pub(super) fn watch_node_pressure(&mut self, node: &Value, deleted: bool) {
+        let Some(name) = node["metadata"]["name"].as_str() else {
+            return;
+        };
+        if deleted {
+            self.node_pressure.remove(name);
+        } else {
+            self.node_pressure.insert(name.to_string(), node_row(node));
+        }
+        self.render_node_pressure();
+    }
+
+    /// Renders `_cluster/nodes/pressure.txt`, a table of the readiness,
+    /// pressure conditions, cordons and taints of every node, for a look at
+    /// the capacity of the cluster as a whole.
+    fn render_node_pressure(&mut self) {
+        let Some(inode) = self.node_pressure_inode else {
+            return;
+        };
+        let rows: Vec<Vec<&str>> = std::iter::once(HEADER.to_vec())
+            .chain(self.node_pressure.iter().map(|(name, row)| {
+                std::iter::once(name.as_str())
+                    .chain(row.iter().map(String::as_str))
+                    .collect()
+            }))
+            .collect();
+        let mut widths = [0; HEADER.len()];
+        for row in &rows {
+            for (width, column) in widths.iter_mut().zip(row) {
+                *width = (*width).max(column.len());
+            }
+        }
+
+        let mut out = String::new();
+        for row in rows {
+            let line: Vec<String> = row
+                .iter()
+                .zip(widths)
+                .map(|(column, width)| format!("{column:width$}"))
+                .collect();
+            out.push_str(line.join("  ").trim_end());
+            out.push('\n');
+        }
+        self.update_node_content(inode, out.into_bytes());
+    }
+}
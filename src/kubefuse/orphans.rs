@@ -0,0 +1,105 @@
+use std::collections::{BTreeMap, HashSet};
+
+use serde_json::Value;
+
+use super::{KubeFilesystem, ROOT_ATTR, namespaces::IMPLICIT_OBJECTS};
+
+pub const ORPHANS_FILE_NAME: &str = "orphans.txt";
+
+// Secrets managed by something that doesn't say so with an owner reference
+const MANAGED_SECRET_TYPES: [&str; 2] =
+    ["kubernetes.io/service-account-token", "helm.sh/release.v1"];
+
+/// Why an object of the given resource looks like it's left over, if it does.
+/// Only objects nothing owns are considered.
+fn orphan_reason(resource: &str, object: &Value, referenced: bool) -> Option<String> {
+    let metadata = &object["metadata"];
+    if metadata["ownerReferences"]
+        .as_array()
+        .is_some_and(|owners| !owners.is_empty())
+    {
+        return None;
+    }
+    let name = metadata["name"].as_str()?;
+    if IMPLICIT_OBJECTS.contains(&(resource, name)) {
+        return None;
+    }
+    match resource {
+        "configmaps" if !referenced => Some("not referenced".to_string()),
+        "secrets" if !referenced => {
+            let secret_type = object["type"].as_str().unwrap_or_default();
+            (!MANAGED_SECRET_TYPES.contains(&secret_type)).then(|| "not referenced".to_string())
+        }
+        "replicasets" => (object["spec"]["replicas"].as_u64() == Some(0))
+            .then(|| "scaled down to 0 replicas".to_string()),
+        "persistentvolumeclaims" => {
+            let phase = object["status"]["phase"].as_str().unwrap_or("Pending");
+            (phase != "Bound").then(|| format!("not bound: {phase}"))
+        }
+        _ => None,
+    }
+}
+
+impl KubeFilesystem {
+    /// The namespace an inode is the `orphans.txt` of, if it is one.
+    pub(super) fn orphans_namespace(&self, inode: u64) -> Option<String> {
+        let node = self.state.inodes.get(&inode)?;
+        let ns_node = self.state.inodes.get(&node.parent)?;
+        (node.name == ORPHANS_FILE_NAME
+            && ns_node.parent == ROOT_ATTR.ino
+            && self.namespace_inode(&ns_node.name) == Some(node.parent))
+        .then(|| ns_node.name.clone())
+    }
+
+    /// Renders the `orphans.txt` of a namespace: the objects in it nothing
+    /// owns and nothing presented references, which are likely left over,
+    /// e.g. ConfigMaps no pod uses any more, ReplicaSets scaled down to 0 or
+    /// claims that never got a volume. One `<resource>/<name>: <reason>` line
+    /// per object.
+    pub(super) fn render_orphans(&mut self, inode: u64) {
+        let Some(namespace) = self.orphans_namespace(inode) else {
+            return;
+        };
+        let referenced: HashSet<_> = self
+            .manifest_references
+            .iter()
+            .filter(|(manifest, _)| {
+                self.manifest_location(**manifest)
+                    .is_some_and(|location| location.namespace.as_ref() == Some(&namespace))
+            })
+            .flat_map(|(_, (_, references))| references.iter().cloned())
+            .collect();
+
+        let mut orphans = BTreeMap::new();
+        for (location, manifest) in self.presented_manifests() {
+            if location.namespace.as_ref() != Some(&namespace) {
+                continue;
+            }
+            let resource = location.resource.as_str();
+            if !matches!(
+                resource,
+                "configmaps" | "secrets" | "replicasets" | "persistentvolumeclaims"
+            ) {
+                continue;
+            }
+            let Some(content) = self.file_content(manifest) else {
+                continue;
+            };
+            let Ok(object) = serde_yaml::from_slice::<Value>(&content) else {
+                continue; // e.g. truncated
+            };
+            let is_referenced = referenced
+                .iter()
+                .any(|(kind, name)| *kind == resource && *name == location.name);
+            if let Some(reason) = orphan_reason(resource, &object, is_referenced) {
+                orphans.insert(format!("{resource}/{}", location.name), reason);
+            }
+        }
+
+        let out: String = orphans
+            .into_iter()
+            .map(|(object, reason)| format!("{object}: {reason}\n"))
+            .collect();
+        self.set_node_content(inode, out.into_bytes());
+    }
+}
@@ -0,0 +1,56 @@
+use std::{
+    backtrace::Backtrace,
+    panic::{self, AssertUnwindSafe},
+    sync::{Mutex, Once},
+};
+
+// report of the latest panic, recorded by the hook before the stack unwinds
+static LAST_REPORT: Mutex<Option<String>> = Mutex::new(None);
+
+static INSTALL_HOOK: Once = Once::new();
+
+/// Panics caught in FUSE callbacks, reported in `.kubefuse/panics`.
+#[derive(Debug, Default)]
+pub struct PanicStats {
+    pub count: u64,
+    // operation, message and backtrace of the latest one
+    pub last: Option<String>,
+}
+
+impl PanicStats {
+    pub fn render(&self) -> String {
+        let mut out = format!("panics: {}\n", self.count);
+        if let Some(last) = &self.last {
+            out.push_str(&format!("last:\n{last}\n"));
+        }
+        out
+    }
+}
+
+/// Records a report with a backtrace of every panic, while still printing it
+/// through the previous hook. Installed once however many trees are built.
+pub fn install_hook() {
+    INSTALL_HOOK.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let report = format!("{info}\n{}", Backtrace::force_capture());
+            if let Ok(mut last) = LAST_REPORT.lock() {
+                *last = Some(report);
+            }
+            previous(info);
+        }));
+    });
+}
+
+/// Runs the body of a FUSE callback, catching a panic in it so that it fails
+/// the request instead of the whole mount. The reply of a callback that
+/// panicked is dropped unsent, which fuser answers with EIO.
+pub fn catch(callback: impl FnOnce()) -> Result<(), String> {
+    panic::catch_unwind(AssertUnwindSafe(callback)).map_err(|_| {
+        LAST_REPORT
+            .lock()
+            .ok()
+            .and_then(|mut last| last.take())
+            .unwrap_or_else(|| "no report was recorded".to_string())
+    })
+}
@@ -0,0 +1,318 @@
+use std::{
+    collections::{BTreeMap, HashSet},
+    time::SystemTime,
+};
+
+use serde_json::Value;
+
+use crate::{client::ApiResource, security};
+
+use super::{KubeFilesystem, NodeContent};
+
+const CONTAINERS_DIR_NAME: &str = "containers";
+const SECURITY_FILE_NAME: &str = "security.txt";
+// may hold values of Secrets, so it's only readable by the owner like them
+const ENV_FILE_NAME: &str = "env";
+
+// spec field -> status field of each kind of container
+const CONTAINER_FIELDS: [(&str, &str); 3] = [
+    ("initContainers", "initContainerStatuses"),
+    ("containers", "containerStatuses"),
+    ("ephemeralContainers", "ephemeralContainerStatuses"),
+];
+
+/// Whether objects of a resource type are pods, which get a directory next to
+/// their manifest.
+pub(super) fn is_pods(api_resource: &ApiResource) -> bool {
+    api_resource.api_version == "v1" && api_resource.plural == "pods"
+}
+
+/// The container names of a pod, init and ephemeral containers included.
+pub(super) fn container_names(pod: &Value) -> impl Iterator<Item = &str> {
+    CONTAINER_FIELDS
+        .iter()
+        .filter_map(|(spec_field, _)| pod["spec"][spec_field].as_array())
+        .flatten()
+        .filter_map(|container| container["name"].as_str())
+}
+
+// the value of a `fieldRef`, for the fields the downward API offers
+fn pod_field<'a>(pod: &'a Value, field_path: &str) -> Option<&'a str> {
+    if let Some((field, key)) = field_path
+        .strip_suffix("']")
+        .and_then(|path| path.split_once("['"))
+    {
+        return match field {
+            "metadata.labels" | "metadata.annotations" => {
+                let field = field.strip_prefix("metadata.")?;
+                pod["metadata"][field][key].as_str()
+            }
+            _ => None,
+        };
+    }
+    let (object, field) = field_path.split_once('.')?;
+    match (object, field) {
+        ("metadata", "name" | "namespace" | "uid")
+        | ("spec", "nodeName" | "serviceAccountName")
+        | ("status", "hostIP" | "podIP") => pod[object][field].as_str(),
+        _ => None,
+    }
+}
+
+impl KubeFilesystem {
+    /// Creates or updates `<pod>/` next to the manifest of a pod, holding the
+    /// logs of its containers, a `containers/<container>/` directory per
+    /// container with its spec, status, image, restart count and environment,
+    /// and `security.txt` summarizing what the pod is allowed to do, which are
+    /// hard to find in the manifest of a pod. Pods have no typed model, so
+    /// this only happens where they are presented as custom resources or
+    /// discovered.
+    pub(super) fn sync_pod_dir(&mut self, pods_inode: u64, pod: &Value) {
+        let Some(name) = pod["metadata"]["name"].as_str() else {
+            return;
+        };
+        let Some(pod_inode) = self
+            .state
+            .child_inode(pods_inode, name)
+            .or_else(|| self.create_dir_node(pods_inode, name))
+        else {
+            return;
+        };
+        self.sync_log_files(pod_inode, pod);
+        self.sync_container_dirs(pod_inode, pod);
+
+        let namespace = pod["metadata"]["namespace"].as_str().unwrap_or_default();
+        let summary = security::summarize(pod, &self.namespace_labels(namespace));
+        match self.state.child_inode(pod_inode, SECURITY_FILE_NAME) {
+            Some(inode) => self.update_node_content(inode, summary.into_bytes()),
+            None => {
+                self.create_content_node(
+                    pod_inode,
+                    SECURITY_FILE_NAME,
+                    summary.into_bytes(),
+                    SystemTime::now(),
+                );
+            }
+        }
+    }
+
+    // the labels of a namespace as presented in its manifest
+    fn namespace_labels(&self, namespace: &str) -> Value {
+        let manifest_name = self.manifest_file_name("manifest");
+        let Some(NodeContent::Bytes(manifest)) = self
+            .namespace_inode(namespace)
+            .and_then(|ns_inode| self.state.child_inode(ns_inode, &manifest_name))
+            .and_then(|inode| self.state.inodes.get(&inode))
+            .map(|node| &node.content)
+        else {
+            return Value::Null;
+        };
+        serde_yaml::from_slice::<Value>(manifest)
+            .map(|namespace| namespace["metadata"]["labels"].clone())
+            .unwrap_or_default()
+    }
+
+    fn sync_container_dirs(&mut self, pod_inode: u64, pod: &Value) {
+        let Some(containers_inode) = self
+            .state
+            .child_inode(pod_inode, CONTAINERS_DIR_NAME)
+            .or_else(|| self.create_dir_node(pod_inode, CONTAINERS_DIR_NAME))
+        else {
+            return;
+        };
+
+        let mut containers = BTreeMap::new();
+        for (spec_field, status_field) in CONTAINER_FIELDS {
+            for container in pod["spec"][spec_field].as_array().into_iter().flatten() {
+                let Some(name) = container["name"].as_str() else {
+                    continue;
+                };
+                let status = pod["status"][status_field]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .find(|status| status["name"].as_str() == Some(name));
+                containers.insert(
+                    name.to_string(),
+                    self.container_files(pod, container, status),
+                );
+            }
+        }
+
+        self.remove_stale_entries(containers_inode, &containers.keys().cloned().collect());
+        for (name, files) in containers {
+            let Some(container_inode) = self
+                .state
+                .child_inode(containers_inode, &name)
+                .or_else(|| self.create_dir_node(containers_inode, &name))
+            else {
+                continue;
+            };
+            let keep: HashSet<String> = files.keys().cloned().collect();
+            for (file_name, content) in files {
+                match self.state.child_inode(container_inode, &file_name) {
+                    Some(inode) => self.update_node_content(inode, content),
+                    None => {
+                        let inode = self.create_content_node(
+                            container_inode,
+                            &file_name,
+                            content,
+                            SystemTime::now(),
+                        );
+                        if file_name == ENV_FILE_NAME
+                            && let Some(node) = inode.and_then(|i| self.state.inodes.get_mut(&i))
+                        {
+                            node.attrs.perm = 0o400;
+                        }
+                    }
+                }
+            }
+            self.remove_stale_entries(container_inode, &keep);
+        }
+    }
+
+    /// Removes the entries of a directory that aren't in `keep`.
+    pub(super) fn remove_stale_entries(&mut self, dir_inode: u64, keep: &HashSet<String>) {
+        let stale: Vec<u64> = match self.state.inodes.get(&dir_inode).map(|n| &n.content) {
+            Some(NodeContent::Children(children)) => children
+                .iter()
+                .filter(|(name, _)| !keep.contains(*name))
+                .map(|(_, inode)| *inode)
+                .collect(),
+            _ => Vec::new(),
+        };
+        for inode in stale {
+            self.state.remove_node(inode);
+        }
+    }
+
+    // the files of the directory of a container, by name
+    fn container_files(
+        &self,
+        pod: &Value,
+        container: &Value,
+        status: Option<&Value>,
+    ) -> BTreeMap<String, Vec<u8>> {
+        let to_yaml = |value: &Value| serde_yaml::to_string(value).unwrap_or_default();
+        let mut files = BTreeMap::new();
+        files.insert("spec.yaml".to_string(), to_yaml(container));
+        files.insert(
+            "status.yaml".to_string(),
+            status.map(to_yaml).unwrap_or_default(),
+        );
+        files.insert(
+            "image".to_string(),
+            format!("{}\n", container["image"].as_str().unwrap_or_default()),
+        );
+        files.insert(
+            "restarts".to_string(),
+            format!(
+                "{}\n",
+                status.and_then(|s| s["restartCount"].as_u64()).unwrap_or(0)
+            ),
+        );
+        files.insert(
+            ENV_FILE_NAME.to_string(),
+            self.container_env(pod, container),
+        );
+        files
+            .into_iter()
+            .map(|(name, content)| (name, content.into_bytes()))
+            .collect()
+    }
+
+    /// Renders the environment of a container as `NAME=value` lines, sorted
+    /// by name, resolving references to ConfigMaps and Secrets from their
+    /// key directories and to pod fields from the pod. Variables that can't
+    /// be resolved, e.g. because Secrets are redacted, are listed as comments.
+    fn container_env(&self, pod: &Value, container: &Value) -> String {
+        let namespace = pod["metadata"]["namespace"].as_str().unwrap_or_default();
+        // name -> value, `Err` with where it comes from if it can't be resolved
+        let mut env: BTreeMap<String, Result<String, String>> = BTreeMap::new();
+
+        for source in container["envFrom"].as_array().into_iter().flatten() {
+            let prefix = source["prefix"].as_str().unwrap_or_default();
+            for (resource, field) in [("configmaps", "configMapRef"), ("secrets", "secretRef")] {
+                let Some(object) = source[field]["name"].as_str() else {
+                    continue;
+                };
+                for (key, value) in self.object_keys(namespace, resource, object) {
+                    env.insert(format!("{prefix}{key}"), Ok(value));
+                }
+            }
+        }
+
+        for var in container["env"].as_array().into_iter().flatten() {
+            let Some(name) = var["name"].as_str() else {
+                continue;
+            };
+            let from = &var["valueFrom"];
+            let value = if let Some(value) = var["value"].as_str() {
+                Ok(value.to_string())
+            } else if let Some(field_path) = from["fieldRef"]["fieldPath"].as_str() {
+                pod_field(pod, field_path)
+                    .map(str::to_string)
+                    .ok_or_else(|| format!("field {field_path}"))
+            } else if from["resourceFieldRef"].is_object() {
+                let resource = from["resourceFieldRef"]["resource"]
+                    .as_str()
+                    .unwrap_or_default();
+                Err(format!("resource {resource}"))
+            } else {
+                let (resource, reference) = if from["secretKeyRef"].is_object() {
+                    ("secrets", &from["secretKeyRef"])
+                } else {
+                    ("configmaps", &from["configMapKeyRef"])
+                };
+                let object = reference["name"].as_str().unwrap_or_default();
+                let key = reference["key"].as_str().unwrap_or_default();
+                self.object_keys(namespace, resource, object)
+                    .remove(key)
+                    .ok_or_else(|| format!("{resource}/{object} key {key}"))
+            };
+            env.insert(name.to_string(), value);
+        }
+
+        env.into_iter()
+            .map(|(name, value)| match value {
+                Ok(value) => format!("{name}={value}\n"),
+                Err(from) => format!("# {name} from {from}, not resolved\n"),
+            })
+            .collect()
+    }
+
+    // the data keys of a ConfigMap or Secret as presented in its key
+    // directory, empty if there is none
+    fn object_keys(
+        &self,
+        namespace: &str,
+        resource: &str,
+        object: &str,
+    ) -> BTreeMap<String, String> {
+        let manifest_name = self.manifest_file_name(object);
+        let Some(dir_inode) = self
+            .namespace_inode(namespace)
+            .and_then(|ns_inode| self.state.child_inode(ns_inode, resource))
+            .and_then(|resource_inode| self.state.child_inode(resource_inode, object))
+        else {
+            return BTreeMap::new();
+        };
+        let Some(NodeContent::Children(children)) =
+            self.state.inodes.get(&dir_inode).map(|node| &node.content)
+        else {
+            return BTreeMap::new();
+        };
+        children
+            .iter()
+            .filter(|(name, _)| **name != manifest_name)
+            .filter_map(
+                |(name, inode)| match &self.state.inodes.get(inode)?.content {
+                    NodeContent::Bytes(value) => {
+                        Some((name.clone(), String::from_utf8_lossy(value).into_owned()))
+                    }
+                    NodeContent::Children(_) => None,
+                },
+            )
+            .collect()
+    }
+}
@@ -0,0 +1,527 @@
+use std::{
+    collections::BTreeMap,
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+    },
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use k8s_openapi::{
+    apimachinery::pkg::apis::meta::v1::{ObjectMeta, WatchEvent},
+    chrono,
+    serde::de::DeserializeOwned,
+};
+
+use crate::{
+    client::{ApiResource, KubeClient, ListParams},
+    config::RefreshWindow,
+};
+
+use super::{CLUSTER_DIR_NAME, CONTROL_DIR_NAME, ENDPOINTS_NAME, KubeFilesystem};
+
+/// A change to the filesystem tree prepared by a background refresher. These
+/// are applied by the FUSE thread so that the inode table is only ever touched
+/// from a single thread.
+pub type Update = Box<dyn FnOnce(&mut KubeFilesystem) + Send>;
+
+#[derive(Debug, Clone, Copy)]
+pub enum RefreshStrategy {
+    /// Changes are streamed from the API server as they happen.
+    Watch,
+    /// The resource is re-listed every interval.
+    Poll(Duration),
+}
+
+impl fmt::Display for RefreshStrategy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RefreshStrategy::Watch => write!(f, "watch"),
+            RefreshStrategy::Poll(interval) => write!(f, "poll every {}s", interval.as_secs()),
+        }
+    }
+}
+
+// how often paused refreshers check whether they were resumed
+const PAUSE_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+
+/// When the background refreshers may talk to the API server, shared with
+/// their threads so that pausing and resuming takes effect without restarting
+/// them.
+#[derive(Debug)]
+pub struct RefreshSchedule {
+    paused: AtomicBool,
+    windows: Vec<RefreshWindow>,
+}
+
+impl RefreshSchedule {
+    pub fn new(windows: Vec<RefreshWindow>, paused: bool) -> Self {
+        RefreshSchedule {
+            paused: AtomicBool::new(paused),
+            windows,
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    /// The interval of the refresh window the current time falls in, if any.
+    pub fn window_interval(&self) -> Option<Duration> {
+        let since_epoch = SystemTime::now().duration_since(UNIX_EPOCH).ok()?;
+        let minute = (since_epoch.as_secs() % (24 * 60 * 60) / 60) as u32;
+        self.windows
+            .iter()
+            .filter(|window| window.contains(minute))
+            .map(|window| Duration::from_secs(window.interval))
+            .max()
+    }
+
+    /// Sleeps for `interval`, or that of the current refresh window if
+    /// longer, and then for as long as refreshing is paused.
+    fn wait(&self, interval: Duration) {
+        thread::sleep(interval.max(self.window_interval().unwrap_or_default()));
+        self.wait_unpaused();
+    }
+
+    fn wait_unpaused(&self) {
+        while self.is_paused() {
+            thread::sleep(PAUSE_CHECK_INTERVAL);
+        }
+    }
+}
+
+/// A change to a single object reported by a watch.
+pub enum WatchChange<T> {
+    /// The object was created or modified.
+    Applied(T),
+    Deleted(T),
+}
+
+/// How a single resource type is kept up to date, reported in `.kubefuse/stats`.
+pub struct RefreshStatus {
+    pub strategy: RefreshStrategy,
+    pub refreshes: u64,
+    pub last_refresh: Option<SystemTime>,
+    pub last_error: Option<String>,
+}
+
+impl RefreshStatus {
+    pub fn new(strategy: RefreshStrategy) -> Self {
+        RefreshStatus {
+            strategy,
+            refreshes: 0,
+            last_refresh: None,
+            last_error: None,
+        }
+    }
+}
+
+/// Renders the contents of the `.kubefuse/stats` file.
+pub fn render_stats(statuses: &BTreeMap<&'static str, RefreshStatus>) -> String {
+    let mut out = String::from("refresh:\n");
+    for (resource, status) in statuses {
+        let last_refresh = status
+            .last_refresh
+            .and_then(format_time)
+            .unwrap_or_else(|| "never".to_string());
+
+        out.push_str(&format!("  {resource}:\n"));
+        out.push_str(&format!("    strategy: {}\n", status.strategy));
+        out.push_str(&format!("    refreshes: {}\n", status.refreshes));
+        out.push_str(&format!("    lastRefresh: {last_refresh}\n"));
+        if let Some(error) = &status.last_error {
+            out.push_str(&format!("    lastError: {error:?}\n"));
+        }
+    }
+    out
+}
+
+/// Formats a point in time as an RFC 3339 timestamp in UTC.
+pub fn format_time(time: SystemTime) -> Option<String> {
+    let since_epoch = time.duration_since(UNIX_EPOCH).ok()?;
+    let time = chrono::DateTime::from_timestamp(since_epoch.as_secs() as i64, 0)?;
+    Some(time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+}
+
+impl KubeFilesystem {
+    /// When the content of a node was last confirmed against the API server,
+    /// and whether it may have changed since, i.e. the refresh of its resource
+    /// type failed or the API server is unreachable. Objects kept up to date
+    /// by a working watch are confirmed continuously. Nodes not presenting
+    /// objects, e.g. those in `.kubefuse`, have no freshness.
+    pub(super) fn freshness(&self, inode: u64) -> Option<(SystemTime, bool)> {
+        let path = self.state.node_path(inode);
+        let components: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+        let resource = match components.as_slice() {
+            [] => return None,
+            [CONTROL_DIR_NAME, ..] => return None,
+            [CLUSTER_DIR_NAME] => return None,
+            [CLUSTER_DIR_NAME, resource, ..] => *resource,
+            [_] | [_, "manifest.yaml"] => "namespaces",
+            [_, "events.log" | "events.raw.log", ..] => "events",
+            [_, ENDPOINTS_NAME] => "endpointslices",
+            [_, resource, ..] => *resource,
+        };
+
+        let populated_at = self.populated_at?;
+        let stale = self.degraded.is_some() || self.refresh_schedule.is_paused();
+        let Some(status) = self.refresh_statuses.get(resource) else {
+            return Some((populated_at, stale)); // not refreshed, e.g. pinned
+        };
+        let stale = stale || status.last_error.is_some();
+        let fetched_at = match (status.strategy, stale) {
+            (RefreshStrategy::Watch, false) => SystemTime::now(),
+            _ => status.last_refresh.unwrap_or(populated_at),
+        };
+        Some((fetched_at, stale))
+    }
+
+    /// Pauses all background refreshing when `true` is written to
+    /// `.kubefuse/paused`, and resumes it when `false` is.
+    pub(super) fn write_paused(&mut self, data: &[u8]) -> Result<(), libc::c_int> {
+        let paused = match String::from_utf8_lossy(data).trim() {
+            "true" | "1" => true,
+            "false" | "0" => false,
+            _ => return Err(libc::EINVAL),
+        };
+        self.refresh_schedule.set_paused(paused);
+        if let Ok(mut health) = self.health.lock() {
+            health.paused = paused;
+        }
+        log::info!(
+            "background refreshing {}",
+            if paused { "paused" } else { "resumed" }
+        );
+        if let Some(inode) = self.paused_inode {
+            self.set_node_content(inode, format!("{paused}\n").into_bytes());
+        }
+        Ok(())
+    }
+}
+
+/// Starts a thread re-listing all objects of type `T` across the cluster every
+/// `interval` and handing them to `apply`. The thread exits once the
+/// filesystem stops receiving updates, i.e. after unmount.
+pub fn spawn_poller<T>(
+    resource: &'static str,
+    client: Arc<KubeClient>,
+    schedule: Arc<RefreshSchedule>,
+    interval: Duration,
+    updates: Sender<Update>,
+    apply: fn(&mut KubeFilesystem, Vec<T>),
+) where
+    T: k8s_openapi::ListableResource + DeserializeOwned + Send + 'static,
+{
+    let spawned = thread::Builder::new()
+        .name(format!("poll-{resource}"))
+        .spawn(move || {
+            poll(
+                resource,
+                &client,
+                &schedule,
+                interval,
+                &updates,
+                list_all::<T>,
+                apply,
+            )
+        });
+
+    if let Err(e) = spawned {
+        log::error!("failed to start the {resource} poller: {e}");
+    }
+}
+
+/// Starts a thread re-listing all objects of a resource type without a typed
+/// model every `interval`, see [`spawn_poller`].
+pub fn spawn_unstructured_poller(
+    resource: &'static str,
+    api_resource: ApiResource,
+    client: Arc<KubeClient>,
+    schedule: Arc<RefreshSchedule>,
+    interval: Duration,
+    updates: Sender<Update>,
+) {
+    let spawned = thread::Builder::new()
+        .name(format!("poll-{resource}"))
+        .spawn(move || {
+            let list = |client: &KubeClient| {
+                client
+                    .list_unstructured(&api_resource, None, &ListParams::default())
+                    .map(|list| list.items)
+            };
+            let apply = {
+                let api_resource = api_resource.clone();
+                move |fs: &mut KubeFilesystem, objects| fs.sync_unstructured(&api_resource, objects)
+            };
+            poll(
+                resource, &client, &schedule, interval, &updates, list, apply,
+            );
+        });
+
+    if let Err(e) = spawned {
+        log::error!("failed to start the {resource} poller: {e}");
+    }
+}
+
+/// Starts a thread re-listing the metadata of all objects of a resource type
+/// every `interval`, see [`spawn_poller`].
+pub fn spawn_metadata_poller(
+    resource: &'static str,
+    api_resource: ApiResource,
+    client: Arc<KubeClient>,
+    schedule: Arc<RefreshSchedule>,
+    interval: Duration,
+    updates: Sender<Update>,
+) {
+    let spawned = thread::Builder::new()
+        .name(format!("poll-{resource}"))
+        .spawn(move || {
+            let list = |client: &KubeClient| {
+                client
+                    .list_metadata(&api_resource, None, &ListParams::default())
+                    .map(|list| list.items.into_iter().map(|item| item.metadata).collect())
+            };
+            let apply = {
+                let api_resource = api_resource.clone();
+                move |fs: &mut KubeFilesystem, objects| {
+                    fs.sync_metadata_only(&api_resource, objects)
+                }
+            };
+            poll(
+                resource, &client, &schedule, interval, &updates, list, apply,
+            );
+        });
+
+    if let Err(e) = spawned {
+        log::error!("failed to start the {resource} poller: {e}");
+    }
+}
+
+/// Lists all objects of type `T` across the cluster.
+fn list_all<T>(client: &KubeClient) -> Result<Vec<T>, reqwest::Error>
+where
+    T: k8s_openapi::ListableResource + DeserializeOwned,
+{
+    client
+        .list::<T>(None, &ListParams::default())
+        .map(|list| list.items)
+}
+
+/// Re-lists the objects every `interval`, stretched by refresh windows and
+/// held while refreshing is paused.
+fn poll<T>(
+    resource: &'static str,
+    client: &KubeClient,
+    schedule: &RefreshSchedule,
+    interval: Duration,
+    updates: &Sender<Update>,
+    list: impl Fn(&KubeClient) -> Result<Vec<T>, reqwest::Error>,
+    apply: impl Fn(&mut KubeFilesystem, Vec<T>) + Clone + Send + 'static,
+) where
+    T: Send + 'static,
+{
+    loop {
+        schedule.wait(interval);
+
+        client.set_operation("poll", resource);
+        let update: Update = match list(client) {
+            Ok(objects) => {
+                let apply = apply.clone();
+                Box::new(move |fs| {
+                    apply(fs, objects);
+                    fs.record_refresh(resource, None);
+                    fs.set_reachable(None);
+                })
+            }
+            Err(e) => failure_update(resource, "polling", e),
+        };
+
+        if updates.send(update).is_err() {
+            return;
+        }
+    }
+}
+
+/// Starts a thread keeping all objects of type `T` across the cluster up to
+/// date with a watch. The objects are listed first and handed to `resync`,
+/// then every change is handed to `apply` as it happens. The objects are
+/// re-listed whenever the watch can't be resumed, e.g. after the API server
+/// was unreachable. If the API server refuses to watch the resource at all,
+/// the thread falls back to polling it every `fallback_interval`. Within a
+/// refresh window the objects are polled at the interval of the window
+/// instead, and nothing is requested while refreshing is paused; an open
+/// watch is dropped on its next event or once the API server ends it. The
+/// thread exits once the filesystem stops receiving updates, i.e. after
+/// unmount.
+pub fn spawn_watcher<T>(
+    resource: &'static str,
+    client: Arc<KubeClient>,
+    schedule: Arc<RefreshSchedule>,
+    fallback_interval: Duration,
+    updates: Sender<Update>,
+    resync: fn(&mut KubeFilesystem, Vec<T>),
+    apply: fn(&mut KubeFilesystem, WatchChange<T>),
+) where
+    T: k8s_openapi::ListableResource
+        + k8s_openapi::Metadata<Ty = ObjectMeta>
+        + DeserializeOwned
+        + Send
+        + 'static,
+{
+    let spawned = thread::Builder::new()
+        .name(format!("watch-{resource}"))
+        .spawn(move || {
+            'relist: loop {
+                schedule.wait_unpaused();
+                client.set_operation("watch", resource);
+                let list = match client.list::<T>(None, &ListParams::default()) {
+                    Ok(list) => list,
+                    Err(e) => {
+                        if updates.send(failure_update(resource, "listing", e)).is_err() {
+                            return;
+                        }
+                        thread::sleep(fallback_interval);
+                        continue;
+                    }
+                };
+
+                let mut resource_version = list.metadata.resource_version.unwrap_or_default();
+                let window_interval = schedule.window_interval();
+                let update: Update = Box::new(move |fs| {
+                    resync(fs, list.items);
+                    fs.record_refresh(resource, None);
+                    fs.set_reachable(None);
+                    fs.set_refresh_strategy(
+                        resource,
+                        window_interval.map_or(RefreshStrategy::Watch, RefreshStrategy::Poll),
+                    );
+                });
+                if updates.send(update).is_err() {
+                    return;
+                }
+                if window_interval.is_some() {
+                    schedule.wait(fallback_interval);
+                    continue;
+                }
+
+                loop {
+                    if schedule.is_paused() || schedule.window_interval().is_some() {
+                        continue 'relist;
+                    }
+                    let events = match client.watch::<T>(None, &resource_version) {
+                        Ok(events) => events,
+                        Err(e) if e.status() == Some(reqwest::StatusCode::GONE) => {
+                            continue 'relist; // the resourceVersion was compacted
+                        }
+                        Err(e) if e.status().is_some() => {
+                            log::warn!(
+                                "watching {resource} was refused, polling it every {}s instead: {e}",
+                                fallback_interval.as_secs()
+                            );
+                            let update: Update = Box::new(move |fs| {
+                                fs.set_refresh_strategy(
+                                    resource,
+                                    RefreshStrategy::Poll(fallback_interval),
+                                );
+                            });
+                            if updates.send(update).is_ok() {
+                                poll(
+                                    resource,
+                                    &client,
+                                    &schedule,
+                                    fallback_interval,
+                                    &updates,
+                                    list_all::<T>,
+                                    resync,
+                                );
+                            }
+                            return;
+                        }
+                        Err(e) => {
+                            if updates.send(failure_update(resource, "watching", e)).is_err() {
+                                return;
+                            }
+                            thread::sleep(fallback_interval);
+                            continue 'relist;
+                        }
+                    };
+
+                    for event in events {
+                        let change = match event {
+                            Ok(WatchEvent::Added(object) | WatchEvent::Modified(object)) => {
+                                WatchChange::Applied(object)
+                            }
+                            Ok(WatchEvent::Deleted(object)) => WatchChange::Deleted(object),
+                            Ok(WatchEvent::Bookmark {
+                                resource_version: bookmark,
+                                ..
+                            }) => {
+                                resource_version = bookmark;
+                                continue;
+                            }
+                            Ok(WatchEvent::ErrorStatus(status)) => {
+                                // typically 410 Gone once the resourceVersion was compacted
+                                log::info!(
+                                    "watch of {resource} ended, listing it again: {}",
+                                    status.message.unwrap_or_default()
+                                );
+                                continue 'relist;
+                            }
+                            Ok(WatchEvent::ErrorOther(_)) => continue 'relist,
+                            Err(e) => {
+                                log::warn!("watch of {resource} broke, listing it again: {e}");
+                                continue 'relist;
+                            }
+                        };
+
+                        let (WatchChange::Applied(object) | WatchChange::Deleted(object)) = &change;
+                        if let Some(object_version) = &object.metadata().resource_version {
+                            resource_version = object_version.clone();
+                        }
+                        let update: Update = Box::new(move |fs| {
+                            apply(fs, change);
+                            fs.record_refresh(resource, None);
+                        });
+                        if updates.send(update).is_err() {
+                            return;
+                        }
+                        if schedule.is_paused() || schedule.window_interval().is_some() {
+                            continue 'relist;
+                        }
+                    }
+                    // the API server ended the watch, resume where it left off
+                }
+            }
+        });
+
+    if let Err(e) = spawned {
+        log::error!("failed to start the {resource} watcher: {e}");
+    }
+}
+
+/// Prepares the update recording a failed request of a refresher.
+fn failure_update(resource: &'static str, action: &'static str, e: reqwest::Error) -> Update {
+    Box::new(move |fs| {
+        log::error!("{action} {resource} failed: {e}");
+        fs.record_refresh(resource, Some(e.to_string()));
+        if e.status() == Some(reqwest::StatusCode::UNAUTHORIZED)
+            && let Ok(mut health) = fs.health.lock()
+        {
+            health.unauthorized = true;
+        }
+        // other errors come from a server that's up
+        if e.is_connect() || e.is_timeout() {
+            fs.set_reachable(Some(e.to_string()));
+        } else {
+            fs.set_reachable(None);
+        }
+    })
+}
@@ -0,0 +1,132 @@
+use std::time::SystemTime;
+
+use reqwest::StatusCode;
+
+use crate::client::{ApiError, ApiResource};
+
+use super::{KubeFilesystem, drafts::ManifestLocation};
+
+const REPLICAS_FILE_NAME: &str = "replicas";
+
+// resources with a scale subresource, along with their API version
+const SCALABLE_RESOURCES: [(&str, &str); 3] = [
+    ("deployments", "apps/v1"),
+    ("replicasets", "apps/v1"),
+    ("statefulsets", "apps/v1"),
+];
+
+/// The error to report for a patch the API server didn't accept.
+pub(super) fn patch_errno(e: &ApiError) -> libc::c_int {
+    match e {
+        ApiError::Rejected(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN, _) => libc::EACCES,
+        ApiError::Rejected(StatusCode::NOT_FOUND, _) => libc::ENOENT,
+        ApiError::Rejected(StatusCode::UNPROCESSABLE_ENTITY, _) => libc::EINVAL,
+        _ => libc::EIO,
+    }
+}
+
+impl KubeFilesystem {
+    /// Presents the replica count of a scalable object as `<name>/replicas`
+    /// next to its manifest. Writing a number to it scales the object.
+    pub(super) fn sync_replicas_file(
+        &mut self,
+        manifests_inode: u64,
+        resource: &str,
+        object_name: &str,
+        object: &serde_json::Value,
+    ) {
+        if !SCALABLE_RESOURCES.iter().any(|(name, _)| *name == resource) {
+            return;
+        }
+        let (Some(replicas), Some(dir_inode)) = (
+            object["spec"]["replicas"].as_u64(),
+            self.state.child_inode(manifests_inode, object_name),
+        ) else {
+            return;
+        };
+        if !self.is_dir(dir_inode) {
+            return; // the manifest itself, without an extension
+        }
+        let content = format!("{replicas}\n").into_bytes();
+        match self.state.child_inode(dir_inode, REPLICAS_FILE_NAME) {
+            Some(inode) => self.update_node_content(inode, content),
+            None => {
+                let inode = self.create_content_node(
+                    dir_inode,
+                    REPLICAS_FILE_NAME,
+                    content,
+                    SystemTime::now(),
+                );
+                if let Some(node) = inode.and_then(|inode| self.state.inodes.get_mut(&inode)) {
+                    node.attrs.perm = 0o644; // scaled by writing to it
+                }
+            }
+        }
+    }
+
+    /// Where the object a `replicas` file scales is, if it is one.
+    pub(super) fn replicas_location(&self, inode: u64) -> Option<ManifestLocation> {
+        let node = self.state.inodes.get(&inode)?;
+        if node.name != REPLICAS_FILE_NAME {
+            return None;
+        }
+        let dir_node = self.state.inodes.get(&node.parent)?;
+        let manifest = self
+            .state
+            .child_inode(dir_node.parent, &self.manifest_file_name(&dir_node.name))?;
+        self.manifest_location(manifest)
+            .filter(|location| location.namespace.is_some())
+            .filter(|location| {
+                SCALABLE_RESOURCES
+                    .iter()
+                    .any(|(name, _)| *name == location.resource)
+            })
+    }
+
+    /// Scales the object of a `replicas` file to the count written to it
+    /// through the scale subresource.
+    pub(super) fn write_replicas(&mut self, inode: u64, data: &[u8]) -> Result<(), libc::c_int> {
+        let location = self.replicas_location(inode).ok_or(libc::EPERM)?;
+        if self.resource_version.is_some() || self.is_terminating(&location) {
+            return Err(libc::EROFS); // pinned to the past or being deleted
+        }
+        let replicas: u32 = String::from_utf8_lossy(data)
+            .trim()
+            .parse()
+            .map_err(|_| libc::EINVAL)?;
+        let (Some(namespace), Some((_, api_version))) = (
+            location.namespace.as_deref(),
+            SCALABLE_RESOURCES
+                .iter()
+                .find(|(name, _)| *name == location.resource),
+        ) else {
+            return Err(libc::EPERM);
+        };
+
+        let path = self.state.node_path(inode);
+        if !self.mutation_quotas.allow(&path) {
+            log::error!("refusing to scale through {path}: mutation limit exceeded");
+            return Err(libc::EAGAIN);
+        }
+        let api_resource = ApiResource {
+            api_version: api_version.to_string(),
+            plural: location.resource.clone(),
+        };
+        if let Err(e) = self
+            .kube_client
+            .scale(&api_resource, namespace, &location.name, replicas)
+        {
+            log::error!("failed to scale through {path}: {e}");
+            return Err(patch_errno(&e));
+        }
+
+        log::info!(
+            "scaled {}/{} to {replicas}",
+            location.resource,
+            location.name
+        );
+        // confirmed by the watch shortly
+        self.set_node_content(inode, format!("{replicas}\n").into_bytes());
+        Ok(())
+    }
+}
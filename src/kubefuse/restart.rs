@@ -0,0 +1,121 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use k8s_openapi::chrono;
+
+use crate::client::ApiResource;
+
+use super::{KubeFilesystem, drafts::ManifestLocation, replicas::patch_errno};
+
+const RESTART_FILE_NAME: &str = "restart";
+
+// what `kubectl rollout restart` sets in the pod template
+const RESTARTED_AT_ANNOTATION: &str = "kubectl.kubernetes.io/restartedAt";
+
+// resources that can be restarted, along with their API version
+const RESTARTABLE_RESOURCES: [(&str, &str); 3] = [
+    ("deployments", "apps/v1"),
+    ("statefulsets", "apps/v1"),
+    ("daemonsets", "apps/v1"),
+];
+
+impl KubeFilesystem {
+    /// Presents `<name>/restart` next to the manifest of a workload that
+    /// rolls out its pods. Writing anything to it restarts the workload.
+    pub(super) fn sync_restart_file(
+        &mut self,
+        manifests_inode: u64,
+        resource: &str,
+        object_name: &str,
+    ) {
+        if !RESTARTABLE_RESOURCES
+            .iter()
+            .any(|(name, _)| *name == resource)
+        {
+            return;
+        }
+        let Some(dir_inode) = self
+            .state
+            .child_inode(manifests_inode, object_name)
+            .filter(|inode| self.is_dir(*inode))
+        else {
+            return;
+        };
+        if self
+            .state
+            .child_inode(dir_inode, RESTART_FILE_NAME)
+            .is_some()
+        {
+            return;
+        }
+        let inode =
+            self.create_content_node(dir_inode, RESTART_FILE_NAME, Vec::new(), SystemTime::now());
+        if let Some(node) = inode.and_then(|inode| self.state.inodes.get_mut(&inode)) {
+            node.attrs.perm = 0o200; // there's nothing to read
+        }
+    }
+
+    /// Where the workload a `restart` file restarts is, if it is one.
+    pub(super) fn restart_location(&self, inode: u64) -> Option<ManifestLocation> {
+        let node = self.state.inodes.get(&inode)?;
+        if node.name != RESTART_FILE_NAME {
+            return None;
+        }
+        let dir_node = self.state.inodes.get(&node.parent)?;
+        let manifest = self
+            .state
+            .child_inode(dir_node.parent, &self.manifest_file_name(&dir_node.name))?;
+        self.manifest_location(manifest)
+            .filter(|location| location.namespace.is_some())
+            .filter(|location| {
+                RESTARTABLE_RESOURCES
+                    .iter()
+                    .any(|(name, _)| *name == location.resource)
+            })
+    }
+
+    /// Restarts the workload of a `restart` file the way `kubectl rollout
+    /// restart` does, by stamping its pod template with the current time.
+    pub(super) fn restart_workload(&mut self, inode: u64) -> Result<(), libc::c_int> {
+        let location = self.restart_location(inode).ok_or(libc::EPERM)?;
+        if self.resource_version.is_some() || self.is_terminating(&location) {
+            return Err(libc::EROFS); // pinned to the past or being deleted
+        }
+        let (Some(namespace), Some((_, api_version))) = (
+            location.namespace.as_deref(),
+            RESTARTABLE_RESOURCES
+                .iter()
+                .find(|(name, _)| *name == location.resource),
+        ) else {
+            return Err(libc::EPERM);
+        };
+
+        let path = self.state.node_path(inode);
+        if !self.mutation_quotas.allow(&path) {
+            log::error!("refusing to restart through {path}: mutation limit exceeded");
+            return Err(libc::EAGAIN);
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .and_then(|d| chrono::DateTime::from_timestamp(d.as_secs() as i64, 0))
+            .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true))
+            .ok_or(libc::EIO)?;
+        let patch = serde_json::json!({
+            "spec": {"template": {"metadata": {"annotations": {RESTARTED_AT_ANNOTATION: now}}}},
+        });
+        let api_resource = ApiResource {
+            api_version: api_version.to_string(),
+            plural: location.resource.clone(),
+        };
+        if let Err(e) =
+            self.kube_client
+                .merge_patch(&api_resource, namespace, &location.name, &patch)
+        {
+            log::error!("failed to restart through {path}: {e}");
+            return Err(patch_errno(&e));
+        }
+
+        log::info!("restarted {}/{}", location.resource, location.name);
+        Ok(())
+    }
+}
@@ -0,0 +1,208 @@
+use std::time::SystemTime;
+
+use serde_yaml::Value;
+
+use crate::client::{ApplyParams, DynamicObject};
+
+use super::{KubeFilesystem, NodeContent};
+
+// suffix of the read-only files holding the validation result of a manifest
+const VALIDATION_SUFFIX: &str = ".validation";
+
+pub fn is_manifest_name(name: &str) -> bool {
+    name.ends_with(".yaml") || name.ends_with(".yml")
+}
+
+/// Guesses the resource name of a kind, e.g. `NetworkPolicy` becomes
+/// `networkpolicies`.
+pub fn resource_plural(kind: &str) -> String {
+    let kind = kind.to_lowercase();
+    if kind.ends_with("ss") || kind.ends_with('x') || kind.ends_with("ch") || kind.ends_with("sh") {
+        format!("{kind}es")
+    } else if kind.ends_with('s') {
+        kind // e.g. Endpoints
+    } else if let Some(stem) = kind.strip_suffix('y')
+        && !stem.ends_with(['a', 'e', 'i', 'o', 'u'])
+    {
+        format!("{stem}ies")
+    } else {
+        format!("{kind}s")
+    }
+}
+
+/// Extracts the identity of the object described by a manifest. The
+/// namespace, if given, takes precedence over the one in the manifest.
+pub(super) fn manifest_object(
+    manifest: &[u8],
+    namespace: Option<&str>,
+) -> Result<DynamicObject, String> {
+    let value: Value =
+        serde_yaml::from_slice(manifest).map_err(|e| format!("invalid manifest: {e}"))?;
+    let field = |value: &Value, name: &str| -> Result<String, String> {
+        value[name]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| format!("{name} is required"))
+    };
+
+    Ok(DynamicObject {
+        api_version: field(&value, "apiVersion")?,
+        plural: resource_plural(&field(&value, "kind")?),
+        namespace: namespace
+            .map(str::to_string)
+            .or_else(|| field(&value["metadata"], "namespace").ok()),
+        name: field(&value["metadata"], "name")?,
+    })
+}
+
+impl KubeFilesystem {
+    pub(super) fn is_scratch_manifest(&self, inode: u64) -> bool {
+        self.state.inodes.get(&inode).is_some_and(|node| {
+            Some(node.parent) == self.scratch_inode && !node.name.ends_with(VALIDATION_SUFFIX)
+        })
+    }
+
+    pub(super) fn create_scratch_file(&mut self, name: &str) -> Option<u64> {
+        let scratch_inode = self.scratch_inode?;
+        if name.ends_with(VALIDATION_SUFFIX)
+            || self.state.child_inode(scratch_inode, name).is_some()
+        {
+            return None;
+        }
+
+        let inode = self.create_content_node(scratch_inode, name, Vec::new(), SystemTime::now())?;
+        if let Some(node) = self.state.inodes.get_mut(&inode) {
+            node.attrs.perm = 0o644;
+        }
+        Some(inode)
+    }
+
+    /// Validates a manifest in the scratch directory with a server-side dry
+    /// run and writes the result to its `.validation` sidecar file.
+    pub(super) fn validate_scratch_file(&mut self, inode: u64) {
+        let Some(node) = self.state.inodes.get(&inode) else {
+            return;
+        };
+        if !is_manifest_name(&node.name) {
+            return; // e.g. editor swap files
+        }
+        let NodeContent::Bytes(manifest) = &node.content else {
+            return;
+        };
+
+        let result = match manifest_object(manifest, None) {
+            Err(e) => format!("invalid: {e}\n"),
+            Ok(object) => match self.kube_client.apply(
+                &object,
+                manifest,
+                &ApplyParams {
+                    dry_run: true,
+                    ..Default::default()
+                },
+            ) {
+                Ok(()) => format!(
+                    "valid: server-side dry run of {} {} passed\n",
+                    object.plural, object.name
+                ),
+                Err(e) => format!("invalid: {e}\n"),
+            },
+        };
+
+        let sidecar_name = format!("{}{VALIDATION_SUFFIX}", node.name);
+        let Some(scratch_inode) = self.scratch_inode else {
+            return;
+        };
+        match self.state.child_inode(scratch_inode, &sidecar_name) {
+            Some(sidecar_inode) => self.set_node_content(sidecar_inode, result.into_bytes()),
+            None => {
+                self.create_content_node(
+                    scratch_inode,
+                    &sidecar_name,
+                    result.into_bytes(),
+                    SystemTime::now(),
+                );
+            }
+        }
+    }
+
+    /// Removes a scratch file along with its validation result.
+    pub(super) fn remove_scratch_file(&mut self, inode: u64) {
+        let (Some(scratch_inode), Some(node)) = (self.scratch_inode, self.state.inodes.get(&inode))
+        else {
+            return;
+        };
+
+        let sidecar_name = format!("{}{VALIDATION_SUFFIX}", node.name);
+        if let Some(sidecar_inode) = self.state.child_inode(scratch_inode, &sidecar_name) {
+            self.state.remove_node(sidecar_inode);
+        }
+        self.state.remove_node(inode);
+    }
+
+    /// Applies a scratch manifest to the cluster after it's been moved into a
+    /// resource directory of a namespace, e.g. `<namespace>/configmaps/`.
+    pub(super) fn promote_scratch_file(
+        &mut self,
+        inode: u64,
+        target_dir: u64,
+        target_name: &str,
+    ) -> Result<(), libc::c_int> {
+        let (Some(node), Some(dir_node)) = (
+            self.state.inodes.get(&inode),
+            self.state.inodes.get(&target_dir),
+        ) else {
+            return Err(libc::ENOENT);
+        };
+        let NodeContent::Bytes(manifest) = &node.content else {
+            return Err(libc::EISDIR);
+        };
+
+        let namespace = self
+            .state
+            .inodes
+            .get(&dir_node.parent)
+            .map(|ns_node| ns_node.name.clone())
+            .filter(|ns_name| self.namespace_inode(ns_name) == Some(dir_node.parent))
+            .ok_or(libc::EXDEV)?;
+
+        let object = manifest_object(manifest, Some(&namespace)).map_err(|e| {
+            log::error!("cannot promote {}: {e}", node.name);
+            libc::EINVAL
+        })?;
+        let expected_name = self.manifest_file_name(&object.name);
+        if object.plural != dir_node.name || target_name != expected_name {
+            log::error!(
+                "cannot promote {} to {}/{target_name}: expected {}/{expected_name}",
+                node.name,
+                dir_node.name,
+                object.plural,
+            );
+            return Err(libc::EINVAL);
+        }
+
+        let target_path = format!("/{namespace}/{}/{target_name}", object.plural);
+        if !self.mutation_quotas.allow(&target_path) {
+            log::error!("refusing to apply {target_path}: mutation limit exceeded");
+            return Err(libc::EAGAIN);
+        }
+
+        let manifest = manifest.to_vec();
+        if let Err(e) = self
+            .kube_client
+            .apply(&object, &manifest, &ApplyParams::default())
+        {
+            log::error!("failed to apply {target_path}: {e}");
+            return Err(libc::EIO);
+        }
+
+        self.remove_scratch_file(inode);
+        // replaced by the server's rendering of the object on the next refresh
+        match self.state.child_inode(target_dir, target_name) {
+            Some(existing_inode) => self.set_node_content(existing_inode, manifest),
+            None => {
+                self.create_content_node(target_dir, target_name, manifest, SystemTime::now());
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,206 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fmt::Write as _,
+    time::SystemTime,
+};
+
+use serde_json::Value;
+
+use super::{KubeFilesystem, NodeContent};
+
+/// Link in the key directory of a Secret to the object it's materialized
+/// from, e.g. `source -> ../../externalsecrets/db.yaml`.
+pub const SOURCE_LINK_NAME: &str = "source";
+
+// link in the directory of the source object to the Secret it materializes
+const SECRET_LINK_NAME: &str = "secret";
+
+const SYNC_STATUS_FILE_NAME: &str = "sync-status.txt";
+
+/// Custom resources whose objects materialize a Secret: those of External
+/// Secrets Operator and Sealed Secrets.
+const SECRET_SOURCES: [&str; 2] = ["externalsecrets", "sealedsecrets"];
+
+/// The name of the Secret an object of a secret source materializes in its
+/// namespace.
+fn target_secret<'a>(resource: &str, object: &'a Value) -> Option<&'a str> {
+    let name = object["metadata"]["name"].as_str();
+    match resource {
+        "externalsecrets" => object["spec"]["target"]["name"]
+            .as_str()
+            .filter(|name| !name.is_empty())
+            .or(name),
+        "sealedsecrets" => name,
+        _ => None,
+    }
+}
+
+/// Renders `sync-status.txt`: a line per condition of the object, e.g.
+/// `Ready=True SecretSynced: Secret was synced`, then when the Secret was
+/// last refreshed, if the controller reports it.
+fn render_sync_status(object: &Value) -> String {
+    let mut out = String::new();
+    let conditions = object["status"]["conditions"].as_array();
+    for condition in conditions.into_iter().flatten() {
+        let field = |name: &str| condition[name].as_str().unwrap_or_default();
+        let _ = write!(out, "{}={}", field("type"), field("status"));
+        if !field("reason").is_empty() {
+            let _ = write!(out, " {}", field("reason"));
+        }
+        if !field("message").is_empty() {
+            let _ = write!(out, ": {}", field("message"));
+        }
+        out.push('\n');
+    }
+    if out.is_empty() {
+        out.push_str("no conditions reported\n");
+    }
+    if let Some(refreshed) = object["status"]["refreshTime"].as_str() {
+        let _ = writeln!(out, "refreshed {refreshed}");
+    }
+    out
+}
+
+impl KubeFilesystem {
+    /// Cross-links the ExternalSecrets and SealedSecrets presented, e.g. as
+    /// custom resources, with the Secrets they materialize, for questions of
+    /// where a Secret comes from. The directory of the source object holds a
+    /// `secret` link to the manifest of the Secret, dangling until it's
+    /// materialized, and `sync-status.txt` from the conditions of the object.
+    /// The key directory of the Secret links back to it as `source`.
+    pub(super) fn sync_secret_sources(&mut self) {
+        // (namespace, Secret) -> target of its `source` link
+        let mut sources: HashMap<(String, String), String> = HashMap::new();
+        for (location, inode) in self.presented_manifests() {
+            let Some(namespace) = &location.namespace else {
+                continue;
+            };
+            if !SECRET_SOURCES.contains(&location.resource.as_str()) {
+                continue;
+            }
+            let Some(manifest) = self.file_content(inode) else {
+                continue;
+            };
+            let Some(node) = self.state.inodes.get(&inode) else {
+                continue;
+            };
+            // truncated manifests don't parse and materialize nothing
+            let Ok(object) = serde_yaml::from_slice::<Value>(&manifest) else {
+                continue;
+            };
+            let Some(secret) = target_secret(&location.resource, &object) else {
+                continue;
+            };
+            let (parent, manifest_name) = (node.parent, node.name.clone());
+
+            let mut files = BTreeMap::new();
+            files.insert(
+                SECRET_LINK_NAME,
+                format!("../../secrets/{}", self.manifest_file_name(secret)),
+            );
+            files.insert(SYNC_STATUS_FILE_NAME, render_sync_status(&object));
+            if let Some(dir_inode) = self
+                .state
+                .child_inode(parent, &location.name)
+                .filter(|inode| self.is_dir(*inode))
+            {
+                self.sync_source_dir(dir_inode, files);
+            }
+            sources
+                .entry((namespace.clone(), secret.to_string()))
+                .or_insert(format!("../../{}/{manifest_name}", location.resource));
+        }
+
+        for namespace in self.namespace_names() {
+            let Some(secrets_inode) = self
+                .namespace_inode(&namespace)
+                .and_then(|ns_inode| self.state.child_inode(ns_inode, "secrets"))
+            else {
+                continue;
+            };
+            for (name, key_dir) in self.children(secrets_inode) {
+                if !self.is_dir(key_dir) {
+                    continue;
+                }
+                let target = sources.remove(&(namespace.clone(), name));
+                self.sync_link(key_dir, SOURCE_LINK_NAME, target.as_deref());
+            }
+        }
+    }
+
+    // the `secret` link and `sync-status.txt` of the directory of a source
+    fn sync_source_dir(&mut self, dir_inode: u64, files: BTreeMap<&str, String>) {
+        for (name, content) in files {
+            if name == SECRET_LINK_NAME {
+                self.sync_link(dir_inode, name, Some(&content));
+                continue;
+            }
+            match self.state.child_inode(dir_inode, name) {
+                Some(inode) => self.update_node_content(inode, content.into_bytes()),
+                None => {
+                    self.create_content_node(
+                        dir_inode,
+                        name,
+                        content.into_bytes(),
+                        SystemTime::now(),
+                    );
+                }
+            }
+        }
+    }
+
+    /// Points a link of a directory at `target`, or removes it, leaving
+    /// anything else of that name alone, e.g. a data key.
+    pub(super) fn sync_link(&mut self, dir_inode: u64, name: &str, target: Option<&str>) {
+        let existing = self.state.child_inode(dir_inode, name);
+        let current = match existing.and_then(|inode| self.state.inodes.get(&inode)) {
+            Some(node) if node.attrs.kind != fuser::FileType::Symlink => return,
+            Some(node) => match &node.content {
+                NodeContent::Bytes(current) => Some(current.as_slice()),
+                NodeContent::Children(_) => return,
+            },
+            None => None,
+        };
+        if current == target.map(str::as_bytes) {
+            return;
+        }
+        if let Some(inode) = existing {
+            self.state.remove_node(inode);
+        }
+        if let Some(target) = target {
+            self.create_symlink_node(dir_inode, name, target);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sync_status_lists_the_conditions() {
+        let object = serde_json::json!({
+            "metadata": {"name": "db"},
+            "spec": {"target": {"name": "db-credentials"}},
+            "status": {
+                "conditions": [{
+                    "type": "Ready",
+                    "status": "False",
+                    "reason": "SecretSyncedError",
+                    "message": "could not get secret data from provider",
+                }],
+                "refreshTime": "2024-05-02T10:00:00Z",
+            },
+        });
+        assert_eq!(
+            render_sync_status(&object),
+            "Ready=False SecretSyncedError: could not get secret data from provider\n\
+             refreshed 2024-05-02T10:00:00Z\n"
+        );
+        assert_eq!(
+            target_secret("externalsecrets", &object),
+            Some("db-credentials")
+        );
+        assert_eq!(target_secret("sealedsecrets", &object), Some("db"));
+    }
+}
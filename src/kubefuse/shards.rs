@@ -0,0 +1,159 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use k8s_openapi::chrono;
+
+use crate::config::Sharding;
+
+use super::{CLUSTER_DIR_NAME, KubeFilesystem, ROOT_ATTR, strict::ERROR_SUFFIX};
+
+/// Prefix of the subdirectories a sharded resource directory is split into.
+/// Object names can't start with it, so neither can they clash.
+pub const SHARD_PREFIX: &str = "_";
+
+/// The subdirectory an object goes to, e.g. `_we` or `_2024-05-02`.
+fn shard_name(sharding: Sharding, object_name: &str, created: SystemTime) -> String {
+    match sharding {
+        Sharding::NamePrefix => {
+            let prefix: String = object_name.chars().take(2).collect();
+            format!("{SHARD_PREFIX}{prefix}")
+        }
+        Sharding::CreationDate => {
+            let day = created
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .and_then(|d| chrono::DateTime::from_timestamp(d.as_secs() as i64, 0))
+                .map(|t| t.format("%Y-%m-%d").to_string())
+                .unwrap_or_default();
+            format!("{SHARD_PREFIX}{day}")
+        }
+    }
+}
+
+impl KubeFilesystem {
+    /// Splits the directories of the resource types configured in `shards`
+    /// into subdirectories, so that they stay listable when holding tens of
+    /// thousands of objects. The entries of an object, its manifest and e.g.
+    /// its directory, are linked from its subdirectory, and left out of the
+    /// listing of the resource directory, though still found there by name.
+    pub(super) fn sync_shards(&mut self) {
+        self.sharded_dirs.clear();
+        if self.profile.shards.is_empty() {
+            return;
+        }
+        let mut dirs: Vec<u64> = self
+            .namespace_names()
+            .iter()
+            .filter_map(|namespace| self.namespace_inode(namespace))
+            .collect();
+        dirs.extend(self.state.child_inode(ROOT_ATTR.ino, CLUSTER_DIR_NAME));
+
+        for dir in dirs {
+            for (resource, resource_inode) in self.children(dir) {
+                let Some(&sharding) = self.profile.shards.get(&resource) else {
+                    continue;
+                };
+                if self.is_dir(resource_inode) {
+                    self.sync_resource_shards(resource_inode, sharding);
+                    self.sharded_dirs.insert(resource_inode);
+                }
+            }
+        }
+    }
+
+    fn sync_resource_shards(&mut self, resource_inode: u64, sharding: Sharding) {
+        // shard -> entry -> inode
+        let mut shards: BTreeMap<String, HashMap<String, u64>> = BTreeMap::new();
+        for (name, inode) in self.children(resource_inode) {
+            if name.starts_with(SHARD_PREFIX) {
+                continue;
+            }
+            let object_name = self.entry_object_name(&name);
+            let manifest = self.manifest_file_name(object_name);
+            let created = self
+                .state
+                .child_inode(resource_inode, &manifest)
+                .or(Some(inode))
+                .and_then(|inode| self.state.inodes.get(&inode))
+                .map_or(UNIX_EPOCH, |node| node.attrs.crtime);
+            shards
+                .entry(shard_name(sharding, object_name, created))
+                .or_default()
+                .insert(name, inode);
+        }
+
+        for (name, inode) in self.children(resource_inode) {
+            if name.starts_with(SHARD_PREFIX) && self.is_dir(inode) && !shards.contains_key(&name) {
+                self.remove_shard(inode);
+            }
+        }
+        for (shard, entries) in shards {
+            let Some(shard_inode) = self
+                .state
+                .child_inode(resource_inode, &shard)
+                .or_else(|| self.create_dir_node(resource_inode, &shard))
+            else {
+                continue;
+            };
+            for (name, inode) in self.children(shard_inode) {
+                if entries.get(&name) != Some(&inode) {
+                    self.state.unlink(shard_inode, &name);
+                }
+            }
+            for (name, inode) in entries {
+                self.state.link(shard_inode, &name, inode);
+            }
+        }
+    }
+
+    // the object an entry of a resource directory is of, e.g. `web` for
+    // `web.yaml`, `web.json` or the directory `web`
+    fn entry_object_name<'a>(&self, name: &'a str) -> &'a str {
+        let name = name.strip_suffix(ERROR_SUFFIX).unwrap_or(name);
+        self.profile
+            .manifest_object_name(name)
+            .or_else(|| name.strip_suffix(".json"))
+            .unwrap_or(name)
+    }
+
+    // unlinks what a shard holds, leaving it to the resource directory, then
+    // removes it
+    fn remove_shard(&mut self, shard_inode: u64) {
+        for (name, _) in self.children(shard_inode) {
+            self.state.unlink(shard_inode, &name);
+        }
+        self.state.remove_node(shard_inode);
+    }
+
+    /// Whether a directory is a shard of a sharded resource directory.
+    pub(super) fn is_shard(&self, inode: u64) -> bool {
+        self.state.inodes.get(&inode).is_some_and(|node| {
+            node.name.starts_with(SHARD_PREFIX)
+                && node.attrs.kind == fuser::FileType::Directory
+                && self.sharded_dirs.contains(&node.parent)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[test]
+    fn shards_by_name_prefix_or_creation_date() {
+        let created = SystemTime::UNIX_EPOCH + Duration::from_secs(1_714_644_000);
+        assert_eq!(
+            shard_name(Sharding::NamePrefix, "web-7d4b9c", created),
+            "_we"
+        );
+        assert_eq!(shard_name(Sharding::NamePrefix, "a", created), "_a");
+        assert_eq!(
+            shard_name(Sharding::CreationDate, "web-7d4b9c", created),
+            "_2024-05-02"
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Component, Path},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use k8s_openapi::{api::core::v1::Namespace, chrono};
+use serde::Serialize;
+
+use crate::{client::ListParams, sanitize};
+
+use super::KubeFilesystem;
+
+// written to the root of a snapshot, describes what it holds
+const SNAPSHOT_MANIFEST_NAME: &str = "snapshot.yaml";
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct SnapshotManifest {
+    resource_version: Option<String>,
+    taken_at: Option<String>,
+    // resource -> number of objects
+    objects: BTreeMap<String, usize>,
+}
+
+impl KubeFilesystem {
+    /// Dumps the sanitized manifests of every presented namespace to a local
+    /// directory, along with `snapshot.yaml` recording the resourceVersion of
+    /// the cluster and how many objects of each resource were written.
+    pub(super) fn take_snapshot(&mut self, target: &str) -> Result<(), libc::c_int> {
+        let target = Path::new(target);
+        if !target.is_absolute() || target.components().any(|c| c == Component::ParentDir) {
+            log::error!(
+                "snapshot target {} must be an absolute path",
+                target.display()
+            );
+            return Err(libc::EINVAL);
+        }
+        // writing into the mount would wait on this very request
+        if target.starts_with(&self.mountpoint) {
+            log::error!("snapshot target {} is inside the mount", target.display());
+            return Err(libc::EINVAL);
+        }
+
+        let resource_version = match self
+            .kube_client
+            .list::<Namespace>(None, &ListParams::default())
+        {
+            Ok(list) => list.metadata.resource_version,
+            Err(e) => {
+                log::warn!("failed to get the resourceVersion for the snapshot: {e}");
+                None
+            }
+        };
+
+        let manifest_name = self.manifest_file_name("manifest");
+        let mut objects = BTreeMap::new();
+        for (path, manifest) in self.sanitized_manifests() {
+            let resource = match path.splitn(3, '/').collect::<Vec<_>>()[..] {
+                [_, resource, _] => Some(resource),
+                [_, file] if file == manifest_name => Some("namespaces"),
+                _ => None,
+            };
+            if let Some(resource) = resource {
+                *objects.entry(resource.to_string()).or_default() += 1;
+            }
+            write_file(&target.join(path), &manifest)?;
+        }
+
+        let manifest = SnapshotManifest {
+            resource_version,
+            taken_at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .ok()
+                .and_then(|d| chrono::DateTime::from_timestamp(d.as_secs() as i64, 0))
+                .map(|t| t.to_rfc3339_opts(chrono::SecondsFormat::Secs, true)),
+            objects,
+        };
+        let manifest = serde_yaml::to_string(&manifest).map_err(|e| {
+            log::error!("failed to render the snapshot manifest: {e}");
+            libc::EIO
+        })?;
+        write_file(&target.join(SNAPSHOT_MANIFEST_NAME), manifest.as_bytes())?;
+        log::info!("wrote a snapshot of the cluster to {}", target.display());
+        Ok(())
+    }
+
+    /// The sanitized manifests of every presented namespace, by their path
+    /// relative to the mount, e.g. `team-a/configmaps/app.yaml`, as written
+    /// to snapshots.
+    pub fn sanitized_manifests(&mut self) -> BTreeMap<String, Vec<u8>> {
+        let mut manifests = BTreeMap::new();
+        for namespace in self.namespace_names() {
+            let Some(ns_inode) = self.namespace_inode(&namespace) else {
+                continue;
+            };
+            let manifest_name = self.manifest_file_name("manifest");
+            let mut files = self.namespace_manifests(&namespace).unwrap_or_default();
+            if let Some(manifest) = self
+                .state
+                .child_inode(ns_inode, &manifest_name)
+                .and_then(|inode| self.file_content(inode))
+            {
+                files.insert(manifest_name, manifest.to_vec());
+            }
+            for (path, manifest) in files {
+                manifests.insert(
+                    format!("{namespace}/{path}"),
+                    sanitize::sanitize_manifest(&manifest).into_bytes(),
+                );
+            }
+        }
+        manifests
+    }
+}
+
+fn write_file(path: &Path, content: &[u8]) -> Result<(), libc::c_int> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| {
+            log::error!("failed to create {}: {e}", dir.display());
+            e.raw_os_error().unwrap_or(libc::EIO)
+        })?;
+    }
+    fs::write(path, content).map_err(|e| {
+        log::error!("failed to write {}: {e}", path.display());
+        e.raw_os_error().unwrap_or(libc::EIO)
+    })
+}
@@ -0,0 +1,66 @@
+use std::time::SystemTime;
+
+use super::{KubeFilesystem, serialize_manifest};
+
+const SPEC_STATUS_FIELDS: [&str; 2] = ["spec", "status"];
+
+impl KubeFilesystem {
+    /// Presents the `spec` and `status` of an object as `<name>/spec.yaml` and
+    /// `<name>/status.yaml` next to its manifest, for scripts interested in
+    /// only one of them. The status comes from the object rather than the
+    /// manifest, so it's there in profiles stripping it from manifests too.
+    /// Objects with neither, e.g. ConfigMaps, don't get these files.
+    pub(super) fn sync_spec_status_files(
+        &mut self,
+        manifests_inode: u64,
+        object_name: &str,
+        object: &serde_json::Value,
+    ) {
+        let dir_inode = match self.state.child_inode(manifests_inode, object_name) {
+            Some(inode) if self.is_dir(inode) => Some(inode),
+            // the manifest itself, without an extension
+            Some(_) => return,
+            None => None,
+        };
+        if dir_inode.is_none()
+            && SPEC_STATUS_FIELDS
+                .iter()
+                .all(|field| object[field].is_null())
+        {
+            return;
+        }
+        let Some(dir_inode) =
+            dir_inode.or_else(|| self.create_dir_node(manifests_inode, object_name))
+        else {
+            return;
+        };
+
+        for field in SPEC_STATUS_FIELDS {
+            let file_name = self.manifest_file_name(field);
+            let existing = self.state.child_inode(dir_inode, &file_name);
+            let content = match &object[field] {
+                serde_json::Value::Null => None,
+                value => serde_yaml::to_value(value)
+                    .map_err(|e| e.to_string())
+                    .and_then(|value| serialize_manifest(&value, self.profile.manifest_format))
+                    .inspect_err(|e| log::error!("failed to render {field} of {object_name}: {e}"))
+                    .ok(),
+            };
+            match (content, existing) {
+                (Some(content), Some(inode)) => {
+                    self.update_node_content(inode, content.into_bytes());
+                }
+                (Some(content), None) => {
+                    self.create_content_node(
+                        dir_inode,
+                        &file_name,
+                        content.into_bytes(),
+                        SystemTime::now(),
+                    );
+                }
+                (None, Some(inode)) => self.state.remove_node(inode),
+                (None, None) => {}
+            }
+        }
+    }
+}
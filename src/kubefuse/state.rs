@@ -0,0 +1,980 @@
+//! The mutable state of the filesystem: the inode table holding both the tree
+//! and the file contents, and the table of open file handles.
+//!
+//! Concurrency model: [`FsState`] has a single owner, the FUSE session thread,
+//! and is only ever accessed through `&mut`, so there are no locks to order.
+//! Background threads (refreshers, watches) never touch it directly, they send
+//! [`super::refresh::Update`]s which the owner applies between two FUSE
+//! operations. Operations spanning several requests are protected by handles
+//! instead: a directory handle lists the entries as they were at `opendir` and
+//! a read-only file handle serves the content as it was at `open`, so neither
+//! sees a half-applied update nor fails because the node was removed meanwhile.
+//!
+//! Should the state ever be shared between threads, the inode table has to be
+//! locked before the handle table, never the other way round.
+//!
+//! Nodes removed while still in use, i.e. while they are open or before the
+//! kernel sent a `forget` for each reply to `lookup` or `create`, are unlisted
+//! from their directory but kept in the inode table with no links, the way
+//! unlinked files are on disk. They are reclaimed once the last handle is
+//! released and the kernel forgot them. This way a process copying a file whose
+//! object has just been deleted gets the last known state rather than ENOENT.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    ops::Range,
+    sync::Arc,
+};
+
+use fuser::{FileAttr, FileType};
+use sha2::{Digest, Sha256};
+
+use super::{ROOT_ATTR, names};
+
+// set on nodes removed from the tree while still in use
+const DELETED_XATTR: &str = "user.kubefuse.deleted";
+
+pub type InodeTable = HashMap<u64, Node>;
+pub struct Node {
+    pub name: String,
+    pub parent: u64,
+    pub attrs: FileAttr,
+    pub content: NodeContent,
+    pub xattrs: BTreeMap<String, Vec<u8>>,
+}
+
+impl Node {
+    // roughly the memory the node holds on to besides its table entry
+    fn size(&self) -> u64 {
+        let content = match &self.content {
+            NodeContent::Bytes(content) => content.capacity(),
+            NodeContent::Children(children) => children.keys().map(String::capacity).sum(),
+        };
+        let xattrs: usize = self
+            .xattrs
+            .iter()
+            .map(|(name, value)| name.capacity() + value.capacity())
+            .sum();
+        (self.name.capacity() + content + xattrs) as u64
+    }
+}
+
+pub type NodeChildren = HashMap<String, u64>;
+pub enum NodeContent {
+    /// Shared with the snapshots of the handles open for reading, so that
+    /// opening a large file doesn't copy it.
+    Bytes(Arc<Vec<u8>>),
+    Children(NodeChildren),
+}
+
+pub struct DirEntry {
+    pub inode: u64,
+    pub kind: FileType,
+    pub name: String,
+}
+
+/// An advisory lock of a byte range of a file, `end` included.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileLock {
+    pub owner: u64,
+    pub start: u64,
+    pub end: u64,
+    /// `F_RDLCK` or `F_WRLCK`, `F_UNLCK` to release.
+    pub typ: i32,
+    pub pid: u32,
+}
+
+impl FileLock {
+    fn overlaps(&self, other: &FileLock) -> bool {
+        self.start <= other.end && other.start <= self.end
+    }
+
+    fn conflicts_with(&self, other: &FileLock) -> bool {
+        self.owner != other.owner
+            && self.overlaps(other)
+            && (self.typ == libc::F_WRLCK || other.typ == libc::F_WRLCK)
+    }
+}
+
+/// What [`FsState::compact`] dropped.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Reclaimed {
+    pub nodes: u64,
+    pub bytes: u64,
+}
+
+enum Handle {
+    File {
+        inode: u64,
+        // content at open time, unset for handles open for writing
+        snapshot: Option<Arc<Vec<u8>>>,
+        locks: Vec<FileLock>,
+    },
+    Dir {
+        inode: u64,
+        entries: Vec<DirEntry>,
+    },
+}
+
+pub struct FsState {
+    pub inodes: InodeTable,
+    // what the inodes of the nodes but the root are picked from
+    inode_range: Range<u64>,
+    // ordered so that the oldest conflicting lock is the one reported
+    handles: BTreeMap<u64, Handle>,
+    handle_counter: u64,
+    // inode -> number of times the kernel has been handed the node and not
+    // forgotten it yet
+    lookups: HashMap<u64, u64>,
+    // removed nodes that are kept until they are no longer in use
+    unlinked: HashSet<u64>,
+}
+
+impl FsState {
+    pub fn new() -> Self {
+        let root_node = Node {
+            name: "/".to_string(),
+            parent: ROOT_ATTR.ino,
+            attrs: ROOT_ATTR,
+            content: NodeContent::Children(NodeChildren::new()),
+            xattrs: BTreeMap::new(),
+        };
+
+        FsState {
+            inodes: InodeTable::from([(ROOT_ATTR.ino, root_node)]),
+            inode_range: ROOT_ATTR.ino + 1..u64::MAX,
+            handles: BTreeMap::new(),
+            // 0 is what handles were before they existed, keep it unused
+            handle_counter: 1,
+            lookups: HashMap::new(),
+            unlinked: HashSet::new(),
+        }
+    }
+
+    /// A tree whose nodes but the root are numbered within `inodes`.
+    pub fn with_inodes(inodes: Range<u64>) -> Self {
+        FsState {
+            inode_range: inodes,
+            ..FsState::new()
+        }
+    }
+
+    /// Picks the inode of a new node from a hash of `key`, e.g. its path, so
+    /// that a node gets the same one whenever it's created again. Should it
+    /// be taken, the next free one is picked instead.
+    pub fn allocate_inode(&mut self, key: &str) -> u64 {
+        let Range { start, end } = self.inode_range;
+        let digest = Sha256::digest(key.as_bytes());
+        let hash = u64::from_be_bytes(digest[..8].try_into().expect("digests are 32 bytes"));
+        let mut inode = start + hash % (end - start);
+        while self.inodes.contains_key(&inode) {
+            inode = match inode + 1 {
+                next if next == end => start,
+                next => next,
+            };
+        }
+        inode
+    }
+
+    pub fn child_inode(&self, parent_inode: u64, name: &str) -> Option<u64> {
+        match &self.inodes.get(&parent_inode)?.content {
+            NodeContent::Children(children) => children.get(name).copied(),
+            NodeContent::Bytes(_) => None,
+        }
+    }
+
+    /// The name of the entry of a directory the kernel knows by `entry`, see
+    /// [`names`]. Names cut short are looked up among the entries.
+    pub fn entry_name(&self, parent_inode: u64, entry: &str) -> Option<String> {
+        if let Some(name) = names::decode_name(entry) {
+            return Some(name.into_owned());
+        }
+        match &self.inodes.get(&parent_inode)?.content {
+            NodeContent::Children(children) => children
+                .keys()
+                .find(|name| names::encode_name(name) == entry)
+                .cloned(),
+            NodeContent::Bytes(_) => None,
+        }
+    }
+
+    /// Returns the path of a node relative to the root of the mount.
+    pub fn node_path(&self, inode: u64) -> String {
+        let mut components = Vec::new();
+        let mut current = inode;
+        while let Some(node) = self.inodes.get(&current) {
+            if node.parent == current {
+                break; // reached the root
+            }
+            components.push(names::encode_name(&node.name));
+            current = node.parent;
+        }
+        components.reverse();
+        format!("/{}", components.join("/"))
+    }
+
+    /// Adds a node to the table and lists it in its parent directory.
+    pub fn insert_node(&mut self, node: Node) -> Option<u64> {
+        let inode = node.attrs.ino;
+        let Some(parent_node) = self.inodes.get_mut(&node.parent) else {
+            log::error!(
+                "failed to create '{}': parent inode {} not found",
+                node.name,
+                node.parent
+            );
+            return None;
+        };
+
+        match &mut parent_node.content {
+            NodeContent::Children(children) => {
+                children.insert(node.name.clone(), inode);
+                if node.attrs.kind == FileType::Directory {
+                    parent_node.attrs.nlink += 1; // each child directory increases the link count of the parent
+                }
+            }
+            NodeContent::Bytes(_) => {
+                log::error!("parent node must be a directory");
+                return None;
+            }
+        }
+
+        self.inodes.insert(inode, node);
+        Some(inode)
+    }
+
+    /// Moves a node to a different directory and/or name, replacing the node
+    /// previously found there.
+    pub fn move_node(&mut self, inode: u64, new_parent: u64, new_name: &str) -> Option<()> {
+        if let Some(replaced_inode) = self.child_inode(new_parent, new_name) {
+            self.remove_node(replaced_inode);
+        }
+
+        let node = self.inodes.get_mut(&inode)?;
+        let old_parent = node.parent;
+        let old_name = std::mem::replace(&mut node.name, new_name.to_string());
+        node.parent = new_parent;
+
+        if let Some(NodeContent::Children(children)) =
+            self.inodes.get_mut(&old_parent).map(|n| &mut n.content)
+        {
+            children.remove(&old_name);
+        }
+        if let Some(NodeContent::Children(children)) =
+            self.inodes.get_mut(&new_parent).map(|n| &mut n.content)
+        {
+            children.insert(new_name.to_string(), inode);
+        }
+        Some(())
+    }
+
+    /// Removes a node and, for directories, everything below it. Nodes still
+    /// in use are only unlinked, see [`Self::reclaim`].
+    pub fn remove_node(&mut self, inode: u64) {
+        self.detach_node(inode);
+        self.drop_subtree(inode);
+    }
+
+    fn drop_subtree(&mut self, inode: u64) {
+        if self.in_use(inode) {
+            let Some(node) = self.inodes.get_mut(&inode) else {
+                return;
+            };
+            let children = match &mut node.content {
+                NodeContent::Children(children) => std::mem::take(children),
+                NodeContent::Bytes(_) => NodeChildren::new(),
+            };
+            node.attrs.nlink = 0;
+            node.xattrs
+                .insert(DELETED_XATTR.to_string(), b"true".to_vec());
+            self.unlinked.insert(inode);
+            for child_inode in children.into_values() {
+                self.drop_subtree(child_inode);
+            }
+            return;
+        }
+
+        let Some(node) = self.inodes.remove(&inode) else {
+            return;
+        };
+        if let NodeContent::Children(children) = node.content {
+            for child_inode in children.into_values() {
+                self.drop_subtree(child_inode);
+            }
+        }
+    }
+
+    /// Records that the kernel has been handed a reference to the node, as
+    /// with every reply to `lookup` or `create`.
+    pub fn remember(&mut self, inode: u64) {
+        *self.lookups.entry(inode).or_default() += 1;
+    }
+
+    /// Drops `count` references the kernel held to the node.
+    pub fn forget(&mut self, inode: u64, count: u64) {
+        let Some(lookups) = self.lookups.get_mut(&inode) else {
+            return;
+        };
+        *lookups = lookups.saturating_sub(count);
+        if *lookups == 0 {
+            self.lookups.remove(&inode);
+            self.reclaim(inode);
+        }
+    }
+
+    /// Whether the kernel still references the node or it is open.
+    fn in_use(&self, inode: u64) -> bool {
+        self.lookups.contains_key(&inode)
+            || self.handles.values().any(|handle| match handle {
+                Handle::File {
+                    inode: handle_inode,
+                    ..
+                }
+                | Handle::Dir {
+                    inode: handle_inode,
+                    ..
+                } => *handle_inode == inode,
+            })
+    }
+
+    /// Removes an unlinked node for good once it's no longer in use.
+    fn reclaim(&mut self, inode: u64) {
+        if self.unlinked.contains(&inode) && !self.in_use(inode) {
+            self.unlinked.remove(&inode);
+            self.inodes.remove(&inode);
+        }
+    }
+
+    /// Drops the nodes that are neither reachable from the root or `roots`
+    /// nor in use, and the entries of the auxiliary indexes that point to
+    /// nodes that are gone, then releases the memory the tables no longer
+    /// need. Nodes are meant to be removed as they go, this catches those
+    /// left behind, e.g. below a node created on lookup.
+    pub fn compact(&mut self, roots: impl IntoIterator<Item = u64>) -> Reclaimed {
+        let mut pending: Vec<u64> = std::iter::once(ROOT_ATTR.ino)
+            .chain(roots)
+            .chain(self.unlinked.iter().copied())
+            .chain(self.lookups.keys().copied())
+            .chain(self.handles.values().map(|handle| match handle {
+                Handle::File { inode, .. } | Handle::Dir { inode, .. } => *inode,
+            }))
+            .collect();
+        let mut reachable = HashSet::new();
+        while let Some(inode) = pending.pop() {
+            if !reachable.insert(inode) {
+                continue;
+            }
+            if let Some(NodeContent::Children(children)) =
+                self.inodes.get(&inode).map(|node| &node.content)
+            {
+                pending.extend(children.values());
+            }
+        }
+
+        let mut reclaimed = Reclaimed::default();
+        let capacity = self.inodes.capacity();
+        self.inodes.retain(|inode, node| {
+            let keep = reachable.contains(inode);
+            if !keep {
+                reclaimed.nodes += 1;
+                reclaimed.bytes += node.size();
+            }
+            keep
+        });
+
+        // e.g. links to nodes removed from their own directory
+        let live: HashSet<u64> = self.inodes.keys().copied().collect();
+        for node in self.inodes.values_mut() {
+            if let NodeContent::Children(children) = &mut node.content {
+                children.retain(|_, inode| live.contains(inode));
+            }
+        }
+        self.lookups.retain(|inode, _| live.contains(inode));
+        self.unlinked.retain(|inode| live.contains(inode));
+
+        self.inodes.shrink_to_fit();
+        self.lookups.shrink_to_fit();
+        self.unlinked.shrink_to_fit();
+        // the table entries are given back when the table shrinks
+        let entry_size = size_of::<(u64, Node)>() as u64;
+        reclaimed.bytes += (capacity - self.inodes.capacity()) as u64 * entry_size;
+        reclaimed
+    }
+
+    /// Lists a node in another directory than its parent, under any name.
+    /// The node stays where it is, e.g. when its parent is removed.
+    pub fn link(&mut self, parent: u64, name: &str, inode: u64) {
+        if let Some(parent_node) = self.inodes.get_mut(&parent)
+            && let NodeContent::Children(children) = &mut parent_node.content
+        {
+            children.insert(name.to_string(), inode);
+        }
+    }
+
+    /// Removes an entry added by [`Self::link`].
+    pub fn unlink(&mut self, parent: u64, name: &str) {
+        if let Some(parent_node) = self.inodes.get_mut(&parent)
+            && let NodeContent::Children(children) = &mut parent_node.content
+        {
+            children.remove(name);
+        }
+    }
+
+    /// Unlists a node from its parent directory. The node stays reachable
+    /// through its inode and keeps pointing to the parent.
+    pub fn detach_node(&mut self, inode: u64) {
+        let Some((parent, name, kind)) = self
+            .inodes
+            .get(&inode)
+            .map(|node| (node.parent, node.name.clone(), node.attrs.kind))
+        else {
+            return;
+        };
+
+        if let Some(parent_node) = self.inodes.get_mut(&parent)
+            && let NodeContent::Children(siblings) = &mut parent_node.content
+            && siblings.remove(&name).is_some()
+            && kind == FileType::Directory
+        {
+            parent_node.attrs.nlink -= 1;
+        }
+    }
+
+    /// Opens a file, taking a snapshot of its content unless it's opened for
+    /// writing, in which case reads see the writes.
+    pub fn open_file(&mut self, inode: u64, writable: bool) -> Option<u64> {
+        let NodeContent::Bytes(content) = &self.inodes.get(&inode)?.content else {
+            return None;
+        };
+
+        let snapshot = (!writable).then(|| content.clone());
+        Some(self.add_handle(Handle::File {
+            inode,
+            snapshot,
+            locks: Vec::new(),
+        }))
+    }
+
+    /// Opens a directory, taking a snapshot of its entries.
+    pub fn open_dir(&mut self, inode: u64) -> Option<u64> {
+        self.open_dir_filtered(inode, |_| true)
+    }
+
+    /// Opens a directory listing only the entries whose names are kept,
+    /// besides `.` and `..`. The others can still be looked up.
+    pub fn open_dir_filtered(&mut self, inode: u64, keep: impl Fn(&str) -> bool) -> Option<u64> {
+        let node = self.inodes.get(&inode)?;
+        let NodeContent::Children(children) = &node.content else {
+            return None;
+        };
+
+        let mut entries = vec![
+            DirEntry {
+                inode,
+                kind: FileType::Directory,
+                name: ".".to_string(),
+            },
+            DirEntry {
+                inode: 1, // FIXME: should be pointing to the parent inode
+                kind: FileType::Directory,
+                name: "..".to_string(),
+            },
+        ];
+        for (name, &child_inode) in children.iter().filter(|(name, _)| keep(name)) {
+            match self.inodes.get(&child_inode) {
+                Some(child_node) => entries.push(DirEntry {
+                    inode: child_inode,
+                    kind: child_node.attrs.kind,
+                    // links may name it differently
+                    name: names::encode_name(name).into_owned(),
+                }),
+                None => {
+                    log::warn!(
+                        "child {name} with inode {child_inode} was not found in inodes table"
+                    )
+                }
+            }
+        }
+
+        Some(self.add_handle(Handle::Dir { inode, entries }))
+    }
+
+    fn add_handle(&mut self, handle: Handle) -> u64 {
+        let fh = self.handle_counter;
+        self.handle_counter += 1;
+        self.handles.insert(fh, handle);
+        fh
+    }
+
+    /// Returns the content of a file as seen through the handle: the snapshot
+    /// taken at open time if there is one, the current content otherwise.
+    pub fn file_content(&self, inode: u64, fh: u64) -> Option<&[u8]> {
+        match self.handles.get(&fh) {
+            Some(Handle::File {
+                inode: handle_inode,
+                snapshot: Some(snapshot),
+                ..
+            }) if *handle_inode == inode => Some(snapshot),
+            _ => match &self.inodes.get(&inode)?.content {
+                NodeContent::Bytes(content) => Some(content),
+                NodeContent::Children(_) => None,
+            },
+        }
+    }
+
+    /// Returns the directory entries taken when the handle was opened.
+    pub fn dir_entries(&self, inode: u64, fh: u64) -> Option<&[DirEntry]> {
+        match self.handles.get(&fh) {
+            Some(Handle::Dir {
+                inode: handle_inode,
+                entries,
+            }) if *handle_inode == inode => Some(entries),
+            _ => None,
+        }
+    }
+
+    /// Returns a lock held on the file by another owner that prevents taking
+    /// the given one.
+    pub fn conflicting_lock(&self, inode: u64, lock: &FileLock) -> Option<FileLock> {
+        self.handles.values().find_map(|handle| match handle {
+            Handle::File {
+                inode: handle_inode,
+                locks,
+                ..
+            } if *handle_inode == inode => {
+                locks.iter().find(|held| held.conflicts_with(lock)).copied()
+            }
+            _ => None,
+        })
+    }
+
+    /// Takes or, with `F_UNLCK`, releases a lock through a file handle. Locks
+    /// of the same owner are merged the way POSIX record locks are: the new
+    /// lock replaces whatever the owner held in its range.
+    pub fn set_lock(&mut self, inode: u64, fh: u64, lock: FileLock) -> Result<(), libc::c_int> {
+        if lock.typ != libc::F_UNLCK && self.conflicting_lock(inode, &lock).is_some() {
+            return Err(libc::EAGAIN);
+        }
+
+        let Some(Handle::File {
+            inode: handle_inode,
+            locks,
+            ..
+        }) = self.handles.get_mut(&fh)
+        else {
+            return Err(libc::EBADF);
+        };
+        if *handle_inode != inode {
+            return Err(libc::EBADF);
+        }
+
+        let mut remaining = Vec::with_capacity(locks.len() + 1);
+        for held in locks.drain(..) {
+            if held.owner != lock.owner || !held.overlaps(&lock) {
+                remaining.push(held);
+                continue;
+            }
+            // keep the parts outside of the new lock's range
+            if held.start < lock.start {
+                remaining.push(FileLock {
+                    end: lock.start - 1,
+                    ..held
+                });
+            }
+            if held.end > lock.end {
+                remaining.push(FileLock {
+                    start: lock.end + 1,
+                    ..held
+                });
+            }
+        }
+        if lock.typ != libc::F_UNLCK {
+            remaining.push(lock);
+        }
+        *locks = remaining;
+        Ok(())
+    }
+
+    /// Releases all locks an owner holds through a file handle, as happens
+    /// when the owner closes the file.
+    pub fn release_locks(&mut self, fh: u64, owner: u64) {
+        if let Some(Handle::File { locks, .. }) = self.handles.get_mut(&fh) {
+            locks.retain(|lock| lock.owner != owner);
+        }
+    }
+
+    /// Whether a file is open for writing through any handle.
+    pub fn has_writers(&self, inode: u64) -> bool {
+        self.handles.values().any(|handle| {
+            matches!(handle, Handle::File { inode: handle_inode, snapshot: None, .. } if *handle_inode == inode)
+        })
+    }
+
+    pub fn release(&mut self, fh: u64) {
+        if let Some(Handle::File { inode, .. } | Handle::Dir { inode, .. }) =
+            self.handles.remove(&fh)
+        {
+            self.reclaim(inode);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc, thread, time::SystemTime};
+
+    use super::*;
+
+    fn node(state: &mut FsState, parent: u64, name: &str, content: NodeContent) -> Node {
+        let kind = match content {
+            NodeContent::Bytes(_) => FileType::RegularFile,
+            NodeContent::Children(_) => FileType::Directory,
+        };
+        Node {
+            name: name.to_string(),
+            parent,
+            attrs: FileAttr {
+                ino: state.allocate_inode(&format!("{parent}/{name}")),
+                kind,
+                nlink: if kind == FileType::Directory { 2 } else { 1 },
+                atime: SystemTime::UNIX_EPOCH,
+                mtime: SystemTime::UNIX_EPOCH,
+                ctime: SystemTime::UNIX_EPOCH,
+                crtime: SystemTime::UNIX_EPOCH,
+                ..ROOT_ATTR
+            },
+            content,
+            xattrs: BTreeMap::new(),
+        }
+    }
+
+    fn add_file(state: &mut FsState, parent: u64, name: &str, content: &[u8]) -> u64 {
+        let file = node(
+            state,
+            parent,
+            name,
+            NodeContent::Bytes(Arc::new(content.to_vec())),
+        );
+        state.insert_node(file).unwrap()
+    }
+
+    fn add_dir(state: &mut FsState, parent: u64, name: &str) -> u64 {
+        let dir = node(
+            state,
+            parent,
+            name,
+            NodeContent::Children(NodeChildren::new()),
+        );
+        state.insert_node(dir).unwrap()
+    }
+
+    type StateUpdate = Box<dyn FnOnce(&mut FsState) + Send>;
+
+    /// Applies updates produced by a background thread in between the steps of
+    /// `operation`, the way the FUSE thread applies refresher updates in
+    /// between FUSE operations.
+    fn interleave(
+        state: &mut FsState,
+        produce: impl FnOnce(mpsc::Sender<StateUpdate>) + Send + 'static,
+        mut operation: impl FnMut(&mut FsState) -> bool,
+    ) {
+        let (tx, rx) = mpsc::channel::<StateUpdate>();
+        let producer = thread::spawn(move || produce(tx));
+
+        let mut producer_done = false;
+        loop {
+            match rx.try_recv() {
+                Ok(update) => update(state),
+                Err(mpsc::TryRecvError::Empty) => thread::yield_now(),
+                Err(mpsc::TryRecvError::Disconnected) => producer_done = true,
+            }
+            if !operation(state) && producer_done {
+                break;
+            }
+        }
+        producer.join().unwrap();
+    }
+
+    #[test]
+    fn readdir_during_update_sees_a_consistent_listing() {
+        let mut state = FsState::new();
+        let dir = add_dir(&mut state, ROOT_ATTR.ino, "configmaps");
+        for i in 0..100 {
+            add_file(&mut state, dir, &format!("cm-{i}.yaml"), b"data: {}\n");
+        }
+
+        let fh = state.open_dir(dir).unwrap();
+        let mut listed = Vec::new();
+        interleave(
+            &mut state,
+            move |tx| {
+                // a refresh replacing every object with a new one
+                for i in 0..100 {
+                    let update: StateUpdate = Box::new(move |state| {
+                        let old = state.child_inode(dir, &format!("cm-{i}.yaml")).unwrap();
+                        state.remove_node(old);
+                        add_file(state, dir, &format!("new-{i}.yaml"), b"data: {}\n");
+                    });
+                    tx.send(update).unwrap();
+                }
+            },
+            |state| {
+                // readdir is called repeatedly with increasing offsets
+                let entries = state.dir_entries(dir, fh).unwrap();
+                match entries.get(listed.len()) {
+                    Some(entry) => {
+                        listed.push(entry.name.clone());
+                        true
+                    }
+                    None => false,
+                }
+            },
+        );
+        state.release(fh);
+
+        let mut expected: Vec<String> = (0..100).map(|i| format!("cm-{i}.yaml")).collect();
+        expected.extend([".".to_string(), "..".to_string()]);
+        expected.sort();
+        listed.sort();
+        assert_eq!(listed, expected);
+
+        // the next listing reflects the refresh
+        let fh = state.open_dir(dir).unwrap();
+        let entries = state.dir_entries(dir, fh).unwrap();
+        assert_eq!(entries.len(), 102);
+        assert!(entries.iter().all(|e| !e.name.starts_with("cm-")));
+    }
+
+    #[test]
+    fn open_during_delete_keeps_the_content_readable() {
+        let mut state = FsState::new();
+        let ns = add_dir(&mut state, ROOT_ATTR.ino, "default");
+        let dir = add_dir(&mut state, ns, "configmaps");
+        let file = add_file(&mut state, dir, "app.yaml", b"data:\n  key: value\n");
+
+        let fh = state.open_file(file, false).unwrap();
+        let mut reads = 0;
+        interleave(
+            &mut state,
+            move |tx| {
+                // the namespace is deleted while the file is being read
+                tx.send(Box::new(move |state: &mut FsState| state.remove_node(ns)))
+                    .unwrap();
+            },
+            |state| {
+                assert_eq!(
+                    state.file_content(file, fh),
+                    Some(&b"data:\n  key: value\n"[..])
+                );
+                reads += 1;
+                reads < 100
+            },
+        );
+
+        // unlinked, but kept for the open handle
+        assert_eq!(state.child_inode(dir, "app.yaml"), None);
+        assert!(!state.inodes.contains_key(&dir));
+        assert_eq!(state.inodes[&ROOT_ATTR.ino].attrs.nlink, 2);
+        assert_eq!(state.inodes[&file].attrs.nlink, 0);
+        assert!(state.inodes[&file].xattrs.contains_key(DELETED_XATTR));
+
+        state.release(fh);
+        assert!(!state.inodes.contains_key(&file));
+        assert_eq!(state.file_content(file, fh), None);
+    }
+
+    #[test]
+    fn removed_nodes_live_until_forgotten() {
+        let mut state = FsState::new();
+        let dir = add_dir(&mut state, ROOT_ATTR.ino, "configmaps");
+        let file = add_file(&mut state, dir, "app.yaml", b"data: {}\n");
+        let other = add_file(&mut state, dir, "other.yaml", b"data: {}\n");
+
+        // the kernel looked the file up twice and never saw the other one
+        state.remember(dir);
+        state.remember(file);
+        state.remember(file);
+
+        state.remove_node(dir);
+        assert_eq!(state.child_inode(ROOT_ATTR.ino, "configmaps"), None);
+        assert_eq!(state.inodes[&ROOT_ATTR.ino].attrs.nlink, 2);
+        assert!(!state.inodes.contains_key(&other));
+        assert_eq!(state.inodes[&file].attrs.nlink, 0);
+        assert_eq!(state.child_inode(dir, "app.yaml"), None);
+
+        state.forget(file, 1);
+        assert!(state.inodes.contains_key(&file));
+        state.forget(file, 1);
+        assert!(!state.inodes.contains_key(&file));
+        state.forget(dir, 1);
+        assert!(!state.inodes.contains_key(&dir));
+    }
+
+    #[test]
+    fn forgetting_listed_nodes_keeps_them() {
+        let mut state = FsState::new();
+        let file = add_file(&mut state, ROOT_ATTR.ino, "app.yaml", b"data: {}\n");
+
+        state.remember(file);
+        state.forget(file, 1);
+        assert_eq!(state.child_inode(ROOT_ATTR.ino, "app.yaml"), Some(file));
+
+        // and once removed, nothing references it anymore
+        state.remove_node(file);
+        assert!(!state.inodes.contains_key(&file));
+    }
+
+    #[test]
+    fn compaction_drops_what_nothing_points_to() {
+        let mut state = FsState::new();
+        let dir = add_dir(&mut state, ROOT_ATTR.ino, "configmaps");
+        let file = add_file(&mut state, dir, "app.yaml", b"data: {}\n");
+        let hidden = add_file(&mut state, dir, "app.yaml#", b"data: {}\n");
+        let open = add_file(&mut state, dir, "open.yaml", b"data: {}\n");
+        let links = add_dir(&mut state, ROOT_ATTR.ino, "_flat");
+        state.link(links, "configmaps_app.yaml", file);
+
+        // left behind below a detached directory, or open
+        let orphans = add_dir(&mut state, ROOT_ATTR.ino, "orphans");
+        let orphan = add_file(&mut state, orphans, "app.yaml", b"data: {}\n");
+        state.detach_node(orphans);
+        state.detach_node(hidden);
+        let fh = state.open_file(open, false).unwrap();
+        state.remove_node(open);
+        // a link outliving the node it pointed to
+        state.remove_node(file);
+
+        let reclaimed = state.compact([hidden]);
+        assert_eq!(reclaimed.nodes, 2);
+        assert!(!state.inodes.contains_key(&orphans));
+        assert!(!state.inodes.contains_key(&orphan));
+        assert!(state.inodes.contains_key(&hidden));
+        assert_eq!(state.file_content(open, fh), Some(&b"data: {}\n"[..]));
+        assert_eq!(state.child_inode(links, "configmaps_app.yaml"), None);
+    }
+
+    fn lock(owner: u64, start: u64, end: u64, typ: i32) -> FileLock {
+        FileLock {
+            owner,
+            start,
+            end,
+            typ,
+            pid: owner as u32,
+        }
+    }
+
+    #[test]
+    fn locks_conflict_across_handles() {
+        let mut state = FsState::new();
+        let file = add_file(&mut state, ROOT_ATTR.ino, "app.yaml", b"data: {}\n");
+        let first = state.open_file(file, false).unwrap();
+        let second = state.open_file(file, false).unwrap();
+
+        let read = lock(1, 0, u64::MAX, libc::F_RDLCK);
+        state.set_lock(file, first, read).unwrap();
+        // shared with other readers, exclusive to writers
+        state
+            .set_lock(file, second, lock(2, 0, 10, libc::F_RDLCK))
+            .unwrap();
+        let write = lock(3, 5, 5, libc::F_WRLCK);
+        assert_eq!(state.conflicting_lock(file, &write), Some(read));
+        assert_eq!(state.set_lock(file, second, write), Err(libc::EAGAIN));
+
+        // closing the first handle releases its locks
+        state.release(first);
+        state
+            .set_lock(file, second, lock(2, 0, 10, libc::F_UNLCK))
+            .unwrap();
+        state.set_lock(file, second, write).unwrap();
+    }
+
+    #[test]
+    fn unlocking_part_of_a_range_keeps_the_rest() {
+        let mut state = FsState::new();
+        let file = add_file(&mut state, ROOT_ATTR.ino, "app.yaml", b"data: {}\n");
+        let fh = state.open_file(file, false).unwrap();
+
+        state
+            .set_lock(file, fh, lock(1, 0, 99, libc::F_WRLCK))
+            .unwrap();
+        state
+            .set_lock(file, fh, lock(1, 10, 19, libc::F_UNLCK))
+            .unwrap();
+
+        assert_eq!(
+            state.conflicting_lock(file, &lock(2, 10, 19, libc::F_WRLCK)),
+            None
+        );
+        assert_eq!(
+            state.conflicting_lock(file, &lock(2, 5, 15, libc::F_RDLCK)),
+            Some(lock(1, 0, 9, libc::F_WRLCK))
+        );
+        assert_eq!(
+            state.conflicting_lock(file, &lock(2, 15, 25, libc::F_RDLCK)),
+            Some(lock(1, 20, 99, libc::F_WRLCK))
+        );
+
+        state.release_locks(fh, 1);
+        assert_eq!(
+            state.conflicting_lock(file, &lock(2, 0, 99, libc::F_WRLCK)),
+            None
+        );
+    }
+
+    #[test]
+    fn writable_handles_see_writes() {
+        let mut state = FsState::new();
+        let file = add_file(&mut state, ROOT_ATTR.ino, "draft.yaml", b"");
+
+        let fh = state.open_file(file, true).unwrap();
+        state.inodes.get_mut(&file).unwrap().content =
+            NodeContent::Bytes(Arc::new(b"kind: Pod\n".to_vec()));
+        assert_eq!(state.file_content(file, fh), Some(&b"kind: Pod\n"[..]));
+    }
+
+    #[test]
+    fn recreated_nodes_keep_their_inode() {
+        let mut state = FsState::with_inodes(100..110);
+        let dir = add_dir(&mut state, ROOT_ATTR.ino, "default");
+        let file = add_file(&mut state, dir, "web.yaml", b"");
+        assert!((100..110).contains(&file));
+
+        state.remove_node(file);
+        assert_eq!(add_file(&mut state, dir, "web.yaml", b""), file);
+
+        // taken while the first one is still around
+        let taken = state.allocate_inode(&format!("{dir}/web.yaml"));
+        assert_ne!(taken, file);
+        assert!((100..110).contains(&taken));
+    }
+
+    #[test]
+    fn encoded_names_look_up_their_entries() {
+        let mut state = FsState::new();
+        let dir = add_dir(&mut state, ROOT_ATTR.ino, "widgets");
+        let bell = add_file(&mut state, dir, "bell\u{7}.yaml", b"");
+        let long_name = format!("{}.yaml", "x".repeat(300));
+        let long = add_file(&mut state, dir, &long_name, b"");
+
+        let fh = state.open_dir(dir).unwrap();
+        let entries: Vec<&str> = state
+            .dir_entries(dir, fh)
+            .unwrap()
+            .iter()
+            .map(|entry| entry.name.as_str())
+            .collect();
+        assert!(entries.contains(&"%bell%07.yaml"));
+        for entry in entries {
+            assert!(entry.len() <= names::NAME_MAX && !entry.contains(char::is_control));
+            let name = state.entry_name(dir, entry).unwrap();
+            assert!(name == "." || name == ".." || state.child_inode(dir, &name).is_some());
+        }
+        let long_entry = names::encode_name(&long_name);
+        assert_eq!(state.entry_name(dir, &long_entry), Some(long_name.clone()));
+        assert_eq!(state.child_inode(dir, &long_name), Some(long));
+        assert_eq!(
+            state.entry_name(dir, "%bell%07.yaml").as_deref(),
+            Some("bell\u{7}.yaml")
+        );
+        assert_eq!(state.child_inode(dir, "bell\u{7}.yaml"), Some(bell));
+    }
+}
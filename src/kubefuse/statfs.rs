@@ -0,0 +1,157 @@
+use std::collections::BTreeMap;
+
+use serde_json::Value;
+
+use super::{BLOCK_SIZE, KubeFilesystem, ROOT_ATTR, footprint::parse_quantity};
+
+// object counts quotas can limit without the `count/` prefix
+const LEGACY_COUNTS: [&str; 7] = [
+    "pods",
+    "services",
+    "secrets",
+    "configmaps",
+    "persistentvolumeclaims",
+    "replicationcontrollers",
+    "resourcequotas",
+];
+
+/// What `statfs` reports for a namespace: its storage quota as the size of
+/// the filesystem, in bytes, and its object count quotas as the number of
+/// inodes, each as hard limit and use.
+#[derive(Debug, Default, PartialEq)]
+pub struct NamespaceCapacity {
+    pub storage: Option<(u64, u64)>,
+    pub objects: Option<(u64, u64)>,
+}
+
+impl NamespaceCapacity {
+    /// The arguments of `ReplyStatfs::statfs` up to the block size: blocks,
+    /// free blocks, blocks available, inodes and free inodes.
+    pub fn statfs(&self) -> (u64, u64, u64, u64, u64) {
+        let block_size = u64::from(BLOCK_SIZE);
+        let (blocks, bfree) = self
+            .storage
+            .map(|(hard, used)| (hard / block_size, hard.saturating_sub(used) / block_size))
+            .unwrap_or_default();
+        let (files, ffree) = self
+            .objects
+            .map(|(hard, used)| (hard, hard.saturating_sub(used)))
+            .unwrap_or_default();
+        (blocks, bfree, bfree, files, ffree)
+    }
+}
+
+/// The capacity of a namespace given by its ResourceQuotas, the tightest
+/// hard limit of each quota item along with its use. Storage is limited by
+/// `requests.storage`, objects by the sum of the object counts limited, e.g.
+/// `count/deployments.apps` and `pods`.
+pub fn namespace_capacity<'a>(quotas: impl IntoIterator<Item = &'a Value>) -> NamespaceCapacity {
+    // item -> (hard, used)
+    let mut items: BTreeMap<String, (f64, f64)> = BTreeMap::new();
+    for quota in quotas {
+        for (item, hard) in quota["status"]["hard"].as_object().into_iter().flatten() {
+            let (Some(hard), used) = (
+                hard.as_str().and_then(parse_quantity),
+                quota["status"]["used"][item]
+                    .as_str()
+                    .and_then(parse_quantity)
+                    .unwrap_or_default(),
+            ) else {
+                continue;
+            };
+            // `count/pods` and `pods` limit the same
+            let item = match LEGACY_COUNTS.contains(&item.as_str()) {
+                true => format!("count/{item}"),
+                false => item.clone(),
+            };
+            let tightest = items.entry(item).or_insert((hard, used));
+            if hard - used < tightest.0 - tightest.1 {
+                *tightest = (hard, used);
+            }
+        }
+    }
+
+    let storage = items
+        .get("requests.storage")
+        .map(|(hard, used)| (*hard as u64, *used as u64));
+    let counts: Vec<&(f64, f64)> = items
+        .iter()
+        .filter(|(item, _)| item.starts_with("count/"))
+        .map(|(_, amounts)| amounts)
+        .collect();
+    let objects = (!counts.is_empty()).then(|| {
+        counts
+            .iter()
+            .fold((0, 0), |(hard, used), (item_hard, item_used)| {
+                (hard + *item_hard as u64, used + *item_used as u64)
+            })
+    });
+    NamespaceCapacity { storage, objects }
+}
+
+impl KubeFilesystem {
+    /// The namespace a node is in, if any: the one whose directory it is or
+    /// is under.
+    fn enclosing_namespace(&self, mut inode: u64) -> Option<String> {
+        while inode != ROOT_ATTR.ino {
+            let node = self.state.inodes.get(&inode)?;
+            if node.parent == ROOT_ATTR.ino {
+                return (self.namespace_inode(&node.name) == Some(inode))
+                    .then(|| node.name.clone());
+            }
+            inode = node.parent;
+        }
+        None
+    }
+
+    /// The capacity `statfs` reports for a node under a namespace with
+    /// ResourceQuotas presented, e.g. as custom resources, so that `df` gives
+    /// a tenant-level answer.
+    pub(super) fn capacity_of(&mut self, inode: u64) -> Option<NamespaceCapacity> {
+        let namespace = self.enclosing_namespace(inode)?;
+        let quotas_inode = self
+            .namespace_inode(&namespace)
+            .and_then(|ns_inode| self.state.child_inode(ns_inode, "resourcequotas"))?;
+        let manifests: Vec<u64> = self
+            .children(quotas_inode)
+            .into_iter()
+            .filter(|(name, _)| self.profile.manifest_object_name(name).is_some())
+            .map(|(_, inode)| inode)
+            .collect();
+        let quotas: Vec<Value> = manifests
+            .into_iter()
+            .filter_map(|inode| serde_yaml::from_slice(&self.file_content(inode)?).ok())
+            .collect();
+        let capacity = namespace_capacity(&quotas);
+        (capacity != NamespaceCapacity::default()).then_some(capacity)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn takes_the_tightest_quota() {
+        let quotas = [
+            json!({"status": {
+                "hard": {"requests.storage": "100Gi", "count/pods": "20", "configmaps": "10"},
+                "used": {"requests.storage": "40Gi", "count/pods": "5", "configmaps": "2"},
+            }}),
+            json!({"status": {
+                "hard": {"requests.storage": "50Gi", "requests.cpu": "4"},
+                "used": {"requests.storage": "40Gi", "requests.cpu": "1"},
+            }}),
+        ];
+        let gib = 1024 * 1024 * 1024;
+        assert_eq!(
+            namespace_capacity(&quotas),
+            NamespaceCapacity {
+                storage: Some((50 * gib, 40 * gib)),
+                objects: Some((30, 7)),
+            }
+        );
+    }
+}
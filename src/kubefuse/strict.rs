@@ -0,0 +1,102 @@
+use std::{collections::BTreeMap, time::SystemTime};
+
+use super::KubeFilesystem;
+
+/// Suffix of the file standing in for a manifest, or a resource directory,
+/// that couldn't be rendered or listed in strict mode, holding the error.
+pub const ERROR_SUFFIX: &str = ".error";
+
+impl KubeFilesystem {
+    /// Logs that an object couldn't be rendered and counts it for
+    /// `stats.txt`. Returns what its file holds instead, a comment saying so,
+    /// unless it's left out in strict mode.
+    pub(super) fn render_failure(
+        &mut self,
+        kind: &str,
+        namespace: Option<&str>,
+        name: &str,
+        error: &str,
+    ) -> Vec<u8> {
+        let reference = match namespace {
+            Some(namespace) => format!("{kind} {namespace}/{name}"),
+            None => format!("{kind} {name}"),
+        };
+        log::error!("failed to render {reference}: {error}");
+        self.render_failures += 1;
+        format!("# failed to render {reference}: {error}\n").into_bytes()
+    }
+
+    /// Reports that the entry `name` of a directory, a manifest or a resource
+    /// directory, couldn't be rendered or listed. In strict mode this makes
+    /// for `<name>.error` holding the error, in place of a manifest, and
+    /// fails the readiness check until it's resolved. Returns whether strict
+    /// mode is on, i.e. whether the entry is to be left out.
+    pub(super) fn report_invalid(&mut self, dir_inode: u64, name: &str, error: &str) -> bool {
+        if !self.profile.strict {
+            return false;
+        }
+        if let Some(inode) = self
+            .state
+            .child_inode(dir_inode, name)
+            .filter(|inode| !self.is_dir(*inode))
+        {
+            self.state.remove_node(inode);
+        }
+        let error_name = format!("{name}{ERROR_SUFFIX}");
+        let content = format!("{error}\n").into_bytes();
+        let inode = match self.state.child_inode(dir_inode, &error_name) {
+            Some(inode) => {
+                self.update_node_content(inode, content);
+                inode
+            }
+            None => {
+                let Some(inode) =
+                    self.create_content_node(dir_inode, &error_name, content, SystemTime::now())
+                else {
+                    return true;
+                };
+                inode
+            }
+        };
+        self.invalid.insert(inode, error.to_string());
+        self.sync_invalid();
+        true
+    }
+
+    /// Removes the `.error` file of an entry once it was rendered or listed.
+    pub(super) fn clear_invalid(&mut self, dir_inode: u64, name: &str) {
+        let error_name = format!("{name}{ERROR_SUFFIX}");
+        if let Some(inode) = self.state.child_inode(dir_inode, &error_name) {
+            self.state.remove_node(inode);
+            if self.invalid.remove(&inode).is_some() {
+                self.sync_invalid();
+            }
+        }
+    }
+
+    /// Removes the `.error` file of a resource directory once it was listed.
+    pub(super) fn clear_list_error(&mut self, dir_inode: u64) {
+        let Some(node) = self.state.inodes.get(&dir_inode) else {
+            return;
+        };
+        let (parent, name) = (node.parent, node.name.clone());
+        self.clear_invalid(parent, &name);
+    }
+
+    /// Mirrors the `.error` files still present into the health checks.
+    pub(super) fn sync_invalid(&mut self) {
+        if !self.profile.strict {
+            return;
+        }
+        let inodes = &self.state.inodes;
+        self.invalid.retain(|inode, _| inodes.contains_key(inode));
+        let invalid: BTreeMap<String, String> = self
+            .invalid
+            .iter()
+            .map(|(inode, error)| (self.state.node_path(*inode), error.clone()))
+            .collect();
+        if let Ok(mut health) = self.health.lock() {
+            health.invalid = invalid;
+        }
+    }
+}
@@ -0,0 +1,205 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use sha2::{Digest as _, Sha256};
+
+use crate::{
+    layout::{Layout, LayoutStrategy, ManifestPath},
+    sanitize,
+};
+
+use super::KubeFilesystem;
+
+pub const TREE_HASH_NAME: &str = "tree-hash";
+
+type Digest = sha2::digest::Output<Sha256>;
+
+/// Hashes of sanitized manifests by inode, along with a hash of the content
+/// they were computed from, so that only changed manifests are sanitized
+/// again.
+pub type ManifestHashes = HashMap<u64, (u64, Digest)>;
+
+// the Merkle tree: directories hash the names and hashes of their entries
+enum HashEntry {
+    Dir(BTreeMap<String, HashEntry>),
+    Manifest(Digest),
+}
+
+impl HashEntry {
+    fn insert(&mut self, path: &[String], digest: Digest) {
+        let (HashEntry::Dir(entries), Some((name, rest))) = (self, path.split_first()) else {
+            return;
+        };
+        if rest.is_empty() {
+            entries.insert(name.clone(), HashEntry::Manifest(digest));
+            return;
+        }
+        entries
+            .entry(name.clone())
+            .or_insert_with(|| HashEntry::Dir(BTreeMap::new()))
+            .insert(rest, digest);
+    }
+
+    fn digest(&self) -> Digest {
+        match self {
+            HashEntry::Manifest(digest) => *digest,
+            HashEntry::Dir(entries) => {
+                let mut listing = Vec::new();
+                for (name, entry) in entries {
+                    listing.extend_from_slice(name.as_bytes());
+                    listing.push(0); // names can't contain NUL
+                    listing.extend_from_slice(&entry.digest());
+                }
+                Sha256::digest(&listing)
+            }
+        }
+    }
+}
+
+impl KubeFilesystem {
+    /// Renders `.kubefuse/tree-hash`: the SHA-256 of a Merkle tree over the
+    /// sanitized manifests of the mount, laid out the way the root of the
+    /// mount is, followed by the hash of every top-level directory of it to
+    /// narrow a difference down. Manifest file names are hashed without the
+    /// extension, so the hash doesn't depend on the manifest format.
+    pub(super) fn render_tree_hash(&mut self) {
+        let Some(tree_hash_inode) = self.tree_hash_inode else {
+            return;
+        };
+
+        let mut tree = HashEntry::Dir(BTreeMap::new());
+        let mut hashes = ManifestHashes::new();
+        for (location, inode) in self.presented_manifests() {
+            let path = Layout::NamespaceFirst.path(&ManifestPath {
+                namespace: location.namespace.as_deref(),
+                resource: &location.resource,
+                file_name: &location.name,
+            });
+            let Some(content) = self.file_content(inode) else {
+                // e.g. evicted while the API server is unreachable
+                if let Some(hashed) = self.manifest_hashes.get(&inode) {
+                    hashes.insert(inode, *hashed);
+                    tree.insert(&path, hashed.1);
+                }
+                continue;
+            };
+            let mut hasher = DefaultHasher::new();
+            content.hash(&mut hasher);
+            let content_hash = hasher.finish();
+            let digest = match self.manifest_hashes.get(&inode) {
+                Some((hashed, digest)) if *hashed == content_hash => *digest,
+                _ => Sha256::digest(sanitize::sanitize_manifest(&content).as_bytes()),
+            };
+            hashes.insert(inode, (content_hash, digest));
+            tree.insert(&path, digest);
+        }
+        // manifests that are gone are dropped along the way
+        self.manifest_hashes = hashes;
+
+        let mut out = format!("{:x}\n", tree.digest());
+        if let HashEntry::Dir(entries) = &tree {
+            for (name, entry) in entries {
+                out.push_str(&format!("{:x}  {name}/\n", entry.digest()));
+            }
+        }
+        self.set_node_content(tree_hash_inode, out.into_bytes());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use crate::{
+        config::Profile,
+        kubefuse::{CONTROL_DIR_NAME, Entry, ROOT_ATTR},
+    };
+
+    use super::*;
+
+    fn config_map(name: &str, port: u16, resource_version: u64) -> String {
+        format!(
+            "apiVersion: v1\nkind: ConfigMap\nmetadata:\n  name: {name}\n  \
+             resourceVersion: '{resource_version}'\ndata:\n  port: '{port}'\n"
+        )
+    }
+
+    // the lines of the tree hash of a mount with the manifests of ConfigMaps,
+    // (namespace, name, manifest), created in the order given
+    fn tree_hash(manifests: &[(&str, &str, String)]) -> Vec<String> {
+        let mut fs = KubeFilesystem::for_tests(Profile::default());
+        fs.create_control_dir(ROOT_ATTR.ino).unwrap();
+        for (namespace, name, manifest) in manifests {
+            let ns_inode = fs
+                .namespace_inode(namespace)
+                .or_else(|| fs.create_dir_node(ROOT_ATTR.ino, namespace))
+                .unwrap();
+            let dir_inode = fs
+                .state
+                .child_inode(ns_inode, "configmaps")
+                .or_else(|| fs.create_dir_node(ns_inode, "configmaps"))
+                .unwrap();
+            let file_name = fs.manifest_file_name(name);
+            fs.create_content_node(
+                dir_inode,
+                &file_name,
+                manifest.as_bytes().to_vec(),
+                SystemTime::now(),
+            )
+            .unwrap();
+        }
+
+        fs.render_tree_hash();
+        match fs.entry(&format!("{CONTROL_DIR_NAME}/{TREE_HASH_NAME}")) {
+            Some(Entry::File(content)) => String::from_utf8(content)
+                .unwrap()
+                .lines()
+                .map(str::to_string)
+                .collect(),
+            _ => panic!("no tree hash"),
+        }
+    }
+
+    #[test]
+    fn hashes_the_same_manifests_the_same_in_any_order() {
+        let manifests = [
+            ("team-a", "app", config_map("app", 80, 1)),
+            ("team-a", "db", config_map("db", 5432, 2)),
+            ("team-b", "app", config_map("app", 8080, 3)),
+        ];
+        let mut reversed = manifests.clone();
+        reversed.reverse();
+
+        let hash = tree_hash(&manifests);
+        assert_eq!(hash.len(), 3, "{hash:?}");
+        assert!(hash[1].ends_with("  team-a/"));
+        assert!(hash[2].ends_with("  team-b/"));
+        assert_eq!(tree_hash(&reversed), hash);
+    }
+
+    #[test]
+    fn ignores_volatile_fields() {
+        let hash = tree_hash(&[("team-a", "app", config_map("app", 80, 1))]);
+        let mut changed = config_map("app", 80, 2);
+        changed.push_str("status:\n  observed: true\n");
+        assert_eq!(tree_hash(&[("team-a", "app", changed)]), hash);
+    }
+
+    #[test]
+    fn narrows_a_change_down_to_its_top_level_directory() {
+        let hash = tree_hash(&[
+            ("team-a", "app", config_map("app", 80, 1)),
+            ("team-b", "app", config_map("app", 8080, 1)),
+        ]);
+        let changed = tree_hash(&[
+            ("team-a", "app", config_map("app", 80, 1)),
+            ("team-b", "app", config_map("app", 9090, 1)),
+        ]);
+        assert_ne!(changed[0], hash[0]);
+        assert_eq!(changed[1], hash[1]);
+        assert_ne!(changed[2], hash[2]);
+        assert!(changed[2].ends_with("  team-b/"));
+    }
+}
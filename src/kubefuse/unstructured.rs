@@ -0,0 +1,353 @@
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use k8s_openapi::chrono;
+
+use crate::{
+    client::{ApiResource, ListParams},
+    config::ManifestFormat,
+    sanitize,
+};
+
+use super::{KubeFilesystem, NodeContent, pods, serialize_manifest};
+
+// set on manifests rendered without a typed model, holds their apiVersion
+const UNTYPED_XATTR: &str = "user.kubefuse.untyped";
+
+fn object_name(object: &serde_json::Value) -> Option<&str> {
+    object["metadata"]["name"].as_str()
+}
+
+/// Whether a string looks like a Kubernetes API version, e.g. `v1` or
+/// `v2beta1`.
+fn is_api_version(version: &str) -> bool {
+    let Some(rest) = version.strip_prefix('v') else {
+        return false;
+    };
+    let (major, rest) = rest.split_at(
+        rest.find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len()),
+    );
+    if major.is_empty() {
+        return false;
+    }
+    rest.is_empty()
+        || ["alpha", "beta"].iter().any(|stage| {
+            rest.strip_prefix(stage)
+                .is_some_and(|n| !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()))
+        })
+}
+
+/// Renders an object of a resource type without a typed model. The object is
+/// converted as returned by the API server rather than through a typed struct,
+/// so that no field unknown to kube-fuse is lost.
+fn render_object(
+    object: &serde_json::Value,
+    sanitize: bool,
+    redact_secrets: bool,
+    format: ManifestFormat,
+) -> Result<String, String> {
+    let mut value = serde_yaml::to_value(object).map_err(|e| e.to_string())?;
+    if redact_secrets && object["kind"] == "Secret" {
+        sanitize::redact_secret_data(&mut value);
+    }
+    if sanitize {
+        sanitize::sanitize_value(&mut value);
+    }
+    serialize_manifest(&value, format)
+}
+
+impl KubeFilesystem {
+    fn render_unstructured(&mut self, object: &serde_json::Value) -> Vec<u8> {
+        self.try_render_unstructured(object).unwrap_or_else(|e| {
+            self.render_failure(
+                object["kind"].as_str().unwrap_or_default(),
+                object["metadata"]["namespace"].as_str(),
+                object_name(object).unwrap_or_default(),
+                &e,
+            )
+        })
+    }
+
+    pub(super) fn try_render_unstructured(
+        &self,
+        object: &serde_json::Value,
+    ) -> Result<Vec<u8>, String> {
+        render_object(
+            object,
+            self.profile.sanitize,
+            self.profile.redact_secrets,
+            self.profile.manifest_format,
+        )
+        .map(String::into_bytes)
+    }
+
+    /// Creates the directory of a resource type without a typed model in a
+    /// namespace, e.g. `<namespace>/certificates/`.
+    pub(super) fn create_unstructured_node(
+        &mut self,
+        ns_inode: u64,
+        namespace: &str,
+        api_resource: &ApiResource,
+        params: &ListParams,
+    ) -> Option<u64> {
+        let dir_inode = self.create_dir_node(ns_inode, &api_resource.plural)?;
+        let client = self.kube_client.clone();
+        let mut keep = HashSet::new();
+        let list_result =
+            client.list_unstructured_pages(api_resource, Some(namespace), params, |page| {
+                if page.relisted {
+                    keep.clear();
+                }
+                self.sync_unstructured_page(dir_inode, api_resource, &page.list.items, &mut keep);
+            });
+        if let Err(e) = list_result {
+            log::error!(
+                "{} fetch failed for namespace {namespace}: {e}",
+                api_resource.plural
+            );
+            self.report_invalid(ns_inode, &api_resource.plural, &e.to_string());
+            return None;
+        }
+
+        self.clear_list_error(dir_inode);
+        self.remove_stale_manifests(dir_inode, &keep);
+        Some(dir_inode)
+    }
+
+    /// Replaces the manifests of every namespace with the listed objects, see
+    /// [`Self::sync_manifests`].
+    pub(super) fn sync_unstructured(
+        &mut self,
+        api_resource: &ApiResource,
+        objects: Vec<serde_json::Value>,
+    ) {
+        let mut by_namespace: HashMap<String, Vec<serde_json::Value>> = HashMap::new();
+        for object in objects {
+            let namespace = object["metadata"]["namespace"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string();
+            by_namespace.entry(namespace).or_default().push(object);
+        }
+
+        for ns_name in self.namespace_names() {
+            let Some(ns_inode) = self.namespace_inode(&ns_name) else {
+                continue;
+            };
+            let Some(dir_inode) = self
+                .state
+                .child_inode(ns_inode, &api_resource.plural)
+                .or_else(|| self.create_dir_node(ns_inode, &api_resource.plural))
+            else {
+                continue;
+            };
+
+            let objects = by_namespace.remove(&ns_name).unwrap_or_default();
+            self.sync_unstructured_files(dir_inode, api_resource, &objects);
+        }
+    }
+
+    /// Materializes `<name>.<version>.yaml`, the manifest of the object in
+    /// `<name>.yaml` as served in another version of its API group, e.g.
+    /// `v1beta1`. The API server converts the object, so this works for any
+    /// version it still serves. The file is fetched again on every lookup.
+    pub(super) fn create_version_node(&mut self, parent_inode: u64, name: &str) {
+        let Some((object_name, version)) = self
+            .profile
+            .manifest_object_name(name)
+            .and_then(|stem| stem.rsplit_once('.'))
+            .filter(|(_, version)| is_api_version(version))
+        else {
+            return;
+        };
+        let Some(manifest_inode) = self
+            .state
+            .child_inode(parent_inode, &self.manifest_file_name(object_name))
+        else {
+            return;
+        };
+        let Some(location) = self.manifest_location(manifest_inode) else {
+            return;
+        };
+        let Some(NodeContent::Bytes(manifest)) = self
+            .state
+            .inodes
+            .get(&manifest_inode)
+            .map(|node| &node.content)
+        else {
+            return;
+        };
+        let group = serde_yaml::from_slice::<serde_yaml::Value>(manifest)
+            .ok()
+            .and_then(|value| value["apiVersion"].as_str().map(str::to_string))
+            .and_then(|api_version| {
+                api_version
+                    .split_once('/')
+                    .map(|(group, _)| group.to_string())
+            });
+
+        let api_resource = ApiResource {
+            api_version: match group {
+                Some(group) => format!("{group}/{version}"),
+                None => version.to_string(),
+            },
+            plural: location.resource,
+        };
+        let object = match self.kube_client.get_unstructured(
+            &api_resource,
+            location.namespace.as_deref(),
+            object_name,
+        ) {
+            Ok(object) => object,
+            Err(e) => {
+                log::debug!(
+                    "{object_name} is not available as {}: {e}",
+                    api_resource.api_version
+                );
+                return;
+            }
+        };
+
+        let manifest = self.render_unstructured(&object);
+        let uid = object["metadata"]["uid"].as_str();
+        let Some(inode) =
+            self.create_manifest_node(parent_inode, name, manifest, SystemTime::now(), uid)
+        else {
+            return;
+        };
+        if let Some(node) = self.state.inodes.get_mut(&inode) {
+            node.xattrs.insert(
+                UNTYPED_XATTR.to_string(),
+                api_resource.api_version.into_bytes(),
+            );
+        }
+        self.hide_node(parent_inode, name, inode);
+    }
+
+    fn sync_unstructured_files(
+        &mut self,
+        dir_inode: u64,
+        api_resource: &ApiResource,
+        objects: &[serde_json::Value],
+    ) {
+        self.clear_list_error(dir_inode);
+        let mut keep = HashSet::new();
+        self.sync_unstructured_page(dir_inode, api_resource, objects, &mut keep);
+        self.remove_stale_manifests(dir_inode, &keep);
+    }
+
+    // renders the manifests of some of the objects of a directory, adding the
+    // entries to keep for them
+    fn sync_unstructured_page(
+        &mut self,
+        dir_inode: u64,
+        api_resource: &ApiResource,
+        objects: &[serde_json::Value],
+        keep: &mut HashSet<String>,
+    ) {
+        for object in objects {
+            let Some(name) = object_name(object) else {
+                continue;
+            };
+            let file_name = self.manifest_file_name(name);
+            let manifest = match self.try_render_unstructured(object) {
+                Ok(manifest) => {
+                    self.clear_invalid(dir_inode, &file_name);
+                    manifest
+                }
+                Err(e) => {
+                    let payload = self.render_failure(
+                        object["kind"].as_str().unwrap_or(&api_resource.plural),
+                        object["metadata"]["namespace"].as_str(),
+                        name,
+                        &e,
+                    );
+                    if self.report_invalid(dir_inode, &file_name, &e) {
+                        keep.insert(file_name); // its .error file
+                        continue;
+                    }
+                    payload
+                }
+            };
+
+            let inode = match self.state.child_inode(dir_inode, &file_name) {
+                Some(inode) => {
+                    if !self.update_evicted(inode, &manifest) {
+                        self.update_node_content(inode, manifest);
+                    }
+                    inode
+                }
+                None => {
+                    let creation_time = object["metadata"]["creationTimestamp"]
+                        .as_str()
+                        .and_then(|t| chrono::DateTime::parse_from_rfc3339(t).ok())
+                        .and_then(|t| t.timestamp().try_into().ok())
+                        .map(|secs| UNIX_EPOCH + Duration::from_secs(secs))
+                        .unwrap_or(UNIX_EPOCH);
+                    let uid = object["metadata"]["uid"].as_str();
+                    let Some(inode) = self.create_manifest_node(
+                        dir_inode,
+                        &file_name,
+                        manifest,
+                        creation_time,
+                        uid,
+                    ) else {
+                        continue;
+                    };
+                    inode
+                }
+            };
+            if let Some(node) = self.state.inodes.get_mut(&inode) {
+                node.xattrs.insert(
+                    UNTYPED_XATTR.to_string(),
+                    api_resource.api_version.clone().into_bytes(),
+                );
+            }
+            self.set_status_xattr(inode, &api_resource.plural, object);
+            keep.insert(file_name);
+            if pods::is_pods(api_resource) {
+                self.sync_pod_dir(dir_inode, object);
+            }
+            self.sync_spec_status_files(dir_inode, name, object);
+            keep.insert(name.to_string()); // its directory
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn unknown_fields_survive_rendering() {
+        let object = serde_json::json!({
+            "apiVersion": "cert-manager.io/v1",
+            "kind": "Certificate",
+            "metadata": {"name": "web", "namespace": "default"},
+            "spec": {
+                "dnsNames": ["example.com"],
+                "x-vendor-extension": {"nested": [1, 2.5, null, true]},
+                "revisionHistoryLimit": 18446744073709551615u64,
+                "renewBefore": "",
+            },
+        });
+        for format in [ManifestFormat::Yaml, ManifestFormat::Json] {
+            let manifest = render_object(&object, false, false, format).unwrap();
+            let parsed: serde_json::Value = serde_yaml::from_str(&manifest).unwrap();
+            assert_eq!(parsed, object);
+        }
+    }
+
+    #[test]
+    fn api_versions_are_recognized() {
+        for version in ["v1", "v2", "v1beta1", "v2alpha12"] {
+            assert!(is_api_version(version), "{version}");
+        }
+        for version in ["1", "v", "vbeta1", "v1beta", "v1gamma1", "yaml"] {
+            assert!(!is_api_version(version), "{version}");
+        }
+    }
+}
@@ -0,0 +1,126 @@
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    hash::{DefaultHasher, Hash, Hasher},
+};
+
+use serde_json::Value;
+
+use crate::references::{self, Reference};
+
+use super::{KubeFilesystem, NodeContent};
+
+pub const USED_BY_DIR_NAME: &str = "used-by";
+
+/// The ConfigMaps and Secrets referenced by the manifests of objects that may
+/// reference them, by inode, along with a hash of the manifest they were found in, so
+/// that only changed manifests are parsed again.
+pub type ManifestReferences = HashMap<u64, (u64, BTreeSet<Reference>)>;
+
+impl KubeFilesystem {
+    /// Brings the `used-by/` directory in the key directory of every
+    /// ConfigMap and Secret in line with the objects referencing it, e.g.
+    /// pods, workloads and Ingresses. It holds a link per referencing object, e.g.
+    /// `deployments_web -> ../../../deployments/web.yaml`, so an empty one
+    /// means nothing presented uses the object.
+    pub(super) fn sync_used_by(&mut self) {
+        // (namespace, resource, object) -> link name -> target
+        let mut used_by: HashMap<(String, &'static str, String), BTreeMap<String, String>> =
+            HashMap::new();
+        let mut cache = ManifestReferences::new();
+        for (location, inode) in self.presented_manifests() {
+            let Some(namespace) = &location.namespace else {
+                continue;
+            };
+            if !references::may_reference(&location.resource) {
+                continue;
+            }
+            let Some(manifest) = self.file_content(inode) else {
+                continue;
+            };
+            let Some(node) = self.state.inodes.get(&inode) else {
+                continue;
+            };
+            let mut hasher = DefaultHasher::new();
+            manifest.hash(&mut hasher);
+            let hash = hasher.finish();
+            let referenced = match self.manifest_references.remove(&inode) {
+                Some((parsed, referenced)) if parsed == hash => referenced,
+                // truncated manifests don't parse and reference nothing
+                _ => serde_yaml::from_slice::<Value>(&manifest)
+                    .map(|object| references::referenced(&location.resource, &object))
+                    .unwrap_or_default(),
+            };
+
+            let link = format!("{}_{}", location.resource, location.name);
+            let target = format!("../../../{}/{}", location.resource, node.name);
+            for (resource, name) in &referenced {
+                used_by
+                    .entry((namespace.clone(), resource, name.clone()))
+                    .or_default()
+                    .insert(link.clone(), target.clone());
+            }
+            cache.insert(inode, (hash, referenced));
+        }
+        self.manifest_references = cache;
+
+        for namespace in self.namespace_names() {
+            for resource in ["configmaps", "secrets"] {
+                let Some(resource_inode) = self
+                    .namespace_inode(&namespace)
+                    .and_then(|ns_inode| self.state.child_inode(ns_inode, resource))
+                else {
+                    continue;
+                };
+                let key_dirs: Vec<(String, u64)> =
+                    match self.state.inodes.get(&resource_inode).map(|n| &n.content) {
+                        Some(NodeContent::Children(children)) => children
+                            .iter()
+                            .filter(|(_, inode)| self.is_dir(**inode))
+                            .map(|(name, inode)| (name.clone(), *inode))
+                            .collect(),
+                        _ => Vec::new(),
+                    };
+                for (name, key_dir) in key_dirs {
+                    let links = used_by
+                        .remove(&(namespace.clone(), resource, name))
+                        .unwrap_or_default();
+                    self.sync_used_by_dir(key_dir, links);
+                }
+            }
+        }
+    }
+
+    fn sync_used_by_dir(&mut self, key_dir: u64, links: BTreeMap<String, String>) {
+        let dir = match self.state.child_inode(key_dir, USED_BY_DIR_NAME) {
+            Some(dir) if self.is_dir(dir) => dir,
+            // a data key of that name
+            Some(_) => return,
+            None => match self.create_dir_node(key_dir, USED_BY_DIR_NAME) {
+                Some(dir) => dir,
+                None => return,
+            },
+        };
+        let keep: HashSet<String> = links.keys().cloned().collect();
+        self.remove_stale_entries(dir, &keep);
+        for (link, target) in links {
+            if let Some(inode) = self.state.child_inode(dir, &link) {
+                // the manifest of the object may have been renamed
+                if let Some(NodeContent::Bytes(current)) =
+                    self.state.inodes.get(&inode).map(|n| &n.content)
+                    && **current == target.as_bytes()
+                {
+                    continue;
+                }
+                self.state.remove_node(inode);
+            }
+            self.create_symlink_node(dir, &link, &target);
+        }
+    }
+
+    pub(super) fn is_dir(&self, inode: u64) -> bool {
+        self.state
+            .inodes
+            .get(&inode)
+            .is_some_and(|node| matches!(node.content, NodeContent::Children(_)))
+    }
+}
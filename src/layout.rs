@@ -0,0 +1,124 @@
+use serde::Deserialize;
+
+/// Where the manifest of an object is in the namespace tree.
+#[derive(Debug, Clone, Copy)]
+pub struct ManifestPath<'a> {
+    /// `None` for cluster-scoped objects, namespaces included.
+    pub namespace: Option<&'a str>,
+    /// Lowercase plural name of the resource, e.g. `deployments`.
+    pub resource: &'a str,
+    pub file_name: &'a str,
+}
+
+/// Arranges the manifests of the mount in a tree of its own, presented next
+/// to the namespace tree as `_<name>/`. A layout only decides where manifests
+/// go, the files are the same as in the namespace tree.
+pub trait LayoutStrategy {
+    fn name(&self) -> &'static str;
+
+    /// Path of a manifest relative to the directory of the layout.
+    fn path(&self, manifest: &ManifestPath) -> Vec<String>;
+}
+
+/// The built-in layouts, as selected with `layouts` in the config file.
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum Layout {
+    /// `<namespace>/<resource>/<file>`, the layout of the root of the mount.
+    NamespaceFirst,
+    /// `<resource>/<namespace>/<file>`.
+    KindFirst,
+    /// `<namespace>_<resource>_<file>`, all in one directory.
+    Flat,
+    /// `namespaces/<namespace>/<resource>-<file>`, cluster-scoped objects
+    /// under `cluster/`, as often found in GitOps repositories.
+    Gitops,
+}
+
+// namespace names can't contain `_`, so neither can they clash with this
+const CLUSTER_SCOPE: &str = "_cluster";
+
+impl LayoutStrategy for Layout {
+    fn name(&self) -> &'static str {
+        match self {
+            Layout::NamespaceFirst => "namespace-first",
+            Layout::KindFirst => "kind-first",
+            Layout::Flat => "flat",
+            Layout::Gitops => "gitops",
+        }
+    }
+
+    fn path(&self, manifest: &ManifestPath) -> Vec<String> {
+        let ManifestPath {
+            namespace,
+            resource,
+            file_name,
+        } = *manifest;
+        let path: Vec<&str> = match (self, namespace) {
+            (Layout::NamespaceFirst, namespace) => {
+                vec![namespace.unwrap_or(CLUSTER_SCOPE), resource, file_name]
+            }
+            (Layout::KindFirst, Some(namespace)) => vec![resource, namespace, file_name],
+            (Layout::KindFirst, None) => vec![resource, file_name],
+            (Layout::Flat, namespace) => {
+                let namespace = namespace.unwrap_or(CLUSTER_SCOPE);
+                return vec![format!("{namespace}_{resource}_{file_name}")];
+            }
+            (Layout::Gitops, namespace) => {
+                let file_name = format!("{resource}-{file_name}");
+                return match namespace {
+                    Some(namespace) => {
+                        vec!["namespaces".to_string(), namespace.to_string(), file_name]
+                    }
+                    None => vec!["cluster".to_string(), file_name],
+                };
+            }
+        };
+        path.into_iter().map(str::to_string).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn layouts_place_manifests() {
+        let deployment = ManifestPath {
+            namespace: Some("default"),
+            resource: "deployments",
+            file_name: "web.yaml",
+        };
+        let node = ManifestPath {
+            namespace: None,
+            resource: "nodes",
+            file_name: "worker-1.yaml",
+        };
+        let cases = [
+            (
+                Layout::NamespaceFirst,
+                "default/deployments/web.yaml",
+                "_cluster/nodes/worker-1.yaml",
+            ),
+            (
+                Layout::KindFirst,
+                "deployments/default/web.yaml",
+                "nodes/worker-1.yaml",
+            ),
+            (
+                Layout::Flat,
+                "default_deployments_web.yaml",
+                "_cluster_nodes_worker-1.yaml",
+            ),
+            (
+                Layout::Gitops,
+                "namespaces/default/deployments-web.yaml",
+                "cluster/nodes-worker-1.yaml",
+            ),
+        ];
+        for (layout, namespaced, cluster_scoped) in cases {
+            assert_eq!(layout.path(&deployment).join("/"), namespaced);
+            assert_eq!(layout.path(&node).join("/"), cluster_scoped);
+        }
+    }
+}
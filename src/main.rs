@@ -1,34 +1,633 @@
+mod auth;
+mod capabilities;
+mod client;
+mod config;
+mod contexts;
+mod diff;
+mod endpoints;
+mod events;
+mod gateway;
+mod guardrail;
+mod healthz;
+mod hooks;
+mod http;
+mod kubeconfig;
 mod kubefuse;
+mod layout;
+mod mesh;
+mod quota;
+mod references;
+mod s3;
+mod sanitize;
+mod security;
+mod status;
+mod subtree;
+mod tunnel;
 
-use client_rs::rest;
+use std::{error::Error, net::SocketAddr, path::PathBuf, time::Duration};
 
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
-use crate::kubefuse::KubeFilesystem;
+use crate::{
+    auth::AuthConfig,
+    client::{Impersonation, KubeClient, TlsConfig},
+    config::{Config, ManifestFormat, Profile, RefreshIntervals},
+    contexts::{CLUSTERS_DIR_NAME, ContextsFilesystem},
+    kubefuse::{KubeFilesystem, TYPED_RESOURCES},
+    quota::MutationQuotas,
+    subtree::{SubtreeFilesystem, single_namespace_path},
+    tunnel::SshTunnel,
+};
 
 #[derive(Parser, Debug)]
+#[command(subcommand_negates_reqs = true)]
 struct Options {
+    /// URL of the API server; read from the kubeconfig file when not given
     #[arg(short, long)]
-    cluster_url: String,
+    cluster_url: Option<String>,
 
+    /// kubeconfig context to connect with instead of the current one
+    #[arg(long, conflicts_with = "cluster_url")]
+    context: Option<String>,
+
+    /// kubeconfig cluster to connect to instead of the context's
+    #[arg(long, conflicts_with = "cluster_url")]
+    cluster: Option<String>,
+
+    /// kubeconfig user to authenticate as instead of the context's
+    #[arg(long, conflicts_with = "cluster_url")]
+    user: Option<String>,
+
+    /// Bearer token to authenticate with, taking precedence over the `auth`
+    /// section of the config file
     #[arg(short, long, env = "KUBE_TOKEN")]
-    token: String,
+    token: Option<String>,
 
-    #[arg(short, long)]
-    mountpoint: String,
+    /// File to read the bearer token from, read again whenever it changes or
+    /// the token is rejected, e.g. a projected service account token
+    #[arg(long, conflicts_with = "token")]
+    token_file: Option<PathBuf>,
+
+    /// User to impersonate on every request, to see what they see
+    #[arg(long = "as")]
+    as_user: Option<String>,
+
+    /// Group to impersonate on every request along with --as; can be repeated
+    #[arg(long, requires = "as_user")]
+    as_group: Vec<String>,
+
+    #[arg(short, long, required_unless_present = "http_listen")]
+    mountpoint: Option<String>,
+
+    /// Serve the tree read-only over HTTP on this address, e.g.
+    /// 127.0.0.1:8080, instead of mounting it
+    #[arg(long, conflicts_with = "mountpoint")]
+    http_listen: Option<SocketAddr>,
+
+    /// Serve health checks for supervisors on this address, e.g.
+    /// 127.0.0.1:8081: /healthz for liveness and /readyz for readiness
+    #[arg(long)]
+    healthz_listen: Option<SocketAddr>,
+
+    /// Name to verify the API server's certificate against instead of the
+    /// host of the cluster URL, e.g. when connecting through a tunnel
+    #[arg(long)]
+    tls_server_name: Option<String>,
+
+    /// PEM client certificate to authenticate with, requires --client-key
+    #[arg(long, requires = "client_key")]
+    client_cert: Option<PathBuf>,
+
+    /// PEM private key of the client certificate
+    #[arg(long, requires = "client_cert")]
+    client_key: Option<PathBuf>,
+
+    /// PEM certificate authority to verify the API server against, in
+    /// addition to the system ones
+    #[arg(long)]
+    certificate_authority: Option<PathBuf>,
+
+    /// Don't verify the API server's certificate, which is insecure
+    #[arg(long)]
+    insecure_skip_tls_verify: bool,
+
+    /// Reach the API server through an SSH port-forward via this bastion,
+    /// e.g. user@bastion
+    #[arg(long)]
+    ssh_tunnel: Option<String>,
+
+    /// Path to the kube-fuse configuration file
+    #[arg(long, env = "KUBEFUSE_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// How often to re-list resources from the cluster, in seconds; 0 disables refreshing
+    #[arg(long, default_value_t = 30)]
+    refresh_interval: u64,
+
+    /// Start without any background refreshing until `false` is written to
+    /// .kubefuse/paused
+    #[arg(long)]
+    paused: bool,
+
+    /// Pin the mount to the cluster state at this resourceVersion
+    #[arg(long)]
+    resource_version: Option<String>,
+
+    /// Append the FUSE operation and a hash of the path that triggered each
+    /// API request to the User-Agent
+    #[arg(long)]
+    user_agent_operations: bool,
+
+    /// Name of the profile to mount the cluster with, e.g. developer, operator or auditor
+    #[arg(long)]
+    profile: Option<String>,
+
+    /// Only present this namespace, a trailing * matches any suffix; may be
+    /// given several times
+    #[arg(short, long = "namespace")]
+    namespaces: Vec<String>,
+
+    /// Only list the namespaces once the root of the mount is listed,
+    /// resolving those accessed by name with a request of their own
+    #[arg(long, conflicts_with = "resource_version")]
+    lazy_namespaces: bool,
+
+    /// List these resource types by the metadata of their objects only,
+    /// fetching a manifest when its file is opened; comma-delimited, e.g.
+    /// configmaps,secrets
+    #[arg(long, value_delimiter = ',')]
+    metadata_only: Vec<String>,
+
+    /// Present objects that fail to render, and resource types that fail to
+    /// list, as .error files holding the error, failing /readyz meanwhile
+    #[arg(long)]
+    strict: bool,
+
+    /// Mount the directory of the only namespace given with --namespace at
+    /// the root of the mount, without the namespace level
+    #[arg(long, requires = "namespaces", conflicts_with = "http_listen")]
+    single_namespace: bool,
+
+    /// Mount only this directory of the tree, e.g. default/configmaps, at
+    /// the root of the mount
+    #[arg(long, conflicts_with_all = ["http_listen", "single_namespace"])]
+    path: Option<String>,
+
+    /// Hide this namespace, a trailing * matches any suffix; may be given
+    /// several times
+    #[arg(long = "exclude-namespace")]
+    exclude_namespaces: Vec<String>,
+
+    /// Only present these resource directories, e.g. pods,configmaps,deployments
+    #[arg(long, value_delimiter = ',')]
+    resources: Vec<String>,
+
+    /// Never present these resource directories, e.g. secrets
+    #[arg(long, value_delimiter = ',')]
+    exclude_resources: Vec<String>,
+
+    /// Hide namespaces of the cluster's own components, e.g. kube-system
+    #[arg(long)]
+    hide_system_namespaces: bool,
+
+    /// Present every namespaced resource type the API server serves,
+    /// including custom resources and pods, which the pod logs/,
+    /// containers/ and security.txt need
+    #[arg(long)]
+    discover_resources: bool,
+
+    /// Delete objects from the cluster when their manifests are removed
+    #[arg(long)]
+    enable_delete: bool,
+
+    /// Format of the rendered manifests: yaml, json, or both, i.e. YAML with a
+    /// JSON copy next to each manifest
+    #[arg(long, value_enum)]
+    format: Option<ManifestFormat>,
+
+    /// Only present objects matching this label selector, e.g. app=web or
+    /// 'tier in (frontend,backend)'
+    #[arg(short = 'l', long)]
+    selector: Option<String>,
+
+    /// Only present objects matching this field selector, e.g.
+    /// metadata.name=web; prefix it with a resource type to only filter that
+    /// type, e.g. pods:status.phase=Running
+    #[arg(long)]
+    field_selector: Vec<String>,
+
+    /// How many times to send a request failing for reasons likely to pass,
+    /// e.g. 429 or 503 answers; 1 disables retries
+    #[arg(long)]
+    retries: Option<u32>,
+
+    /// The longest to wait before retrying a request, in seconds
+    #[arg(long)]
+    retry_max_delay: Option<u64>,
+
+    /// Drop lines of changes.log and followed logs older than this many
+    /// seconds; 0 keeps them
+    #[arg(long)]
+    history_max_age: Option<u64>,
+
+    /// Keep at most this many bytes of changes.log and every followed log,
+    /// dropping the oldest lines; 0 disables the limit
+    #[arg(long)]
+    history_max_bytes: Option<u64>,
+
+    /// Keep at most this many bytes of manifests in memory, fetching those
+    /// read least recently again when opened; 0 disables the limit
+    #[arg(long)]
+    memory_budget: Option<u64>,
+
+    /// Truncate rendered files larger than this many bytes; 0 disables the limit
+    #[arg(long, default_value_t = 4 * 1024 * 1024)]
+    max_file_size: u64,
+
+    /// Mount every context of the kubeconfig under clusters/<context>/,
+    /// connecting to each cluster on first access
+    #[arg(long, conflicts_with_all = [
+        "cluster_url", "context", "cluster", "user", "token", "token_file", "tls_server_name",
+        "client_cert", "ssh_tunnel", "resource_version", "http_listen", "healthz_listen",
+        "single_namespace", "path",
+    ])]
+    all_contexts: bool,
+
+    /// Mount the given contexts of the kubeconfig under clusters/<context>/,
+    /// like --all-contexts; comma-delimited
+    #[arg(long, value_delimiter = ',', conflicts_with_all = [
+        "cluster_url", "context", "cluster", "user", "token", "token_file", "tls_server_name",
+        "client_cert", "ssh_tunnel", "resource_version", "http_listen", "healthz_listen",
+        "single_namespace", "path", "all_contexts",
+    ])]
+    contexts: Vec<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Mount the cluster of the current kubeconfig context and print the
+    /// mountpoint, as run by kubectl for `kubectl fuse mount <mountpoint>`
+    Mount {
+        /// kubeconfig context to mount instead of the current one
+        #[arg(long)]
+        context: Option<String>,
+
+        mountpoint: String,
+    },
+    /// Upload the sanitized manifests of the cluster to S3, or a store
+    /// compatible with it, skipping those unchanged since the last sync
+    Sync {
+        /// Where to upload them, e.g. s3://bucket/prefix
+        target: String,
+    },
 }
 
 fn main() {
     env_logger::init();
     log::info!("starting");
 
-    let opts = Options::parse();
-    let rest_client = rest::rest_client_for(&rest::Config {
-        base_url: opts.cluster_url.to_string(),
-        user_agent: None,
-        bearer_token: opts.token.to_string().into(),
+    let mut opts = Options::parse();
+    let plugin = matches!(opts.command, Some(Command::Mount { .. }));
+    let mut sync_target = None;
+    match opts.command.take() {
+        Some(Command::Mount {
+            context,
+            mountpoint,
+        }) => {
+            if opts.cluster_url.is_some() && context.is_some() {
+                log::error!("--context can't be combined with --cluster-url");
+                std::process::exit(1);
+            }
+            opts.context = context.or(opts.context);
+            opts.mountpoint = Some(mountpoint);
+        }
+        Some(Command::Sync { target }) => sync_target = Some(target),
+        None => {}
+    }
+
+    let config = match &opts.config {
+        Some(path) => Config::load(path).unwrap_or_else(|e| {
+            log::error!("failed to load config file {}: {e}", path.display());
+            std::process::exit(1);
+        }),
+        None => Config::default(),
+    };
+    let mut profile = config
+        .mount_profile(opts.profile.as_deref())
+        .unwrap_or_else(|| {
+            log::error!(
+                "profile {} not found",
+                opts.profile.as_deref().unwrap_or_default()
+            );
+            std::process::exit(1);
+        });
+    profile.hide_system_namespaces |= opts.hide_system_namespaces;
+    profile.discover_resources |= opts.discover_resources;
+    profile.enable_delete |= opts.enable_delete;
+    profile.lazy_namespaces |= opts.lazy_namespaces;
+    profile.strict |= opts.strict;
+    profile
+        .metadata_only
+        .extend(opts.metadata_only.iter().cloned());
+    if let Some(format) = opts.format {
+        profile.manifest_format = format;
+    }
+    if let Some(selector) = opts.selector.take() {
+        profile.label_selector = Some(selector);
+    }
+    for selector in &opts.field_selector {
+        let (resource, selector) = match selector.split_once(':') {
+            Some((resource, selector)) if !resource.contains(['=', '!']) => (resource, selector),
+            _ => ("*", selector.as_str()),
+        };
+        profile
+            .field_selectors
+            .insert(resource.to_string(), selector.to_string());
+    }
+    profile
+        .hidden_namespaces
+        .extend(opts.exclude_namespaces.iter().cloned());
+    if !opts.namespaces.is_empty() {
+        profile.namespaces = opts.namespaces.clone();
+    }
+    let subtree = match single_namespace_path(&opts.namespaces) {
+        _ if !opts.single_namespace => opts.path.clone(),
+        Some(namespace) => Some(namespace.to_string()),
+        None => {
+            log::error!("--single-namespace requires exactly one --namespace without a *");
+            std::process::exit(1);
+        }
+    };
+    // namespaces other than that of the path can't be reached, don't list them
+    let path_namespace = opts
+        .path
+        .as_deref()
+        .and_then(|path| path.split('/').find(|name| !name.is_empty()))
+        .filter(|name| !name.starts_with(['_', '.']));
+    if let Some(namespace) = path_namespace
+        && profile.namespaces.is_empty()
+    {
+        profile.namespaces = vec![namespace.to_string()];
+    }
+    if !opts.resources.is_empty() {
+        profile.resources = Some(opts.resources.clone());
+    }
+    profile
+        .excluded_resources
+        .extend(opts.exclude_resources.iter().cloned());
+
+    let mut mount_options = vec![];
+    if profile.read_only {
+        mount_options.push(fuser::MountOption::RO);
+    }
+
+    // compared against snapshot targets, resolved before it's shadowed by the mount
+    let mountpoint = opts
+        .mountpoint
+        .as_ref()
+        .map(|mountpoint| {
+            std::fs::canonicalize(mountpoint).unwrap_or_else(|_| PathBuf::from(mountpoint))
+        })
+        .unwrap_or_default();
+    if plugin {
+        println!("{}", mountpoint.display());
+    }
+
+    let mut retries = config.retries;
+    retries.attempts = opts.retries.unwrap_or(retries.attempts).max(1);
+    retries.max_delay = opts.retry_max_delay.unwrap_or(retries.max_delay);
+    let mut history_retention = config.history_retention;
+    history_retention.max_age = opts.history_max_age.unwrap_or(history_retention.max_age);
+    history_retention.max_bytes = opts
+        .history_max_bytes
+        .unwrap_or(history_retention.max_bytes);
+    let memory_budget = opts.memory_budget.unwrap_or(config.memory_budget);
+    let impersonation = Impersonation {
+        user: opts.as_user.clone(),
+        groups: opts.as_group.clone(),
+    };
+
+    if opts.all_contexts || !opts.contexts.is_empty() {
+        if sync_target.is_some() {
+            log::error!("sync takes a single cluster, not --contexts or --all-contexts");
+            std::process::exit(1);
+        }
+        let mut contexts = kubeconfig::context_names().unwrap_or_else(|e| {
+            log::error!("failed to load the kubeconfig: {e}");
+            std::process::exit(1);
+        });
+        if !opts.contexts.is_empty() {
+            if let Some(unknown) = opts.contexts.iter().find(|name| !contexts.contains(name)) {
+                log::error!(
+                    "context {unknown} is not in the kubeconfig, it has {}",
+                    contexts.join(", ")
+                );
+                std::process::exit(1);
+            }
+            contexts.retain(|name| opts.contexts.contains(name));
+        }
+        let Some(mount_path) = opts.mountpoint.clone() else {
+            unreachable!("clap requires a mountpoint")
+        };
+        let connect = Box::new(move |context: &str| {
+            let (server, auth, mut tls) = from_kubeconfig(&config, Some(context), None, None)?;
+            apply_tls_flags(&opts, &mut tls);
+            let kube_client = KubeClient::new(
+                &server,
+                &auth,
+                &tls,
+                opts.user_agent_operations,
+                profile.label_selector.clone(),
+                profile.field_selectors.clone(),
+                &impersonation,
+            )?
+            .with_retries(retries);
+            let mut profile = profile.clone();
+            discover_resources(&kube_client, &mut profile);
+            Ok(KubeFilesystem::new(
+                kube_client,
+                profile,
+                None,
+                RefreshIntervals {
+                    paused: opts.paused,
+                    ..RefreshIntervals::new(Duration::from_secs(opts.refresh_interval), &config)
+                },
+                MutationQuotas::new(config.mutation_limits.clone()),
+                opts.max_file_size,
+                mountpoint.join(CLUSTERS_DIR_NAME).join(context),
+            )
+            .with_history_retention(history_retention)
+            .with_memory_budget(memory_budget))
+        });
+        let fs = ContextsFilesystem::new(contexts, connect);
+        fuser::mount2(fs, mount_path, &mount_options).unwrap();
+        return;
+    }
+
+    let (mut cluster_url, auth, mut tls) = match &opts.cluster_url {
+        Some(cluster_url) => (
+            cluster_url.clone(),
+            config.auth.clone(),
+            TlsConfig::default(),
+        ),
+        None => from_kubeconfig(
+            &config,
+            opts.context.as_deref(),
+            opts.cluster.as_deref(),
+            opts.user.as_deref(),
+        )
+        .unwrap_or_else(|e| {
+            log::error!("failed to load the kubeconfig, pass --cluster-url instead: {e}");
+            std::process::exit(1);
+        }),
+    };
+    let auth = match (&opts.token, &opts.token_file) {
+        (Some(token), _) => vec![AuthConfig::Token {
+            token: token.clone(),
+        }],
+        (None, Some(token_file)) => vec![AuthConfig::TokenFile {
+            token_file: token_file.clone(),
+        }],
+        (None, None) => auth,
+    };
+    apply_tls_flags(&opts, &mut tls);
+    // stopped once the mount ends
+    let _tunnel = opts.ssh_tunnel.as_deref().map(|destination| {
+        SshTunnel::for_cluster(destination, &mut cluster_url, &mut tls).unwrap_or_else(|e| {
+            log::error!("failed to tunnel to the API server through {destination}: {e}");
+            std::process::exit(1);
+        })
     });
+    let kube_client = KubeClient::new(
+        &cluster_url,
+        &auth,
+        &tls,
+        opts.user_agent_operations,
+        profile.label_selector.clone(),
+        profile.field_selectors.clone(),
+        &impersonation,
+    )
+    .unwrap_or_else(|e| {
+        log::error!("failed to create the API client: {e}");
+        std::process::exit(1);
+    })
+    .with_retries(retries);
+
+    discover_resources(&kube_client, &mut profile);
+
+    let refresh_intervals = RefreshIntervals {
+        paused: opts.paused,
+        ..RefreshIntervals::new(Duration::from_secs(opts.refresh_interval), &config)
+    };
+
+    let fs = KubeFilesystem::new(
+        kube_client,
+        profile,
+        opts.resource_version,
+        refresh_intervals,
+        MutationQuotas::new(config.mutation_limits),
+        opts.max_file_size,
+        mountpoint,
+    )
+    .with_history_retention(history_retention)
+    .with_memory_budget(memory_budget);
+    if let Some(target) = sync_target {
+        match s3::sync(fs, &target) {
+            Ok(stats) => println!(
+                "uploaded {}, unchanged {}, deleted {}",
+                stats.uploaded, stats.unchanged, stats.deleted
+            ),
+            Err(e) => {
+                log::error!("failed to sync to {target}: {e}");
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    if let Some(addr) = opts.healthz_listen {
+        let mountpoint = opts.mountpoint.as_ref().map(PathBuf::from);
+        healthz::spawn(addr, fs.health(), mountpoint);
+    }
+    match (opts.http_listen, opts.mountpoint) {
+        (Some(addr), _) => {
+            if let Err(e) = http::serve(fs, addr) {
+                log::error!("failed to serve the tree on {addr}: {e}");
+                std::process::exit(1);
+            }
+        }
+        (None, Some(mountpoint)) => match subtree {
+            Some(path) => {
+                let fs = SubtreeFilesystem::new(fs, &path);
+                fuser::mount2(fs, mountpoint, &mount_options).unwrap()
+            }
+            None => fuser::mount2(fs, mountpoint, &mount_options).unwrap(),
+        },
+        (None, None) => unreachable!("clap requires one of them"),
+    }
+}
+
+/// Where and how to connect to the cluster of a kubeconfig context. The
+/// credentials of the config file apply when the user has none.
+fn from_kubeconfig(
+    config: &Config,
+    context: Option<&str>,
+    cluster: Option<&str>,
+    user: Option<&str>,
+) -> Result<(String, Vec<AuthConfig>, TlsConfig), Box<dyn Error>> {
+    let connection = kubeconfig::load(context, cluster, user)?;
+    let auth = if connection.auth.is_empty() {
+        config.auth.clone()
+    } else {
+        connection.auth
+    };
+    Ok((connection.server, auth, connection.tls))
+}
+
+/// Overrides the TLS settings of a connection with those given on the
+/// command line.
+fn apply_tls_flags(opts: &Options, tls: &mut TlsConfig) {
+    if let Some(server_name) = &opts.tls_server_name {
+        tls.server_name = Some(server_name.clone());
+    }
+    let read_pem = |path: &PathBuf| {
+        std::fs::read(path).unwrap_or_else(|e| {
+            log::error!("failed to read {}: {e}", path.display());
+            std::process::exit(1);
+        })
+    };
+    if let (Some(cert), Some(key)) = (&opts.client_cert, &opts.client_key) {
+        tls.client_identity = Some((read_pem(cert), read_pem(key)));
+    }
+    if let Some(ca) = &opts.certificate_authority {
+        tls.ca_certificates.push(read_pem(ca));
+    }
+    tls.insecure |= opts.insecure_skip_tls_verify;
+}
 
-    let fs = KubeFilesystem::new(&rest_client);
-    fuser::mount2(fs, opts.mountpoint, &[]).unwrap();
+/// Adds the resource types the API server serves to those the profile
+/// presents, if it asks for discovery.
+fn discover_resources(kube_client: &KubeClient, profile: &mut Profile) {
+    if !profile.discover_resources {
+        return;
+    }
+    match kube_client.discover() {
+        Ok(discovered) => {
+            for api_resource in discovered {
+                // typed models and resources listed earlier take precedence,
+                // e.g. events over events.k8s.io/v1 events
+                let plural = api_resource.plural.as_str();
+                if !TYPED_RESOURCES.contains(&plural)
+                    && !profile.custom_resources.iter().any(|r| r.plural == plural)
+                {
+                    profile.custom_resources.push(api_resource);
+                }
+            }
+        }
+        Err(e) => {
+            log::warn!("resource discovery failed, presenting known resources only: {e}")
+        }
+    }
 }
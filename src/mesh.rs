@@ -0,0 +1,268 @@
+use std::fmt::Write as _;
+
+use serde_json::Value;
+
+/// API group of the Istio networking resources.
+pub const ISTIO_GROUP: &str = "networking.istio.io";
+
+/// The fully qualified name of a host of a VirtualService or DestinationRule
+/// in the given namespace: Istio interprets short names like `reviews`
+/// relative to the namespace of the rule.
+pub fn qualify_host(host: &str, namespace: &str) -> String {
+    match host.contains('.') || host.contains('*') {
+        true => host.to_string(),
+        false => format!("{host}.{namespace}.svc.cluster.local"),
+    }
+}
+
+/// The hosts a VirtualService routes traffic for, qualified.
+pub fn virtual_service_hosts(virtual_service: &Value, namespace: &str) -> Vec<String> {
+    virtual_service["spec"]["hosts"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .map(|host| qualify_host(host, namespace))
+        .collect()
+}
+
+/// The host a DestinationRule applies to, qualified.
+pub fn destination_rule_host(destination_rule: &Value, namespace: &str) -> Option<String> {
+    destination_rule["spec"]["host"]
+        .as_str()
+        .map(|host| qualify_host(host, namespace))
+}
+
+// a StringMatch, e.g. `prefix /v2`
+fn describe_string_match(matched: &Value) -> String {
+    ["exact", "prefix", "regex"]
+        .iter()
+        .find_map(|kind| {
+            matched[kind]
+                .as_str()
+                .map(|value| format!("{kind} {value}"))
+        })
+        .unwrap_or_default()
+}
+
+// an HTTPMatchRequest, e.g. `uri prefix /v2, header end-user exact jason`
+fn describe_match(matched: &Value) -> String {
+    let mut parts = Vec::new();
+    for field in ["uri", "method", "authority", "scheme"] {
+        if matched[field].is_object() {
+            parts.push(format!(
+                "{field} {}",
+                describe_string_match(&matched[field])
+            ));
+        }
+    }
+    for (field, label) in [("headers", "header"), ("queryParams", "query")] {
+        for (name, value) in matched[field].as_object().into_iter().flatten() {
+            parts.push(format!("{label} {name} {}", describe_string_match(value)));
+        }
+    }
+    if let Some(port) = matched["port"].as_u64() {
+        parts.push(format!("port {port}"));
+    }
+    for gateway in matched["gateways"].as_array().into_iter().flatten() {
+        parts.push(format!("gateway {}", gateway.as_str().unwrap_or_default()));
+    }
+    parts.join(", ")
+}
+
+// a destination of a route, e.g. `reviews subset v2 weight 90`
+fn describe_destination(route: &Value, namespace: &str) -> String {
+    let destination = &route["destination"];
+    let mut out = qualify_host(destination["host"].as_str().unwrap_or_default(), namespace);
+    if let Some(subset) = destination["subset"].as_str() {
+        let _ = write!(out, " subset {subset}");
+    }
+    if let Some(port) = destination["port"]["number"].as_u64() {
+        let _ = write!(out, " port {port}");
+    }
+    if let Some(weight) = route["weight"].as_u64() {
+        let _ = write!(out, " weight {weight}");
+    }
+    out
+}
+
+/// Renders the routes of a VirtualService: the matches and destinations of
+/// every HTTP, TLS and TCP route, in the order Istio evaluates them, along
+/// with rewrites, timeouts, retries and fault injection.
+fn render_virtual_service(out: &mut String, name: &str, object: &Value, namespace: &str) {
+    let gateways: Vec<&str> = object["spec"]["gateways"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(Value::as_str)
+        .collect();
+    let _ = match gateways.is_empty() {
+        true => writeln!(out, "virtualservice {name} (gateways: mesh)"),
+        false => writeln!(
+            out,
+            "virtualservice {name} (gateways: {})",
+            gateways.join(", ")
+        ),
+    };
+    for protocol in ["http", "tls", "tcp"] {
+        let routes = object["spec"][protocol].as_array().into_iter().flatten();
+        for (i, route) in routes.enumerate() {
+            let _ = write!(out, "  {protocol} {}", i + 1);
+            if let Some(name) = route["name"].as_str() {
+                let _ = write!(out, " ({name})");
+            }
+            let matches: Vec<String> = route["match"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .map(describe_match)
+                .collect();
+            let _ = match matches.is_empty() {
+                true => writeln!(out, ": any"),
+                false => writeln!(out, ": {}", matches.join(" | ")),
+            };
+            for destination in route["route"].as_array().into_iter().flatten() {
+                let _ = writeln!(
+                    out,
+                    "    -> {}",
+                    describe_destination(destination, namespace)
+                );
+            }
+            if route["redirect"].is_object() {
+                let _ = writeln!(out, "    redirect {}", route["redirect"]);
+            }
+            if let Some(prefix) = route["rewrite"]["uri"].as_str() {
+                let _ = writeln!(out, "    rewrite uri {prefix}");
+            }
+            if let Some(timeout) = route["timeout"].as_str() {
+                let _ = writeln!(out, "    timeout {timeout}");
+            }
+            if let Some(attempts) = route["retries"]["attempts"].as_u64() {
+                let _ = writeln!(out, "    retries {attempts}");
+            }
+            if route["fault"].is_object() {
+                let _ = writeln!(out, "    fault {}", route["fault"]);
+            }
+        }
+    }
+}
+
+// the traffic policy of a DestinationRule or one of its subsets
+fn describe_traffic_policy(policy: &Value) -> Option<String> {
+    let mut parts = Vec::new();
+    if let Some(simple) = policy["loadBalancer"]["simple"].as_str() {
+        parts.push(format!("loadBalancer {simple}"));
+    }
+    if let Some(mode) = policy["tls"]["mode"].as_str() {
+        parts.push(format!("tls {mode}"));
+    }
+    if let Some(max) = policy["connectionPool"]["tcp"]["maxConnections"].as_u64() {
+        parts.push(format!("maxConnections {max}"));
+    }
+    if let Some(errors) = policy["outlierDetection"]["consecutive5xxErrors"].as_u64() {
+        parts.push(format!("outlierDetection after {errors} 5xx"));
+    }
+    (!parts.is_empty()).then(|| parts.join(", "))
+}
+
+fn render_destination_rule(out: &mut String, name: &str, object: &Value) {
+    let _ = writeln!(out, "destinationrule {name}");
+    if let Some(policy) = describe_traffic_policy(&object["spec"]["trafficPolicy"]) {
+        let _ = writeln!(out, "  trafficPolicy: {policy}");
+    }
+    for subset in object["spec"]["subsets"].as_array().into_iter().flatten() {
+        let labels: Vec<String> = subset["labels"]
+            .as_object()
+            .into_iter()
+            .flatten()
+            .map(|(key, value)| format!("{key}={}", value.as_str().unwrap_or_default()))
+            .collect();
+        let _ = write!(
+            out,
+            "  subset {}: {}",
+            subset["name"].as_str().unwrap_or_default(),
+            labels.join(",")
+        );
+        if let Some(policy) = describe_traffic_policy(&subset["trafficPolicy"]) {
+            let _ = write!(out, " ({policy})");
+        }
+        out.push('\n');
+    }
+}
+
+/// Renders `routing.txt` of a host: the routes of the VirtualServices for
+/// it, then the policies and subsets of the DestinationRules applying to
+/// it, each given by name along with its object.
+pub fn render_routing(
+    host: &str,
+    namespace: &str,
+    virtual_services: &[(&str, &Value)],
+    destination_rules: &[(&str, &Value)],
+) -> String {
+    let mut out = format!("host: {host}\n");
+    for (name, object) in virtual_services {
+        render_virtual_service(&mut out, name, object, namespace);
+    }
+    if virtual_services.is_empty() {
+        out.push_str("no virtualservice, traffic goes to the host as is\n");
+    }
+    for (name, object) in destination_rules {
+        render_destination_rule(&mut out, name, object);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn renders_the_routing_of_a_host() {
+        let virtual_service = json!({"spec": {
+            "hosts": ["reviews"],
+            "http": [
+                {
+                    "match": [{"headers": {"end-user": {"exact": "jason"}}}],
+                    "route": [{"destination": {"host": "reviews", "subset": "v2"}}],
+                },
+                {
+                    "route": [
+                        {"destination": {"host": "reviews", "subset": "v1"}, "weight": 90},
+                        {"destination": {"host": "reviews", "subset": "v3"}, "weight": 10},
+                    ],
+                    "timeout": "10s",
+                },
+            ],
+        }});
+        let destination_rule = json!({"spec": {
+            "host": "reviews.shop.svc.cluster.local",
+            "trafficPolicy": {"tls": {"mode": "ISTIO_MUTUAL"}},
+            "subsets": [{"name": "v1", "labels": {"version": "v1"}}],
+        }});
+        assert_eq!(
+            virtual_service_hosts(&virtual_service, "shop"),
+            ["reviews.shop.svc.cluster.local"]
+        );
+        assert_eq!(
+            render_routing(
+                "reviews.shop.svc.cluster.local",
+                "shop",
+                &[("reviews", &virtual_service)],
+                &[("reviews", &destination_rule)],
+            ),
+            "host: reviews.shop.svc.cluster.local\n\
+             virtualservice reviews (gateways: mesh)\n  \
+             http 1: header end-user exact jason\n    \
+             -> reviews.shop.svc.cluster.local subset v2\n  \
+             http 2: any\n    \
+             -> reviews.shop.svc.cluster.local subset v1 weight 90\n    \
+             -> reviews.shop.svc.cluster.local subset v3 weight 10\n    \
+             timeout 10s\n\
+             destinationrule reviews\n  \
+             trafficPolicy: tls ISTIO_MUTUAL\n  \
+             subset v1: version=v1\n"
+        );
+    }
+}
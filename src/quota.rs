@@ -0,0 +1,139 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use serde::Deserialize;
+
+/// Limits how often the objects behind the paths matching `path` can be
+/// mutated through the mount, e.g. at most once per minute per deployment.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MutationLimit {
+    /// Path pattern relative to the mount root, where `*` matches a single
+    /// path component and `**` any number of them, e.g. `/*/deployments/*`.
+    pub path: String,
+    /// Mutations allowed per matching path within the period.
+    pub max: u32,
+    /// Length of the period in seconds.
+    pub per_seconds: u64,
+}
+
+/// Enforces the configured mutation limits. Each path matching a limit has
+/// its own budget.
+#[derive(Default)]
+pub struct MutationQuotas {
+    limits: Vec<MutationLimit>,
+    // (limit index, path) -> times of the mutations within the current period
+    history: HashMap<(usize, String), VecDeque<Instant>>,
+}
+
+impl MutationQuotas {
+    pub fn new(limits: Vec<MutationLimit>) -> Self {
+        MutationQuotas {
+            limits,
+            history: HashMap::new(),
+        }
+    }
+
+    /// Records a mutation of the given path, returning false without
+    /// recording it if any of the matching limits has been exhausted.
+    pub fn allow(&mut self, path: &str) -> bool {
+        let now = Instant::now();
+        let matching: Vec<usize> = self
+            .limits
+            .iter()
+            .enumerate()
+            .filter(|(_, limit)| path_matches(&limit.path, path))
+            .map(|(i, _)| i)
+            .collect();
+
+        for &i in &matching {
+            let period = Duration::from_secs(self.limits[i].per_seconds);
+            let times = self.history.entry((i, path.to_string())).or_default();
+            while times
+                .front()
+                .is_some_and(|t| now.duration_since(*t) >= period)
+            {
+                times.pop_front();
+            }
+            if times.len() >= self.limits[i].max as usize {
+                return false;
+            }
+        }
+
+        for i in matching {
+            self.history
+                .entry((i, path.to_string()))
+                .or_default()
+                .push_back(now);
+        }
+        true
+    }
+}
+
+/// Whether a path relative to the mount root matches a pattern, see
+/// [`MutationLimit::path`].
+pub fn path_matches(pattern: &str, path: &str) -> bool {
+    let pattern: Vec<&str> = pattern.split('/').filter(|c| !c.is_empty()).collect();
+    let path: Vec<&str> = path.split('/').filter(|c| !c.is_empty()).collect();
+    components_match(&pattern, &path)
+}
+
+fn components_match(pattern: &[&str], path: &[&str]) -> bool {
+    match (pattern.first(), path.first()) {
+        (None, None) => true,
+        (Some(&"**"), _) => {
+            components_match(&pattern[1..], path)
+                || (!path.is_empty() && components_match(pattern, &path[1..]))
+        }
+        (Some(p), Some(c)) if *p == "*" || p == c => components_match(&pattern[1..], &path[1..]),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn star_matches_a_single_component() {
+        assert!(path_matches(
+            "/*/deployments/*",
+            "/shop/deployments/web.yaml"
+        ));
+        assert!(!path_matches("/*/deployments/*", "/shop/deployments"));
+        assert!(!path_matches(
+            "/*/deployments/*",
+            "/shop/deployments/web/web.yaml"
+        ));
+        assert!(!path_matches("/*", "/"));
+    }
+
+    #[test]
+    fn double_star_matches_any_number_of_components() {
+        assert!(path_matches("/**", "/"));
+        assert!(path_matches("/**/web.yaml", "/web.yaml"));
+        assert!(path_matches("/**/web.yaml", "/shop/deployments/web.yaml"));
+        assert!(path_matches("/shop/**", "/shop/deployments/web/web.yaml"));
+        assert!(!path_matches("/**/web.yaml", "/shop/deployments/api.yaml"));
+    }
+
+    #[test]
+    fn empty_trailing_segments_are_ignored() {
+        assert!(path_matches("/*/deployments/", "/shop/deployments"));
+        assert!(path_matches("/*/deployments", "/shop/deployments/"));
+        assert!(path_matches("shop//deployments", "/shop/deployments"));
+    }
+
+    #[test]
+    fn prefixes_alone_do_not_match() {
+        assert!(!path_matches("/shop", "/shop/deployments/web.yaml"));
+        assert!(!path_matches("/shop/deployments/web.yaml", "/shop"));
+        assert!(!path_matches("/sh*/deployments", "/shop/deployments"));
+        assert!(!path_matches(
+            "/shop/deployments/*",
+            "/shopping/deployments/web.yaml"
+        ));
+    }
+}
@@ -0,0 +1,132 @@
+use std::collections::BTreeSet;
+
+use serde_json::Value;
+
+/// A ConfigMap or Secret referenced by an object, e.g. `("configmaps", "app")`.
+pub type Reference = (&'static str, String);
+
+/// The resources whose objects run pods, along with where their pod spec is.
+const POD_SPECS: [(&str, &[&str]); 7] = [
+    ("pods", &["spec"]),
+    ("deployments", &["spec", "template", "spec"]),
+    ("replicasets", &["spec", "template", "spec"]),
+    ("statefulsets", &["spec", "template", "spec"]),
+    ("daemonsets", &["spec", "template", "spec"]),
+    ("jobs", &["spec", "template", "spec"]),
+    (
+        "cronjobs",
+        &["spec", "jobTemplate", "spec", "template", "spec"],
+    ),
+];
+
+/// Whether objects of a resource may reference ConfigMaps and Secrets: those
+/// running pods, Ingresses and ServiceAccounts.
+pub fn may_reference(resource: &str) -> bool {
+    matches!(resource, "ingresses" | "serviceaccounts")
+        || POD_SPECS.iter().any(|(name, _)| *name == resource)
+}
+
+/// The ConfigMaps and Secrets an object of the given resource references, in
+/// its namespace: those the pods it runs use for their environment, volumes
+/// or image pull secrets, the TLS Secrets of an Ingress and the Secrets of a
+/// ServiceAccount. Optional references count too.
+pub fn referenced(resource: &str, object: &Value) -> BTreeSet<Reference> {
+    let mut references = BTreeSet::new();
+    let mut add = |resource: &'static str, name: &Value| {
+        if let Some(name) = name.as_str().filter(|name| !name.is_empty()) {
+            references.insert((resource, name.to_string()));
+        }
+    };
+    match resource {
+        "ingresses" => {
+            for tls in object["spec"]["tls"].as_array().into_iter().flatten() {
+                add("secrets", &tls["secretName"]);
+            }
+            return references;
+        }
+        "serviceaccounts" => {
+            for field in ["secrets", "imagePullSecrets"] {
+                for secret in object[field].as_array().into_iter().flatten() {
+                    add("secrets", &secret["name"]);
+                }
+            }
+            return references;
+        }
+        _ => {}
+    }
+    let Some((_, path)) = POD_SPECS.iter().find(|(name, _)| *name == resource) else {
+        return references;
+    };
+    let spec = path.iter().fold(object, |value, field| &value[field]);
+
+    for field in ["initContainers", "containers", "ephemeralContainers"] {
+        for container in spec[field].as_array().into_iter().flatten() {
+            for var in container["env"].as_array().into_iter().flatten() {
+                add("configmaps", &var["valueFrom"]["configMapKeyRef"]["name"]);
+                add("secrets", &var["valueFrom"]["secretKeyRef"]["name"]);
+            }
+            for source in container["envFrom"].as_array().into_iter().flatten() {
+                add("configmaps", &source["configMapRef"]["name"]);
+                add("secrets", &source["secretRef"]["name"]);
+            }
+        }
+    }
+    for volume in spec["volumes"].as_array().into_iter().flatten() {
+        add("configmaps", &volume["configMap"]["name"]);
+        add("secrets", &volume["secret"]["secretName"]);
+        for source in volume["projected"]["sources"]
+            .as_array()
+            .into_iter()
+            .flatten()
+        {
+            add("configmaps", &source["configMap"]["name"]);
+            add("secrets", &source["secret"]["name"]);
+        }
+    }
+    for secret in spec["imagePullSecrets"].as_array().into_iter().flatten() {
+        add("secrets", &secret["name"]);
+    }
+    references
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn finds_references_in_the_pod_template() {
+        let deployment = json!({
+            "spec": {"template": {"spec": {
+                "containers": [{
+                    "env": [
+                        {"name": "A", "value": "a"},
+                        {"name": "B", "valueFrom": {"secretKeyRef": {"name": "creds", "key": "b"}}},
+                    ],
+                    "envFrom": [{"configMapRef": {"name": "settings"}}],
+                }],
+                "volumes": [
+                    {"name": "config", "configMap": {"name": "files"}},
+                    {"name": "all", "projected": {"sources": [{"secret": {"name": "tls"}}]}},
+                ],
+                "imagePullSecrets": [{"name": "registry"}],
+            }}},
+        });
+        let references: Vec<_> = referenced("deployments", &deployment)
+            .into_iter()
+            .map(|(resource, name)| format!("{resource}/{name}"))
+            .collect();
+        assert_eq!(
+            references,
+            [
+                "configmaps/files",
+                "configmaps/settings",
+                "secrets/creds",
+                "secrets/registry",
+                "secrets/tls",
+            ]
+        );
+        assert!(referenced("configmaps", &deployment).is_empty());
+    }
+}
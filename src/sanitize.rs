@@ -0,0 +1,74 @@
+use serde_yaml::Value;
+
+// fields populated by the API server that differ between otherwise identical objects
+const VOLATILE_METADATA_FIELDS: &[&str] = &[
+    "uid",
+    "resourceVersion",
+    "creationTimestamp",
+    "generation",
+    "managedFields",
+    "selfLink",
+    "namespace",
+];
+
+const REDACTED: &str = "REDACTED";
+
+const VOLATILE_ANNOTATIONS: &[&str] = &["kubectl.kubernetes.io/last-applied-configuration"];
+
+/// Strips server-populated metadata and status from a YAML manifest so that
+/// the same object can be compared across namespaces or clusters.
+///
+/// Manifests that fail to parse are returned unchanged.
+pub fn sanitize_manifest(manifest: &[u8]) -> String {
+    let mut value: Value = match serde_yaml::from_slice(manifest) {
+        Ok(value) => value,
+        Err(e) => {
+            log::warn!("failed to parse manifest for sanitization: {e}");
+            return String::from_utf8_lossy(manifest).into_owned();
+        }
+    };
+
+    sanitize_value(&mut value);
+    serde_yaml::to_string(&value).unwrap_or_default()
+}
+
+/// In-place variant of [`sanitize_manifest`] for already parsed objects.
+pub fn sanitize_value(value: &mut Value) {
+    if let Some(object) = value.as_mapping_mut() {
+        object.remove("status");
+
+        if let Some(metadata) = object.get_mut("metadata").and_then(Value::as_mapping_mut) {
+            for field in VOLATILE_METADATA_FIELDS {
+                metadata.remove(*field);
+            }
+
+            if let Some(annotations) = metadata
+                .get_mut("annotations")
+                .and_then(Value::as_mapping_mut)
+            {
+                for annotation in VOLATILE_ANNOTATIONS {
+                    annotations.remove(*annotation);
+                }
+                if annotations.is_empty() {
+                    metadata.remove("annotations");
+                }
+            }
+        }
+    }
+}
+
+/// Replaces the values of a Secret's `data` and `stringData` keys, keeping the
+/// keys themselves visible.
+pub fn redact_secret_data(value: &mut Value) {
+    let Some(object) = value.as_mapping_mut() else {
+        return;
+    };
+
+    for field in ["data", "stringData"] {
+        if let Some(data) = object.get_mut(field).and_then(Value::as_mapping_mut) {
+            for (_, data_value) in data.iter_mut() {
+                *data_value = Value::String(REDACTED.to_string());
+            }
+        }
+    }
+}
@@ -0,0 +1,152 @@
+//! Summaries of the security settings pods run with, which are spread over
+//! the pod and its containers in manifests.
+
+use std::fmt::Write;
+
+use serde_json::Value;
+
+const POD_SECURITY_LABEL_PREFIX: &str = "pod-security.kubernetes.io/";
+const POD_SECURITY_MODES: [&str; 3] = ["enforce", "audit", "warn"];
+
+// a container setting, the pod setting it falls back to and its default
+const CONTAINER_SETTINGS: [(&str, Option<&str>, &str); 7] = [
+    ("runAsUser", Some("runAsUser"), "image default"),
+    ("runAsGroup", Some("runAsGroup"), "image default"),
+    ("runAsNonRoot", Some("runAsNonRoot"), "false"),
+    ("privileged", None, "false"),
+    ("allowPrivilegeEscalation", None, "true"),
+    ("readOnlyRootFilesystem", None, "false"),
+    ("seccompProfile", Some("seccompProfile"), "Unconfined"),
+];
+
+fn render_setting(name: &str, value: &Value) -> String {
+    match (name, value) {
+        ("seccompProfile", profile) => match profile["localhostProfile"].as_str() {
+            Some(path) => format!("Localhost {path}"),
+            None => profile["type"].as_str().unwrap_or_default().to_string(),
+        },
+        (_, Value::String(s)) => s.clone(),
+        (_, other) => other.to_string(),
+    }
+}
+
+/// Summarizes the security of a pod: the Pod Security admission levels of
+/// its namespace as found in the labels given, the host namespaces it shares
+/// and the effective security context of each container, saying which
+/// settings are inherited from the pod or left to their defaults.
+pub fn summarize(pod: &Value, namespace_labels: &Value) -> String {
+    let mut out = String::new();
+    let levels: Vec<String> = POD_SECURITY_MODES
+        .iter()
+        .map(|mode| {
+            let label = |suffix: &str| {
+                namespace_labels[format!("{POD_SECURITY_LABEL_PREFIX}{mode}{suffix}")].as_str()
+            };
+            match (label(""), label("-version")) {
+                (Some(level), Some(version)) => format!("{mode}={level}:{version}"),
+                (Some(level), None) => format!("{mode}={level}"),
+                (None, _) => format!("{mode}=unset"),
+            }
+        })
+        .collect();
+    let _ = writeln!(out, "namespace pod security: {}", levels.join(" "));
+
+    let spec = &pod["spec"];
+    let host: Vec<&str> = [
+        ("hostNetwork", "network"),
+        ("hostPID", "pid"),
+        ("hostIPC", "ipc"),
+    ]
+    .into_iter()
+    .filter(|(field, _)| spec[field].as_bool() == Some(true))
+    .map(|(_, name)| name)
+    .collect();
+    let host = if host.is_empty() {
+        "none".to_string()
+    } else {
+        host.join(", ")
+    };
+    let _ = writeln!(out, "host namespaces: {host}");
+
+    let pod_context = &spec["securityContext"];
+    for field in ["initContainers", "containers", "ephemeralContainers"] {
+        for container in spec[field].as_array().into_iter().flatten() {
+            let name = container["name"].as_str().unwrap_or_default();
+            let context = &container["securityContext"];
+            let _ = writeln!(out, "\n[{name}]");
+            for (setting, pod_setting, default) in CONTAINER_SETTINGS {
+                let value = if !context[setting].is_null() {
+                    render_setting(setting, &context[setting])
+                } else if let Some(pod_value) = pod_setting
+                    .map(|s| &pod_context[s])
+                    .filter(|v| !v.is_null())
+                {
+                    format!("{} (pod)", render_setting(setting, pod_value))
+                } else {
+                    format!("{default} (default)")
+                };
+                let _ = writeln!(out, "{setting}: {value}");
+            }
+            let capabilities = |field: &str| {
+                let names: Vec<&str> = context["capabilities"][field]
+                    .as_array()
+                    .into_iter()
+                    .flatten()
+                    .filter_map(Value::as_str)
+                    .collect();
+                match names.is_empty() {
+                    true => "none".to_string(),
+                    false => names.join(" "),
+                }
+            };
+            let _ = writeln!(
+                out,
+                "capabilities: add {}, drop {}",
+                capabilities("add"),
+                capabilities("drop")
+            );
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn container_settings_fall_back_to_the_pod_then_defaults() {
+        let pod = json!({
+            "spec": {
+                "hostNetwork": true,
+                "securityContext": {"runAsUser": 1000, "seccompProfile": {"type": "RuntimeDefault"}},
+                "containers": [{
+                    "name": "app",
+                    "securityContext": {
+                        "runAsUser": 0,
+                        "capabilities": {"add": ["NET_ADMIN"], "drop": ["ALL"]},
+                    },
+                }],
+            },
+        });
+        let labels = json!({"pod-security.kubernetes.io/enforce": "baseline"});
+        let summary = summarize(&pod, &labels);
+        for line in [
+            "namespace pod security: enforce=baseline audit=unset warn=unset",
+            "host namespaces: network",
+            "[app]",
+            "runAsUser: 0",
+            "runAsGroup: image default (default)",
+            "privileged: false (default)",
+            "seccompProfile: RuntimeDefault (pod)",
+            "capabilities: add NET_ADMIN, drop ALL",
+        ] {
+            assert!(
+                summary.lines().any(|l| l == line),
+                "{line:?} not in:\n{summary}"
+            );
+        }
+    }
+}
@@ -0,0 +1,255 @@
+use serde_json::Value;
+
+/// How an object is doing, independently of its kind.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Health {
+    Healthy,
+    /// On its way to healthy, e.g. rolling out or starting.
+    Progressing,
+    Unhealthy,
+    /// Ran to completion, e.g. a finished Job.
+    Completed,
+}
+
+impl Health {
+    pub const ALL: [Health; 4] = [
+        Health::Healthy,
+        Health::Progressing,
+        Health::Unhealthy,
+        Health::Completed,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Health::Healthy => "healthy",
+            Health::Progressing => "progressing",
+            Health::Unhealthy => "unhealthy",
+            Health::Completed => "completed",
+        }
+    }
+}
+
+/// Summarizes the status of an object of the given resource, e.g.
+/// `unhealthy: CrashLoopBackOff` for a pod or `progressing: 1/3 available`
+/// for a deployment. Objects without a status worth reporting, e.g.
+/// ConfigMaps, have none.
+pub fn summarize(resource: &str, object: &Value) -> Option<String> {
+    let (health, detail) = match resource {
+        "pods" => pod_status(object),
+        "deployments" | "replicasets" | "statefulsets" => replicas_status(object)?,
+        "daemonsets" => daemon_set_status(&object["status"])?,
+        "jobs" => job_status(object)?,
+        "nodes" => node_status(object)?,
+        "namespaces" | "persistentvolumes" | "persistentvolumeclaims" => {
+            phase_status(&object["status"])?
+        }
+        _ => ready_condition_status(&object["status"])?,
+    };
+    Some(format!("{}: {detail}", health.as_str()))
+}
+
+fn pod_status(pod: &Value) -> (Health, String) {
+    if pod["metadata"]["deletionTimestamp"].is_string() {
+        return (Health::Progressing, "Terminating".to_string());
+    }
+    let status = &pod["status"];
+    let phase = status["phase"].as_str().unwrap_or("Unknown");
+    match phase {
+        "Succeeded" => return (Health::Completed, phase.to_string()),
+        "Failed" | "Unknown" => return (Health::Unhealthy, phase.to_string()),
+        _ => {}
+    }
+
+    let containers = status["containerStatuses"].as_array().map(Vec::as_slice);
+    let containers = containers.unwrap_or_default();
+    // e.g. CrashLoopBackOff or ImagePullBackOff, but not the ContainerCreating
+    // of a starting pod
+    let stuck = containers.iter().find_map(|container| {
+        container["state"]["waiting"]["reason"]
+            .as_str()
+            .filter(|reason| *reason != "ContainerCreating" && *reason != "PodInitializing")
+    });
+    if let Some(reason) = stuck {
+        return (Health::Unhealthy, reason.to_string());
+    }
+    let all_ready = !containers.is_empty() && containers.iter().all(|c| c["ready"] == true);
+    match (phase, all_ready) {
+        ("Running", true) => (Health::Healthy, phase.to_string()),
+        ("Running", false) => (Health::Progressing, "NotReady".to_string()),
+        _ => (Health::Progressing, phase.to_string()),
+    }
+}
+
+// Deployments, ReplicaSets and StatefulSets
+fn replicas_status(object: &Value) -> Option<(Health, String)> {
+    let status = &object["status"];
+    if condition(status, "Progressing").is_some_and(|c| c["reason"] == "ProgressDeadlineExceeded") {
+        return Some((Health::Unhealthy, "ProgressDeadlineExceeded".to_string()));
+    }
+    let desired = object["spec"]["replicas"].as_u64().unwrap_or(1);
+    // StatefulSets don't report available replicas before 1.22
+    let available = status["availableReplicas"]
+        .as_u64()
+        .or(status["readyReplicas"].as_u64())
+        .unwrap_or(0);
+    let updated = status["updatedReplicas"].as_u64().unwrap_or(desired);
+    Some(if desired == 0 {
+        (Health::Healthy, "ScaledToZero".to_string())
+    } else if available >= desired && updated >= desired {
+        (Health::Healthy, "Available".to_string())
+    } else if available == 0 {
+        (Health::Unhealthy, "Unavailable".to_string())
+    } else {
+        (
+            Health::Progressing,
+            format!("{available}/{desired} available"),
+        )
+    })
+}
+
+fn daemon_set_status(status: &Value) -> Option<(Health, String)> {
+    let desired = status["desiredNumberScheduled"].as_u64()?;
+    let available = status["numberAvailable"].as_u64().unwrap_or(0);
+    Some(if available >= desired {
+        (Health::Healthy, "Available".to_string())
+    } else if available == 0 {
+        (Health::Unhealthy, "Unavailable".to_string())
+    } else {
+        (
+            Health::Progressing,
+            format!("{available}/{desired} available"),
+        )
+    })
+}
+
+fn job_status(job: &Value) -> Option<(Health, String)> {
+    let status = &job["status"];
+    if condition(status, "Complete").is_some_and(|c| c["status"] == "True") {
+        return Some((Health::Completed, "Complete".to_string()));
+    }
+    if let Some(failed) = condition(status, "Failed").filter(|c| c["status"] == "True") {
+        let reason = failed["reason"].as_str().unwrap_or("Failed");
+        return Some((Health::Unhealthy, reason.to_string()));
+    }
+    if job["spec"]["suspend"] == true {
+        return Some((Health::Healthy, "Suspended".to_string()));
+    }
+    Some((Health::Progressing, "Running".to_string()))
+}
+
+fn node_status(node: &Value) -> Option<(Health, String)> {
+    let ready = condition(&node["status"], "Ready")?;
+    let mut status = if ready["status"] == "True" {
+        (Health::Healthy, "Ready".to_string())
+    } else {
+        (Health::Unhealthy, "NotReady".to_string())
+    };
+    if node["spec"]["unschedulable"] == true {
+        status.1.push_str(",SchedulingDisabled");
+    }
+    Some(status)
+}
+
+// Namespaces, PersistentVolumes and PersistentVolumeClaims
+fn phase_status(status: &Value) -> Option<(Health, String)> {
+    let phase = status["phase"].as_str()?;
+    let health = match phase {
+        "Active" | "Bound" | "Available" => Health::Healthy,
+        "Terminating" | "Pending" | "Released" => Health::Progressing,
+        _ => Health::Unhealthy,
+    };
+    Some((health, phase.to_string()))
+}
+
+// the convention of most custom resources, e.g. cert-manager Certificates
+fn ready_condition_status(status: &Value) -> Option<(Health, String)> {
+    let ready = condition(status, "Ready")?;
+    Some(match ready["status"].as_str() {
+        Some("True") => (Health::Healthy, "Ready".to_string()),
+        Some("False") => (
+            Health::Unhealthy,
+            ready["reason"].as_str().unwrap_or("NotReady").to_string(),
+        ),
+        _ => (Health::Progressing, "Unknown".to_string()),
+    })
+}
+
+fn condition<'a>(status: &'a Value, type_: &str) -> Option<&'a Value> {
+    status["conditions"]
+        .as_array()?
+        .iter()
+        .find(|condition| condition["type"] == type_)
+}
+
+/// A shell function listing a directory like `ls -1`, with names colored by
+/// the health in their `user.kube.status` attribute, e.g. to be sourced from
+/// `.bashrc`.
+pub fn ls_colors_script() -> String {
+    let colors = Health::ALL
+        .iter()
+        .map(|health| {
+            let color = match health {
+                Health::Healthy => "32",
+                Health::Progressing => "33",
+                Health::Unhealthy => "1;31",
+                Health::Completed => "2",
+            };
+            format!("      {}:*) color='{color}' ;;\n", health.as_str())
+        })
+        .collect::<String>();
+
+    format!(
+        r#"# Lists directories of a kube-fuse mount with names colored by the health
+# in their user.kube.status attribute. Needs getfattr from the attr package.
+#   source .kubefuse/ls-colors && kls default/deployments
+kls() {{
+  local dir="${{1:-.}}" name status color
+  for name in $(ls -1 "$dir"); do
+    status=$(getfattr --only-values -n user.kube.status "$dir/$name" 2>/dev/null)
+    case "$status" in
+{colors}      *) color='' ;;
+    esac
+    if [ -n "$color" ]; then
+      printf '\033[%sm%s\033[0m  %s\n' "$color" "$name" "$status"
+    else
+      printf '%s\n' "$name"
+    fi
+  done
+}}
+"#
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn pods_waiting_on_a_failure_are_unhealthy() {
+        let pod = json!({
+            "status": {
+                "phase": "Running",
+                "containerStatuses": [
+                    {"ready": true, "state": {"running": {}}},
+                    {"ready": false, "state": {"waiting": {"reason": "CrashLoopBackOff"}}},
+                ],
+            },
+        });
+        assert_eq!(
+            summarize("pods", &pod).as_deref(),
+            Some("unhealthy: CrashLoopBackOff")
+        );
+
+        let deployment = json!({
+            "spec": {"replicas": 3},
+            "status": {"availableReplicas": 1, "updatedReplicas": 3},
+        });
+        assert_eq!(
+            summarize("deployments", &deployment).as_deref(),
+            Some("progressing: 1/3 available")
+        );
+        assert_eq!(summarize("configmaps", &json!({"data": {}})), None);
+    }
+}
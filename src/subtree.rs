@@ -0,0 +1,388 @@
+use std::{ffi::OsStr, time::SystemTime};
+
+use fuser::{Filesystem, Request};
+
+use crate::kubefuse::{self, CONTROL_DIR_NAME, KubeFilesystem, ROOT_ATTR};
+
+/// Presents a single directory of the tree at the root of the mount, e.g.
+/// the directory of a namespace, leaving the rest of the tree out of reach
+/// but for `.kubefuse`, which is looked up at the root as well. Every other
+/// node keeps its inode, so only the root is translated.
+pub struct SubtreeFilesystem {
+    fs: KubeFilesystem,
+    // relative to the root of the tree, e.g. `team-a`
+    path: String,
+    // the directory at `path`, known once the tree is built
+    root: u64,
+}
+
+impl SubtreeFilesystem {
+    pub fn new(fs: KubeFilesystem, path: &str) -> Self {
+        SubtreeFilesystem {
+            fs,
+            path: path.trim_matches('/').to_string(),
+            root: ROOT_ATTR.ino,
+        }
+    }
+
+    // looks up the directory presented at the root, once the tree is built
+    fn resolve_root(&mut self) -> Result<(), libc::c_int> {
+        self.root = self.fs.dir_inode(&self.path).ok_or_else(|| {
+            log::error!("{} is not a directory of the tree", self.path);
+            libc::ENOENT
+        })?;
+        Ok(())
+    }
+
+    // the inode of a node in the tree
+    fn local(&self, ino: u64) -> u64 {
+        match ino {
+            ino if ino == ROOT_ATTR.ino => self.root,
+            ino => ino,
+        }
+    }
+
+    // the directory in the tree to look a name up in, the root of the tree
+    // for the control directory
+    fn lookup_parent(&self, parent: u64, name: &OsStr) -> u64 {
+        match parent == ROOT_ATTR.ino && name == CONTROL_DIR_NAME {
+            true => ROOT_ATTR.ino,
+            false => self.local(parent),
+        }
+    }
+}
+
+/// The directory `--single-namespace` presents at the root, that of the one
+/// namespace given without a `*`, or `None` if there's no such namespace.
+pub fn single_namespace_path(namespaces: &[String]) -> Option<&str> {
+    match namespaces {
+        [namespace] if !namespace.ends_with('*') => Some(namespace),
+        _ => None,
+    }
+}
+
+impl Filesystem for SubtreeFilesystem {
+    fn init(
+        &mut self,
+        _req: &Request<'_>,
+        config: &mut fuser::KernelConfig,
+    ) -> Result<(), libc::c_int> {
+        kubefuse::configure_kernel(config);
+        self.fs.build_tree()?;
+        self.resolve_root()
+    }
+
+    fn lookup(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: fuser::ReplyEntry) {
+        self.fs
+            .lookup(req, self.lookup_parent(parent, name), name, reply);
+    }
+
+    fn forget(&mut self, req: &Request<'_>, ino: u64, nlookup: u64) {
+        self.fs.forget(req, self.local(ino), nlookup);
+    }
+
+    fn batch_forget(&mut self, req: &Request<'_>, nodes: &[fuser::fuse_forget_one]) {
+        let nodes: Vec<_> = nodes
+            .iter()
+            .map(|node| fuser::fuse_forget_one {
+                nodeid: self.local(node.nodeid),
+                nlookup: node.nlookup,
+            })
+            .collect();
+        self.fs.batch_forget(req, &nodes);
+    }
+
+    fn getattr(&mut self, req: &Request<'_>, ino: u64, fh: Option<u64>, reply: fuser::ReplyAttr) {
+        self.fs.getattr(req, self.local(ino), fh, reply);
+    }
+
+    fn readlink(&mut self, req: &Request<'_>, ino: u64, reply: fuser::ReplyData) {
+        self.fs.readlink(req, self.local(ino), reply);
+    }
+
+    fn statfs(&mut self, req: &Request<'_>, ino: u64, reply: fuser::ReplyStatfs) {
+        self.fs.statfs(req, self.local(ino), reply);
+    }
+
+    fn opendir(&mut self, req: &Request<'_>, ino: u64, flags: i32, reply: fuser::ReplyOpen) {
+        self.fs.opendir(req, self.local(ino), flags, reply);
+    }
+
+    fn readdir(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        reply: fuser::ReplyDirectory,
+    ) {
+        self.fs.readdir(req, self.local(ino), fh, offset, reply);
+    }
+
+    fn releasedir(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        flags: i32,
+        reply: fuser::ReplyEmpty,
+    ) {
+        self.fs.releasedir(req, self.local(ino), fh, flags, reply);
+    }
+
+    fn open(&mut self, req: &Request<'_>, ino: u64, flags: i32, reply: fuser::ReplyOpen) {
+        self.fs.open(req, self.local(ino), flags, reply);
+    }
+
+    fn read(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        size: u32,
+        flags: i32,
+        lock_owner: Option<u64>,
+        reply: fuser::ReplyData,
+    ) {
+        let ino = self.local(ino);
+        self.fs
+            .read(req, ino, fh, offset, size, flags, lock_owner, reply);
+    }
+
+    fn release(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        flags: i32,
+        lock_owner: Option<u64>,
+        flush: bool,
+        reply: fuser::ReplyEmpty,
+    ) {
+        let ino = self.local(ino);
+        self.fs
+            .release(req, ino, fh, flags, lock_owner, flush, reply);
+    }
+
+    fn flush(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        lock_owner: u64,
+        reply: fuser::ReplyEmpty,
+    ) {
+        self.fs.flush(req, self.local(ino), fh, lock_owner, reply);
+    }
+
+    fn getxattr(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        name: &OsStr,
+        size: u32,
+        reply: fuser::ReplyXattr,
+    ) {
+        self.fs.getxattr(req, self.local(ino), name, size, reply);
+    }
+
+    fn listxattr(&mut self, req: &Request<'_>, ino: u64, size: u32, reply: fuser::ReplyXattr) {
+        self.fs.listxattr(req, self.local(ino), size, reply);
+    }
+
+    fn setattr(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        mode: Option<u32>,
+        uid: Option<u32>,
+        gid: Option<u32>,
+        size: Option<u64>,
+        atime: Option<fuser::TimeOrNow>,
+        mtime: Option<fuser::TimeOrNow>,
+        ctime: Option<SystemTime>,
+        fh: Option<u64>,
+        crtime: Option<SystemTime>,
+        chgtime: Option<SystemTime>,
+        bkuptime: Option<SystemTime>,
+        flags: Option<u32>,
+        reply: fuser::ReplyAttr,
+    ) {
+        let ino = self.local(ino);
+        self.fs.setattr(
+            req, ino, mode, uid, gid, size, atime, mtime, ctime, fh, crtime, chgtime, bkuptime,
+            flags, reply,
+        );
+    }
+
+    fn write(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        offset: i64,
+        data: &[u8],
+        write_flags: u32,
+        flags: i32,
+        lock_owner: Option<u64>,
+        reply: fuser::ReplyWrite,
+    ) {
+        let ino = self.local(ino);
+        self.fs.write(
+            req,
+            ino,
+            fh,
+            offset,
+            data,
+            write_flags,
+            flags,
+            lock_owner,
+            reply,
+        );
+    }
+
+    fn create(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        flags: i32,
+        reply: fuser::ReplyCreate,
+    ) {
+        let parent = self.local(parent);
+        self.fs.create(req, parent, name, mode, umask, flags, reply);
+    }
+
+    fn unlink(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: fuser::ReplyEmpty) {
+        self.fs.unlink(req, self.local(parent), name, reply);
+    }
+
+    fn rename(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        newparent: u64,
+        newname: &OsStr,
+        flags: u32,
+        reply: fuser::ReplyEmpty,
+    ) {
+        let (parent, newparent) = (self.local(parent), self.local(newparent));
+        self.fs
+            .rename(req, parent, name, newparent, newname, flags, reply);
+    }
+
+    fn mkdir(
+        &mut self,
+        req: &Request<'_>,
+        parent: u64,
+        name: &OsStr,
+        mode: u32,
+        umask: u32,
+        reply: fuser::ReplyEntry,
+    ) {
+        self.fs
+            .mkdir(req, self.local(parent), name, mode, umask, reply);
+    }
+
+    fn rmdir(&mut self, req: &Request<'_>, parent: u64, name: &OsStr, reply: fuser::ReplyEmpty) {
+        self.fs.rmdir(req, self.local(parent), name, reply);
+    }
+
+    fn getlk(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        lock_owner: u64,
+        start: u64,
+        end: u64,
+        typ: i32,
+        pid: u32,
+        reply: fuser::ReplyLock,
+    ) {
+        let ino = self.local(ino);
+        self.fs
+            .getlk(req, ino, fh, lock_owner, start, end, typ, pid, reply);
+    }
+
+    fn setlk(
+        &mut self,
+        req: &Request<'_>,
+        ino: u64,
+        fh: u64,
+        lock_owner: u64,
+        start: u64,
+        end: u64,
+        typ: i32,
+        pid: u32,
+        sleep: bool,
+        reply: fuser::ReplyEmpty,
+    ) {
+        let ino = self.local(ino);
+        self.fs
+            .setlk(req, ino, fh, lock_owner, start, end, typ, pid, sleep, reply);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::config::Profile;
+
+    use super::*;
+
+    #[test]
+    fn single_namespace_takes_exactly_one_namespace() {
+        let namespaces = |names: &[&str]| names.iter().map(|n| n.to_string()).collect::<Vec<_>>();
+        assert_eq!(single_namespace_path(&namespaces(&["shop"])), Some("shop"));
+        assert_eq!(single_namespace_path(&namespaces(&[])), None);
+        assert_eq!(single_namespace_path(&namespaces(&["shop", "web"])), None);
+        assert_eq!(single_namespace_path(&namespaces(&["shop-*"])), None);
+    }
+
+    #[test]
+    fn the_root_is_rewritten_to_the_subtree() {
+        let mut fs = KubeFilesystem::for_tests(Profile::default());
+        let shop = fs.create_test_dir(ROOT_ATTR.ino, "shop");
+        let configmaps = fs.create_test_dir(shop, "configmaps");
+
+        let mut subtree = SubtreeFilesystem::new(fs, "/shop/");
+        assert_eq!(subtree.path, "shop");
+        subtree.resolve_root().unwrap();
+        assert_eq!(subtree.local(ROOT_ATTR.ino), shop);
+        // every other node keeps its inode
+        assert_eq!(subtree.local(configmaps), configmaps);
+        assert_eq!(subtree.local(shop), shop);
+    }
+
+    #[test]
+    fn the_control_dir_is_looked_up_at_the_root_of_the_tree() {
+        let mut fs = KubeFilesystem::for_tests(Profile::default());
+        let shop = fs.create_test_dir(ROOT_ATTR.ino, "shop");
+        let configmaps = fs.create_test_dir(shop, "configmaps");
+
+        let mut subtree = SubtreeFilesystem::new(fs, "shop");
+        subtree.resolve_root().unwrap();
+        let control_dir = OsStr::new(CONTROL_DIR_NAME);
+        assert_eq!(
+            subtree.lookup_parent(ROOT_ATTR.ino, control_dir),
+            ROOT_ATTR.ino
+        );
+        assert_eq!(
+            subtree.lookup_parent(ROOT_ATTR.ino, OsStr::new("configmaps")),
+            shop
+        );
+        // only at the root of the subtree
+        assert_eq!(subtree.lookup_parent(configmaps, control_dir), configmaps);
+    }
+
+    #[test]
+    fn subtrees_must_be_directories_of_the_tree() {
+        let fs = KubeFilesystem::for_tests(Profile::default());
+        let mut subtree = SubtreeFilesystem::new(fs, "shop");
+        assert_eq!(subtree.resolve_root(), Err(libc::ENOENT));
+    }
+}
@@ -0,0 +1,96 @@
+use std::{
+    error::Error,
+    io,
+    net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream},
+    process::{Child, Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+use crate::client::TlsConfig;
+
+// how long ssh gets to authenticate and start forwarding
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// A local port forwarded to the API server through a bastion by an `ssh`
+/// process, which is stopped when the tunnel is dropped.
+pub struct SshTunnel {
+    ssh: Child,
+    pub local_addr: SocketAddr,
+}
+
+impl SshTunnel {
+    /// Forwards a free local port to `host:port` through `destination`, e.g.
+    /// `user@bastion`, waiting until the forward accepts connections. The
+    /// destination is authenticated to non-interactively, e.g. with an agent.
+    pub fn open(destination: &str, host: &str, port: u16) -> io::Result<Self> {
+        let local_addr = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))?.local_addr()?;
+        let forward = format!("{local_addr}:{host}:{port}");
+        let ssh = Command::new("ssh")
+            .args([
+                "-N",
+                "-o",
+                "ExitOnForwardFailure=yes",
+                "-o",
+                "BatchMode=yes",
+            ])
+            .args(["-L", &forward, destination])
+            .stdin(Stdio::null())
+            .spawn()?;
+        let mut tunnel = SshTunnel { ssh, local_addr };
+
+        let deadline = Instant::now() + CONNECT_TIMEOUT;
+        loop {
+            if let Some(status) = tunnel.ssh.try_wait()? {
+                return Err(io::Error::other(format!("ssh exited with {status}")));
+            }
+            if TcpStream::connect(local_addr).is_ok() {
+                log::info!("tunneling to {host}:{port} through {destination} on {local_addr}");
+                return Ok(tunnel);
+            }
+            if Instant::now() >= deadline {
+                return Err(io::Error::new(
+                    io::ErrorKind::TimedOut,
+                    format!("ssh didn't start forwarding within {CONNECT_TIMEOUT:?}"),
+                ));
+            }
+            thread::sleep(Duration::from_millis(200));
+        }
+    }
+
+    /// Tunnels to the API server at the cluster URL, then points the URL at
+    /// the local end of the tunnel. The certificate is still verified against
+    /// the original host unless another server name is configured.
+    pub fn for_cluster(
+        destination: &str,
+        cluster_url: &mut String,
+        tls: &mut TlsConfig,
+    ) -> Result<Self, Box<dyn Error>> {
+        let mut url = reqwest::Url::parse(cluster_url)?;
+        let host = url
+            .host_str()
+            .ok_or("the cluster URL has no host")?
+            .to_string();
+        let port = url
+            .port_or_known_default()
+            .ok_or("the cluster URL has no port")?;
+
+        let tunnel = SshTunnel::open(destination, &host, port)?;
+        tls.server_name.get_or_insert(host);
+        url.set_ip_host(tunnel.local_addr.ip())
+            .map_err(|()| "failed to point the cluster URL at the tunnel")?;
+        url.set_port(Some(tunnel.local_addr.port()))
+            .map_err(|()| "failed to point the cluster URL at the tunnel")?;
+        *cluster_url = url.to_string();
+        Ok(tunnel)
+    }
+}
+
+impl Drop for SshTunnel {
+    fn drop(&mut self) {
+        if let Err(e) = self.ssh.kill() {
+            log::warn!("failed to stop the ssh tunnel: {e}");
+        }
+        let _ = self.ssh.wait();
+    }
+}